@@ -0,0 +1,585 @@
+//! trashcan's compile-time evaluation of constant expressions
+
+use std::collections::HashMap;
+use std::cmp::Ordering;
+
+use ast::*;
+use super::*;
+
+/// Evaluate every constant and optional parameter default in a dumpster,
+/// reporting cycles, overflows, and the like
+pub fn check_constants(dumpster: &Dumpster) -> AnalysisResultMany<()> {
+    let mut eval = ConstEvaluator::new(dumpster);
+
+    for m in &dumpster.modules {
+        match m.data {
+            ModuleKind::Normal(ref items) => for i in items {
+                match *i {
+                    NormalItem::Const(ref c) => {
+                        eval.const_value(&m.name, &c.name);
+                    },
+
+                    NormalItem::Function(ref f) => {
+                        if let Some(FunOptParams::Named(ref params)) =
+                          f.optparams {
+                            for &(ref p, ref default) in params {
+                                eval.eval_as(default, &p.ty, &m.name);
+                            }
+                        }
+                    },
+
+                    _ => { },
+                }
+            },
+        }
+    }
+
+    if eval.errors.is_empty() {
+        Ok(())
+    } else {
+        Err(eval.errors)
+    }
+}
+
+/// An intermediate value during evaluation; literals of every type we can
+/// compute with widen into one of these
+#[derive(Clone, Debug)]
+enum Value {
+    Int(i64),
+    Float(f64),
+    Str(String),
+    Bool(bool),
+}
+
+/// An evaluator for the constant expressions in a dumpster; the values of
+/// consts are memoized as they're computed
+pub struct ConstEvaluator<'a> {
+    defs: HashMap<(String, String), &'a Constant>,
+    // None for consts which failed to evaluate (already reported)
+    values: HashMap<(String, String), Option<Literal>>,
+    // consts currently under evaluation (for cycle detection)
+    stack: Vec<(String, String)>,
+    errors: Vec<AnalysisError>,
+}
+
+impl<'a> ConstEvaluator<'a> {
+    pub fn new(dumpster: &'a Dumpster) -> Self {
+        let mut defs = HashMap::new();
+
+        for m in &dumpster.modules {
+            match m.data {
+                ModuleKind::Normal(ref items) => for i in items {
+                    if let NormalItem::Const(ref c) = *i {
+                        defs.insert((m.name.0.clone(), c.name.0.clone()), c);
+                    }
+                },
+            }
+        }
+
+        ConstEvaluator {
+            defs,
+            values: HashMap::new(),
+            stack: Vec::new(),
+            errors: Vec::new(),
+        }
+    }
+
+    /// the value of a const, as a literal of its declared type
+    pub fn const_value(&mut self, module: &Ident, name: &Ident)
+      -> Option<Literal> {
+        let key = (module.0.clone(), name.0.clone());
+
+        if let Some(val) = self.values.get(&key) {
+            return val.clone();
+        }
+
+        let c = *self.defs.get(&key).unwrap_or_else(|| panic!(
+          "dumpster fire: no const definition for {}::{}", module, name));
+
+        if let Some(pos) = self.stack.iter().position(|k| *k == key) {
+            let cycle: Vec<(String, String)> = self.stack[pos..].to_vec();
+
+            let mut desc = cycle.iter().map(|k| k.1.as_str())
+                .collect::<Vec<_>>().join(" -> ");
+            desc.push_str(" -> ");
+            desc.push_str(&key.1);
+
+            for k in cycle {
+                let loc = self.defs[&k].loc.clone();
+                self.errors.push(AnalysisError {
+                    kind: AnalysisErrorKind::InvalidExpr,
+                    regarding: Some(format!("cyclic constant definition: {}",
+                      desc)),
+                    loc,
+                });
+                self.values.insert(k, None);
+            }
+
+            return None;
+        }
+
+        self.stack.push(key.clone());
+        let val = self.eval_as(&c.value, &c.ty, module);
+        self.stack.pop();
+
+        // members of a cycle have already been marked as failed
+        self.values.entry(key).or_insert(val).clone()
+    }
+
+    /// evaluate an expression, converting the result to a given type
+    pub fn eval_as(&mut self, expr: &Expr, ty: &Type, module: &Ident)
+      -> Option<Literal> {
+        let lit = self.eval(expr, module)?;
+        self.convert(lit, ty, &expr.loc)
+    }
+
+    /// evaluate an expression to a literal of its own type
+    pub fn eval(&mut self, expr: &Expr, module: &Ident) -> Option<Literal> {
+        let ty = expr.ty.as_ref().expect("dumpster fire: \
+          untyped expression in constant evaluation");
+
+        match expr.data {
+            ExprKind::Lit(ref lit) => Some(lit.clone()),
+
+            ExprKind::Name(ref path) => {
+                let lit = {
+                    let m = path.0.as_ref().unwrap_or(module);
+                    self.const_value(m, &path.1)?
+                };
+
+                self.convert(lit, ty, &expr.loc)
+            },
+
+            ExprKind::UnOpApp(ref e, op) => {
+                let lit = self.eval(e, module)?;
+                let operand_ty = e.ty.as_ref().expect("dumpster fire: \
+                  untyped expression in constant evaluation");
+                let val = self.value_of(lit, &e.loc)?;
+
+                let res = match (op, val) {
+                    (UnOp::Negate, Value::Int(i)) => match i.checked_neg() {
+                        Some(i) => Value::Int(i),
+                        None => return self.overflow(&expr.loc),
+                    },
+                    (UnOp::Negate, Value::Float(f)) => Value::Float(-f),
+
+                    // VB's Not on a Byte stays in range
+                    (UnOp::BitNot, Value::Int(i)) =>
+                        if *operand_ty == Type::UInt8 {
+                            Value::Int(255 - i)
+                        } else {
+                            Value::Int(!i)
+                        },
+                    (UnOp::BitNot, Value::Bool(b)) => Value::Bool(!b),
+
+                    (UnOp::LogNot, Value::Bool(b)) => Value::Bool(!b),
+
+                    _ => return self.unevaluable(expr),
+                };
+
+                self.literal_of(res, ty, &expr.loc)
+            },
+
+            ExprKind::BinOpApp(ref lhs, ref rhs, op) => {
+                let lhs_lit = self.eval(lhs, module)?;
+                let rhs_lit = self.eval(rhs, module)?;
+                let lhs_val = self.value_of(lhs_lit, &lhs.loc)?;
+                let rhs_val = self.value_of(rhs_lit, &rhs.loc)?;
+
+                let res = self.binop(op, lhs_val, rhs_val, ty, expr)?;
+                self.literal_of(res, ty, &expr.loc)
+            },
+
+            ExprKind::CondExpr { ref cond, ref if_expr, ref else_expr } => {
+                let cond = self.eval(cond, module)?;
+                let branch = match cond {
+                    Literal::Bool(true) => if_expr,
+                    Literal::Bool(false) => else_expr,
+                    _ => return self.unevaluable(expr),
+                };
+
+                self.eval_as(branch, ty, module)
+            },
+
+            _ => self.unevaluable(expr),
+        }
+    }
+
+    fn binop(&mut self, op: BinOp, lhs: Value, rhs: Value, ty: &Type,
+      expr: &Expr) -> Option<Value> {
+        let loc = &expr.loc;
+
+        let res = match (op, lhs, rhs) {
+            (BinOp::Add, Value::Int(l), Value::Int(r)) =>
+                l.checked_add(r).map(Value::Int),
+            (BinOp::Sub, Value::Int(l), Value::Int(r)) =>
+                l.checked_sub(r).map(Value::Int),
+            (BinOp::Mul, Value::Int(l), Value::Int(r)) =>
+                l.checked_mul(r).map(Value::Int),
+
+            // integer division truncates, like VB's \
+            (BinOp::Div, Value::Int(l), Value::Int(r))
+              if ty.is_integral() => {
+                if r == 0 {
+                    return self.div_by_zero(loc);
+                }
+                l.checked_div(r).map(Value::Int)
+            },
+
+            (BinOp::Mod, Value::Int(l), Value::Int(r)) => {
+                if r == 0 {
+                    return self.div_by_zero(loc);
+                }
+                l.checked_rem(r).map(Value::Int)
+            },
+
+            (BinOp::Add, l, r) => float_op(l, r, |l, r| l + r),
+            (BinOp::Sub, l, r) => float_op(l, r, |l, r| l - r),
+            (BinOp::Mul, l, r) => float_op(l, r, |l, r| l * r),
+
+            (BinOp::Div, l, r) => {
+                if let Some(0.0) = as_float(&r) {
+                    return self.div_by_zero(loc);
+                }
+                float_op(l, r, |l, r| l / r)
+            },
+
+            // VB's Mod rounds its operands to integers first
+            (BinOp::Mod, l, r) => {
+                let (l, r) = match (as_float(&l), as_float(&r)) {
+                    (Some(l), Some(r)) => (vb_round(l), vb_round(r)),
+                    _ => return self.unevaluable(expr),
+                };
+                if r == 0.0 {
+                    return self.div_by_zero(loc);
+                }
+                Some(Value::Float(l % r))
+            },
+
+            (BinOp::Pow, l, r) => float_op(l, r, f64::powf),
+
+            (BinOp::StrCat, Value::Str(mut l), Value::Str(r)) => {
+                l.push_str(&r);
+                Some(Value::Str(l))
+            },
+
+            (BinOp::Eq, l, r) =>
+                compare(&l, &r).map(|o| Value::Bool(o == Ordering::Equal)),
+            (BinOp::NotEq, l, r) =>
+                compare(&l, &r).map(|o| Value::Bool(o != Ordering::Equal)),
+            (BinOp::Lt, l, r) =>
+                compare(&l, &r).map(|o| Value::Bool(o == Ordering::Less)),
+            (BinOp::Gt, l, r) =>
+                compare(&l, &r).map(|o| Value::Bool(o == Ordering::Greater)),
+            (BinOp::LtEq, l, r) =>
+                compare(&l, &r).map(|o| Value::Bool(o != Ordering::Greater)),
+            (BinOp::GtEq, l, r) =>
+                compare(&l, &r).map(|o| Value::Bool(o != Ordering::Less)),
+
+            (BinOp::BitAnd, Value::Int(l), Value::Int(r)) =>
+                Some(Value::Int(l & r)),
+            (BinOp::BitOr, Value::Int(l), Value::Int(r)) =>
+                Some(Value::Int(l | r)),
+            (BinOp::BitXor, Value::Int(l), Value::Int(r)) =>
+                Some(Value::Int(l ^ r)),
+            (BinOp::BitAnd, Value::Bool(l), Value::Bool(r))
+          | (BinOp::LogAnd, Value::Bool(l), Value::Bool(r)) =>
+                Some(Value::Bool(l && r)),
+            (BinOp::BitOr, Value::Bool(l), Value::Bool(r))
+          | (BinOp::LogOr, Value::Bool(l), Value::Bool(r)) =>
+                Some(Value::Bool(l || r)),
+            (BinOp::BitXor, Value::Bool(l), Value::Bool(r)) =>
+                Some(Value::Bool(l ^ r)),
+
+            // these follow the runtime helpers: shift counts are masked
+            //   to the width, and left shifts wrap
+            (BinOp::Shl, Value::Int(x), Value::Int(n)) => {
+                let (bits, signed) = int_width(ty);
+                let modulus = 1i128 << bits;
+                let mut v = ((x as i128) << (n & (bits as i64 - 1)))
+                    .rem_euclid(modulus);
+                if signed && v >= modulus / 2 {
+                    v -= modulus;
+                }
+                Some(Value::Int(v as i64))
+            },
+
+            (BinOp::Shr, Value::Int(x), Value::Int(n)) => {
+                let (bits, _) = int_width(ty);
+                Some(Value::Int(x >> (n & (bits as i64 - 1))))
+            },
+
+            _ => return self.unevaluable(expr),
+        };
+
+        match res {
+            Some(val) => Some(val),
+            None => self.overflow(loc),
+        }
+    }
+
+    fn convert(&mut self, lit: Literal, ty: &Type, loc: &SrcLoc)
+      -> Option<Literal> {
+        // vars just keep the type of their value; anything already of the
+        //   right type (e.g. dates) is passed through untouched
+        if *ty == Type::Variant || lit.ty() == *ty {
+            return Some(lit);
+        }
+
+        let val = self.value_of(lit, loc)?;
+        self.literal_of(val, ty, loc)
+    }
+
+    fn value_of(&mut self, lit: Literal, loc: &SrcLoc) -> Option<Value> {
+        match lit {
+            Literal::Bool(b) => Some(Value::Bool(b)),
+            Literal::UInt8(i) => Some(Value::Int(i as i64)),
+            Literal::Int16(i) => Some(Value::Int(i as i64)),
+            Literal::Int32(i) => Some(Value::Int(i as i64)),
+            Literal::IntPtr(i) => Some(Value::Int(i)),
+            Literal::Float32(f) => Some(Value::Float(f as f64)),
+            Literal::Float64(f) => Some(Value::Float(f)),
+            Literal::String(s) => Some(Value::Str(s)),
+            Literal::Currency(c) => Some(Value::Float(c as f64 / 10000.0)),
+            Literal::NonDecimal(_, lit) => self.value_of(*lit, loc),
+
+            lit => {
+                self.errors.push(AnalysisError {
+                    kind: AnalysisErrorKind::InvalidExpr,
+                    regarding: Some(format!("cannot evaluate with {} \
+                      at compile time", lit)),
+                    loc: loc.clone(),
+                });
+                None
+            },
+        }
+    }
+
+    fn literal_of(&mut self, val: Value, ty: &Type, loc: &SrcLoc)
+      -> Option<Literal> {
+        let lit = match (val, ty) {
+            (Value::Bool(b), &Type::Bool) => Some(Literal::Bool(b)),
+
+            (Value::Str(s), &Type::String)
+          | (Value::Str(s), &Type::FixedString(_)) =>
+                Some(Literal::String(s)),
+
+            (Value::Int(i), &Type::Float32) =>
+                Some(Literal::Float32(i as f32)),
+            (Value::Int(i), &Type::Float64) =>
+                Some(Literal::Float64(i as f64)),
+            (Value::Float(f), &Type::Float32) => Some(Literal::Float32(f as f32)),
+            (Value::Float(f), &Type::Float64) => Some(Literal::Float64(f)),
+
+            (Value::Int(i), &Type::Currency) =>
+                i.checked_mul(10000).map(Literal::Currency),
+            (Value::Float(f), &Type::Currency) => {
+                let c = vb_round(f * 10000.0);
+                if c.abs() < 9.2e18 {
+                    Some(Literal::Currency(c as i64))
+                } else {
+                    None
+                }
+            },
+
+            // (e.g. integer exponentiation)
+            (Value::Float(f), ty) if ty.is_integral() => {
+                let f = vb_round(f);
+                if f.abs() < 9.2e18 {
+                    return self.literal_of(Value::Int(f as i64), ty, loc);
+                } else {
+                    None
+                }
+            },
+
+            (Value::Int(i), &Type::UInt8) if i as u8 as i64 == i =>
+                Some(Literal::UInt8(i as u8)),
+            (Value::Int(i), &Type::Int16) if i as i16 as i64 == i =>
+                Some(Literal::Int16(i as i16)),
+            (Value::Int(i), &Type::Int32) if i as i32 as i64 == i =>
+                Some(Literal::Int32(i as i32)),
+            (Value::Int(i), &Type::IntPtr) => Some(Literal::IntPtr(i)),
+
+            (Value::Int(_), ty) if ty.is_integral() => None,
+
+            (Value::Int(i), &Type::Variant) =>
+                if i as i32 as i64 == i {
+                    Some(Literal::Int32(i as i32))
+                } else {
+                    Some(Literal::IntPtr(i))
+                },
+            (Value::Float(f), &Type::Variant) => Some(Literal::Float64(f)),
+            (Value::Str(s), &Type::Variant) => Some(Literal::String(s)),
+            (Value::Bool(b), &Type::Variant) => Some(Literal::Bool(b)),
+
+            (val, ty) => panic!("dumpster fire: constant value {:?} \
+              cannot be converted to type {}", val, ty),
+        };
+
+        match lit {
+            Some(lit) => Some(lit),
+            None => {
+                self.errors.push(AnalysisError {
+                    kind: AnalysisErrorKind::InvalidExpr,
+                    regarding: Some(format!("overflow converting constant \
+                      expression to type {}", ty)),
+                    loc: loc.clone(),
+                });
+                None
+            },
+        }
+    }
+
+    fn overflow<T>(&mut self, loc: &SrcLoc) -> Option<T> {
+        self.errors.push(AnalysisError {
+            kind: AnalysisErrorKind::InvalidExpr,
+            regarding: Some(String::from("overflow in constant expression")),
+            loc: loc.clone(),
+        });
+        None
+    }
+
+    fn div_by_zero<T>(&mut self, loc: &SrcLoc) -> Option<T> {
+        self.errors.push(AnalysisError {
+            kind: AnalysisErrorKind::InvalidExpr,
+            regarding: Some(String::from("division by zero in constant \
+              expression")),
+            loc: loc.clone(),
+        });
+        None
+    }
+
+    fn unevaluable<T>(&mut self, expr: &Expr) -> Option<T> {
+        self.errors.push(AnalysisError {
+            kind: AnalysisErrorKind::InvalidExpr,
+            regarding: Some(String::from("cannot evaluate expression \
+              at compile time")),
+            loc: expr.loc.clone(),
+        });
+        None
+    }
+}
+
+fn as_float(val: &Value) -> Option<f64> {
+    match *val {
+        Value::Int(i) => Some(i as f64),
+        Value::Float(f) => Some(f),
+        _ => None,
+    }
+}
+
+fn float_op<F: Fn(f64, f64) -> f64>(lhs: Value, rhs: Value, op: F)
+  -> Option<Value> {
+    match (as_float(&lhs), as_float(&rhs)) {
+        (Some(l), Some(r)) => {
+            let res = op(l, r);
+            if res.is_finite() {
+                Some(Value::Float(res))
+            } else {
+                None
+            }
+        },
+
+        _ => None,
+    }
+}
+
+fn compare(lhs: &Value, rhs: &Value) -> Option<Ordering> {
+    match (lhs, rhs) {
+        (&Value::Int(l), &Value::Int(r)) => Some(l.cmp(&r)),
+        (&Value::Str(ref l), &Value::Str(ref r)) => Some(l.cmp(r)),
+        // VB's True is -1, so it sorts before False
+        (&Value::Bool(l), &Value::Bool(r)) => Some(r.cmp(&l)),
+        (l, r) => match (as_float(l), as_float(r)) {
+            (Some(l), Some(r)) => l.partial_cmp(&r),
+            _ => None,
+        },
+    }
+}
+
+// VB rounds halves to even
+fn vb_round(f: f64) -> f64 {
+    if (f - f.trunc()).abs() == 0.5 {
+        2.0 * (f / 2.0).round()
+    } else {
+        f.round()
+    }
+}
+
+fn int_width(ty: &Type) -> (u32, bool) {
+    ty.int_width().unwrap_or_else(|| panic!("dumpster fire: shift of \
+      non-fixed-width type {}", ty))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use parser;
+
+    // typecheck (and so evaluate) a module m, then look up one of its consts
+    fn const_value(src: &str, name: &str)
+      -> AnalysisResultMany<Option<Literal>> {
+        let mut dumpster = parser::parse_dumpster("test", src.as_bytes())
+            .expect("syntax error");
+        let symtab = SymbolTable::build(&mut dumpster)?;
+        typecheck(&mut dumpster, &symtab)?;
+
+        let mut eval = ConstEvaluator::new(&dumpster);
+        Ok(eval.const_value(&Ident(String::from("m"), None),
+          &Ident(String::from(name), None)))
+    }
+
+    fn errors(src: &str) -> Vec<String> {
+        match const_value(src, "A") {
+            Ok(lit) => panic!("evaluated: {:?}", lit),
+            Err(errs) => errs.into_iter()
+                .map(|e| e.regarding.unwrap_or_default()).collect(),
+        }
+    }
+
+    #[test]
+    fn eval_arithmetic() {
+        assert_eq!(const_value("mod m { const A: i32 = 300 * 200; }", "A")
+          .unwrap(), Some(Literal::Int32(60000)));
+        assert_eq!(const_value("mod m { const A: i32 = -7 / 2; }", "A")
+          .unwrap(), Some(Literal::Int32(-3)));
+        assert_eq!(const_value("mod m { const A: i32 = B * 2 + 1; \
+          const B: i32 = 20; }", "A").unwrap(), Some(Literal::Int32(41)));
+        assert_eq!(const_value("mod m { const A: f64 = 10 * 0.25; }", "A")
+          .unwrap(), Some(Literal::Float64(2.5)));
+        assert_eq!(const_value("mod m { const A: str = \"a\" @ \"b\"; }", "A")
+          .unwrap(), Some(Literal::String(String::from("ab"))));
+    }
+
+    #[test]
+    fn eval_shifts() {
+        // left shifts wrap, counts are masked to the width
+        assert_eq!(const_value("mod m { const A: u8 = 255u8 << 4; }", "A")
+          .unwrap(), Some(Literal::UInt8(240)));
+        assert_eq!(const_value("mod m { const A: i16 = 1i16 << 15; }", "A")
+          .unwrap(), Some(Literal::Int16(-32768)));
+        assert_eq!(const_value("mod m { const A: i32 = -16 >> 34; }", "A")
+          .unwrap(), Some(Literal::Int32(-4)));
+    }
+
+    #[test]
+    fn eval_bools() {
+        assert_eq!(const_value("mod m { const A: bool = 1 < 2 && \
+          !(true == false); }", "A").unwrap(), Some(Literal::Bool(true)));
+        assert_eq!(compare(&Value::Bool(true), &Value::Bool(false)),
+          Some(Ordering::Less));
+    }
+
+    #[test]
+    fn eval_errors() {
+        assert_eq!(errors("mod m { const A: i16 = 300i16 * 200i16; }"),
+          vec!["overflow converting constant expression to type i16"]);
+        // (each step stays in range, as it would have to in VB)
+        assert_eq!(errors("mod m { const A: i32 = 2147483647 + 1 - 1; }"),
+          vec!["overflow converting constant expression to type i32"]);
+        assert_eq!(errors("mod m { const A: i32 = 1 / 0; }").len(), 1);
+        assert_eq!(errors("mod m { const A: i32 = B; const B: i32 = C; \
+          const C: i32 = A; }"),
+          vec!["cyclic constant definition: A -> B -> C -> A"; 3]);
+    }
+}
@@ -33,6 +33,8 @@ impl fmt::Display for AnalysisError {
                 write!(f, "private item in public interface")?,
             AnalysisErrorKind::RecursiveType =>
                 write!(f, "invalid recursive type")?,
+            AnalysisErrorKind::InvalidAttribute =>
+                write!(f, "invalid attribute")?,
         };
 
         if let Some(ref msg) = self.regarding {
@@ -54,6 +56,7 @@ pub enum AnalysisErrorKind {
     FnCallError,
     PrivateInPublic,
     RecursiveType,
+    InvalidAttribute,
 }
 
 pub type AnalysisResult<T> = Result<T, AnalysisError>;
@@ -63,3 +66,5 @@ pub mod symtab;
 pub use self::symtab::*;
 pub mod typecheck;
 pub use self::typecheck::*;
+pub mod consteval;
+pub use self::consteval::*;
@@ -104,6 +104,15 @@ impl SymbolTable {
             };
         }
 
+        // then a pass to infer the types of unannotated tuple destructuring
+        //   declarations from their initializers, and of the key variables
+        //   of loops over maps (we need the values collected above to type
+        //   these, and they need resolved types)
+        if errors.is_empty() {
+            let mut inferrer = LocalTypeInferrer { symtab: &mut symtab };
+            inferrer.visit_dumpster(dumpster);
+        }
+
         if errors.is_empty() {
             Ok(symtab)
         } else {
@@ -201,13 +210,61 @@ impl SymbolTable {
         }
     }
 
+    /// is this name a local variable (or parameter) of the given function?
+    pub fn is_local(&self, ident: &Ident, module: &Ident, function: &Ident)
+      -> bool {
+        match self.module_table(module).and_then(|tab| tab.get(&function.0)) {
+            Some(&Symbol::Fun { ref locals, .. }) =>
+                locals.contains_key(&ident.0),
+            _ => false,
+        }
+    }
+
+    pub fn add_struct_entry(&mut self, def: &StructDef, module: &Ident)
+      -> AnalysisResult<()> {
+        {
+            let mut type_collector =
+                TypeCollectingSymbolTableBuilder::build(self);
+            type_collector.visit_structdef(def, module);
+
+            if let Err(errs) = type_collector.result() {
+                return Err(errs.into_iter().next()
+                  .expect("dumpster fire: empty error vector"));
+            }
+        }
+
+        let mut value_collector =
+            ValueCollectingSymbolTableBuilder::build(self);
+        value_collector.visit_structdef(def, module);
+
+        match value_collector.result() {
+            Ok(_) => Ok(()),
+            Err(errs) => Err(errs.into_iter().next()
+              .expect("dumpster fire: empty error vector")),
+        }
+    }
+
     pub fn type_access(&self, ty: &Type, module: &Ident, err_loc: &SrcLoc)
       -> AnalysisResult<Access> {
         match *ty {
             Type::Array(ref base, _) => self.type_access(base, module, err_loc),
 
+            Type::ArrayView(ref base) =>
+                self.type_access(base, module, err_loc),
+
             Type::Object(_) => Ok(Access::Public), // TODO: for now
 
+            Type::Nullable(ref base) => self.type_access(base, module, err_loc),
+
+            Type::List(ref base) => self.type_access(base, module, err_loc),
+
+            // as private as its key or value type
+            Type::Map(ref key, ref value) =>
+                match self.type_access(key, module, err_loc)? {
+                    Access::Private => Ok(Access::Private),
+                    Access::Public => self.type_access(value, module, err_loc),
+                },
+
             Type::Struct(ref path) => {
                 match *self.symbol_at_path(path,
                   NameCtxt::Type(module, Access::Private), err_loc)? {
@@ -219,6 +276,28 @@ impl SymbolTable {
 
             Type::Enum(_) => Ok(Access::Public), // TODO: for now,
 
+            // as private as the most private element
+            Type::Tuple(ref tys) => {
+                for ty in tys {
+                    if self.type_access(ty, module, err_loc)?
+                      == Access::Private {
+                        return Ok(Access::Private);
+                    }
+                }
+                Ok(Access::Public)
+            },
+
+            // as private as the most private parameter or return type
+            Type::FnPtr(ref params, ref ret) => {
+                for &(_, ref ty) in params {
+                    if self.type_access(ty, module, err_loc)?
+                      == Access::Private {
+                        return Ok(Access::Private);
+                    }
+                }
+                self.type_access(ret, module, err_loc)
+            },
+
             Type::Deferred(_) => panic!("dumpster fire: deferred type in \
               access check"),
 
@@ -234,6 +313,26 @@ impl SymbolTable {
         self.symtab.get_mut(&module.0)
     }
 
+    // update the type of an already-collected local variable
+    fn set_local_type(&mut self, ident: &Ident, module: &Ident,
+      function: &Ident, ty: &Type) {
+        let mod_tab = self.module_table_mut(module).expect(
+            "dumpster fire: no module entry in symbol table");
+
+        let locals = match mod_tab.get_mut(&function.0) {
+            Some(&mut Symbol::Fun { ref mut locals, .. }) => locals,
+            _ => panic!("dumpster fire: \
+              no fn entry in symbol table for {}::{}", module, function),
+        };
+
+        match locals.get_mut(&ident.0) {
+            Some(&mut Symbol::Value(ref mut local_ty, _, _)) =>
+                *local_ty = ty.clone(),
+            _ => panic!("dumpster fire: \
+              no local entry in symbol table for {}", ident),
+        }
+    }
+
     fn new() -> SymbolTable {
         SymbolTable {
             symtab: Symtab::new(),
@@ -392,6 +491,8 @@ impl<'a> ASTVisitor for TypeCollectingSymbolTableBuilder<'a> {
 struct ValueCollectingSymbolTableBuilder<'a> {
     symtab: &'a mut SymbolTable,
     errors: Vec<AnalysisError>,
+    // &name exprs which aren't (yet) values; fine if they name functions
+    fn_addrs: Vec<(Path, Ident, AnalysisError)>,
 }
 
 impl<'a> ValueCollectingSymbolTableBuilder<'a> {
@@ -399,19 +500,50 @@ impl<'a> ValueCollectingSymbolTableBuilder<'a> {
         ValueCollectingSymbolTableBuilder {
             symtab,
             errors: Vec::new(),
+            fn_addrs: Vec::new(),
         }
     }
 
     fn result(self) -> AnalysisResultMany<()> {
-        if self.errors.is_empty() {
+        let ValueCollectingSymbolTableBuilder {
+            symtab,
+            mut errors,
+            fn_addrs,
+        } = self;
+
+        for (path, module, err) in fn_addrs {
+            if symtab.symbol_at_path(&path,
+              NameCtxt::Function(&module, Access::Private), &err.loc)
+              .is_err() {
+                errors.push(err);
+            }
+        }
+
+        if errors.is_empty() {
             Ok(())
         } else {
-            Err(self.errors)
+            Err(errors)
         }
     }
 }
 
 impl<'a> ASTVisitor for ValueCollectingSymbolTableBuilder<'a> {
+    fn visit_expr(&mut self, expr: &Expr, m: &Ident, f: Option<&Ident>) {
+        // &name may take the address of a function we haven't collected
+        //   yet; we can only tell once we've seen them all
+        if let ExprKind::UnOpApp(ref inner, UnOp::AddressOf) = expr.data {
+            if let ExprKind::Name(ref p @ Path(None, _)) = inner.data {
+                if let Err(e) = self.symtab.symbol_at_path(p,
+                  NameCtxt::Value(m, f, Access::Private), &inner.loc) {
+                    self.fn_addrs.push((p.clone(), m.clone(), e));
+                    return;
+                }
+            }
+        }
+
+        self.walk_expr(expr, m, f);
+    }
+
     fn visit_fundef(&mut self, def: &FunDef, m: &Ident) {
         {
             let mod_tab = self.symtab.module_table_mut(m).expect(
@@ -465,10 +597,13 @@ impl<'a> ASTVisitor for ValueCollectingSymbolTableBuilder<'a> {
         // TODO something something statics and consts
         //   (related: those guys need lifted to the top of modules)
         // ensure declare-before-use of "local" names
+        //   (module-scope names, like those in const initializers and
+        //   optional defaults, may refer forward; cycles among consts are
+        //   caught when they're evaluated)
         match *p {
             Path(None, _) => {
                 match ctxt {
-                    NameCtxt::Value(_, _, _) => {
+                    NameCtxt::Value(_, Some(_), _) => {
                         if let Err(e) =
                           self.symtab.symbol_at_path(p, ctxt, loc) {
                             self.errors.push(e);
@@ -578,6 +713,12 @@ impl<'a> ASTVisitorMut for DeferredResolver<'a> {
                 }
             }
 
+            // named lengths of fixed-length strings are module constants;
+            //   qualify them so the type means the same thing anywhere
+            Type::FixedString(StaticArrayDim::Named(Path(None, ref name))) =>
+                Type::FixedString(StaticArrayDim::Named(
+                  Path(Some(module.clone()), name.clone()))),
+
             // this is a goofy way to build this but I don't feel like
             //   fighting the damn borrow checker right now
             _ => {
@@ -590,3 +731,52 @@ impl<'a> ASTVisitorMut for DeferredResolver<'a> {
         *t = new_type;
     }
 }
+
+// initializers haven't been typechecked yet, so a bad one just leaves its
+//   variables untyped (as var); the typechecker reports it along with
+//   everything else
+struct LocalTypeInferrer<'a> {
+    symtab: &'a mut SymbolTable,
+}
+
+impl<'a> ASTVisitorMut for LocalTypeInferrer<'a> {
+    fn visit_stmt(&mut self, stmt: &mut Stmt, module: &Ident,
+      function: &Ident) {
+        // (before the body, which may use the key)
+        if let StmtKind::ForLoop {
+            spec: ForSpec::Each(ref mut expr, ref indices), ..
+        } = stmt.data {
+            // anything else is an error the typechecker will report
+            if let Ok(Type::Map(key, _)) = type_of_expr(expr, self.symtab,
+              module, Some(function)) {
+                if indices.len() == 1 {
+                    self.symtab.set_local_type(&indices[0], module, function,
+                      &key);
+                }
+            }
+        }
+
+        self.walk_stmt(stmt, module, function);
+
+        if let StmtKind::Destructure(ref idents, ref mut ty @ None,
+          ref mut init) = stmt.data {
+            let init_ty = match type_of_expr(init, self.symtab, module,
+              Some(function)) {
+                Ok(init_ty) => init_ty,
+                Err(_) => return,
+            };
+
+            // anything else is an error the typechecker will report
+            if let Type::Tuple(ref tys) = init_ty {
+                if tys.len() == idents.len() {
+                    for (ident, elem_ty) in idents.iter().zip(tys) {
+                        self.symtab.set_local_type(ident, module, function,
+                          elem_ty);
+                    }
+                }
+            }
+
+            *ty = Some(init_ty);
+        }
+    }
+}
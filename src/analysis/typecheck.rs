@@ -2,10 +2,12 @@
 
 use ast::*;
 use super::*;
+use visit::ASTVisitor;
 use visit::ASTVisitorMut;
 use visit::NameCtxt;
 
 use std::collections::HashSet;
+use std::fmt;
 
 /// Typecheck a dumpster
 pub fn typecheck(dumpster: &mut Dumpster, symtab: &SymbolTable)
@@ -13,12 +15,35 @@ pub fn typecheck(dumpster: &mut Dumpster, symtab: &SymbolTable)
     let mut v = TypecheckVisitor {
         symtab,
         errors: Vec::new(),
+        narrowed: Vec::new(),
+        withs: Vec::new(),
     };
 
     v.visit_dumpster(dumpster);
 
     if v.errors.is_empty() {
-        Ok(())
+        // only well-typed constants can be evaluated
+        check_constants(dumpster)
+    } else {
+        Err(v.errors)
+    }
+}
+
+/// Typecheck a single expression, returning its type
+pub fn type_of_expr(expr: &mut Expr, symtab: &SymbolTable, module: &Ident,
+  function: Option<&Ident>) -> AnalysisResultMany<Type> {
+    let mut v = TypecheckVisitor {
+        symtab,
+        errors: Vec::new(),
+        narrowed: Vec::new(),
+        withs: Vec::new(),
+    };
+
+    v.visit_expr(expr, module, function);
+
+    if v.errors.is_empty() {
+        Ok(expr.ty.clone().expect("dumpster fire: untyped expression \
+          after typecheck"))
     } else {
         Err(v.errors)
     }
@@ -38,11 +63,39 @@ pub fn is_constexpr(expr: &Expr, symtab: &SymbolTable, module: &Ident,
               }
           },
 
+          // taking an address isn't constant (and VB won't do it in a
+          //   const); nor is object identity
+          ExprKind::UnOpApp(_, UnOp::AddressOf) => Ok(false),
+
+          ExprKind::UnOpApp(ref e, _) =>
+              is_constexpr(e, symtab, module, function),
+
+          ExprKind::BinOpApp(_, _, BinOp::IdentEq)
+        | ExprKind::BinOpApp(_, _, BinOp::NotIdentEq) => Ok(false),
+
+          ExprKind::BinOpApp(ref lhs, ref rhs, _) =>
+              Ok(is_constexpr(lhs, symtab, module, function)?
+                && is_constexpr(rhs, symtab, module, function)?),
+
+          ExprKind::CondExpr { ref cond, ref if_expr, ref else_expr } =>
+              Ok(is_constexpr(cond, symtab, module, function)?
+                && is_constexpr(if_expr, symtab, module, function)?
+                && is_constexpr(else_expr, symtab, module, function)?),
+
           _ => Ok(false),
       }
 }
 
 pub fn upper_bound_type(lhs: &Type, rhs: &Type) -> Option<Type> {
+    // fixed-length strings are just a storage format; as values
+    //   they're ordinary strings
+    match (lhs, rhs) {
+        (&Type::FixedString(_), &Type::FixedString(_))
+      | (&Type::FixedString(_), &Type::String)
+      | (&Type::String, &Type::FixedString(_)) => return Some(Type::String),
+        _ => { },
+    };
+
     // TODO: revisit this for object types
     if lhs == rhs {
         Some(lhs.clone())
@@ -55,6 +108,26 @@ pub fn upper_bound_type(lhs: &Type, rhs: &Type) -> Option<Type> {
     }
 }
 
+/// date arithmetic doesn't follow the usual common-type rules:
+///   date - date is a difference in days (as f64), and a date plus or minus
+///   some number of days is another date
+fn date_arith_type(lhs: &Type, rhs: &Type, op: &BinOp) -> Option<Type> {
+    fn is_days(ty: &Type) -> bool {
+        *ty != Type::Variant && may_coerce(ty, &Type::Float64)
+    }
+
+    match (lhs, rhs, op) {
+        (&Type::Date, &Type::Date, &BinOp::Sub) => Some(Type::Float64),
+
+        (&Type::Date, days, &BinOp::Add)
+      | (&Type::Date, days, &BinOp::Sub)
+      | (days, &Type::Date, &BinOp::Add) if is_days(days) =>
+            Some(Type::Date),
+
+        _ => None,
+    }
+}
+
 pub fn may_coerce(from: &Type, to: &Type) -> bool {
     if let Type::Deferred(ref path) = *to {
         panic!("dumpster fire: attempt to coerce-check deferred type {}", path);
@@ -122,8 +195,11 @@ pub fn may_coerce(from: &Type, to: &Type) -> bool {
             _ => false,
         },
 
-        Type::String => match *to {
+        // (VB pads or truncates to the fixed length)
+        Type::String
+      | Type::FixedString(_) => match *to {
             Type::String
+          | Type::FixedString(_)
           | Type::Variant => true,
             _ => false,
         },
@@ -134,10 +210,22 @@ pub fn may_coerce(from: &Type, to: &Type) -> bool {
             _ => false,
         },
 
+        Type::Date => match *to {
+            Type::Date
+          | Type::Variant => true,
+            _ => false,
+        },
+
         // TODO: do we want to allow this? coercion to var may fail at runtime
         Type::Variant => match *to {
             // can't assign to statically-dimensioned array
             Type::Array(_, ArrayBounds::Static(_)) => false,
+            // views only ever come from arrays
+            Type::ArrayView(_) => false,
+            // tuples and lists are UDTs, which can't live in variants
+            Type::Tuple(_) | Type::List(_) => false,
+            // we'd have no way to check the signature
+            Type::FnPtr(_, _) => false,
             Type::Void => false,
             _ => true,
         },
@@ -145,7 +233,8 @@ pub fn may_coerce(from: &Type, to: &Type) -> bool {
         Type::Obj => match *to {
             Type::Obj
           | Type::Variant
-          | Type::Object(_) => true,
+          | Type::Object(_)
+          | Type::Nullable(_) => true,
             _ => false,
         },
 
@@ -165,17 +254,41 @@ pub fn may_coerce(from: &Type, to: &Type) -> bool {
             _ => false,
         },
 
-        Type::VarArgsArray => false,
+        Type::VarArgsArray(_) => false,
+
+        // (a copy is made of the viewed elements)
+        Type::ArrayView(ref basety) => match *to {
+            Type::Array(ref targetty, ArrayBounds::Dynamic(1)) =>
+                targetty == basety,
+            _ => false,
+        },
 
         // TODO: thread the symbol table through here
         //   and check actual subtyping info
         Type::Object(_) => match *to {
             Type::Obj
           | Type::Variant
-          | Type::Object(_) => true,
+          | Type::Object(_)
+          | Type::Nullable(_) => true,
+            _ => false,
+        },
+
+        // a nullable object can't go anywhere it might not be checked
+        Type::Nullable(_) => match *to {
+            Type::Variant
+          | Type::Nullable(_) => true,
             _ => false,
         },
 
+        // maps are dictionaries underneath, but only coerce to maps of
+        //   the exact same key and value types
+        Type::Map(_, _) => match *to {
+            Type::Obj
+          | Type::Variant => true,
+            Type::Nullable(ref base) => **base == *from,
+            _ => from == to,
+        },
+
         Type::Struct(ref path) => match *to {
             Type::Struct(ref path2) => path == path2,
             _ => false,
@@ -187,14 +300,30 @@ pub fn may_coerce(from: &Type, to: &Type) -> bool {
             _ => false,
         },
 
+        // like structs, tuples and lists only coerce to the exact same shape
+        Type::Tuple(_) | Type::List(_) => from == to,
+
+        // function pointers must match signatures exactly, but they're
+        //   still just addresses
+        Type::FnPtr(_, _) => from == to || *to == Type::IntPtr,
+
         Type::Deferred(ref path) => panic!("dumpster fire: \
             attempt to coerce-check deferred type {}", path),
 
         Type::Void => false,
+    }
+}
 
-        // TODO: handle currency, date, etc
-        _ => panic!("dumpster fire: we haven't figured out the rules for \
-          this type yet."),
+/// can an argument of type `from` be passed to a by-reference parameter of
+///   type `to`? (exact matches only, except that an array view can refer to
+///   any one-dimensional array, or another view, of the same element type)
+pub fn may_pass_by_ref(from: &Type, to: &Type) -> bool {
+    match (from, to) {
+        (&Type::Array(ref basety, ArrayBounds::Dynamic(1)),
+         &Type::ArrayView(ref targetty))
+      | (&Type::ArrayView(ref basety), &Type::ArrayView(ref targetty)) =>
+            basety == targetty,
+        _ => from == to,
     }
 }
 
@@ -203,6 +332,12 @@ pub fn may_cast(from: &Type, to: &Type) -> bool {
         panic!("dumpster fire: attempt to coerce-check deferred type {}", path);
     }
 
+    // there's no conversion function to a fixed-length string; assign to
+    //   one instead
+    if let Type::FixedString(_) = *to {
+        return false;
+    }
+
     match *from {
         // as 1 or 0
         Type::Bool => to.might_be_numeric() || *to == Type::Bool,
@@ -213,26 +348,47 @@ pub fn may_cast(from: &Type, to: &Type) -> bool {
       | Type::IntPtr
       | Type::Float32
       | Type::Float64
-      | Type::Currency => to.might_be_numeric(),
+      | Type::Currency => to.might_be_numeric() || *to == Type::Date,
+
+        Type::String
+      | Type::FixedString(_) => to.might_be_string() || to.might_be_numeric()
+          || *to == Type::Date,
 
-        Type::String => to.might_be_string() || to.might_be_numeric(),
+        // as a serial date, or formatted
+        Type::Date => to.might_be_numeric() || to.might_be_string()
+          || *to == Type::Date,
 
         Type::Variant => match *to {
             // can't assign to statically-dimensioned array
             Type::Array(_, ArrayBounds::Static(_)) => false,
+            // views only ever come from arrays
+            Type::ArrayView(_) => false,
+            // tuples and lists are UDTs, which can't live in variants
+            Type::Tuple(_) | Type::List(_) => false,
+            // we'd have no way to check the signature
+            Type::FnPtr(_, _) => false,
             Type::Void => false,
             _ => true,
         },
 
+        // (an unchecked claim that the object is a dictionary of these)
         Type::Obj => match *to {
             Type::Obj
           | Type::Variant
-          | Type::Object(_) => true,
+          | Type::Object(_)
+          | Type::Nullable(_)
+          | Type::Map(_, _) => true,
             _ => false,
         },
 
+        Type::ArrayView(_) => false,
+
         // for now, don't allow cast array-to-array (even if exact type!)
-        Type::Array(ref basety, _) => match *to {
+        Type::Array(ref basety, ref bounds) => match *to {
+            // (the list gets a copy of the elements)
+            Type::List(ref targetty) =>
+                targetty == basety && bounds.dims() == 1,
+
             Type::Variant => {
                 match **basety {
                     // can't put structs into Arrays inside Variants :/
@@ -249,11 +405,31 @@ pub fn may_cast(from: &Type, to: &Type) -> bool {
         Type::Object(_) => match *to {
             Type::Obj
           | Type::Variant
-          | Type::Object(_) => true,
+          | Type::Object(_)
+          | Type::Nullable(_) => true,
+            _ => false,
+        },
+
+        // a nullable object can't go anywhere it might not be checked
+        Type::Nullable(_) => match *to {
+            Type::Variant
+          | Type::Nullable(_) => true,
             _ => false,
         },
 
-        Type::Struct(_) => false,
+        Type::Struct(_) | Type::Tuple(_) => false,
+
+        Type::Map(_, _) => may_coerce(from, to),
+
+        // (the array gets a copy of the live elements)
+        Type::List(ref basety) => match *to {
+            Type::Array(ref targetty, ArrayBounds::Dynamic(1)) =>
+                targetty == basety,
+            _ => false,
+        },
+
+        Type::FnPtr(_, _) => from == to || *to == Type::IntPtr
+          || *to == Type::Variant,
 
         Type::Enum(_) => to.might_be_numeric(),
 
@@ -291,14 +467,127 @@ macro_rules! try_collect {
     }
 }
 
+/// What an attribute is attached to
+#[derive(Copy, Clone, Debug)]
+enum AttributeTarget {
+    NormalModule,
+    Function,
+    Struct,
+    Static,
+    Const,
+}
+
+impl fmt::Display for AttributeTarget {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", match *self {
+            AttributeTarget::NormalModule => "mod",
+            AttributeTarget::Function => "fn",
+            AttributeTarget::Struct => "struct",
+            AttributeTarget::Static => "static",
+            AttributeTarget::Const => "const",
+        })
+    }
+}
+
+// collects the locals a block of statements might assign
+struct AssignedLocalsVisitor {
+    names: Vec<Ident>,
+}
+
+impl ASTVisitor for AssignedLocalsVisitor {
+    fn visit_stmt(&mut self, stmt: &Stmt, m: &Ident, f: &Ident) {
+        match stmt.data {
+            StmtKind::Assign(Expr {
+                data: ExprKind::Name(Path(None, ref name)), ..
+            }, _, _) => self.names.push(name.clone()),
+
+            StmtKind::VbBlock(_, _, ref writes) =>
+                self.names.extend(writes.iter().map(|w| w.1.clone())),
+
+            _ => { },
+        }
+
+        self.walk_stmt(stmt, m, f);
+    }
+}
+
 struct TypecheckVisitor<'a> {
     symtab: &'a SymbolTable,
     errors: Vec<AnalysisError>,
+    // nullable locals known to be non-null (i.e. we're inside the body
+    //   of an `if x !== nullptr`)
+    narrowed: Vec<Ident>,
+    // subject types of enclosing with blocks, innermost last
+    //   (None if the subject was ill-typed; that's already been reported),
+    //   and whether methods can be called on them (methods take the subject
+    //   as an argument, so it gets evaluated again)
+    withs: Vec<(Option<Type>, bool)>,
 }
 
 impl<'a> ASTVisitorMut for TypecheckVisitor<'a> {
+    fn visit_module(&mut self, module: &mut Module) {
+        self.walk_module(module);
+        self.check_attributes(&module.attrs, AttributeTarget::NormalModule);
+    }
+
     fn visit_fundef(&mut self, def: &mut FunDef, m: &Ident) {
         self.walk_fundef(def, m);
+        self.check_attributes(&def.attrs, AttributeTarget::Function);
+
+        if def.attrs.iter().any(|a| a.name.0 == "udf") {
+            self.check_udf_signature(def, m);
+        }
+
+        if def.lib.is_some() {
+            self.check_extern_signature(def, m);
+        }
+
+        if let Type::FixedString(_) = def.ret {
+            self.errors.push(AnalysisError {
+                kind: AnalysisErrorKind::TypeError,
+                regarding: Some(format!("fn {}::{} cannot return fixed-length \
+                  string type {}", m, def.name, def.ret)),
+                loc: def.loc.clone(),
+            });
+        }
+
+        if let Type::ArrayView(_) = def.ret {
+            self.errors.push(AnalysisError {
+                kind: AnalysisErrorKind::TypeError,
+                regarding: Some(format!("fn {}::{} cannot return array \
+                  view type {}", m, def.name, def.ret)),
+                loc: def.loc.clone(),
+            });
+        }
+
+        // variadic arguments travel in a ParamArray, so their elements
+        //   have to be things a var can hold (and convert back out of)
+        if let Some(FunOptParams::VarArgs(ref name, ref ty, ref loc)) =
+          def.optparams {
+            match *ty {
+                Type::Bool
+              | Type::UInt8
+              | Type::Int16
+              | Type::Int32
+              | Type::IntPtr
+              | Type::Float32
+              | Type::Float64
+              | Type::String
+              | Type::Currency
+              | Type::Date
+              | Type::Variant
+              | Type::Obj
+              | Type::Object(_)
+              | Type::Nullable(_) => { },
+
+                _ => self.errors.push(AnalysisError {
+                    kind: AnalysisErrorKind::TypeError,
+                    regarding: Some(format!("variadic parameter {} cannot \
+                      have element type {}", name, ty)),
+                    loc: loc.clone(),
+                }),
+            }
+        }
 
         // private-in-public check: a pub fn may not have a private return type
         if def.access == Access::Public {
@@ -317,6 +606,16 @@ impl<'a> ASTVisitorMut for TypecheckVisitor<'a> {
     fn visit_funparam(&mut self, p: &mut FunParam, m: &Ident, f: &Ident) {
         self.walk_funparam(p, m, f);
 
+        // VB doesn't allow these as parameters at all
+        if let Type::FixedString(_) = p.ty {
+            self.errors.push(AnalysisError {
+                kind: AnalysisErrorKind::FnCallError,
+                regarding: Some(format!("fixed-length string type {} cannot \
+                  be used as a parameter type", p.ty)),
+                loc: p.loc.clone(),
+            });
+        }
+
         match p.mode {
             ParamMode::ByRef => match p.ty {
                 Type::Array(_, ArrayBounds::Static(_)) =>
@@ -337,6 +636,13 @@ impl<'a> ASTVisitorMut for TypecheckVisitor<'a> {
                     loc: p.loc.clone(),
                 }),
 
+                Type::ArrayView(_) => self.errors.push(AnalysisError {
+                    kind: AnalysisErrorKind::FnCallError,
+                    regarding: Some(String::from("array view types cannot \
+                      be passed by value")),
+                    loc: p.loc.clone(),
+                }),
+
                 Type::Struct(_) => self.errors.push(AnalysisError {
                     kind: AnalysisErrorKind::FnCallError,
                     regarding: Some(String::from("struct types cannot \
@@ -344,6 +650,20 @@ impl<'a> ASTVisitorMut for TypecheckVisitor<'a> {
                     loc: p.loc.clone(),
                 }),
 
+                Type::Tuple(_) => self.errors.push(AnalysisError {
+                    kind: AnalysisErrorKind::FnCallError,
+                    regarding: Some(String::from("tuple types cannot \
+                      be passed by value")),
+                    loc: p.loc.clone(),
+                }),
+
+                Type::List(_) => self.errors.push(AnalysisError {
+                    kind: AnalysisErrorKind::FnCallError,
+                    regarding: Some(String::from("list types cannot \
+                      be passed by value")),
+                    loc: p.loc.clone(),
+                }),
+
                 _ => { },
             },
         };
@@ -355,7 +675,7 @@ impl<'a> ASTVisitorMut for TypecheckVisitor<'a> {
         }
     }
 
-    fn visit_optparam(&mut self, p: &mut (FunParam, Literal),
+    fn visit_optparam(&mut self, p: &mut (FunParam, Expr),
       m: &Ident, f: &Ident) {
         // run defaults to typecheck param, literal
         self.walk_optparam(p, m, f);
@@ -371,7 +691,22 @@ impl<'a> ASTVisitorMut for TypecheckVisitor<'a> {
             });
         }
 
-        let lit_ty = default.ty();
+        let lit_ty = match default.ty {
+            Some(ref ty) => ty.clone(),
+            // already reported
+            None => return,
+        };
+
+        if !try_collect!(is_constexpr(default, self.symtab, m, None)
+          => self.errors) {
+            self.errors.push(AnalysisError {
+                kind: AnalysisErrorKind::InvalidExpr,
+                regarding: Some(format!("default for optional parameter {} \
+                  is not a constant expression", p.name)),
+                loc: default.loc.clone(),
+            });
+            return;
+        }
 
         match p.mode {
             ParamMode::ByRef =>
@@ -400,8 +735,30 @@ impl<'a> ASTVisitorMut for TypecheckVisitor<'a> {
         }
     }
 
+    fn visit_structdef(&mut self, def: &mut StructDef, m: &Ident) {
+        self.walk_structdef(def, m);
+        self.check_attributes(&def.attrs, AttributeTarget::Struct);
+
+        // a derived string conversion has to be able to render each member
+        if derives_debug(&def.attrs) {
+            for mem in &def.members {
+                if !mem.ty.is_scalar()
+                  && !self.may_render_debug(&mem.ty, m, &mem.loc) {
+                    self.errors.push(AnalysisError {
+                        kind: AnalysisErrorKind::InvalidAttribute,
+                        regarding: Some(format!("member {} of struct {} has \
+                          type {}, which does not derive Debug", mem.name,
+                          def.name, mem.ty)),
+                        loc: mem.loc.clone(),
+                    });
+                }
+            }
+        }
+    }
+
     fn visit_static(&mut self, s: &mut Static, m: &Ident) {
         self.walk_static(s, m);
+        self.check_attributes(&s.attrs, AttributeTarget::Static);
 
         // private-in-public check: a pub static may not have a private type
         if s.access == Access::Public {
@@ -416,6 +773,26 @@ impl<'a> ASTVisitorMut for TypecheckVisitor<'a> {
             }
         }
 
+        if let Type::ArrayView(_) = s.ty {
+            self.errors.push(AnalysisError {
+                kind: AnalysisErrorKind::TypeError,
+                regarding: Some(format!("static {}::{} cannot have array \
+                  view type {}", m, s.name, s.ty)),
+                loc: s.loc.clone(),
+            });
+        }
+
+        // statics can't be initialized, so they start off as Nothing
+        if s.ty.starts_null() {
+            self.errors.push(AnalysisError {
+                kind: AnalysisErrorKind::TypeError,
+                regarding: Some(format!("static {}::{} has type {}, which \
+                  would start off holding Nothing (use a nullable object \
+                  type)", m, s.name, s.ty)),
+                loc: s.loc.clone(),
+            });
+        }
+
         match s.init {
             Some(ref lit) => if !may_coerce(&lit.ty(), &s.ty) {
                 self.errors.push(AnalysisError {
@@ -433,15 +810,33 @@ impl<'a> ASTVisitorMut for TypecheckVisitor<'a> {
 
     fn visit_constant(&mut self, c: &mut Constant, m: &Ident) {
         self.walk_constant(c, m);
+        self.check_attributes(&c.attrs, AttributeTarget::Const);
 
         // no private-in-public check: all constable types are public
 
-        if !may_coerce(&c.value.ty(), &c.ty) {
+        let value_ty = match c.value.ty {
+            Some(ref ty) => ty.clone(),
+            // already reported
+            None => return,
+        };
+
+        if !try_collect!(is_constexpr(&c.value, self.symtab, m, None)
+          => self.errors) {
+            self.errors.push(AnalysisError {
+                kind: AnalysisErrorKind::InvalidExpr,
+                regarding: Some(format!("initializer of const {}::{} \
+                  is not a constant expression", m, c.name)),
+                loc: c.value.loc.clone(),
+            });
+            return;
+        }
+
+        if !may_coerce(&value_ty, &c.ty) {
             self.errors.push(AnalysisError {
                 kind: AnalysisErrorKind::TypeError,
                 regarding: Some(format!(
                   "const {}::{} has type {}; initializer of type {} \
-                    provided", m, c.name, c.ty, c.value.ty())),
+                    provided", m, c.name, c.ty, value_ty)),
                 loc: c.loc.clone(),
             });
         }
@@ -450,6 +845,26 @@ impl<'a> ASTVisitorMut for TypecheckVisitor<'a> {
     fn visit_structmem(&mut self, mem: &mut StructMem, m: &Ident, st: &Ident) {
         self.walk_structmem(mem, m, st);
 
+        // members start off as Nothing, too
+        if mem.ty.starts_null() {
+            self.errors.push(AnalysisError {
+                kind: AnalysisErrorKind::TypeError,
+                regarding: Some(format!("member {} of struct {}::{} has \
+                  type {}, which would start off holding Nothing (use a \
+                  nullable object type)", mem.name, m, st, mem.ty)),
+                loc: mem.loc.clone(),
+            });
+        }
+
+        if let Type::ArrayView(_) = mem.ty {
+            self.errors.push(AnalysisError {
+                kind: AnalysisErrorKind::TypeError,
+                regarding: Some(format!("member {} of struct {}::{} cannot \
+                  have array view type {}", mem.name, m, st, mem.ty)),
+                loc: mem.loc.clone(),
+            });
+        }
+
         // TODO: also recurse into nested types
         // recursive type check
         if mem.ty == Type::Struct(Path(Some(m.clone()), st.clone())) {
@@ -486,10 +901,129 @@ impl<'a> ASTVisitorMut for TypecheckVisitor<'a> {
     }
 
     fn visit_stmt(&mut self, stmt: &mut Stmt, m: &Ident, f: &Ident) {
-        self.walk_stmt(stmt, m, f);
+        // a later iteration could see anything the body assigns
+        match stmt.data {
+            StmtKind::WhileLoop { ref body, .. }
+          | StmtKind::ForLoop { ref body, .. }
+          | StmtKind::ForAlong { ref body, .. } => {
+                let mut assigned = AssignedLocalsVisitor { names: Vec::new() };
+                for stmt in body {
+                    assigned.visit_stmt(stmt, m, f);
+                }
+                self.narrowed.retain(|n| !assigned.names.contains(n));
+            },
+            _ => { },
+        }
+
+        match stmt.data {
+            StmtKind::IfStmt { .. } | StmtKind::WhileLoop { .. } =>
+                self.walk_narrowing_stmt(stmt, m, f),
+            StmtKind::With(_, _) => self.walk_with_stmt(stmt, m, f),
+            StmtKind::Assign(ref mut lhs, _, ref mut rhs) => {
+                // the value is computed before the name is overwritten
+                self.visit_expr(rhs, m, Some(f));
+                if let ExprKind::Name(Path(None, ref name)) = lhs.data {
+                    self.narrowed.retain(|n| n != name);
+                }
+                self.visit_expr(lhs, m, Some(f));
+                self.visit_srcloc(&mut stmt.loc);
+            },
+            _ => self.walk_stmt(stmt, m, f),
+        };
+
         self.typecheck_stmt_shallow(stmt, m, f);
     }
 
+    fn visit_type(&mut self, ty: &mut Type, m: &Ident, loc: &SrcLoc) {
+        self.walk_type(ty, m, loc);
+
+        if let Type::FixedString(ref len) = *ty {
+            self.typecheck_fixed_string_len(len, m, loc);
+        }
+
+        match *ty {
+            // VB allocates (and resizes) arrays full of Nothing
+            Type::Array(ref base, _) | Type::ArrayView(ref base)
+              if base.starts_null() => self.errors.push(AnalysisError {
+                kind: AnalysisErrorKind::TypeError,
+                regarding: Some(format!("array elements of type {} would \
+                  start off holding Nothing (use a nullable object type)",
+                  base)),
+                loc: loc.clone(),
+            }),
+            _ => { },
+        }
+
+        if let Type::Nullable(ref base) = *ty {
+            if !base.is_non_null_object() {
+                self.errors.push(AnalysisError {
+                    kind: AnalysisErrorKind::TypeError,
+                    regarding: Some(format!("only object types may be \
+                      nullable; found {}", ty)),
+                    loc: loc.clone(),
+                });
+            }
+        }
+
+        if let Type::Map(ref key, ref value) = *ty {
+            // dictionary keys compare by value, so they have to be one of
+            //   VB's own value types (we convert to it on every lookup)
+            match **key {
+                Type::Bool
+              | Type::UInt8
+              | Type::Int16
+              | Type::Int32
+              | Type::IntPtr
+              | Type::Float32
+              | Type::Float64
+              | Type::String
+              | Type::Currency
+              | Type::Date => { },
+                ref key => self.errors.push(AnalysisError {
+                    kind: AnalysisErrorKind::TypeError,
+                    regarding: Some(format!("invalid map key type {}",
+                      key)),
+                    loc: loc.clone(),
+                }),
+            }
+
+            // dictionary items are stored as var
+            match **value {
+                Type::VarArgsArray(_) => self.errors.push(AnalysisError {
+                    kind: AnalysisErrorKind::TypeError,
+                    regarding: Some(format!("invalid map value type {}",
+                      value)),
+                    loc: loc.clone(),
+                }),
+                ref value if !value.is_scalar() =>
+                    self.errors.push(AnalysisError {
+                        kind: AnalysisErrorKind::TypeError,
+                        regarding: Some(format!("invalid map value type {} \
+                          (values cannot be arrays, structs, or tuples)",
+                          value)),
+                        loc: loc.clone(),
+                    }),
+                _ => { },
+            }
+        }
+
+        // list items live in a dynamic array inside a UDT
+        if let Type::List(ref base) = *ty {
+            match **base {
+                Type::Array(_, _)
+              | Type::ArrayView(_)
+              | Type::VarArgsArray(_)
+              | Type::Void => self.errors.push(AnalysisError {
+                    kind: AnalysisErrorKind::TypeError,
+                    regarding: Some(format!("invalid list element type {}",
+                      base)),
+                    loc: loc.clone(),
+                }),
+                _ => { },
+            }
+        }
+    }
+
     fn visit_expr(&mut self, expr: &mut Expr, module: &Ident,
       function: Option<&Ident>) {
         // ok, here's the reasoning.
@@ -507,8 +1041,33 @@ impl<'a> ASTVisitorMut for TypecheckVisitor<'a> {
         //       subexpr types
         //   * report shallow errors if any
 
+        // &f on a function (not a value) makes a function pointer; the
+        //   name isn't a value, so we can't walk it like other subexprs
+        if let ExprKind::UnOpApp(ref mut inner, UnOp::AddressOf) = expr.data {
+            if let ExprKind::Name(ref path) = inner.data {
+                if self.names_function(path, module, function, &inner.loc) {
+                    expr.ty = self.fn_ptr_type(path, module, &inner.loc);
+                    inner.ty = expr.ty.clone();
+                    return;
+                }
+            }
+        }
+
         // first, walk subexprs and typecheck
-        self.walk_expr(expr, module, function);
+        match expr.data {
+            ExprKind::BinOpApp(_, _, BinOp::LogAnd)
+          | ExprKind::CondExpr { .. } =>
+                self.walk_narrowing_expr(expr, module, function),
+            _ => self.walk_expr(expr, module, function),
+        };
+
+        // raw VB cast to a type is taken at its word: it has that type,
+        //   rather than being converted from var
+        if let ExprKind::Cast(ref mut inner, ref ty) = expr.data {
+            if let ExprKind::VbExpr(_) = inner.data {
+                inner.ty = Some(ty.clone());
+            }
+        }
 
         // now do a shallow check on our type, iff all subexprs
         //   were successfully typed
@@ -522,6 +1081,9 @@ impl<'a> ASTVisitorMut for TypecheckVisitor<'a> {
                   NameCtxt::Value(module, function, Access::Private),
                   &expr.loc) => self.errors) {
                     Symbol::Const(ref ty, _) => Some(ty.clone()),
+                    Symbol::Value(Type::Nullable(ref base), _, _)
+                      if path.0.is_none() && self.narrowed.contains(&path.1) =>
+                        Some((**base).clone()),
                     Symbol::Value(ref ty, _, _) => Some(ty.clone()),
                     _ => panic!("dumpster fire: non-value slipped past \
                       lookup typecheck"),
@@ -531,7 +1093,13 @@ impl<'a> ASTVisitorMut for TypecheckVisitor<'a> {
             ExprKind::Index(ref expr, ref indices) => {
                 let expr_t = try_type!(expr);
 
-                for index in indices {
+                // (map keys are checked below)
+                let is_map = match *expr_t {
+                    Type::Map(_, _) => true,
+                    _ => false,
+                };
+
+                for index in indices.iter().filter(|_| !is_map) {
                     let index_t = try_type!(index);
                     if !may_coerce(index_t, &Type::Int32) {
                         self.errors.push(AnalysisError {
@@ -543,6 +1111,32 @@ impl<'a> ASTVisitorMut for TypecheckVisitor<'a> {
                 }
 
                 match *expr_t {
+                    Type::Map(ref key_t, ref value_t) => {
+                        if indices.len() != 1 {
+                            self.errors.push(AnalysisError {
+                                kind: AnalysisErrorKind::TypeError,
+                                regarding: Some(format!("map indexed with \
+                                  {} keys; 1 required", indices.len())),
+                                loc: expr.loc.clone(),
+                            });
+                            return;
+                        }
+
+                        let index_t = try_type!(indices[0]);
+                        if may_coerce(index_t, key_t) {
+                            Some((**value_t).clone())
+                        } else {
+                            self.errors.push(AnalysisError {
+                                kind: AnalysisErrorKind::TypeError,
+                                regarding: Some(format!("key of type {} not \
+                                  coercible to map key type {}", index_t,
+                                  key_t)),
+                                loc: indices[0].loc.clone(),
+                            });
+                            None
+                        }
+                    },
+
                     Type::Array(ref base_t, ref bounds) => {
                         if bounds.dims() == indices.len() {
                             Some((**base_t).clone())
@@ -558,7 +1152,36 @@ impl<'a> ASTVisitorMut for TypecheckVisitor<'a> {
                         }
                     },
 
-                    Type::VarArgsArray => {
+                    Type::ArrayView(ref base_t) => {
+                        if indices.len() == 1 {
+                            Some((**base_t).clone())
+                        } else {
+                            self.errors.push(AnalysisError {
+                                kind: AnalysisErrorKind::TypeError,
+                                regarding: Some(format!("array view indexed \
+                                  with {} dimensions; 1 required",
+                                  indices.len())),
+                                loc: expr.loc.clone(),
+                            });
+                            None
+                        }
+                    },
+
+                    Type::List(ref base_t) => {
+                        if indices.len() == 1 {
+                            Some((**base_t).clone())
+                        } else {
+                            self.errors.push(AnalysisError {
+                                kind: AnalysisErrorKind::TypeError,
+                                regarding: Some(format!("list indexed with \
+                                  {} dimensions; 1 required", indices.len())),
+                                loc: expr.loc.clone(),
+                            });
+                            None
+                        }
+                    },
+
+                    Type::VarArgsArray(_) => {
                         if indices.len() == 1 {
                             Some(Type::Variant)
                         } else {
@@ -608,6 +1231,11 @@ impl<'a> ASTVisitorMut for TypecheckVisitor<'a> {
                     Type::Variant | Type::Obj | Type::Object(_) =>
                         Some(Type::Variant),
 
+                    Type::Nullable(_) => {
+                        self.errors.push(self.possibly_null(expr));
+                        None
+                    },
+
                     Type::Struct(ref path) => {
                         let members = match *try_collect!(
                           self.symtab.symbol_at_path(
@@ -648,12 +1276,72 @@ impl<'a> ASTVisitorMut for TypecheckVisitor<'a> {
                 }
             },
 
-            ExprKind::MemberInvoke(ref expr, ref _mem, ref _args) => {
+            ExprKind::MemberInvoke(ref expr, ref mem, ref args) => {
                 match *try_type!(expr) {
                     // for now
                     Type::Variant | Type::Obj | Type::Object(_) =>
                         Some(Type::Variant),
 
+                    // methods from impl blocks, which take the struct
+                    //   by reference as their first argument
+                    Type::Struct(ref path) => {
+                        let method = Path(path.0.clone(),
+                          method_name(&path.1, mem));
+                        let fun = match self.symtab.symbol_at_path(&method,
+                          NameCtxt::Function(module, Access::Private),
+                          &expr.loc) {
+                            Ok(&Symbol::Fun { ref def, .. }) => def,
+                            Ok(_) => panic!("dumpster fire: non-function \
+                              slipped past lookup typecheck"),
+                            Err(AnalysisError {
+                                kind: AnalysisErrorKind::NotDefined, ..
+                            }) => {
+                                self.errors.push(AnalysisError {
+                                    kind: AnalysisErrorKind::NotDefined,
+                                    regarding: Some(format!(
+                                      "method {} of struct {}", mem, path)),
+                                    loc: expr.loc.clone(),
+                                });
+                                return;
+                            },
+                            Err(e) => {
+                                self.errors.push(e);
+                                return;
+                            },
+                        };
+
+                        if let ExprKind::WithSubject = expr.data {
+                            if let Some(&(_, false)) = self.withs.last() {
+                                self.errors.push(AnalysisError {
+                                    kind: AnalysisErrorKind::TypeError,
+                                    regarding: Some(format!("method {} called \
+                                      on the subject of a with block which \
+                                      isn't a variable (or its members, with \
+                                      literal indices)", mem)),
+                                    loc: expr.loc.clone(),
+                                });
+                                return;
+                            }
+                        }
+
+                        let mut self_args = vec![(**expr).clone()];
+                        self_args.extend(args.iter().cloned());
+                        self.typecheck_fn_call(fun, &self_args, &vec![],
+                          &method, &expr.loc);
+                        Some(fun.ret.clone())
+                    },
+
+                    ref ty @ Type::Map(_, _) =>
+                        self.typecheck_map_method(ty, mem, args, &expr.loc),
+
+                    Type::List(_) =>
+                        self.typecheck_list_method(expr, mem, args),
+
+                    Type::Nullable(_) => {
+                        self.errors.push(self.possibly_null(expr));
+                        None
+                    },
+
                     ref ty => {
                         self.errors.push(AnalysisError {
                             kind: AnalysisErrorKind::TypeError,
@@ -732,6 +1420,12 @@ impl<'a> ASTVisitorMut for TypecheckVisitor<'a> {
             ExprKind::BinOpApp(ref lhs, ref rhs, ref op) => {
                 let lhs_ty = try_type!(lhs);
                 let rhs_ty = try_type!(rhs);
+
+                if let Some(ty) = date_arith_type(lhs_ty, rhs_ty, op) {
+                    expr.ty = Some(ty);
+                    return;
+                }
+
                 let ub_ty = match upper_bound_type(lhs_ty, rhs_ty) {
                     None => {
                         self.errors.push(AnalysisError {
@@ -783,16 +1477,20 @@ impl<'a> ASTVisitorMut for TypecheckVisitor<'a> {
                     },
 
                     BinOp::Eq | BinOp::NotEq => {
-                        if !lhs_ty.is_scalar() || !rhs_ty.is_scalar() {
+                        if lhs_ty.is_scalar() && rhs_ty.is_scalar() {
+                            Some(Type::Bool)
+                        } else if self.may_compare_structurally(&ub_ty, module,
+                          &expr.loc) {
+                            // compared by generated functions (see eq_rewrite)
+                            Some(Type::Bool)
+                        } else {
                             self.errors.push(AnalysisError {
                                 kind: AnalysisErrorKind::TypeError,
-                                regarding: Some(String::from("non-scalar type in \
-                                  equality test")),
+                                regarding: Some(format!("type {} cannot be \
+                                  compared for equality", ub_ty)),
                                 loc: expr.loc.clone(),
                             });
                             None
-                        } else {
-                            Some(Type::Bool)
                         }
                     },
 
@@ -830,9 +1528,11 @@ impl<'a> ASTVisitorMut for TypecheckVisitor<'a> {
 
                     BinOp::Lt | BinOp::Gt | BinOp::LtEq | BinOp::GtEq => {
                         if !(lhs_ty.might_be_numeric()
-                             || lhs_ty.might_be_string())
+                             || lhs_ty.might_be_string()
+                             || *lhs_ty == Type::Date)
                           || !(rhs_ty.might_be_numeric()
-                             || rhs_ty.might_be_string()) {
+                             || rhs_ty.might_be_string()
+                             || *rhs_ty == Type::Date) {
                             self.errors.push(AnalysisError {
                                 kind: AnalysisErrorKind::TypeError,
                                 regarding: Some(String::from("non-comparable type \
@@ -845,7 +1545,7 @@ impl<'a> ASTVisitorMut for TypecheckVisitor<'a> {
                         }
                     },
 
-                    BinOp::BitAnd | BinOp::BitOr => {
+                    BinOp::BitAnd | BinOp::BitOr | BinOp::BitXor => {
                         if !lhs_ty.might_be_bitwise()
                           || !rhs_ty.might_be_bitwise() {
                             self.errors.push(AnalysisError {
@@ -860,6 +1560,36 @@ impl<'a> ASTVisitorMut for TypecheckVisitor<'a> {
                         }
                     },
 
+                    // VB has no shifts, so we lower these to fixed-width
+                    //   helper functions; no shifting isize or var
+                    BinOp::Shl | BinOp::Shr => {
+                        match *lhs_ty {
+                            Type::UInt8 | Type::Int16 | Type::Int32 => {},
+                            _ => {
+                                self.errors.push(AnalysisError {
+                                    kind: AnalysisErrorKind::TypeError,
+                                    regarding: Some(format!("shift of \
+                                      type {} (only u8, i16, and i32 may \
+                                      be shifted)", lhs_ty)),
+                                    loc: lhs.loc.clone(),
+                                });
+                                return;
+                            },
+                        };
+
+                        if !rhs_ty.might_be_bitwise() {
+                            self.errors.push(AnalysisError {
+                                kind: AnalysisErrorKind::TypeError,
+                                regarding: Some(String::from("non-bitwise type \
+                                  as shift amount")),
+                                loc: rhs.loc.clone(),
+                            });
+                            None
+                        } else {
+                            Some(lhs_ty.clone())
+                        }
+                    },
+
                     BinOp::LogAnd | BinOp::LogOr => {
                         if !may_coerce(&lhs_ty, &Type::Bool)
                           || !may_coerce(&rhs_ty, &Type::Bool) {
@@ -901,83 +1631,835 @@ impl<'a> ASTVisitorMut for TypecheckVisitor<'a> {
                         None
                     },
 
-                    Some(ub_ty) => Some(ub_ty),
+                    Some(ub_ty) => Some(ub_ty),
+                }
+            },
+
+            ExprKind::ExtentExpr(ref expr, _, dim) => {
+                let expr_ty = try_type!(expr);
+                match *expr_ty {
+                    Type::Array(_, ref bounds) => {
+                        if dim < bounds.dims() {
+                            Some(Type::Int32)
+                        } else {
+                            self.errors.push(AnalysisError {
+                                kind: AnalysisErrorKind::TypeError,
+                                regarding: Some(format!("dimension {} not \
+                                  valid for type {}", dim, expr_ty)),
+                                loc: expr.loc.clone(),
+                            });
+                            None
+                        }
+                    },
+
+                    Type::VarArgsArray(_) => {
+                        if dim == 0 {
+                            Some(Type::Int32)
+                        } else {
+                            self.errors.push(AnalysisError {
+                                kind: AnalysisErrorKind::TypeError,
+                                regarding: Some(format!("dimension {} not \
+                                  valid for variadic arguments array", dim)),
+                                loc: expr.loc.clone(),
+                            });
+                            None
+                        }
+                    },
+
+                    Type::ArrayView(_) => {
+                        if dim == 0 {
+                            Some(Type::Int32)
+                        } else {
+                            self.errors.push(AnalysisError {
+                                kind: AnalysisErrorKind::TypeError,
+                                regarding: Some(format!("dimension {} not \
+                                  valid for array view", dim)),
+                                loc: expr.loc.clone(),
+                            });
+                            None
+                        }
+                    },
+
+                    // TODO: maybe allow variants here (checked at runtime)?
+
+                    _ => {
+                        self.errors.push(AnalysisError {
+                            kind: AnalysisErrorKind::TypeError,
+                            regarding: Some(format!("cannot get extents for \
+                              non-array type {}", expr_ty)),
+                            loc: expr.loc.clone(),
+                        });
+                        None
+                    },
+                }
+            },
+
+            ExprKind::Slice(ref expr, ref lo, ref hi) => {
+                let expr_ty = try_type!(expr);
+
+                for bound in &[lo, hi] {
+                    let bound_ty = try_type!(bound);
+                    if !bound_ty.is_integral() {
+                        self.errors.push(AnalysisError {
+                            kind: AnalysisErrorKind::TypeError,
+                            regarding: Some(format!("slice bound of \
+                              non-integral type {}", bound_ty)),
+                            loc: bound.loc.clone(),
+                        });
+                    }
+                }
+
+                match *expr_ty {
+                    Type::Array(ref base_t, ref bounds)
+                      if bounds.dims() == 1 =>
+                        Some(Type::Array(base_t.clone(),
+                          ArrayBounds::Dynamic(1))),
+
+                    Type::ArrayView(ref base_t) =>
+                        Some(Type::Array(base_t.clone(),
+                          ArrayBounds::Dynamic(1))),
+
+                    _ => {
+                        self.errors.push(AnalysisError {
+                            kind: AnalysisErrorKind::TypeError,
+                            regarding: Some(format!("cannot slice expression \
+                              of type {}; only one-dimensional arrays may be \
+                              sliced", expr_ty)),
+                            loc: expr.loc.clone(),
+                        });
+                        None
+                    },
+                }
+            },
+
+            ExprKind::Cast(ref expr, ref ty) => {
+                let expr_ty = try_type!(expr);
+                // derived Debug renderings also cast to strings
+                if expr_ty == ty || may_cast(expr_ty, ty)
+                  || (*ty == Type::String
+                    && self.may_render_debug(expr_ty, module, &expr.loc)) {
+                    Some(ty.clone())
+                } else {
+                    self.errors.push(AnalysisError {
+                        kind: AnalysisErrorKind::TypeError,
+                        regarding: Some(format!("cannot cast expression of type {} \
+                          to type {}", expr_ty, ty)),
+                        loc: expr.loc.clone(),
+                    });
+                    None
+                }
+            },
+
+            ExprKind::Tuple(ref exprs) => {
+                let mut tys = Vec::new();
+
+                for e in exprs {
+                    // (static arrays decay, since the tuple is built
+                    //   up by member assignment)
+                    match *try_type!(e) {
+                        Type::Void
+                      | Type::VarArgsArray(_)
+                      | Type::ArrayView(_) => {
+                            self.errors.push(AnalysisError {
+                                kind: AnalysisErrorKind::TypeError,
+                                regarding: Some(format!("expression of type \
+                                  {} cannot be a tuple element",
+                                  try_type!(e))),
+                                loc: e.loc.clone(),
+                            });
+                            return;
+                        },
+
+                        ref ty => tys.push(ty.decay()),
+                    }
+                }
+
+                Some(Type::Tuple(tys))
+            },
+
+            // could be anything
+            ExprKind::VbExpr(_) => Some(Type::Variant),
+
+            ExprKind::WithSubject => match self.withs.last() {
+                Some(&(ref ty, _)) => ty.clone(),
+                None => {
+                    self.errors.push(AnalysisError {
+                        kind: AnalysisErrorKind::InvalidExpr,
+                        regarding: Some(String::from("leading-dot member \
+                          access outside of with block")),
+                        loc: expr.loc.clone(),
+                    });
+                    None
+                },
+            },
+
+            ExprKind::CheckedArith(_, _) => panic!("dumpster fire: checked \
+              arithmetic before typecheck"),
+        };
+    }
+
+    fn visit_allocextent(&mut self, extent: &mut AllocExtent, m: &Ident,
+      f: &Ident, loc: &SrcLoc) {
+        self.walk_allocextent(extent, m, f, loc);
+        self.typecheck_allocextent(extent, loc);
+    }
+}
+
+impl<'a> TypecheckVisitor<'a> {
+    // check that each attribute is known, appears at most once, has the
+    //   right arguments, and is legal on what it's attached to
+    fn check_attributes(&mut self, attrs: &[Attribute],
+      target: AttributeTarget) {
+        for (i, attr) in attrs.iter().enumerate() {
+            // doc comments become one doc attribute per line
+            if attr.name.0 != "doc"
+              && attrs[..i].iter().any(|a| a.name == attr.name) {
+                self.errors.push(AnalysisError {
+                    kind: AnalysisErrorKind::InvalidAttribute,
+                    regarding: Some(format!("duplicate attribute {}",
+                      attr.name)),
+                    loc: attr.loc.clone(),
+                });
+                continue;
+            }
+
+            let err = match attr.name.0.as_str() {
+                "doc" => match (attr.lit_arg(), attr.args.len()) {
+                    (Some(&Literal::String(_)), 1) => None,
+                    _ => Some(String::from("attribute doc takes a single \
+                      string literal")),
+                },
+
+                "description" => match target {
+                    AttributeTarget::NormalModule
+                  | AttributeTarget::Function
+                  | AttributeTarget::Static => match (attr.lit_arg(),
+                      attr.args.len()) {
+                        (Some(&Literal::String(_)), 1) => None,
+                        _ => Some(String::from("attribute description \
+                          takes a single string literal")),
+                    },
+
+                    _ => Some(format!("attribute description is not legal \
+                      on {}", target)),
+                },
+
+                "udf" => match target {
+                    AttributeTarget::Function =>
+                        attr.args.iter().filter_map(|arg| match *arg {
+                            AttributeArg::Named(ref key, Literal::String(_))
+                              if key.0 == "category" => None,
+                            AttributeArg::Flag(ref key)
+                              if key.0 == "volatile" => None,
+                            _ => Some(String::from("udf options are \
+                              category = \"...\" and volatile")),
+                        }).next(),
+
+                    _ => Some(format!("attribute udf is not legal on {}",
+                      target)),
+                },
+
+                "derive" => match target {
+                    AttributeTarget::Struct if !attr.args.is_empty() =>
+                        attr.args.iter().filter_map(|arg| match *arg {
+                            AttributeArg::Flag(ref trait_name)
+                              if trait_name.0 == "Debug" => None,
+                            _ => Some(String::from("only Debug may be \
+                              derived")),
+                        }).next(),
+
+                    AttributeTarget::Struct => Some(String::from(
+                      "attribute derive takes a list of traits")),
+
+                    _ => Some(format!("attribute derive is not legal on {}",
+                      target)),
+                },
+
+                // these only mean anything in class modules
+                "default_member"
+              | "enumerator"
+              | "predeclared_id"
+              | "exposed" => Some(format!("attribute {} is only legal \
+                  in class modules", attr.name)),
+
+                _ => Some(format!("unknown attribute {}", attr.name)),
+            };
+
+            if let Some(msg) = err {
+                self.errors.push(AnalysisError {
+                    kind: AnalysisErrorKind::InvalidAttribute,
+                    regarding: Some(msg),
+                    loc: attr.loc.clone(),
+                });
+            }
+        }
+    }
+
+    // structs compare member-wise, and arrays extent- and element-wise, as
+    //   long as everything inside them can be compared (objects are compared
+    //   by identity)
+    fn may_compare_structurally(&self, ty: &Type, module: &Ident,
+      loc: &SrcLoc) -> bool {
+        match *ty {
+            Type::Struct(ref path) => {
+                match self.symtab.symbol_at_path(path,
+                  NameCtxt::Type(module, Access::Private), loc) {
+                    Ok(&Symbol::Struct { ref members, .. }) =>
+                        members.values().all(|mem_ty| {
+                            mem_ty.is_scalar()
+                              || self.may_compare_structurally(mem_ty, module,
+                                   loc)
+                        }),
+                    _ => false,
+                }
+            },
+
+            Type::Tuple(ref tys) => tys.iter().all(|ty| {
+                ty.is_scalar()
+                  || self.may_compare_structurally(ty, module, loc)
+            }),
+
+            Type::Array(ref base, _)
+          | Type::ArrayView(ref base) => match **base {
+                Type::Array(_, _) | Type::ArrayView(_) => false,
+                ref base => base.is_scalar()
+                  || self.may_compare_structurally(base, module, loc),
+            },
+
+            _ => false,
+        }
+    }
+
+    // the built-in methods on maps
+    fn typecheck_map_method(&mut self, ty: &Type, method: &Ident,
+      args: &[Expr], loc: &SrcLoc) -> Option<Type> {
+        let key = match *ty {
+            Type::Map(ref key, _) => &**key,
+            _ => panic!("dumpster fire: map method on non-map type {}", ty),
+        };
+
+        let (params, ret) = match method.0.as_str() {
+            "contains" => (vec![key.clone()], Type::Bool),
+            "remove" => (vec![key.clone()], Type::Void),
+            "keys" => (vec![], Type::Array(Box::new(key.clone()),
+              ArrayBounds::Dynamic(1))),
+            "len" => (vec![], Type::Int32),
+            _ => {
+                self.errors.push(AnalysisError {
+                    kind: AnalysisErrorKind::NotDefined,
+                    regarding: Some(format!("method {} of type {} (maps \
+                      have contains, remove, keys, and len)", method, ty)),
+                    loc: loc.clone(),
+                });
+                return None;
+            },
+        };
+
+        if args.len() != params.len() {
+            self.errors.push(AnalysisError {
+                kind: AnalysisErrorKind::FnCallError,
+                regarding: Some(format!("map method {} takes {} argument(s); \
+                  {} provided", method, params.len(), args.len())),
+                loc: loc.clone(),
+            });
+            return None;
+        }
+
+        for (arg, param) in args.iter().zip(params.iter()) {
+            let arg_ty = arg.ty.as_ref()?;
+            if !may_coerce(arg_ty, param) {
+                self.errors.push(AnalysisError {
+                    kind: AnalysisErrorKind::TypeError,
+                    regarding: Some(format!("key of type {} not coercible \
+                      to map key type {}", arg_ty, key)),
+                    loc: arg.loc.clone(),
+                });
+                return None;
+            }
+        }
+
+        Some(ret)
+    }
+
+    // the built-in methods on lists
+    fn typecheck_list_method(&mut self, list: &Expr, method: &Ident,
+      args: &[Expr]) -> Option<Type> {
+        let ty = list.ty.as_ref()?;
+        let elem = match *ty {
+            Type::List(ref elem) => &**elem,
+            _ => panic!("dumpster fire: list method on non-list type {}", ty),
+        };
+
+        let (params, ret, mutates) = match method.0.as_str() {
+            "push" => (vec![elem.clone()], Type::Void, true),
+            "pop" => (vec![], elem.clone(), true),
+            "len" => (vec![], Type::Int32, false),
+            _ => {
+                self.errors.push(AnalysisError {
+                    kind: AnalysisErrorKind::NotDefined,
+                    regarding: Some(format!("method {} of type {} (lists \
+                      have push, pop, and len)", method, ty)),
+                    loc: list.loc.clone(),
+                });
+                return None;
+            },
+        };
+
+        if mutates && !list.is_lvalue() {
+            self.errors.push(AnalysisError {
+                kind: AnalysisErrorKind::TypeError,
+                regarding: Some(format!("list method {} called on \
+                  non-lvalue", method)),
+                loc: list.loc.clone(),
+            });
+            return None;
+        }
+
+        if args.len() != params.len() {
+            self.errors.push(AnalysisError {
+                kind: AnalysisErrorKind::FnCallError,
+                regarding: Some(format!("list method {} takes {} argument(s); \
+                  {} provided", method, params.len(), args.len())),
+                loc: list.loc.clone(),
+            });
+            return None;
+        }
+
+        for (arg, param) in args.iter().zip(params.iter()) {
+            let arg_ty = arg.ty.as_ref()?;
+            if !may_coerce(arg_ty, param) {
+                self.errors.push(AnalysisError {
+                    kind: AnalysisErrorKind::TypeError,
+                    regarding: Some(format!("element of type {} not \
+                      coercible to list element type {}", arg_ty, elem)),
+                    loc: arg.loc.clone(),
+                });
+                return None;
+            }
+        }
+
+        Some(ret)
+    }
+
+    // structs render through their derived Debug conversion, and arrays
+    //   element-wise, as long as the elements can be rendered
+    fn may_render_debug(&self, ty: &Type, module: &Ident, loc: &SrcLoc)
+      -> bool {
+        match *ty {
+            Type::Struct(ref path) => {
+                match self.symtab.symbol_at_path(path,
+                  NameCtxt::Type(module, Access::Private), loc) {
+                    Ok(&Symbol::Struct { ref def, .. }) =>
+                        derives_debug(&def.attrs),
+                    _ => false,
+                }
+            },
+
+            Type::Array(ref base, _)
+          | Type::ArrayView(ref base) => match **base {
+                Type::Array(_, _) | Type::ArrayView(_) => false,
+                ref base => base.is_scalar()
+                  || self.may_render_debug(base, module, loc),
+            },
+
+            _ => false,
+        }
+    }
+
+    // Excel can only call public functions, and can only pass values it
+    //   knows how to convert to and from worksheet values
+    fn check_udf_signature(&mut self, def: &FunDef, m: &Ident) {
+        fn excel_passable(ty: &Type) -> bool {
+            match *ty {
+                Type::Struct(_)
+              | Type::Tuple(_)
+              | Type::List(_)
+              | Type::Map(_, _)
+              | Type::ArrayView(_)
+              | Type::FnPtr(_, _) => false,
+                Type::Array(ref base, _) => excel_passable(base),
+                _ => true,
+            }
+        }
+
+        if def.access != Access::Public {
+            self.errors.push(AnalysisError {
+                kind: AnalysisErrorKind::InvalidAttribute,
+                regarding: Some(format!("udf {}::{} must be pub",
+                  m, def.name)),
+                loc: def.loc.clone(),
+            });
+        }
+
+        if def.ret == Type::Void {
+            self.errors.push(AnalysisError {
+                kind: AnalysisErrorKind::InvalidAttribute,
+                regarding: Some(format!("udf {}::{} must return a value",
+                  m, def.name)),
+                loc: def.loc.clone(),
+            });
+        } else if !excel_passable(&def.ret) {
+            self.errors.push(AnalysisError {
+                kind: AnalysisErrorKind::TypeError,
+                regarding: Some(format!("udf {}::{} cannot return type {} \
+                  to Excel", m, def.name, def.ret)),
+                loc: def.loc.clone(),
+            });
+        }
+
+        let optparams = match def.optparams {
+            Some(FunOptParams::Named(ref optparams)) =>
+                optparams.iter().map(|&(ref p, _)| p).collect(),
+            _ => Vec::new(),
+        };
+
+        for p in def.params.iter().chain(optparams) {
+            if !excel_passable(&p.ty) {
+                self.errors.push(AnalysisError {
+                    kind: AnalysisErrorKind::TypeError,
+                    regarding: Some(format!("udf {}::{} parameter {} has \
+                      type {}, which Excel cannot pass", m, def.name,
+                      p.name, p.ty)),
+                    loc: p.loc.clone(),
+                });
+            }
+        }
+    }
+
+    // extern fns become Declare statements, whose signatures can't use the
+    //   types we lower to generated structures (no library knows them)
+    fn check_extern_signature(&mut self, def: &FunDef, m: &Ident) {
+        fn declarable(ty: &Type) -> bool {
+            match *ty {
+                Type::Tuple(_)
+              | Type::List(_)
+              | Type::Map(_, _)
+              | Type::ArrayView(_) => false,
+                Type::Array(ref base, _) => declarable(base),
+                _ => true,
+            }
+        }
+
+        if def.optparams.is_some() {
+            self.errors.push(AnalysisError {
+                kind: AnalysisErrorKind::FnCallError,
+                regarding: Some(format!("extern fn {}::{} cannot have \
+                  optional or variadic parameters", m, def.name)),
+                loc: def.loc.clone(),
+            });
+        }
+
+        // (a Declare statement has nowhere to put attribute lines)
+        for attr in def.attrs.iter().filter(|a| a.name.0 != "doc") {
+            self.errors.push(AnalysisError {
+                kind: AnalysisErrorKind::InvalidAttribute,
+                regarding: Some(format!("attribute {} cannot be used on \
+                  extern fn {}::{}", attr.name, m, def.name)),
+                loc: attr.loc.clone(),
+            });
+        }
+
+        if !def.ret.is_scalar() && def.ret != Type::Void {
+            self.errors.push(AnalysisError {
+                kind: AnalysisErrorKind::TypeError,
+                regarding: Some(format!("extern fn {}::{} cannot return \
+                  type {}", m, def.name, def.ret)),
+                loc: def.loc.clone(),
+            });
+        }
+
+        for p in &def.params {
+            if !declarable(&p.ty) {
+                self.errors.push(AnalysisError {
+                    kind: AnalysisErrorKind::TypeError,
+                    regarding: Some(format!("extern fn {}::{} parameter {} \
+                      has type {}, which cannot be passed to a library",
+                      m, def.name, p.name, p.ty)),
+                    loc: p.loc.clone(),
+                });
+            }
+        }
+    }
+
+    // walk an if or while statement, treating nullable locals which the
+    //   condition checks against nullptr as non-null within the body
+    fn walk_narrowing_stmt(&mut self, stmt: &mut Stmt, module: &Ident,
+      function: &Ident) {
+        match stmt.data {
+            StmtKind::IfStmt {
+                ref mut cond,
+                ref mut body,
+                ref mut elsifs,
+                ref mut els,
+            } => {
+                self.visit_expr(cond, module, Some(function));
+                self.visit_narrowed_body(cond, body, module, function);
+
+                for &mut (ref mut cond, ref mut body) in elsifs {
+                    self.visit_expr(cond, module, Some(function));
+                    self.visit_narrowed_body(cond, body, module, function);
+                }
+
+                if let Some(ref mut body) = *els {
+                    for stmt in body {
+                        self.visit_stmt(stmt, module, function);
+                    }
+                }
+            },
+
+            StmtKind::WhileLoop { ref mut cond, ref mut body } => {
+                self.visit_expr(cond, module, Some(function));
+                self.visit_narrowed_body(cond, body, module, function);
+            },
+
+            _ => panic!("dumpster fire: non-conditional statement \
+              in null check narrowing"),
+        }
+
+        self.visit_srcloc(&mut stmt.loc);
+    }
+
+    // walk a with statement, typing leading-dot members in the body against
+    //   the subject
+    fn walk_with_stmt(&mut self, stmt: &mut Stmt, module: &Ident,
+      function: &Ident) {
+        match stmt.data {
+            StmtKind::With(ref mut subject, ref mut body) => {
+                self.visit_expr(subject, module, Some(function));
+
+                let subject_ty = match subject.ty {
+                    Some(Type::Struct(_))
+                  | Some(Type::Obj)
+                  | Some(Type::Object(_))
+                  | Some(Type::Variant) => subject.ty.clone(),
+
+                    Some(Type::Nullable(_)) => {
+                        self.errors.push(AnalysisError {
+                            kind: AnalysisErrorKind::TypeError,
+                            regarding: Some(format!("with block on \
+                              possibly-null expression of type {} (check \
+                              it with !== nullptr first)",
+                              subject.ty.as_ref().unwrap())),
+                            loc: subject.loc.clone(),
+                        });
+                        None
+                    },
+
+                    Some(ref ty) => {
+                        self.errors.push(AnalysisError {
+                            kind: AnalysisErrorKind::TypeError,
+                            regarding: Some(format!("with block on type {} \
+                              (not struct, class, obj, or var)", ty)),
+                            loc: subject.loc.clone(),
+                        });
+                        None
+                    },
+
+                    None => None,
+                };
+
+                let outer_stable = match self.withs.last() {
+                    Some(&(_, stable)) => stable,
+                    None => true,
+                };
+                let stable = outer_stable && subject.is_stable_place();
+                self.withs.push((subject_ty, stable));
+                for stmt in body {
+                    self.visit_stmt(stmt, module, function);
                 }
+                self.withs.pop();
             },
 
-            ExprKind::ExtentExpr(ref expr, _, dim) => {
-                let expr_ty = try_type!(expr);
-                match *expr_ty {
-                    Type::Array(_, ref bounds) => {
-                        if dim < bounds.dims() {
-                            Some(Type::Int32)
-                        } else {
-                            self.errors.push(AnalysisError {
-                                kind: AnalysisErrorKind::TypeError,
-                                regarding: Some(format!("dimension {} not \
-                                  valid for type {}", dim, expr_ty)),
-                                loc: expr.loc.clone(),
-                            });
-                            None
-                        }
-                    },
+            _ => panic!("dumpster fire: non-with statement in with \
+              typecheck"),
+        }
 
-                    Type::VarArgsArray => {
-                        if dim == 0 {
-                            Some(Type::Int32)
-                        } else {
-                            self.errors.push(AnalysisError {
-                                kind: AnalysisErrorKind::TypeError,
-                                regarding: Some(format!("dimension {} not \
-                                  valid for variadic arguments array", dim)),
-                                loc: expr.loc.clone(),
-                            });
-                            None
-                        }
-                    },
+        self.visit_srcloc(&mut stmt.loc);
+    }
 
-                    // TODO: maybe allow variants here (checked at runtime)?
+    // walk an && or ?: expression, treating nullable locals which the
+    //   left side (or condition) checks against nullptr as non-null on
+    //   the right side (or in the true branch); && short-circuits, so
+    //   that's only ever evaluated once the check passes
+    fn walk_narrowing_expr(&mut self, expr: &mut Expr, module: &Ident,
+      function: Option<&Ident>) {
+        let outer = self.narrowed.clone();
+
+        match expr.data {
+            ExprKind::BinOpApp(ref mut lhs, ref mut rhs, BinOp::LogAnd) => {
+                self.visit_expr(lhs, module, function);
+                if let Some(function) = function {
+                    self.push_non_null(lhs, module, function);
+                }
+                self.visit_expr(rhs, module, function);
+            },
+
+            ExprKind::CondExpr {
+                ref mut cond,
+                ref mut if_expr,
+                ref mut else_expr,
+            } => {
+                self.visit_expr(cond, module, function);
+                if let Some(function) = function {
+                    self.push_non_null(cond, module, function);
+                }
+                self.visit_expr(if_expr, module, function);
+                self.narrowed = outer.clone();
+                self.visit_expr(else_expr, module, function);
+            },
+
+            _ => panic!("dumpster fire: non-conditional expression \
+              in null check narrowing"),
+        }
+
+        self.narrowed = outer;
+        self.visit_srcloc(&mut expr.loc);
+    }
+
+    fn visit_narrowed_body(&mut self, cond: &Expr, body: &mut Vec<Stmt>,
+      module: &Ident, function: &Ident) {
+        let outer = self.narrowed.clone();
+        self.push_non_null(cond, module, function);
+
+        for stmt in body {
+            self.visit_stmt(stmt, module, function);
+        }
+
+        // drop what the condition proved, but keep forgetting anything
+        //   the body assigned
+        self.narrowed.retain(|n| outer.contains(n));
+    }
+
+    // find the locals a condition proves non-null: `x !== nullptr`,
+    //   possibly &&-ed together
+    fn push_non_null(&mut self, cond: &Expr, module: &Ident,
+      function: &Ident) {
+        match cond.data {
+            ExprKind::BinOpApp(ref lhs, ref rhs, BinOp::LogAnd) => {
+                self.push_non_null(lhs, module, function);
+                self.push_non_null(rhs, module, function);
+            },
+
+            ExprKind::BinOpApp(ref lhs, ref rhs, BinOp::NotIdentEq) => {
+                let name = match (&lhs.data, &rhs.data) {
+                    (&ExprKind::Name(Path(None, ref name)),
+                     &ExprKind::Lit(Literal::NullPtr))
+                  | (&ExprKind::Lit(Literal::NullPtr),
+                     &ExprKind::Name(Path(None, ref name))) => name,
+                    _ => return,
+                };
+
+                // statics could be reset out from under us by any fn call
+                if self.symtab.is_local(name, module, function) {
+                    self.narrowed.push(name.clone());
+                }
+            },
+
+            _ => { },
+        }
+    }
+
+    fn typecheck_fixed_string_len(&mut self, len: &StaticArrayDim,
+      module: &Ident, loc: &SrcLoc) {
+        match *len {
+            StaticArrayDim::Lit(ref lit) => match lit.int_value() {
+                Some(n) if n >= 1 && n <= 65535 => { },
+                _ => self.errors.push(AnalysisError {
+                    kind: AnalysisErrorKind::TypeError,
+                    regarding: Some(format!("fixed-length string length {} \
+                      is not an integer between 1 and 65535", lit)),
+                    loc: loc.clone(),
+                }),
+            },
 
+            // (qualified during symbol table construction; we look it up
+            //   from its own module, wherever the type is used)
+            StaticArrayDim::Named(ref path) => {
+                let home = path.0.as_ref().unwrap_or(module);
+                let ty = match *try_collect!(self.symtab.symbol_at_path(
+                  &Path(None, path.1.clone()),
+                  NameCtxt::Value(home, None, Access::Private), loc)
+                  => self.errors) {
+                    Symbol::Const(ref ty, _) => ty,
                     _ => {
                         self.errors.push(AnalysisError {
                             kind: AnalysisErrorKind::TypeError,
-                            regarding: Some(format!("cannot get extents for \
-                              non-array type {}", expr_ty)),
-                            loc: expr.loc.clone(),
+                            regarding: Some(format!("fixed-length string \
+                              length {} is not a const", path)),
+                            loc: loc.clone(),
                         });
-                        None
+                        return;
                     },
-                }
-            },
+                };
 
-            ExprKind::Cast(ref expr, ref ty) => {
-                let expr_ty = try_type!(expr);
-                if may_cast(expr_ty, ty) {
-                    Some(ty.clone())
-                } else {
+                if !ty.is_integral() {
                     self.errors.push(AnalysisError {
                         kind: AnalysisErrorKind::TypeError,
-                        regarding: Some(format!("cannot cast expression of type {} \
-                          to type {}", expr_ty, ty)),
-                        loc: expr.loc.clone(),
+                        regarding: Some(format!("fixed-length string length \
+                          {} has non-integral type {}", path, ty)),
+                        loc: loc.clone(),
                     });
-                    None
                 }
             },
+        }
+    }
 
-            // could be anything
-            ExprKind::VbExpr(_) => Some(Type::Variant),
+    // does this path name a function (and not a value shadowing it)?
+    fn names_function(&self, path: &Path, module: &Ident,
+      function: Option<&Ident>, loc: &SrcLoc) -> bool {
+        self.symtab.symbol_at_path(path,
+          NameCtxt::Value(module, function, Access::Private), loc).is_err()
+          && self.symtab.symbol_at_path(path,
+            NameCtxt::Function(module, Access::Private), loc).is_ok()
+    }
+
+    fn fn_ptr_type(&mut self, path: &Path, module: &Ident, loc: &SrcLoc)
+      -> Option<Type> {
+        let def = match self.symtab.symbol_at_path(path,
+          NameCtxt::Function(module, Access::Private), loc) {
+            Ok(&Symbol::Fun { ref def, .. }) => def,
+            _ => panic!("dumpster fire: non-function slipped past \
+              lookup typecheck"),
         };
+
+        // AddressOf only works on procedures in our own modules
+        if def.lib.is_some() {
+            self.errors.push(AnalysisError {
+                kind: AnalysisErrorKind::TypeError,
+                regarding: Some(format!("cannot take address of extern fn \
+                  {}", path)),
+                loc: loc.clone(),
+            });
+            return None;
+        }
+
+        // VB has no way to supply these through a pointer
+        if def.optparams.is_some() {
+            self.errors.push(AnalysisError {
+                kind: AnalysisErrorKind::TypeError,
+                regarding: Some(format!("cannot take address of function {} \
+                  with optional parameters", path)),
+                loc: loc.clone(),
+            });
+            return None;
+        }
+
+        let params = def.params.iter()
+            .map(|p| (p.mode, p.ty.clone()))
+            .collect();
+        Some(Type::FnPtr(params, Box::new(def.ret.clone())))
     }
 
-    fn visit_allocextent(&mut self, extent: &mut AllocExtent, m: &Ident,
-      f: &Ident, loc: &SrcLoc) {
-        self.walk_allocextent(extent, m, f, loc);
-        self.typecheck_allocextent(extent, loc);
+    fn possibly_null(&self, expr: &Expr) -> AnalysisError {
+        AnalysisError {
+            kind: AnalysisErrorKind::TypeError,
+            regarding: Some(format!("member access on possibly-null \
+              expression of type {} (check it with !== nullptr first)",
+              expr.ty.as_ref().expect("dumpster fire: untyped expression"))),
+            loc: expr.loc.clone(),
+        }
     }
-}
 
-impl<'a> TypecheckVisitor<'a> {
 
     fn typecheck_stmt_shallow(&mut self, stmt: &Stmt, module: &Ident,
       function: &Ident) {
@@ -1004,6 +2486,34 @@ impl<'a> TypecheckVisitor<'a> {
                           variable {} has same name as function", ident);
                     }
 
+                    // views only exist as parameters
+                    if let Type::ArrayView(ref base) = *ty {
+                        self.errors.push(AnalysisError {
+                            kind: AnalysisErrorKind::TypeError,
+                            regarding: Some(format!("variable {} cannot have \
+                              array view type {} (use {}[]?)", ident, ty,
+                              base)),
+                            loc: stmt.loc.clone(),
+                        });
+                    }
+
+                    // VB would start it off as Nothing (except that we
+                    //   create a new, empty dictionary for a map)
+                    let new_map = match *ty {
+                        Type::Map(_, _) => true,
+                        _ => false,
+                    };
+                    if init.is_none() && ty.starts_null() && !new_map {
+                        self.errors.push(AnalysisError {
+                            kind: AnalysisErrorKind::TypeError,
+                            regarding: Some(format!("variable {} of type {} \
+                              would start off holding Nothing; it requires \
+                              an initializer (or use a nullable object \
+                              type)", ident, ty)),
+                            loc: stmt.loc.clone(),
+                        });
+                    }
+
                     if let Some(ref init) = *init {
                         let init_ty = try_type!(init);
                         if !may_coerce(&init_ty, &ty) {
@@ -1018,6 +2528,59 @@ impl<'a> TypecheckVisitor<'a> {
                 }
             },
 
+            StmtKind::Destructure(ref idents, ref ty, ref init) => {
+                // these should already be gensymmed away
+                for ident in idents {
+                    if ident == function {
+                        panic!("dumpster fire: \
+                          variable {} has same name as function", ident);
+                    }
+                }
+
+                let init_ty = try_type!(init);
+
+                // symbol table construction fills in the type of
+                //   unannotated destructures from the initializer
+                let ty = match *ty {
+                    Some(ref ty) => ty,
+                    None => init_ty,
+                };
+
+                match *ty {
+                    Type::Tuple(ref tys) if tys.len() == idents.len() => { },
+
+                    Type::Tuple(ref tys) => {
+                        self.errors.push(AnalysisError {
+                            kind: AnalysisErrorKind::TypeError,
+                            regarding: Some(format!("cannot destructure \
+                              tuple type {} ({} elements) into {} variables",
+                              ty, tys.len(), idents.len())),
+                            loc: stmt.loc.clone(),
+                        });
+                        return;
+                    },
+
+                    _ => {
+                        self.errors.push(AnalysisError {
+                            kind: AnalysisErrorKind::TypeError,
+                            regarding: Some(format!("cannot destructure \
+                              non-tuple type {}", ty)),
+                            loc: stmt.loc.clone(),
+                        });
+                        return;
+                    },
+                };
+
+                if !may_coerce(init_ty, ty) {
+                    self.errors.push(AnalysisError {
+                        kind: AnalysisErrorKind::TypeError,
+                        regarding: Some(format!("initializer (of type {}) \
+                          not coercible to declared type {}", init_ty, ty)),
+                        loc: stmt.loc.clone(),
+                    });
+                }
+            },
+
             StmtKind::Assign(ref lhs, ref op, ref rhs) => { 
                 if !lhs.is_lvalue() {
                     self.errors.push(AnalysisError {
@@ -1075,8 +2638,9 @@ impl<'a> TypecheckVisitor<'a> {
 
                     AssignOp::BitAndAssign
                   | AssignOp::BitOrAssign
-                    if !lhs_ty.might_be_string()
-                      || !rhs_ty.might_be_string() => {
+                  | AssignOp::BitXorAssign
+                    if !lhs_ty.might_be_bitwise()
+                      || !rhs_ty.might_be_bitwise() => {
                         self.errors.push(AnalysisError {
                             kind: AnalysisErrorKind::TypeError,
                             regarding: Some(String::from("non-bitwise \
@@ -1258,9 +2822,46 @@ impl<'a> TypecheckVisitor<'a> {
                         }
                     },
 
-                    ForSpec::Each(ref expr) => {
+                    ForSpec::Each(ref expr, ref indices) => {
+                        // (a map loop binds its key like an index)
+                        let dims = match *try_type!(expr) {
+                            Type::Array(_, ref bounds) => bounds.dims(),
+                            Type::VarArgsArray(_)
+                          | Type::ArrayView(_)
+                          | Type::List(_)
+                          | Type::Map(_, _) => 1,
+                            _ => 0,
+                        };
+
+                        if !indices.is_empty() && indices.len() != dims {
+                            let msg = if let Type::Map(_, _) = *try_type!(expr) {
+                                format!("for-each loop over map binds {} key \
+                                  variables; 1 allowed", indices.len())
+                            } else if let Type::List(_) = *try_type!(expr) {
+                                format!("for-each loop over list binds {} \
+                                  index variables; 1 allowed", indices.len())
+                            } else if dims == 0 {
+                                format!("index variables can only be bound \
+                                  when looping over an array; found type {}",
+                                  try_type!(expr))
+                            } else {
+                                format!("for-each loop over {}-dimensional \
+                                  array binds {} index variable(s)",
+                                  dims, indices.len())
+                            };
+
+                            self.errors.push(AnalysisError {
+                                kind: AnalysisErrorKind::InvalidStmt,
+                                regarding: Some(msg),
+                                loc: stmt.loc.clone(),
+                            });
+                        }
+
                         match *try_type!(expr) {
-                            Type::Array(ref base, _) => {
+                            Type::Array(ref base, _)
+                          | Type::ArrayView(ref base)
+                          | Type::List(ref base)
+                          | Type::Map(_, ref base) => {
                                 match *mode {
                                     ParamMode::ByVal => {
                                         if !may_coerce(base, ty) {
@@ -1290,15 +2891,16 @@ impl<'a> TypecheckVisitor<'a> {
                                 }
                             },
 
-                            Type::VarArgsArray => {
+                            Type::VarArgsArray(ref elem) => {
                                 match *mode {
                                     ParamMode::ByVal => {
-                                        if !may_coerce(&Type::Variant, ty) {
+                                        if !may_coerce(elem, ty) {
                                             self.errors.push(AnalysisError {
                                                 kind: AnalysisErrorKind::TypeError,
                                                 regarding: Some(format!(
-                                                  "element type var not coercible \
-                                                    to variable type {}", ty)),
+                                                  "element type {} not coercible \
+                                                    to variable type {}",
+                                                  elem, ty)),
                                                 loc: stmt.loc.clone(),
                                             });
                                         }
@@ -1332,8 +2934,31 @@ impl<'a> TypecheckVisitor<'a> {
                                         loc: stmt.loc.clone(),
                                     });
                                 }
+
+                                // elements come out as var, and get
+                                //   converted to the variable's type
+                                if !ty.is_scalar() {
+                                    self.errors.push(AnalysisError {
+                                        kind: AnalysisErrorKind::TypeError,
+                                        regarding: Some(format!("loop \
+                                          variable {} has non-scalar type {} \
+                                          (elements of {} are var)",
+                                          var, ty, try_type!(expr))),
+                                        loc: stmt.loc.clone(),
+                                    });
+                                }
                             },
 
+                            Type::Nullable(_) =>
+                                self.errors.push(AnalysisError {
+                                    kind: AnalysisErrorKind::TypeError,
+                                    regarding: Some(format!("for-each loop \
+                                      over possibly-null expression of type \
+                                      {} (check it with !== nullptr first)",
+                                      try_type!(expr))),
+                                    loc: stmt.loc.clone(),
+                                }),
+
                             ref expr_ty => self.errors.push(AnalysisError {
                                 kind: AnalysisErrorKind::TypeError,
                                 regarding: Some(format!("for-each loop \
@@ -1352,7 +2977,7 @@ impl<'a> TypecheckVisitor<'a> {
                 let dims = match *try_type!(along) {
                     Type::Array(_, ref bounds) => bounds.dims(),
 
-                    Type::VarArgsArray => 1,
+                    Type::VarArgsArray(_) | Type::ArrayView(_) => 1,
 
                     // TODO: maybe allow variants (checked at runtime)?
 
@@ -1461,7 +3086,7 @@ impl<'a> TypecheckVisitor<'a> {
                         });
                     },
 
-                    Type::VarArgsArray => {
+                    Type::VarArgsArray(_) => {
                         self.errors.push(AnalysisError {
                             kind: AnalysisErrorKind::InvalidStmt,
                             regarding: Some(String::from("attempt to allocate \
@@ -1531,7 +3156,7 @@ impl<'a> TypecheckVisitor<'a> {
                         });
                     },
 
-                    Type::VarArgsArray => {
+                    Type::VarArgsArray(_) => {
                         self.errors.push(AnalysisError {
                             kind: AnalysisErrorKind::InvalidStmt,
                             regarding: Some(String::from("attempt to reallocate \
@@ -1564,7 +3189,7 @@ impl<'a> TypecheckVisitor<'a> {
                         });
                     },
 
-                    Type::VarArgsArray => {
+                    Type::VarArgsArray(_) => {
                         self.errors.push(AnalysisError {
                             kind: AnalysisErrorKind::InvalidStmt,
                             regarding: Some(String::from("attempt to deallocate \
@@ -1594,7 +3219,62 @@ impl<'a> TypecheckVisitor<'a> {
                             loc: stmt.loc.clone(),
                         }),
 
-                        _ => { },
+                        ref ty if ty.is_scalar() => { },
+
+                        ref ty => if !self.may_render_debug(ty, module,
+                          &expr.loc) {
+                            self.errors.push(AnalysisError {
+                                kind: AnalysisErrorKind::TypeError,
+                                regarding: Some(format!("cannot print \
+                                  expression of type {} (structs must \
+                                  derive Debug)", ty)),
+                                loc: expr.loc.clone(),
+                            });
+                        },
+                    }
+                }
+            },
+
+            // checked as we walked it
+            StmtKind::With(_, _) => { },
+
+            StmtKind::VbBlock(_, ref reads, ref writes) => {
+                for path in reads.iter().chain(writes.iter()) {
+                    if !self.symtab.is_local(&path.1, module, function) {
+                        self.errors.push(AnalysisError {
+                            kind: AnalysisErrorKind::InvalidStmt,
+                            regarding: Some(format!("raw VB block may only \
+                              declare effects on locals; {} is not a local",
+                              path)),
+                            loc: stmt.loc.clone(),
+                        });
+                    }
+                }
+
+                // the block could set it back to Nothing
+                self.narrowed.retain(|n| !writes.iter().any(|w| w.1 == *n));
+            },
+
+            StmtKind::Assert(ref cond, ref msg, _) => {
+                let cond_ty = try_type!(cond);
+                if !may_coerce(&cond_ty, &Type::Bool) {
+                    self.errors.push(AnalysisError {
+                        kind: AnalysisErrorKind::TypeError,
+                        regarding: Some(String::from(
+                          "assertion condition not coercible to bool")),
+                        loc: cond.loc.clone(),
+                    });
+                }
+
+                if let Some(ref msg) = *msg {
+                    let msg_ty = try_type!(msg);
+                    if !may_coerce(&msg_ty, &Type::String) {
+                        self.errors.push(AnalysisError {
+                            kind: AnalysisErrorKind::TypeError,
+                            regarding: Some(format!("assertion message of \
+                              type {} not coercible to str", msg_ty)),
+                            loc: msg.loc.clone(),
+                        });
                     }
                 }
             },
@@ -1609,7 +3289,9 @@ impl<'a> TypecheckVisitor<'a> {
                 match *try_type!(expr) {
                     Type::Array(_, _) => { },
 
-                    Type::VarArgsArray => { },
+                    Type::VarArgsArray(_) => { },
+
+                    Type::ArrayView(_) => { },
 
                     ref ty => self.errors.push(AnalysisError {
                         kind: AnalysisErrorKind::TypeError,
@@ -1651,7 +3333,32 @@ impl<'a> TypecheckVisitor<'a> {
     fn typecheck_fn_call(&mut self, fun: &FunDef, args: &Vec<Expr>,
       optargs: &Vec<(Ident, Expr)>, invoke_path: &Path,
       invoke_loc: &SrcLoc) {
-        if args.len() < fun.params.len() {
+        // named arguments may also supply required parameters which
+        //   weren't given positionally; sort those out first
+        let mut required: Vec<Option<&Expr>> = (0..fun.params.len())
+            .map(|i| args.get(i))
+            .collect();
+        let mut named_optargs = Vec::new();
+
+        for &(ref argname, ref arg) in optargs {
+            match fun.params.iter().position(|p| param_named(&p.name, argname)) {
+                Some(i) => {
+                    if required[i].is_some() {
+                        self.errors.push(AnalysisError {
+                            kind: AnalysisErrorKind::DuplicateSymbol,
+                            regarding: Some(format!("argument {} to {} was \
+                              duplicated", argname, invoke_path)),
+                            loc: arg.loc.clone(),
+                        });
+                    }
+                    required[i] = Some(arg);
+                },
+
+                None => named_optargs.push((argname, arg)),
+            }
+        }
+
+        if optargs.is_empty() && args.len() < fun.params.len() {
             self.errors.push(AnalysisError {
                 kind: AnalysisErrorKind::FnCallError,
                 regarding: Some(format!("{} requires {} arguments; \
@@ -1661,7 +3368,21 @@ impl<'a> TypecheckVisitor<'a> {
             return;
         }
 
-        if !optargs.is_empty() && args.len() > fun.params.len() {
+        let missing: Vec<_> = fun.params.iter().zip(required.iter())
+            .filter(|&(_, arg)| arg.is_none())
+            .map(|(param, _)| param.name.to_string())
+            .collect();
+        if !missing.is_empty() {
+            self.errors.push(AnalysisError {
+                kind: AnalysisErrorKind::FnCallError,
+                regarding: Some(format!("{} called without required \
+                  argument(s) {}", invoke_path, missing.join(", "))),
+                loc: invoke_loc.clone(),
+            });
+            return;
+        }
+
+        if !named_optargs.is_empty() && args.len() > fun.params.len() {
             self.errors.push(AnalysisError {
                 kind: AnalysisErrorKind::FnCallError,
                 regarding: Some(String::from("positional optional arguments \
@@ -1690,36 +3411,58 @@ impl<'a> TypecheckVisitor<'a> {
             }
         }
 
-        for (i, param) in fun.params.iter().enumerate() {
-            let arg_type = try_type!(args[i]).decay();
+        for (param, arg) in fun.params.iter().zip(required) {
+            let arg = arg.expect("dumpster fire: missing required argument");
+            let arg_type = try_type!(arg).decay();
 
             match param.mode {
                 ParamMode::ByRef =>
-                    if param.ty != arg_type {
+                    if !may_pass_by_ref(&arg_type, &param.ty) {
                         self.errors.push(AnalysisError {
                             kind: AnalysisErrorKind::TypeError,
                             regarding: Some(format!(
                               "parameter {} has type &{}; type {} provided",
                               param.name, param.ty, arg_type)),
-                            loc: args[i].loc.clone(),
+                            loc: arg.loc.clone(),
                         });
+                    } else if let ExprKind::Slice(_, _, _) = arg.data {
+                        // only a view can see (and write) the original
+                        //   elements; anything else gets a copy
+                        if let Type::Array(ref base, _) = param.ty {
+                            self.errors.push(AnalysisError {
+                                kind: AnalysisErrorKind::TypeError,
+                                regarding: Some(format!("slice passed to \
+                                  parameter {} of type &{}, which would \
+                                  only see a copy (declare it &{}[..])",
+                                  param.name, param.ty, base)),
+                                loc: arg.loc.clone(),
+                            });
+                        }
                     },
                 ParamMode::ByVal =>
                     if !may_coerce(&arg_type, &param.ty) {
+                        // (callbacks must match signatures exactly)
+                        let regarding = match (&param.ty, &arg_type) {
+                            (&Type::FnPtr(_, _), &Type::FnPtr(_, _)) =>
+                                format!("callback parameter {} has type {}; \
+                                  function of type {} provided",
+                                  param.name, param.ty, arg_type),
+                            _ => format!(
+                              "parameter {} has type {}; type {} provided",
+                              param.name, param.ty, arg_type),
+                        };
                         self.errors.push(AnalysisError {
                             kind: AnalysisErrorKind::TypeError,
-                            regarding: Some(format!(
-                              "parameter {} has type {}; type {} provided",
-                              param.name, param.ty, arg_type)),
-                            loc: args[i].loc.clone(),
+                            regarding: Some(regarding),
+                            loc: arg.loc.clone(),
                         });
                     },
             }
         }
 
-        if optargs.is_empty() {
+        if named_optargs.is_empty() {
             // any optional arguments are positional
-            let optargs = &args[fun.params.len()..];
+            let optargs = args.get(fun.params.len()..).unwrap_or(&[]);
             match fun.optparams {
                 Some(FunOptParams::Named(ref optparams)) => {
                     for (i, &(ref param, _)) in optparams.iter().enumerate() {
@@ -1755,15 +3498,15 @@ impl<'a> TypecheckVisitor<'a> {
                     }
                 },
 
-                Some(FunOptParams::VarArgs(_, _)) => {
+                Some(FunOptParams::VarArgs(_, ref elem_ty, _)) => {
                     for arg in optargs.iter() {
                         let arg_ty = try_type!(arg).decay();
-                        if !may_coerce(&arg_ty, &Type::Variant) {
+                        if !may_coerce(&arg_ty, elem_ty) {
                             self.errors.push(AnalysisError {
                                 kind: AnalysisErrorKind::TypeError,
                                 regarding: Some(format!(
                                     "variadic optional argument has type {}; not \
-                                      coercible to var", arg_ty)),
+                                      coercible to {}", arg_ty, elem_ty)),
                                 loc: arg.loc.clone(),
                             });
                         }
@@ -1776,28 +3519,23 @@ impl<'a> TypecheckVisitor<'a> {
             let optparams = match fun.optparams {
                 Some(FunOptParams::Named(ref optparams)) => optparams,
                 _ => {
-                    self.errors.push(AnalysisError {
-                        kind: AnalysisErrorKind::FnCallError,
-                        regarding: Some(format!("fn {} called with \
-                          named optional arguments; none specified in \
-                          function definition",
-                          invoke_path)),
-                        loc: optargs[0].1.loc.clone(),
-                    });
+                    for &(argname, arg) in named_optargs.iter() {
+                        self.errors.push(AnalysisError {
+                            kind: AnalysisErrorKind::FnCallError,
+                            regarding: Some(format!("{} has no parameter {}",
+                              invoke_path, argname)),
+                            loc: arg.loc.clone(),
+                        });
+                    }
                     return;
                 },
             };
 
             let mut seen = HashSet::new();
 
-            for &(ref argname, ref arg) in optargs {
+            for &(argname, arg) in named_optargs.iter() {
                 let which = optparams.iter().enumerate()
-                    .find(|&(_, &(ref param, _))| {
-                        match param.name {
-                            Ident(ref name, None) => *name == argname.0,
-                            Ident(_, Some(ref prev)) => *prev == argname.0,
-                        }
-                    });
+                    .find(|&(_, &(ref param, _))| param_named(&param.name, argname));
 
                 match which {
                     Some((i, _)) => {
@@ -1841,7 +3579,7 @@ impl<'a> TypecheckVisitor<'a> {
 
                     None => self.errors.push(AnalysisError {
                         kind: AnalysisErrorKind::FnCallError,
-                        regarding: Some(format!("{} has no optional argument {}",
+                        regarding: Some(format!("{} has no parameter {}",
                           invoke_path, argname)),
                         loc: arg.loc.clone(),
                     }),
@@ -1850,3 +3588,94 @@ impl<'a> TypecheckVisitor<'a> {
         }
     }
 }
+
+// does a (possibly gensymmed) parameter name match a name given at a call site?
+pub fn param_named(param: &Ident, name: &Ident) -> bool {
+    match *param {
+        Ident(ref param, None) => *param == name.0,
+        Ident(_, Some(ref prev)) => *prev == name.0,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use parser;
+
+    // the messages of the errors typechecking a dumpster reports
+    fn errors(src: &str) -> Vec<String> {
+        let mut dumpster = parser::parse_dumpster("test", src.as_bytes())
+            .expect("syntax error");
+        let res = SymbolTable::build(&mut dumpster)
+            .and_then(|symtab| typecheck(&mut dumpster, &symtab));
+        match res {
+            Ok(()) => vec![],
+            Err(errs) => errs.into_iter()
+                .map(|e| e.regarding.unwrap_or_default()).collect(),
+        }
+    }
+
+    #[test]
+    fn check_extern_callbacks() {
+        let enum_windows = "extern \"user32\" fn EnumWindows(\
+          cb: fn(isize, isize) -> i32, param: isize) -> i32;";
+
+        assert_eq!(errors(&format!("mod m {{ {} \
+          fn each(hwnd: isize, param: isize) -> i32 {{ return 1; }} \
+          fn f() {{ EnumWindows(&each, 0); }} }}", enum_windows)),
+          Vec::<String>::new());
+
+        assert_eq!(errors(&format!("mod m {{ {} \
+          fn each(hwnd: isize) -> i32 {{ return 1; }} \
+          fn f() {{ EnumWindows(&each, 0); }} }}", enum_windows)),
+          vec!["callback parameter cb has type fn(isize, isize) -> i32; \
+            function of type fn(isize) -> i32 provided"]);
+
+        assert_eq!(errors(&format!("mod m {{ {} \
+          fn each(hwnd: isize, param: &isize) -> i32 {{ return 1; }} \
+          fn f() {{ EnumWindows(&each, 0); }} }}", enum_windows)).len(), 1);
+
+        assert_eq!(errors(&format!("mod m {{ {} \
+          fn f() {{ EnumWindows(&EnumWindows, 0); }} }}", enum_windows)),
+          vec!["cannot take address of extern fn EnumWindows"]);
+    }
+
+    #[test]
+    fn check_extern_signatures() {
+        assert_eq!(errors("mod m { extern \"k\" fn f(t: &(i32, i32)); }"),
+          vec!["extern fn m::f parameter t has type (i32, i32), which \
+            cannot be passed to a library"]);
+        assert_eq!(errors("mod m { extern \"k\" fn f(; x: i32 = 1); }"),
+          vec!["extern fn m::f cannot have optional or variadic \
+            parameters"]);
+        assert_eq!(errors("mod m { #[udf] pub extern \"k\" fn f() -> i32; }")
+          .len(), 1);
+    }
+
+    #[test]
+    fn check_named_args() {
+        let f = "fn f(a: i32, b: f64; c: i32 = 3) { }";
+
+        assert_eq!(errors(&format!("mod m {{ {} \
+          fn g() {{ f(b = 2.0, a = 1); f(1, c = 4, b = 2.0); }} }}", f)),
+          Vec::<String>::new());
+
+        assert_eq!(errors(&format!("mod m {{ {} \
+          fn g() {{ f(1, b = 2.0, a = 1); }} }}", f)),
+          vec!["argument a to f was duplicated"]);
+        assert_eq!(errors(&format!("mod m {{ {} \
+          fn g() {{ f(1, 2.0, c = 1, c = 2); }} }}", f)),
+          vec!["optional argument c to f was duplicated"]);
+
+        assert_eq!(errors(&format!("mod m {{ {} \
+          fn g() {{ f(1, b = 2.0, z = 1); }} }}", f)),
+          vec!["f has no parameter z"]);
+
+        assert_eq!(errors(&format!("mod m {{ {} \
+          fn g() {{ f(c = 1, b = 2.0); }} }}", f)),
+          vec!["f called without required argument(s) a"]);
+        assert_eq!(errors(&format!("mod m {{ {} \
+          fn g() {{ f(1); }} }}", f)),
+          vec!["f requires 2 arguments; 1 were provided"]);
+    }
+}
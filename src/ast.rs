@@ -25,6 +25,7 @@ pub enum ModuleKind {
 pub struct Module {
     pub name: Ident,
     pub data: ModuleKind,
+    pub attrs: Vec<Attribute>,
     pub loc: SrcLoc,
 }
 
@@ -38,6 +39,56 @@ impl Module {
     }
 }
 
+/// Attributes attached to items or modules: `#[name]` or `#[name(args)]`
+///   (these mostly become hidden VB `Attribute` lines)
+#[derive(Clone, Debug)]
+pub struct Attribute {
+    pub name: Ident,
+    pub args: Vec<AttributeArg>,
+    pub loc: SrcLoc,
+}
+
+/// Attribute arguments: `"lit"`, `flag`, or `key = "lit"`
+#[derive(Clone, Debug)]
+pub enum AttributeArg {
+    Lit(Literal),
+    Flag(Ident),
+    Named(Ident, Literal),
+}
+
+impl Attribute {
+    pub fn lit_arg(&self) -> Option<&Literal> {
+        match self.args.first() {
+            Some(&AttributeArg::Lit(ref lit)) => Some(lit),
+            _ => None,
+        }
+    }
+}
+
+/// The description of an item or module: an explicit `#[description]`, or
+///   else the first non-blank line of its doc comment
+pub fn description(attrs: &[Attribute]) -> Option<&Literal> {
+    let explicit = attrs.iter().find(|a| a.name.0 == "description");
+    let doc = attrs.iter().filter(|a| a.name.0 == "doc")
+        .filter_map(|a| a.lit_arg())
+        .find(|lit| match **lit {
+            Literal::String(ref text) => !text.trim().is_empty(),
+            _ => false,
+        });
+
+    explicit.and_then(|a| a.lit_arg()).or(doc)
+}
+
+/// Does this item carry `#[derive(Debug)]`?
+pub fn derives_debug(attrs: &[Attribute]) -> bool {
+    attrs.iter().filter(|a| a.name.0 == "derive")
+        .flat_map(|a| a.args.iter())
+        .any(|arg| match *arg {
+            AttributeArg::Flag(ref trait_name) => trait_name.0 == "Debug",
+            _ => false,
+        })
+}
+
 /// Items define functions or types, and make up modules
 #[derive(Clone, Debug)]
 pub enum NormalItem {
@@ -57,9 +108,21 @@ pub struct FunDef {
     pub optparams: Option<FunOptParams>,
     pub ret: Type,
     pub body: Vec<Stmt>,
+    /// the library an `extern "lib" fn` is declared from (these become VB
+    ///   `Declare` statements, and have no body)
+    pub lib: Option<String>,
+    pub attrs: Vec<Attribute>,
     pub loc: SrcLoc,
 }
 
+/// Methods in impl blocks become module-level functions with mangled names,
+///   taking the struct by reference as their first parameter (self)
+pub fn method_name(st: &Ident, method: &Ident) -> Ident {
+    let st = st.1.as_ref().unwrap_or(&st.0);
+    Ident(format!("{}ø{}", st, method.0),
+      Some(format!("{}::{}", st, method.0)))
+}
+
 /// An individual function parameter
 #[derive(Clone, Debug)]
 pub struct FunParam {
@@ -72,14 +135,15 @@ pub struct FunParam {
 /// A function's optional arguments: either named or variadic
 #[derive(Clone, Debug)]
 pub enum FunOptParams {
-    VarArgs(Ident, SrcLoc),
-    Named(Vec<(FunParam, Literal)>),
+    /// xs: T... (T is var if unspecified)
+    VarArgs(Ident, Type, SrcLoc),
+    Named(Vec<(FunParam, Expr)>),
 }
 
 impl FunOptParams {
     pub fn max_len(&self) -> Option<usize> {
         match *self {
-            FunOptParams::VarArgs(_, _) => None,
+            FunOptParams::VarArgs(_, _, _) => None,
             FunOptParams::Named(ref vec) => Some(vec.len()),
         }
     }
@@ -101,6 +165,10 @@ pub enum StmtKind {
     /// variable declaration(s) with optional initializer(s)
     VarDecl(Vec<(Ident, Type, Option<Expr>)>),
 
+    /// tuple destructuring declaration e.g. `let (a, b) = f();`
+    ///   (the tuple type is inferred from the initializer if not given)
+    Destructure(Vec<Ident>, Option<Type>, Expr),
+
     // TODO: why did I make this infix and BinOpApp et al postfix
     /// assignment statement (including += et. al)
     Assign(Expr, AssignOp, Expr),
@@ -148,6 +216,17 @@ pub enum StmtKind {
 
     /// `print` statement (i.e. Debug.Print)
     Print(Vec<Expr>),
+
+    /// with block; leading-dot members in the body refer to the subject
+    With(Expr, Vec<Stmt>),
+
+    /// assertion with an optional message (the condition's source text is
+    ///   kept for the failure message)
+    Assert(Expr, Option<Expr>, String),
+
+    /// raw VB statement block, with the locals it declares it reads
+    ///   and writes
+    VbBlock(Vec<u8>, Vec<Path>, Vec<Path>),
 }
 
 /* TODO: maybe use for-each by-ref to signify local
@@ -163,11 +242,22 @@ pub enum StmtKind {
  */
 // TODO: move variable into forspec; this might make many
 //   things easier
-/// For loop specs: range (from, to, step) or each (expr)
+/// For loop specs: range (from, to, step) or each (expr, index variables)
+///   (index variables, one per dimension, are only bound by e.g.
+///   `for (i, j, x: T) in xs`)
 #[derive(Clone, Debug)]
 pub enum ForSpec {
     Range(Expr, Expr, Option<Expr>),
-    Each(Expr),
+    Each(Expr, Vec<Ident>),
+}
+
+/// The type of a for-each loop's index variables: a map's key type, and
+/// i32 for arrays and lists (also assumed before the loop's typechecked)
+pub fn index_type(of: &Expr) -> Type {
+    match of.ty {
+        Some(Type::Map(ref key, _)) => (**key).clone(),
+        _ => Type::Int32,
+    }
 }
 
 /// allocation extents for an array alloc statement
@@ -198,6 +288,10 @@ pub enum ExprKind {
     /// An indexing expression `e1[e2]` or e1[e2;e3;...]
     Index(Box<Expr>, Vec<Expr>),
 
+    /// A slice of a one-dimensional array `e1[e2:e3]` (inclusive; elements
+    ///   keep their original indices)
+    Slice(Box<Expr>, Box<Expr>, Box<Expr>),
+
     /// A function call `f(a1, a2, ... [| x = e, ... ])`
     Call(Path, Vec<Expr>, Vec<(Ident, Expr)>),
 
@@ -226,8 +320,19 @@ pub enum ExprKind {
     /// a cast `x as ty`
     Cast(Box<Expr>, Type),
 
+    /// a tuple expression e.g. `(x, "y")`
+    Tuple(Vec<Expr>),
+
     /// pass-through literal VB expression (raw bytes)
     VbExpr(Vec<u8>),
+
+    /// the subject of the innermost enclosing with block
+    ///   (the implicit base of `.m` or `.f(args, ...)`)
+    WithSubject,
+
+    /// integer arithmetic which reports this expression's source location on
+    ///   overflow (inserted by the checked arithmetic rewriter)
+    CheckedArith(CheckedOp, Vec<Expr>),
 }
 
 impl Expr {
@@ -240,6 +345,25 @@ impl Expr {
             _ => false,
         }
     }
+
+    /// whether evaluating this again names the same place without side
+    ///   effects (a variable, or members of one, with literal indices)
+    pub fn is_stable_place(&self) -> bool {
+        match self.data {
+            ExprKind::Name(_)
+          | ExprKind::WithSubject => true,
+
+            ExprKind::Member(ref base, _) => base.is_stable_place(),
+
+            ExprKind::Index(ref base, ref indices) =>
+                base.is_stable_place() && indices.iter().all(|i| match i.data {
+                    ExprKind::Lit(_) => true,
+                    _ => false,
+                }),
+
+            _ => false,
+        }
+    }
 }
 
 /// an array-extents expression
@@ -256,6 +380,7 @@ pub struct StructDef {
     pub name: Ident,
     pub access: Access,
     pub members: Vec<StructMem>,
+    pub attrs: Vec<Attribute>,
     pub loc: SrcLoc,
 }
 
@@ -276,6 +401,7 @@ pub struct Static {
     // i'm going to regret not requiring the initializer...
     // TODO: it turns out you can't have one anyway
     pub init: Option<Literal>,
+    pub attrs: Vec<Attribute>,
     pub loc: SrcLoc,
 }
 
@@ -285,7 +411,8 @@ pub struct Constant {
     pub name: Ident,
     pub access: Access,
     pub ty: Type,
-    pub value: Literal,
+    pub value: Expr,
+    pub attrs: Vec<Attribute>,
     pub loc: SrcLoc,
 }
 
@@ -350,6 +477,8 @@ pub enum Type {
     Float64,
     /// str
     String,
+    /// fstr<N> (fixed-length string)
+    FixedString(StaticArrayDim),
     /// currency
     Currency,
     /// date
@@ -360,14 +489,28 @@ pub enum Type {
     Obj,
     /// T[] (possibly multidimensional)
     Array(Box<Type>, ArrayBounds),
-    /// ... (variadic argument arrays)
-    VarArgsArray,
+    /// T... (variadic argument arrays; elements are stored as var)
+    VarArgsArray(Box<Type>),
+    /// T[..] (a view of some slice of a one-dimensional array; only for
+    ///   by-reference parameters)
+    ArrayView(Box<Type>),
     /// named object type
     Object(Path),
     /// named structure type
     Struct(Path),
     /// named enum type
     Enum(Path),
+    /// (T1, T2, ...) (tuple types)
+    Tuple(Vec<Type>),
+    /// T? (object types which may be nullptr)
+    Nullable(Box<Type>),
+    /// fn(T1, &T2) -> R (function pointers, e.g. callbacks passed to extern
+    /// fns such as EnumWindows)
+    FnPtr(Vec<(ParamMode, Type)>, Box<Type>),
+    /// map<K, V> (a Scripting.Dictionary with typed keys and values)
+    Map(Box<Type>, Box<Type>),
+    /// list<T> (a growable array, kept as a backing array and a length)
+    List(Box<Type>),
     /// identifier-as-typename; unknown until symbol table construction
     Deferred(Path),
     /// unit type (only used in function returns)
@@ -380,7 +523,10 @@ impl Type {
     /// type; if "maybe" at runtime we return None here
     pub fn is_object(&self) -> Option<bool> {
         match *self {
-            Type::Obj | Type::Object(_) => Some(true),
+            Type::Obj
+          | Type::Object(_)
+          | Type::Nullable(_)
+          | Type::Map(_, _) => Some(true),
             Type::Variant | Type::Deferred(_) => None,
             _ => Some(false),
         }
@@ -390,11 +536,34 @@ impl Type {
     /// i.e. can we check equality "directly"?
     pub fn is_scalar(&self) -> bool {
         match *self {
-            Type::Array(_, _) | Type::Struct(_) | Type::Void => false,
+            Type::Array(_, _)
+          | Type::ArrayView(_)
+          | Type::Struct(_)
+          | Type::Tuple(_)
+          | Type::List(_)
+          | Type::Void => false,
             _ => true,
         }
     }
 
+    /// is this a (non-nullable) object type, which must always
+    /// hold a valid reference?
+    pub fn is_non_null_object(&self) -> bool {
+        match *self {
+            Type::Obj | Type::Object(_) | Type::Map(_, _) => true,
+            _ => false,
+        }
+    }
+
+    /// would VB's default value for this type leave Nothing somewhere
+    /// a non-null object type promises a valid reference?
+    pub fn starts_null(&self) -> bool {
+        match *self {
+            Type::Tuple(ref tys) => tys.iter().any(Type::starts_null),
+            ref ty => ty.is_non_null_object(),
+        }
+    }
+
     /// does this Type describe a numeric type?
     pub fn might_be_numeric(&self) -> bool {
         match *self {
@@ -426,6 +595,7 @@ impl Type {
     pub fn might_be_string(&self) -> bool {
         match *self {
             Type::String
+          | Type::FixedString(_)
           | Type::Variant => true,
             _ => false,
         }
@@ -443,6 +613,16 @@ impl Type {
         }
     }
 
+    /// the width in bits and signedness of a fixed-width integer type
+    pub fn int_width(&self) -> Option<(u32, bool)> {
+        match *self {
+            Type::UInt8 => Some((8, false)),
+            Type::Int16 => Some((16, true)),
+            Type::Int32 => Some((32, true)),
+            _ => None,
+        }
+    }
+
     /// what does this type decay to when passed as a function
     /// argument; we only use this for array types so far
     pub fn decay(&self) -> Type {
@@ -465,6 +645,12 @@ impl fmt::Display for Type {
             Type::Float32 => write!(f, "f32"),
             Type::Float64 => write!(f, "f64"),
             Type::String => write!(f, "str"),
+            Type::FixedString(StaticArrayDim::Lit(ref lit)) =>
+                match lit.int_value() {
+                    Some(len) => write!(f, "fstr<{}>", len),
+                    None => write!(f, "fstr<{}>", lit),
+                },
+            Type::FixedString(ref len) => write!(f, "fstr<{}>", len),
             Type::Currency => write!(f, "currency"),
             Type::Date => write!(f, "date"),
             Type::Variant => write!(f, "var"),
@@ -475,7 +661,39 @@ impl fmt::Display for Type {
             Type::Deferred(ref path) => write!(f, "{}", path),
             Type::Array(ref base, ref bounds) =>
                 write!(f, "{}[{}]", base, bounds),
-            Type::VarArgsArray => write!(f, "..."),
+            Type::Tuple(ref tys) => {
+                f.write_str("(")?;
+                for (i, ty) in tys.iter().enumerate() {
+                    if i != 0 {
+                        f.write_str(", ")?;
+                    }
+                    ty.fmt(f)?;
+                }
+                f.write_str(")")
+            },
+            Type::Nullable(ref base) => write!(f, "{}?", base),
+            Type::FnPtr(ref params, ref ret) => {
+                f.write_str("fn(")?;
+                for (i, &(mode, ref ty)) in params.iter().enumerate() {
+                    if i != 0 {
+                        f.write_str(", ")?;
+                    }
+                    if mode == ParamMode::ByRef {
+                        f.write_str("&")?;
+                    }
+                    ty.fmt(f)?;
+                }
+                f.write_str(")")?;
+                match **ret {
+                    Type::Void => Ok(()),
+                    ref ret => write!(f, " -> {}", ret),
+                }
+            },
+            Type::Map(ref key, ref value) =>
+                write!(f, "map<{}, {}>", key, value),
+            Type::List(ref elem) => write!(f, "list<{}>", elem),
+            Type::VarArgsArray(ref elem) => write!(f, "{}...", elem),
+            Type::ArrayView(ref elem) => write!(f, "{}[..]", elem),
             Type::Void => write!(f, "void"),
         }
     }
@@ -579,9 +797,8 @@ pub enum AssignOp {
     BitAndAssign,
     /// `x |= y`
     BitOrAssign,
-    // nah
-    // /// `x = y`
-    // BitXorAssign,
+    /// `x ^^= y`
+    BitXorAssign,
     /// `x &&= y`
     LogAndAssign,
     /// `x ||= y`
@@ -597,8 +814,15 @@ pub enum UnOp {
     AddressOf, // -> VarPtr, ObjPtr, AddressOf
 }
 
+/// Integer operations which can be checked for overflow
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CheckedOp {
+    Bin(BinOp),
+    Negate,
+}
+
 /// Binary operators
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum BinOp {
     Add,
     Sub,
@@ -617,8 +841,9 @@ pub enum BinOp {
     GtEq,
     BitAnd,
     BitOr,
-    // nah
-    // BitXor,
+    BitXor,
+    Shl,
+    Shr,
     LogAnd,
     LogOr,
 }
@@ -652,13 +877,16 @@ pub enum Literal {
     Currency(i64),
     /// date
     Date(f64),
+    /// integer literal written in hex, octal, or binary
+    ///   (we hang on to the radix so we can emit it the same way)
+    NonDecimal(Radix, Box<Literal>),
     // later: array literals & struct literals & ...
 }
 
 impl Literal {
     pub fn ty(&self) -> Type {
         match *self {
-            Literal::NullPtr => Type::Obj,
+            Literal::NullPtr => Type::Nullable(Box::new(Type::Obj)),
             Literal::NullVar => Type::Variant,
             Literal::EmptyVar => Type::Variant,
             Literal::Bool(_) => Type::Bool,
@@ -671,6 +899,19 @@ impl Literal {
             Literal::String(_) => Type::String,
             Literal::Currency(_) => Type::Currency,
             Literal::Date(_) => Type::Date,
+            Literal::NonDecimal(_, ref lit) => lit.ty(),
+        }
+    }
+
+    /// the value of an integer literal, in any radix
+    pub fn int_value(&self) -> Option<i64> {
+        match *self {
+            Literal::UInt8(i) => Some(i as i64),
+            Literal::Int16(i) => Some(i as i64),
+            Literal::Int32(i) => Some(i as i64),
+            Literal::IntPtr(i) => Some(i),
+            Literal::NonDecimal(_, ref lit) => lit.int_value(),
+            _ => None,
         }
     }
 
@@ -711,10 +952,139 @@ impl fmt::Display for Literal {
             Literal::String(ref s) => unescape_string(&s, f),
             Literal::Currency(c) =>
                 write!(f, "{}.{}currency", c / 10000, c % 10000),
-            Literal::Date(_) =>
-                panic!("dumpster fire: no format for literal dates yet"),
+            Literal::Date(d) => {
+                let dt = DateTime::from_serial(d);
+                write!(f, "#{:04}-{:02}-{:02}", dt.year, dt.month, dt.day)?;
+                if dt.has_time() {
+                    write!(f, " {:02}:{:02}:{:02}",
+                      dt.hour, dt.minute, dt.second)?;
+                }
+                f.write_char('#')
+            },
+            Literal::NonDecimal(radix, ref lit) => {
+                let n = lit.int_value().unwrap();
+                match radix {
+                    Radix::Hex => write!(f, "0x{:X}", n)?,
+                    Radix::Octal => write!(f, "0o{:o}", n)?,
+                    Radix::Binary => write!(f, "0b{:b}", n)?,
+                };
+                f.write_str(match **lit {
+                    Literal::UInt8(_) => "u8",
+                    Literal::Int16(_) => "i16",
+                    Literal::Int32(_) => "i32",
+                    _ => "isize",
+                })
+            },
+        }
+    }
+}
+
+/// the radix of a non-decimal integer literal
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Radix {
+    Hex,
+    Octal,
+    Binary,
+}
+
+/// a broken-down calendar date, as written in date literals.
+///   date literals are stored as VB serial dates (days since 1899-12-30,
+///   with the time of day as the fractional part); this converts
+///   between the two.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct DateTime {
+    pub year: i64,
+    pub month: u32,
+    pub day: u32,
+    pub hour: u32,
+    pub minute: u32,
+    pub second: u32,
+}
+
+impl DateTime {
+    /// is this a date VB can represent?
+    pub fn is_valid(&self) -> bool {
+        let leap = (self.year % 4 == 0 && self.year % 100 != 0)
+          || self.year % 400 == 0;
+
+        let days_in_month = match self.month {
+            1 | 3 | 5 | 7 | 8 | 10 | 12 => 31,
+            4 | 6 | 9 | 11 => 30,
+            2 => if leap { 29 } else { 28 },
+            _ => return false,
+        };
+
+        self.year >= 100 && self.year <= 9999
+          && self.day >= 1 && self.day <= days_in_month
+          && self.hour < 24 && self.minute < 60 && self.second < 60
+    }
+
+    pub fn has_time(&self) -> bool {
+        self.hour != 0 || self.minute != 0 || self.second != 0
+    }
+
+    pub fn to_serial(&self) -> f64 {
+        let days = days_from_civil(self.year, self.month, self.day)
+          - days_from_civil(1899, 12, 30);
+        let secs = self.hour * 3600 + self.minute * 60 + self.second;
+        let frac = secs as f64 / 86400.0;
+
+        // VB stores the time of day as a positive fraction even before
+        //   the epoch, so e.g. -1.25 is 1899-12-29 06:00
+        if days < 0 {
+            days as f64 - frac
+        } else {
+            days as f64 + frac
         }
     }
+
+    pub fn from_serial(serial: f64) -> Self {
+        let days = serial.trunc();
+        let mut secs = ((serial - days).abs() * 86400.0).round() as i64;
+        let mut days = days as i64;
+
+        if secs >= 86400 {
+            secs -= 86400;
+            days += if serial < 0.0 { -1 } else { 1 };
+        }
+
+        let (year, month, day) =
+          civil_from_days(days + days_from_civil(1899, 12, 30));
+
+        DateTime {
+            year,
+            month,
+            day,
+            hour: (secs / 3600) as u32,
+            minute: (secs / 60 % 60) as u32,
+            second: (secs % 60) as u32,
+        }
+    }
+}
+
+// days since 1970-01-01 in the proleptic gregorian calendar
+fn days_from_civil(y: i64, m: u32, d: u32) -> i64 {
+    let y = if m <= 2 { y - 1 } else { y };
+    let era = if y >= 0 { y } else { y - 399 } / 400;
+    let yoe = y - era * 400;
+    let m = m as i64;
+    let doy = (153 * (if m > 2 { m - 3 } else { m + 9 }) + 2) / 5
+      + d as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+fn civil_from_days(z: i64) -> (i64, u32, u32) {
+    let z = z + 719468;
+    let era = if z >= 0 { z } else { z - 146096 } / 146097;
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let m = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let y = yoe + era * 400 + if m <= 2 { 1 } else { 0 };
+    (y, m, d)
 }
 
 fn unescape_string(escaped: &str, f: &mut fmt::Formatter) -> fmt::Result {
@@ -67,6 +67,7 @@ impl Emit<()> for AssignOp {
             AssignOp::StrCatAssign => b" & ",
             AssignOp::BitAndAssign => b" And ",
             AssignOp::BitOrAssign => b" Or ",
+            AssignOp::BitXorAssign => b" Xor ",
             AssignOp::LogAndAssign => b" And ",
             AssignOp::LogOrAssign => b" Or ",
             AssignOp::Assign => panic!("dumpster fire basic assign should \
@@ -97,10 +98,13 @@ impl Emit<()> for BinOp {
             BinOp::GtEq => b" >= ",
             BinOp::BitAnd => b" And ",
             BinOp::BitOr => b" Or ",
+            BinOp::BitXor => b" Xor ",
             BinOp::LogAnd => b" And ",
             BinOp::LogOr => b" Or ",
             BinOp::NotIdentEq => panic!("dumpster fire: BinOp::NotIdentEq \
               should never be emitted directly"),
+            BinOp::Shl | BinOp::Shr => panic!("dumpster fire: shifts \
+              should never be emitted directly"),
         };
         out.write_all(op)
     }
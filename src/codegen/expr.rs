@@ -5,6 +5,8 @@ use std::io::Write;
 
 use ast::*;
 use super::*;
+use super::runtime::RuntimeHelper;
+use super::runtime::is_fn_address;
 
 #[derive(Copy, Clone, Debug)]
 pub enum ExprPos {
@@ -47,7 +49,7 @@ impl Emit<ExprPos> for Expr {
 
                 for (i, arg) in args.iter().enumerate() {
                     if i != 0 { out.write_all(b", ")?; }
-                    arg.emit(out, symtab, ExprPos::Expr, 0)?;
+                    emit_arg(arg, out, symtab)?;
                 }
 
                 if !args.is_empty() && !optargs.is_empty() {
@@ -58,7 +60,7 @@ impl Emit<ExprPos> for Expr {
                     if i != 0 { out.write_all(b", ")?; }
                     name.emit(out, symtab, (), 0)?;
                     out.write_all(b" := ")?;
-                    arg.emit(out, symtab, ExprPos::Expr, 0)?;
+                    emit_arg(arg, out, symtab)?;
                 }
 
                 match ctxt {
@@ -87,7 +89,7 @@ impl Emit<ExprPos> for Expr {
 
                 for (i, arg) in args.iter().enumerate() {
                     if i != 0 { out.write_all(b", ")?; }
-                    arg.emit(out, symtab, ExprPos::Expr, 0)?;
+                    emit_arg(arg, out, symtab)?;
                 }
 
                 match ctxt {
@@ -116,8 +118,13 @@ impl Emit<ExprPos> for Expr {
                             .expect("dumpster fire: untyped expression \
                                     in codegen");
                         match *expr_ty {
-                            Type::Obj | Type::Object(_) =>
+                            Type::Obj | Type::Object(_) | Type::Nullable(_) =>
                                 out.write_all(b"ObjPtr(")?,
+                            Type::FnPtr(_, _) => {
+                                RuntimeHelper::FnPtr.name()
+                                    .emit(out, symtab, (), 0)?;
+                                out.write_all(b"(AddressOf ")?;
+                            },
                             _ => out.write_all(b"VarPtr(")?,
                         };
                         expr.emit(out, symtab, ctxt, 0)?;
@@ -128,6 +135,24 @@ impl Emit<ExprPos> for Expr {
             },
 
             ExprKind::BinOpApp(ref lhs, ref rhs, ref op) => {
+                // no shifts in VB6; call the appropriate runtime helper
+                if let BinOp::Shl | BinOp::Shr = *op {
+                    let lhs_ty = lhs.ty.as_ref()
+                        .expect("dumpster fire: untyped expression \
+                                in codegen");
+                    let helper = match *op {
+                        BinOp::Shl => RuntimeHelper::Shl(lhs_ty.clone()),
+                        _ => RuntimeHelper::Shr(lhs_ty.clone()),
+                    };
+
+                    helper.name().emit(out, symtab, (), indent)?;
+                    out.write_all(b"(")?;
+                    lhs.emit(out, symtab, ExprPos::Expr, 0)?;
+                    out.write_all(b", ")?;
+                    rhs.emit(out, symtab, ExprPos::Expr, 0)?;
+                    return out.write_all(b")");
+                }
+
                 // no infix "IsNot" in VB6; convert to Not (... Is ...)
                 let op = match *op {
                     BinOp::NotIdentEq => {
@@ -154,9 +179,9 @@ impl Emit<ExprPos> for Expr {
                                     in codegen");
 
                         if lhs_ty.is_integral() && rhs_ty.is_integral() {
-                            lhs.emit(out, symtab, ctxt, 0)?;
+                            emit_operand(lhs, out, symtab, ctxt)?;
                             out.write_all(b" \\ ")?;
-                            rhs.emit(out, symtab, ctxt, 0)?;
+                            emit_operand(rhs, out, symtab, ctxt)?;
                             return out.write_all(b")")
                         } else {
                             BinOp::Div // use normal-case code
@@ -170,9 +195,20 @@ impl Emit<ExprPos> for Expr {
                     },
                 };
 
-                lhs.emit(out, symtab, ctxt, 0)?;
-                op.emit(out, symtab, (), 0)?;
-                rhs.emit(out, symtab, ctxt, 0)?;
+                match op {
+                    BinOp::Add | BinOp::Sub | BinOp::Mul | BinOp::Div
+                  | BinOp::Mod => {
+                        emit_operand(lhs, out, symtab, ctxt)?;
+                        op.emit(out, symtab, (), 0)?;
+                        emit_operand(rhs, out, symtab, ctxt)?;
+                    },
+
+                    _ => {
+                        lhs.emit(out, symtab, ctxt, 0)?;
+                        op.emit(out, symtab, (), 0)?;
+                        rhs.emit(out, symtab, ctxt, 0)?;
+                    },
+                }
                 out.write_all(b")")
             },
 
@@ -180,6 +216,14 @@ impl Emit<ExprPos> for Expr {
                 panic!("dumpster fire: raw CondExpr in codegen");
             },
 
+            ExprKind::Tuple(_) => {
+                panic!("dumpster fire: raw Tuple in codegen");
+            },
+
+            ExprKind::Slice(_, _, _) => {
+                panic!("dumpster fire: raw Slice in codegen");
+            },
+
             ExprKind::ExtentExpr(ref expr, kind, dim) => {
                 let expr_ty = expr.ty.as_ref()
                     .expect("dumpster fire: untyped expression \
@@ -187,7 +231,7 @@ impl Emit<ExprPos> for Expr {
 
                 let emit_dim = match *expr_ty {
                     Type::Array(_, ref bounds) => bounds.dims() != 1,
-                    Type::VarArgsArray => false,
+                    Type::VarArgsArray(_) => false,
                     // TODO: maybe allow variants here (checked at runtime)?
                     _ => panic!("dumpster fire: non-array expression \
                       in extent expr"),
@@ -237,6 +281,7 @@ impl Emit<ExprPos> for Expr {
 
             ExprKind::Cast(ref expr, ref ty) => {
                 let cast_op = match *ty {
+                    Type::Bool => "CBool",
                     Type::UInt8 => "CByte",
                     Type::Int16 => "CInt",
                     Type::Int32 => "CLng",
@@ -261,6 +306,57 @@ impl Emit<ExprPos> for Expr {
                 write!(out, "{:in$}", "", in = (indent * INDENT) as usize)?;
                 out.write_all(bytes)
             },
+
+            // VB fills in the subject of a With block for us
+            ExprKind::WithSubject =>
+                write!(out, "{:in$}", "", in = (indent * INDENT) as usize),
+
+            // the runtime helper takes the location to report after the
+            //   operands
+            ExprKind::CheckedArith(op, ref args) => {
+                let ty = self.ty.as_ref()
+                    .expect("dumpster fire: untyped expression in codegen");
+                RuntimeHelper::Checked(op, ty.clone()).name()
+                    .emit(out, symtab, (), indent)?;
+                out.write_all(b"(")?;
+                for arg in args {
+                    arg.emit(out, symtab, ExprPos::Expr, 0)?;
+                    out.write_all(b", ")?;
+                }
+                Literal::String(self.loc.to_string()).emit(out, symtab, (), 0)?;
+                out.write_all(b")")
+            },
         }
     }
 }
+
+// VB allows AddressOf as it is in an argument list, and only there
+fn emit_arg<W: Write>(arg: &Expr, out: &mut W, symtab: &SymbolTable)
+  -> io::Result<()> {
+    match arg.data {
+        ExprKind::UnOpApp(ref f, UnOp::AddressOf) if is_fn_address(arg) => {
+            out.write_all(b"AddressOf ")?;
+            f.emit(out, symtab, ExprPos::Expr, 0)
+        },
+        _ => arg.emit(out, symtab, ExprPos::Expr, 0),
+    }
+}
+
+// VB types a bare literal as Integer when it fits, and does arithmetic on
+//   two Integers in Integer, so e.g. 300 * 200 overflows; our i32 literals
+//   get Long's suffix wherever they're an arithmetic operand
+fn emit_operand<W: Write>(operand: &Expr, out: &mut W, symtab: &SymbolTable,
+  ctxt: ExprPos) -> io::Result<()> {
+    match operand.data {
+        ExprKind::Lit(Literal::Int32(n)) if n as i16 as i32 == n =>
+            write!(out, "{}&", n),
+
+        ExprKind::UnOpApp(ref e, UnOp::Negate) => match e.data {
+            ExprKind::Lit(Literal::Int32(n)) if n as i16 as i32 == n =>
+                write!(out, "-{}&", n),
+            _ => operand.emit(out, symtab, ctxt, 0),
+        },
+
+        _ => operand.emit(out, symtab, ctxt, 0),
+    }
+}
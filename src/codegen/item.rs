@@ -6,6 +6,7 @@ use std::io::Write;
 use ast::*;
 use super::*;
 use super::ty::*;
+use super::expr::*;
 
 impl<'a> Emit<()> for NormalItem {
     fn emit<W: Write>(&self, out: &mut W, symtab: &SymbolTable,
@@ -29,6 +30,7 @@ impl<'a> Emit<()> for NormalItem {
 impl<'a> Emit<()> for FunDef {
     fn emit<W: Write>(&self, out: &mut W, symtab: &SymbolTable,
       _ctxt: (), indent: u32) -> io::Result<()> {
+        write_doc_comments(&self.attrs, out, indent)?;
         self.access.emit(out, symtab, (), indent)?;
 
         let fnsub = match self.ret {
@@ -36,10 +38,25 @@ impl<'a> Emit<()> for FunDef {
             _ => "Function",
         };
 
+        if self.lib.is_some() {
+            out.write_all(b" Declare PtrSafe")?;
+        }
+
         write!(out, " {} ", fnsub)?;
 
         self.name.emit(out, symtab, (), 0)?;
 
+        // a renamed extern fn still has to find its original entry point
+        if let Some(ref lib) = self.lib {
+            out.write_all(b" Lib ")?;
+            Literal::String(lib.clone()).emit(out, symtab, (), 0)?;
+            if let Some(ref orig) = self.name.1 {
+                out.write_all(b" Alias ")?;
+                Literal::String(orig.clone()).emit(out, symtab, (), 0)?;
+            }
+            out.write_all(b" ")?;
+        }
+
         out.write_all(b"(")?;
 
         // regular params
@@ -64,11 +81,11 @@ impl<'a> Emit<()> for FunDef {
                     out.write_all(b"Optional ")?;
                     p.emit(out, symtab, (), 0)?;
                     out.write_all(b" = ")?;
-                    default.emit(out, symtab, (), 0)?;
+                    default.emit(out, symtab, ExprPos::Expr, 0)?;
                 }
             },
 
-            Some(FunOptParams::VarArgs(ref name, _)) => {
+            Some(FunOptParams::VarArgs(ref name, _, _)) => {
                 out.write_all(b"ParamArray ")?;
                 name.emit(out, symtab, (), 0)?;
                 out.write_all(b"() As Variant")?;
@@ -86,6 +103,13 @@ impl<'a> Emit<()> for FunDef {
 
         out.write_all(b"\n")?;
 
+        if self.lib.is_some() {
+            return Ok(());
+        }
+
+        write_item_attributes(&self.name, &self.attrs, false, out, symtab,
+          indent)?;
+
         for stmt in self.body.iter() {
             stmt.emit(out, symtab, &self, indent + 1)?;
         }
@@ -108,6 +132,7 @@ impl Emit<()> for FunParam {
 impl<'a> Emit<()> for StructDef {
     fn emit<W: Write>(&self, out: &mut W, symtab: &SymbolTable,
       _ctxt: (), indent: u32) -> io::Result<()> {
+        write_doc_comments(&self.attrs, out, indent)?;
         self.access.emit(out, symtab, (), indent)?;
         out.write_all(b" Type ")?;
         self.name.emit(out, symtab, (), 0)?;
@@ -133,6 +158,7 @@ impl Emit<()> for StructMem {
 impl<'a> Emit<()> for Static {
     fn emit<W: Write>(&self, out: &mut W, symtab: &SymbolTable,
       _ctxt: (), indent: u32) -> io::Result<()> {
+        write_doc_comments(&self.attrs, out, indent)?;
         self.access.emit(out, symtab, (), indent)?;
         out.write_all(b" ")?;
         self.name.emit(out, symtab, (), 0)?;
@@ -141,19 +167,71 @@ impl<'a> Emit<()> for Static {
             panic!("dumpster fire: we can't emit static initializers \
                      (lazy statics) yet");
         }
-        out.write_all(b"\n")
+        out.write_all(b"\n")?;
+        write_item_attributes(&self.name, &self.attrs, true, out, symtab,
+          indent)
     }
 }
 
 impl<'a> Emit<()> for Constant {
     fn emit<W: Write>(&self, out: &mut W, symtab: &SymbolTable,
       _ctxt: (), indent: u32) -> io::Result<()> {
+        write_doc_comments(&self.attrs, out, indent)?;
         self.access.emit(out, symtab, (), indent)?;
         out.write_all(b" Const ")?;
         self.name.emit(out, symtab, (), 0)?;
         self.ty.emit(out, symtab, TypePos::Decl, 0)?;
         out.write_all(b" = ")?;
-        self.value.emit(out, symtab, (), 0)?;
+        self.value.emit(out, symtab, ExprPos::Expr, 0)?;
         out.write_all(b"\n")
     }
 }
+
+// hidden VB attribute lines go just after the procedure header (or the
+//   variable declaration); typecheck has already rejected any attributes
+//   which aren't legal where they're attached
+fn write_item_attributes<W: Write>(name: &Ident, attrs: &[Attribute],
+  is_var: bool, out: &mut W, symtab: &SymbolTable, indent: u32)
+  -> io::Result<()> {
+    if let Some(desc) = description(attrs) {
+        write!(out, "{:in$}Attribute ", "", in = (indent * INDENT) as usize)?;
+        name.emit(out, symtab, (), 0)?;
+        out.write_all(if is_var {
+            b".VB_VarDescription = "
+        } else {
+            b".VB_Description = "
+        })?;
+        desc.emit(out, symtab, (), 0)?;
+        out.write_all(b"\n")?;
+    }
+
+    for attr in attrs {
+        let mem_id = match attr.name.0.as_str() {
+            "default_member" => "0",
+            "enumerator" => "-4",
+            _ => continue,
+        };
+
+        write!(out, "{:in$}Attribute ", "", in = (indent * INDENT) as usize)?;
+        name.emit(out, symtab, (), 0)?;
+        write!(out, ".VB_UserMemId = {}\n", mem_id)?;
+    }
+
+    Ok(())
+}
+
+/// Doc comments are carried over as ordinary VB comments
+pub fn write_doc_comments<W: Write>(attrs: &[Attribute], out: &mut W,
+  indent: u32) -> io::Result<()> {
+    for attr in attrs.iter().filter(|a| a.name.0 == "doc") {
+        if let Some(&Literal::String(ref text)) = attr.lit_arg() {
+            write!(out, "{:in$}'", "", in = (indent * INDENT) as usize)?;
+            if !text.is_empty() {
+                write!(out, " {}", text)?;
+            }
+            out.write_all(b"\n")?;
+        }
+    }
+
+    Ok(())
+}
@@ -23,8 +23,8 @@ impl Emit<()> for Literal {
             Literal::Float64(n) => format!("{}#", n),
             Literal::Currency(n) => format!("{}@", currency_string(n)),
             Literal::String(ref s) => vb_string(s),
-            // TODO: handle wacky types
-            _ => panic!("dumpster fire: don't know how to emit that yet"),
+            Literal::Date(d) => vb_date(d),
+            Literal::NonDecimal(radix, ref lit) => vb_radix_int(radix, lit),
         };
 
         write!(out, "{:in$}{}", "", as_str, in = (indent * INDENT) as usize)
@@ -38,6 +38,44 @@ fn currency_string(currency: i64) -> String {
     format!("{}.{:04}", whole, frac)
 }
 
+// VB has no binary literals, so those go out as hex; we always emit a type
+//   suffix so e.g. &HFFFF stays 65535 rather than becoming an Integer -1
+fn vb_radix_int(radix: Radix, lit: &Literal) -> String {
+    let n = lit.int_value()
+        .expect("dumpster fire: non-integer non-decimal literal");
+
+    let suffix = match *lit {
+        Literal::UInt8(_) => "",
+        Literal::Int16(_) => "%",
+        Literal::Int32(_) => "&",
+        // LongPtr literals only exist on 64-bit hosts
+        Literal::IntPtr(n) if n <= i32::MAX as i64 => "&",
+        Literal::IntPtr(_) => "^",
+        _ => panic!("dumpster fire: non-integer non-decimal literal"),
+    };
+
+    match radix {
+        Radix::Octal => format!("&O{:o}{}", n, suffix),
+        Radix::Hex | Radix::Binary => format!("&H{:X}{}", n, suffix),
+    }
+}
+
+// VB wants US-style #m/d/yyyy h:mm:ss AM/PM#
+fn vb_date(serial: f64) -> String {
+    let dt = DateTime::from_serial(serial);
+    let mut out = format!("#{}/{}/{}", dt.month, dt.day, dt.year);
+    if dt.has_time() {
+        let hour12 = match dt.hour % 12 {
+            0 => 12,
+            h => h,
+        };
+        out.push_str(&format!(" {}:{:02}:{:02} {}", hour12, dt.minute,
+          dt.second, if dt.hour < 12 { "AM" } else { "PM" }));
+    }
+    out.push('#');
+    out
+}
+
 fn vb_string(s: &String) -> String {
     let mut out = String::from("\"");
     for c in s.chars() {
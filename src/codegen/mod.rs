@@ -20,3 +20,4 @@ mod expr;
 mod ty;
 mod lit;
 mod bits;
+pub mod runtime;
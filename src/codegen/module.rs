@@ -6,29 +6,60 @@ use std::io::Write;
 use ast::*;
 use super::*;
 use analysis::SymbolTable;
+use super::runtime::*;
+use super::item::write_doc_comments;
 
 impl Emit<()> for Module {
     fn emit<W: Write>(&self, out: &mut W, symtab: &SymbolTable,
       _ctxt: (), indent: u32) -> io::Result<()> {
         match self.data {
             ModuleKind::Normal(ref items) => {
-                write_normal_header(&self.name, out, symtab, indent)?;
+                write_normal_header(&self.name, &self.attrs, out, symtab,
+                  indent)?;
                 for (i, item) in items.iter().enumerate() {
                     if i != 0 {
                         out.write_all(b"\n")?;
                     }
                     item.emit(out, symtab, (), indent)?;
                 }
+
+                for helper in runtime_helpers(self) {
+                    out.write_all(b"\n")?;
+                    helper.emit(out, symtab, (), indent)?;
+                }
+
                 Ok(())
             },
         }
     }
 }
 
-fn write_normal_header<W: Write>(name: &Ident, out: &mut W,
-  symtab: &SymbolTable, indent: u32) -> io::Result<()> {
+fn write_normal_header<W: Write>(name: &Ident, attrs: &[Attribute],
+  out: &mut W, symtab: &SymbolTable, indent: u32) -> io::Result<()> {
     write!(out, "{:in$}Attribute VB_Name = \"", "",
       in = (indent * INDENT) as usize)?;
     name.emit(out, symtab, (), 0)?; 
-    out.write_all(b"\"\nOption Explicit\n\n")
+    out.write_all(b"\"\n")?;
+
+    // module-level attributes go right after VB_Name
+    if let Some(desc) = description(attrs) {
+        write!(out, "{:in$}Attribute VB_Description = ", "",
+          in = (indent * INDENT) as usize)?;
+        desc.emit(out, symtab, (), 0)?;
+        out.write_all(b"\n")?;
+    }
+
+    for attr in attrs {
+        let attr_name = match attr.name.0.as_str() {
+            "predeclared_id" => "VB_PredeclaredId",
+            "exposed" => "VB_Exposed",
+            _ => continue,
+        };
+
+        write!(out, "{:in$}Attribute {} = True\n", "", attr_name,
+          in = (indent * INDENT) as usize)?;
+    }
+
+    write_doc_comments(attrs, out, indent)?;
+    out.write_all(b"Option Explicit\n\n")
 }
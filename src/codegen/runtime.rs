@@ -0,0 +1,307 @@
+//! runtime support functions for generated VB
+
+use std::io;
+use std::io::Write;
+
+use ast::*;
+use super::*;
+use visit::ASTVisitor;
+
+/// a runtime support function; these are emitted (privately) at the end of
+///   each module which uses them
+#[derive(Clone, Debug, PartialEq)]
+pub enum RuntimeHelper {
+    /// wrapping left shift at the width of a fixed-width integer type
+    Shl(Type),
+    /// right shift (arithmetic for signed types) at the width of a
+    ///   fixed-width integer type
+    Shr(Type),
+    /// integer arithmetic which reports the source location of overflow
+    ///   (or division by zero) errors
+    Checked(CheckedOp, Type),
+    /// pass a function address through unchanged (VB only allows AddressOf
+    ///   directly in an argument list; arguments don't need this)
+    FnPtr,
+    /// raise the error for a failed assertion
+    AssertFail,
+}
+
+impl RuntimeHelper {
+    /// our names can't collide with user code; they're like gensyms
+    pub fn name(&self) -> Ident {
+        let name = match *self {
+            RuntimeHelper::Shl(ref ty) => format!("øshl_{}", ty),
+            RuntimeHelper::Shr(ref ty) => format!("øshr_{}", ty),
+            RuntimeHelper::Checked(op, ref ty) => format!("øchk_{}_{}",
+              checked_op_name(op), ty),
+            RuntimeHelper::FnPtr => String::from("øfnptr"),
+            RuntimeHelper::AssertFail => String::from("øassert_fail"),
+        };
+        Ident(name, None)
+    }
+
+    /// is there a checked-arithmetic helper for an operation at a given type?
+    pub fn checkable(op: CheckedOp, ty: &Type) -> bool {
+        match op {
+            CheckedOp::Bin(BinOp::Add)
+          | CheckedOp::Bin(BinOp::Sub)
+          | CheckedOp::Bin(BinOp::Mul)
+          | CheckedOp::Bin(BinOp::Div)
+          | CheckedOp::Bin(BinOp::Mod)
+          | CheckedOp::Negate => { },
+            _ => return false,
+        };
+
+        match *ty {
+            Type::UInt8
+          | Type::Int16
+          | Type::Int32 => true,
+            _ => false,
+        }
+    }
+}
+
+/// is this a function's address (which VB lets us write as a bare AddressOf
+///   when it's passed straight to a call)?
+pub fn is_fn_address(expr: &Expr) -> bool {
+    match (&expr.data, &expr.ty) {
+        (&ExprKind::UnOpApp(_, UnOp::AddressOf), &Some(Type::FnPtr(_, _))) =>
+            true,
+        _ => false,
+    }
+}
+
+/// find the runtime helpers needed by a module, in order of first use
+pub fn runtime_helpers(module: &Module) -> Vec<RuntimeHelper> {
+    let mut v = RuntimeHelperVisitor {
+        helpers: Vec::new(),
+    };
+    v.visit_module(module);
+    v.helpers
+}
+
+struct RuntimeHelperVisitor {
+    helpers: Vec<RuntimeHelper>,
+}
+
+impl RuntimeHelperVisitor {
+    fn require(&mut self, helper: RuntimeHelper) {
+        if !self.helpers.contains(&helper) {
+            self.helpers.push(helper);
+        }
+    }
+
+    fn visit_arg(&mut self, arg: &Expr, module: &Ident,
+      function: Option<&Ident>) {
+        if !is_fn_address(arg) {
+            self.visit_expr(arg, module, function);
+        }
+    }
+}
+
+impl ASTVisitor for RuntimeHelperVisitor {
+    fn visit_expr(&mut self, expr: &Expr, module: &Ident,
+      function: Option<&Ident>) {
+        match expr.data {
+            ExprKind::Call(_, ref args, ref optargs) => {
+                for arg in args.iter().chain(optargs.iter().map(|a| &a.1)) {
+                    self.visit_arg(arg, module, function);
+                }
+            },
+
+            ExprKind::MemberInvoke(ref obj, _, ref args) => {
+                self.visit_expr(obj, module, function);
+                for arg in args {
+                    self.visit_arg(arg, module, function);
+                }
+            },
+
+            _ => self.walk_expr(expr, module, function),
+        }
+
+        match expr.data {
+            ExprKind::BinOpApp(ref lhs, _, op) => {
+                let lhs_ty = lhs.ty.as_ref()
+                    .expect("dumpster fire: untyped expression in codegen");
+
+                match op {
+                    BinOp::Shl =>
+                        self.require(RuntimeHelper::Shl(lhs_ty.clone())),
+                    BinOp::Shr =>
+                        self.require(RuntimeHelper::Shr(lhs_ty.clone())),
+                    _ => { },
+                }
+            },
+
+            ExprKind::UnOpApp(_, UnOp::AddressOf) => {
+                if let Some(Type::FnPtr(_, _)) = expr.ty {
+                    self.require(RuntimeHelper::FnPtr);
+                }
+            },
+
+            ExprKind::CheckedArith(op, _) => {
+                let ty = expr.ty.as_ref()
+                    .expect("dumpster fire: untyped expression in codegen");
+                self.require(RuntimeHelper::Checked(op, ty.clone()));
+            },
+
+            ExprKind::Call(Path(None, ref name), _, _)
+              if *name == RuntimeHelper::AssertFail.name() =>
+                self.require(RuntimeHelper::AssertFail),
+
+            _ => { },
+        }
+    }
+}
+
+impl Emit<()> for RuntimeHelper {
+    fn emit<W: Write>(&self, out: &mut W, symtab: &SymbolTable,
+      _ctxt: (), indent: u32) -> io::Result<()> {
+        let name = self.name();
+
+        match *self {
+            // VB has no shifts, and overflows rather than wrapping, so
+            //   we do the arithmetic in doubles (exact for these widths)
+            //   and then wrap it back into range
+            RuntimeHelper::Shl(ref ty) => {
+                let (vbty, bits, signed) = int_width(ty);
+                let modulus = 2f64.powi(bits as i32);
+
+                write!(out, "{:in$}Private Function ", "",
+                  in = (indent * INDENT) as usize)?;
+                name.emit(out, symtab, (), 0)?;
+                write!(out, "(ByVal x As {}, ByVal n As Long) As {}\n",
+                  vbty, vbty)?;
+
+                write!(out, "{:in$}Dim v As Double\n", "",
+                  in = ((indent + 1) * INDENT) as usize)?;
+                write!(out, "{:in$}v = x * 2 ^ (n And {})\n", "", bits - 1,
+                  in = ((indent + 1) * INDENT) as usize)?;
+                write!(out, "{:in$}v = v - Int(v / {}#) * {}#\n", "",
+                  modulus, modulus, in = ((indent + 1) * INDENT) as usize)?;
+                if signed {
+                    write!(out, "{:in$}If v >= {}# Then v = v - {}#\n", "",
+                      modulus / 2.0, modulus,
+                      in = ((indent + 1) * INDENT) as usize)?;
+                }
+                write!(out, "{:in$}", "",
+                  in = ((indent + 1) * INDENT) as usize)?;
+                name.emit(out, symtab, (), 0)?;
+                out.write_all(b" = v\n")?;
+            },
+
+            // for signed types, flooring division gives us
+            //   sign extension for free
+            RuntimeHelper::Shr(ref ty) => {
+                let (vbty, bits, _) = int_width(ty);
+
+                write!(out, "{:in$}Private Function ", "",
+                  in = (indent * INDENT) as usize)?;
+                name.emit(out, symtab, (), 0)?;
+                write!(out, "(ByVal x As {}, ByVal n As Long) As {}\n",
+                  vbty, vbty)?;
+
+                write!(out, "{:in$}", "",
+                  in = ((indent + 1) * INDENT) as usize)?;
+                name.emit(out, symtab, (), 0)?;
+                write!(out, " = Int(x / 2 ^ (n And {}))\n", bits - 1)?;
+            },
+
+            // trap the error so we can re-raise it with the location
+            //   attached
+            RuntimeHelper::Checked(op, ref ty) => {
+                let (vbty, _, _) = int_width(ty);
+                let (params, value) = match op {
+                    CheckedOp::Negate => (format!("ByVal x As {}", vbty),
+                      String::from("-x")),
+                    CheckedOp::Bin(op) => {
+                        let vbop = match op {
+                            BinOp::Add => "+",
+                            BinOp::Sub => "-",
+                            BinOp::Mul => "*",
+                            BinOp::Div => "\\",
+                            BinOp::Mod => "Mod",
+                            _ => panic!("dumpster fire: bad checked \
+                              operation in codegen"),
+                        };
+                        (format!("ByVal x As {}, ByVal y As {}", vbty, vbty),
+                          format!("x {} y", vbop))
+                    },
+                };
+
+                write!(out, "{:in$}Private Function ", "",
+                  in = (indent * INDENT) as usize)?;
+                name.emit(out, symtab, (), 0)?;
+                write!(out, "({}, ByVal loc As String) As {}\n", params,
+                  vbty)?;
+
+                write!(out, "{:in$}On Error GoTo fail\n", "",
+                  in = ((indent + 1) * INDENT) as usize)?;
+                write!(out, "{:in$}", "",
+                  in = ((indent + 1) * INDENT) as usize)?;
+                name.emit(out, symtab, (), 0)?;
+                write!(out, " = {}\n", value)?;
+                write!(out, "{:in$}Exit Function\n", "",
+                  in = ((indent + 1) * INDENT) as usize)?;
+                write!(out, "{:in$}fail:\n", "",
+                  in = (indent * INDENT) as usize)?;
+                write!(out, "{:in$}Err.Raise Err.Number, loc, \
+                  Err.Description & \" @ \" & loc\n", "",
+                  in = ((indent + 1) * INDENT) as usize)?;
+            },
+
+            RuntimeHelper::FnPtr => {
+                write!(out, "{:in$}Private Function ", "",
+                  in = (indent * INDENT) as usize)?;
+                name.emit(out, symtab, (), 0)?;
+                out.write_all(b"(ByVal p As LongPtr) As LongPtr\n")?;
+                write!(out, "{:in$}", "",
+                  in = ((indent + 1) * INDENT) as usize)?;
+                name.emit(out, symtab, (), 0)?;
+                out.write_all(b" = p\n")?;
+            },
+
+            // (in the user-defined range of error numbers)
+            RuntimeHelper::AssertFail => {
+                write!(out, "{:in$}Private Sub ", "",
+                  in = (indent * INDENT) as usize)?;
+                name.emit(out, symtab, (), 0)?;
+                out.write_all(b"(ByVal loc As String, ByVal msg As String)\n")?;
+                write!(out, "{:in$}Err.Raise vbObjectError + 513, loc, msg\n",
+                  "", in = ((indent + 1) * INDENT) as usize)?;
+                return write!(out, "{:in$}End Sub\n", "",
+                  in = (indent * INDENT) as usize);
+            },
+        };
+
+        write!(out, "{:in$}End Function\n", "",
+          in = (indent * INDENT) as usize)
+    }
+}
+
+fn checked_op_name(op: CheckedOp) -> &'static str {
+    match op {
+        CheckedOp::Bin(BinOp::Add) => "add",
+        CheckedOp::Bin(BinOp::Sub) => "sub",
+        CheckedOp::Bin(BinOp::Mul) => "mul",
+        CheckedOp::Bin(BinOp::Div) => "div",
+        CheckedOp::Bin(BinOp::Mod) => "mod",
+        CheckedOp::Negate => "neg",
+        _ => panic!("dumpster fire: bad checked operation"),
+    }
+}
+
+// VB type name, width in bits, and signedness
+fn int_width(ty: &Type) -> (&'static str, u32, bool) {
+    let vbty = match *ty {
+        Type::UInt8 => "Byte",
+        Type::Int16 => "Integer",
+        _ => "Long",
+    };
+
+    match ty.int_width() {
+        Some((bits, signed)) => (vbty, bits, signed),
+        None => panic!("dumpster fire: fixed-width operation on type {} \
+          in codegen", ty),
+    }
+}
@@ -127,6 +127,14 @@ impl<'a> Emit<&'a FunDef> for Stmt {
                 out.write_all(b"\n")
             },
 
+            // asserts which survive to codegen are debug-only
+            StmtKind::Assert(ref cond, _, _) => {
+                write!(out, "{:in$}Debug.Assert ", "",
+                  in = (indent * INDENT) as usize)?;
+                cond.emit(out, symtab, ExprPos::Expr, 0)?;
+                out.write_all(b"\n")
+            },
+
             StmtKind::Print(ref exprs) => {
                 write!(out, "{:in$}Debug.Print ", "",
                   in = (indent * INDENT) as usize)?;
@@ -185,6 +193,20 @@ impl<'a> Emit<&'a FunDef> for Stmt {
                   in = (indent * INDENT) as usize)
             },
 
+            StmtKind::With(ref subject, ref body) => {
+                write!(out, "{:in$}With ", "",
+                  in = (indent * INDENT) as usize)?;
+                subject.emit(out, symtab, ExprPos::Expr, 0)?;
+                out.write_all(b"\n")?;
+
+                for stmt in body {
+                    stmt.emit(out, symtab, ctxt, indent + 1)?;
+                }
+
+                write!(out, "{:in$}End With\n", "",
+                  in = (indent * INDENT) as usize)
+            },
+
             StmtKind::ForLoop { ref var, ref spec, ref body } => {
                 let vardecl = Stmt {
                     data: StmtKind::VarDecl(
@@ -213,7 +235,10 @@ impl<'a> Emit<&'a FunDef> for Stmt {
                         out.write_all(b"\n")?;
                     },
 
-                    ForSpec::Each(ref expr) => {
+                    ForSpec::Each(_, ref indices) if !indices.is_empty() =>
+                        panic!("dumpster fire: indexed for-each in codegen"),
+
+                    ForSpec::Each(ref expr, _) => {
                         out.write_all(b"Each ")?;
                         var.0.emit(out, symtab, (), 0)?;
                         out.write_all(b" In ")?;
@@ -235,8 +260,120 @@ impl<'a> Emit<&'a FunDef> for Stmt {
             StmtKind::ForAlong { .. } => {
                 panic!("dumpster fire: raw ForAlong in codegen");
             },
+
+            StmtKind::Destructure(..) => {
+                panic!("dumpster fire: raw Destructure in codegen");
+            },
+
+            StmtKind::VbBlock(ref code, ref reads, ref writes) => {
+                let paths: Vec<_> = reads.iter().chain(writes.iter())
+                    .collect();
+                emit_vb_block(out, code, &paths, symtab, indent)
+            },
+        }
+    }
+}
+
+// raw VB goes out a line at a time at our indentation (less whatever
+//   indentation its lines share), with any declared locals we've renamed
+//   replaced by their new names
+fn emit_vb_block<W: Write>(out: &mut W, code: &[u8], paths: &[&Path],
+  symtab: &SymbolTable, indent: u32) -> io::Result<()> {
+    let mut renames = Vec::new();
+    for path in paths {
+        if let Some(ref orig) = (path.1).1 {
+            let mut name = Vec::new();
+            path.1.emit(&mut name, symtab, (), 0)?;
+            renames.push((orig.as_bytes(), name));
         }
     }
+
+    let is_blank = |line: &[u8]| line.iter().all(|b| b.is_ascii_whitespace());
+    let lines: Vec<&[u8]> = code.split(|&b| b == b'\n')
+        .skip_while(|line| is_blank(line))
+        .collect();
+    let len = lines.iter().rposition(|line| !is_blank(line))
+        .map_or(0, |last| last + 1);
+    let lines = &lines[..len];
+
+    let margin = lines.iter()
+        .filter(|line| !is_blank(line))
+        .map(|line| line.iter().take_while(|b| **b == b' ' || **b == b'\t')
+          .count())
+        .min()
+        .unwrap_or(0);
+
+    for line in lines {
+        if is_blank(line) {
+            out.write_all(b"\n")?;
+            continue;
+        }
+
+        let line = &line[margin..];
+        let line = match line.last() {
+            Some(&b'\r') => &line[..line.len() - 1],
+            _ => line,
+        };
+        write!(out, "{:in$}", "", in = (indent * INDENT) as usize)?;
+        out.write_all(&substitute_idents(line, &renames))?;
+        out.write_all(b"\n")?;
+    }
+
+    Ok(())
+}
+
+// replace whole identifiers (outside string literals, comments, and member
+//   accesses)
+fn substitute_idents(line: &[u8], renames: &[(&[u8], Vec<u8>)]) -> Vec<u8> {
+    let is_ident = |b: u8| b.is_ascii_alphanumeric() || b == b'_';
+
+    let mut result = Vec::new();
+    let mut in_str = false;
+    let mut i = 0;
+    while i < line.len() {
+        let b = line[i];
+
+        if in_str {
+            in_str = b != b'"';
+            result.push(b);
+            i += 1;
+            continue;
+        }
+
+        match b {
+            b'"' => {
+                in_str = true;
+                result.push(b);
+                i += 1;
+            },
+
+            b'\'' => {
+                result.extend_from_slice(&line[i..]);
+                break;
+            },
+
+            b if is_ident(b) => {
+                let start = i;
+                while i < line.len() && is_ident(line[i]) {
+                    i += 1;
+                }
+
+                let word = &line[start..i];
+                let member = start > 0 && line[start - 1] == b'.';
+                match renames.iter().find(|r| r.0 == word) {
+                    Some(r) if !member => result.extend_from_slice(&r.1),
+                    _ => result.extend_from_slice(word),
+                }
+            },
+
+            b => {
+                result.push(b);
+                i += 1;
+            },
+        }
+    }
+
+    result
 }
 
 fn emit_decl<'a, W: Write>(out: &mut W, decl: &(Ident, Type, Option<Expr>),
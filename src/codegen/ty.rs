@@ -61,16 +61,30 @@ fn emit_basename<W: Write>(out: &mut W, symtab: &SymbolTable, ty: &Type)
         &Type::Float32 => out.write_all(b"Single"),
         &Type::Float64 => out.write_all(b"Double"),
         &Type::String => out.write_all(b"String"),
+        &Type::FixedString(ref len) => {
+            out.write_all(b"String * ")?;
+            emit_static_dim(out, len, symtab, false)
+        },
         &Type::Currency => out.write_all(b"Currency"),
         &Type::Date => out.write_all(b"Date"),
         &Type::Variant => out.write_all(b"Variant"),
         &Type::Obj => out.write_all(b"Object"),
         &Type::Array(ref basety, _) => emit_basename(out, symtab, basety),
-        &Type::VarArgsArray => out.write_all(b"Variant"),
+        &Type::VarArgsArray(_) => out.write_all(b"Variant"),
         &Type::Object(ref path) => path.emit(out, symtab, (), 0),
         &Type::Struct(ref path) => path.emit(out, symtab, (), 0),
         &Type::Enum(ref path) => path.emit(out, symtab, (), 0),
         &Type::Deferred(ref path) => path.emit(out, symtab, (), 0),
+        &Type::Nullable(ref basety) => emit_basename(out, symtab, basety),
+        &Type::FnPtr(_, _) => out.write_all(b"LongPtr"),
+        &Type::Tuple(_) =>
+            panic!("dumpster fire: untransformed tuple type in codegen"),
+        &Type::ArrayView(_) =>
+            panic!("dumpster fire: untransformed array view type in codegen"),
+        &Type::Map(_, _) =>
+            panic!("dumpster fire: untransformed map type in codegen"),
+        &Type::List(_) =>
+            panic!("dumpster fire: untransformed list type in codegen"),
         &Type::Void =>
             panic!("dumpster fire: tried to emit void type"),
     }
@@ -117,6 +131,11 @@ fn emit_static_dim<W: Write>(out: &mut W, dim: &StaticArrayDim,
                     write!(out, "{}", if subtract_one { i - 1 } else { i }),
                 Literal::Int32(i) =>
                     write!(out, "{}", if subtract_one { i - 1 } else { i }),
+                Literal::NonDecimal(_, ref lit)
+                  if lit.ty() != Type::IntPtr => {
+                    let i = lit.int_value().unwrap();
+                    write!(out, "{}", if subtract_one { i - 1 } else { i })
+                },
                 _ => panic!("dumpster fire: invalid literal type in static \
                   array bound during codegen"),
             }?;
@@ -45,13 +45,13 @@ pub trait ASTFolder {
         noop_fold_optparams(self, optparams, module, function)
     }
 
-    fn fold_optparam_list(&mut self, params: Vec<(FunParam, Literal)>,
-      module: &Ident, function: &Ident) -> Vec<(FunParam, Literal)> {
+    fn fold_optparam_list(&mut self, params: Vec<(FunParam, Expr)>,
+      module: &Ident, function: &Ident) -> Vec<(FunParam, Expr)> {
         noop_fold_optparam_list(self, params, module, function)
     }
 
-    fn fold_optparam(&mut self, param: (FunParam, Literal), module: &Ident,
-      function: &Ident) -> (FunParam, Literal) {
+    fn fold_optparam(&mut self, param: (FunParam, Expr), module: &Ident,
+      function: &Ident) -> (FunParam, Expr) {
         noop_fold_optparam(self, param, module, function)
     }
 
@@ -77,6 +77,15 @@ pub trait ASTFolder {
         noop_fold_constant(self, c, module)
     }
 
+    fn fold_attribute_list(&mut self, attrs: Vec<Attribute>)
+      -> Vec<Attribute> {
+        noop_fold_attribute_list(self, attrs)
+    }
+
+    fn fold_attribute(&mut self, attr: Attribute) -> Attribute {
+        noop_fold_attribute(self, attr)
+    }
+
     fn fold_stmt_list(&mut self, stmts: Vec<Stmt>, module: &Ident,
       function: &Ident) -> Vec<Stmt> {
         noop_fold_stmt_list(self, stmts, module, function)
@@ -158,17 +167,19 @@ pub fn noop_fold_dumpster<F: ASTFolder + ?Sized>(folder: &mut F,
 }
 
 pub fn noop_fold_module<F: ASTFolder + ?Sized>(folder: &mut F,
-  Module { name, data, loc }: Module) -> Module {
+  Module { name, data, attrs, loc }: Module) -> Module {
     let name = folder.fold_ident(name, NameCtxt::DefModule, &loc);
     let data = match data {
         ModuleKind::Normal(items) =>
             ModuleKind::Normal(folder.fold_normal_item_list(items, &name)),
     };
+    let attrs = folder.fold_attribute_list(attrs);
     let loc = folder.fold_srcloc(loc);
 
     Module {
         name,
         data,
+        attrs,
         loc,
     }
 }
@@ -193,7 +204,8 @@ pub fn noop_fold_normal_item<F: ASTFolder + ?Sized>(folder: &mut F,
 }
 
 pub fn noop_fold_fundef<F: ASTFolder + ?Sized>(folder: &mut F,
-  FunDef { name, access, params, optparams, ret, body, loc }: FunDef,
+  FunDef { name, access, params, optparams, ret, body, lib, attrs, loc }
+    : FunDef,
   module: &Ident)
   -> FunDef {
     let name = folder.fold_ident(name, NameCtxt::DefFunction(module), &loc);
@@ -202,6 +214,7 @@ pub fn noop_fold_fundef<F: ASTFolder + ?Sized>(folder: &mut F,
     let optparams = optparams.map(|o| folder.fold_optparams(o, module, &name));
     let ret = folder.fold_type(ret, module, &loc);
     let body = folder.fold_stmt_list(body, module, &name);
+    let attrs = folder.fold_attribute_list(attrs);
     let loc = folder.fold_srcloc(loc);
 
     FunDef {
@@ -211,6 +224,8 @@ pub fn noop_fold_fundef<F: ASTFolder + ?Sized>(folder: &mut F,
         access,
         ret,
         body,
+        lib,
+        attrs,
         loc,
     }
 }
@@ -240,17 +255,19 @@ pub fn noop_fold_funparam<F: ASTFolder + ?Sized>(folder: &mut F,
 pub fn noop_fold_optparams<F: ASTFolder + ?Sized>(folder: &mut F,
   optparams: FunOptParams, module: &Ident, function: &Ident) -> FunOptParams {
     match optparams {
-        FunOptParams::VarArgs(name, loc) => {
+        FunOptParams::VarArgs(name, ty, loc) => {
             let loc = folder.fold_srcloc(loc);
+            let ty = folder.fold_type(ty, module, &loc);
             FunOptParams::VarArgs(
                 folder.fold_ident(name,
                   NameCtxt::DefParam(
                       module,
                       function,
-                      &Type::VarArgsArray,
+                      &Type::VarArgsArray(Box::new(ty.clone())),
                       ParamMode::ByRef
                   ),
                   &loc),
+                  ty,
                   loc
             )
         },
@@ -262,31 +279,33 @@ pub fn noop_fold_optparams<F: ASTFolder + ?Sized>(folder: &mut F,
 }
 
 pub fn noop_fold_optparam_list<F: ASTFolder + ?Sized>(folder: &mut F,
-  params : Vec<(FunParam, Literal)>, module: &Ident, function: &Ident)
-  -> Vec<(FunParam, Literal)> {
+  params : Vec<(FunParam, Expr)>, module: &Ident, function: &Ident)
+  -> Vec<(FunParam, Expr)> {
     params.into_iter().map(|p| folder.fold_optparam(p, module, function))
         .collect()
 }
 
 pub fn noop_fold_optparam<F: ASTFolder + ?Sized>(folder: &mut F,
-  (param, default) : (FunParam, Literal), module: &Ident, function: &Ident)
-  -> (FunParam, Literal) {
+  (param, default) : (FunParam, Expr), module: &Ident, function: &Ident)
+  -> (FunParam, Expr) {
     let param = folder.fold_funparam(param, module, function);
-    let default = folder.fold_literal(default, module,
-      Some(function), &param.loc);
+    // defaults are constant expressions, so they live at module scope
+    let default = folder.fold_expr(default, module, None);
     (param, default)
 }
 
 pub fn noop_fold_structdef<F: ASTFolder + ?Sized>(folder: &mut F,
-  StructDef { name, access, members, loc } : StructDef, module: &Ident)
+  StructDef { name, access, members, attrs, loc } : StructDef, module: &Ident)
   -> StructDef {
     let name = folder.fold_ident(name, NameCtxt::DefType(module), &loc);
     let members = folder.fold_structmem_list(members, module, &name);
+    let attrs = folder.fold_attribute_list(attrs);
     let loc = folder.fold_srcloc(loc);
     StructDef {
         name,
         access,
         members,
+        attrs,
         loc,
     }
 }
@@ -311,34 +330,53 @@ pub fn noop_fold_structmem<F: ASTFolder + ?Sized>(folder: &mut F,
 }
 
 pub fn noop_fold_static<F: ASTFolder + ?Sized>(folder: &mut F,
-  Static { name, access, ty, init, loc } : Static, module: &Ident) -> Static {
+  Static { name, access, ty, init, attrs, loc } : Static, module: &Ident) -> Static {
     let name = folder.fold_ident(name,
       NameCtxt::DefValue(module, None, &ty, access), &loc);
     let ty = folder.fold_type(ty, module, &loc);
     let init = init.map(|i| folder.fold_literal(i, module, None, &loc));
+    let attrs = folder.fold_attribute_list(attrs);
     let loc = folder.fold_srcloc(loc);
     Static {
         name,
         access,
         ty,
         init,
+        attrs,
         loc,
     }
 }
 
 pub fn noop_fold_constant<F: ASTFolder + ?Sized>(folder: &mut F,
-  Constant { name, access, ty, value, loc } : Constant,
+  Constant { name, access, ty, value, attrs, loc } : Constant,
   module: &Ident) -> Constant {
     let name = folder.fold_ident(name,
       NameCtxt::DefConstant(module, &ty, access), &loc);
     let ty = folder.fold_type(ty, module, &loc);
-    let value = folder.fold_literal(value, module, None, &loc);
+    let value = folder.fold_expr(value, module, None);
+    let attrs = folder.fold_attribute_list(attrs);
     let loc = folder.fold_srcloc(loc);
     Constant {
         name,
         access,
         ty,
         value,
+        attrs,
+        loc,
+    }
+}
+
+pub fn noop_fold_attribute_list<F: ASTFolder + ?Sized>(folder: &mut F,
+  attrs: Vec<Attribute>) -> Vec<Attribute> {
+    attrs.into_iter().map(|a| folder.fold_attribute(a)).collect()
+}
+
+pub fn noop_fold_attribute<F: ASTFolder + ?Sized>(folder: &mut F,
+  Attribute { name, args, loc }: Attribute) -> Attribute {
+    let loc = folder.fold_srcloc(loc);
+    Attribute {
+        name,
+        args,
         loc,
     }
 }
@@ -370,6 +408,25 @@ pub fn noop_fold_stmt<F: ASTFolder + ?Sized>(folder: &mut F,
                 )
             }).collect()),
 
+        StmtKind::Destructure(idents, ty, init) => {
+            let init = folder.fold_expr(init, module, Some(function));
+            let ty = ty.map(|ty| folder.fold_type(ty, module, &loc));
+            let idents = idents.into_iter().enumerate().map(|(i, ident)| {
+                let elem_ty = match ty {
+                    Some(Type::Tuple(ref tys)) if i < tys.len() =>
+                        tys[i].clone(),
+                    _ => Type::Variant,
+                };
+
+                folder.fold_ident(
+                    ident,
+                    NameCtxt::DefValue(module, Some(function),
+                      &elem_ty, Access::Private),
+                    &loc)
+            }).collect();
+            StmtKind::Destructure(idents, ty, init)
+        },
+
         StmtKind::Assign(lhs, op, rhs) =>
             StmtKind::Assign(
                 folder.fold_expr(lhs, module, Some(function)),
@@ -449,6 +506,26 @@ pub fn noop_fold_stmt<F: ASTFolder + ?Sized>(folder: &mut F,
         StmtKind::Print(exprs) =>
             StmtKind::Print(folder.fold_expr_list(exprs, module,
               Some(function))),
+
+        StmtKind::With(subject, body) =>
+            StmtKind::With(folder.fold_expr(subject, module, Some(function)),
+              folder.fold_stmt_list(body, module, function)),
+
+        StmtKind::VbBlock(code, reads, writes) => {
+            let code = folder.fold_vbexpr(code, module, Some(function), &loc);
+            let mut fold_paths = |paths: Vec<Path>| paths.into_iter()
+                .map(|p| folder.fold_path(p,
+                  NameCtxt::Value(module, Some(function), Access::Private), &loc))
+                .collect();
+            let reads = fold_paths(reads);
+            let writes = fold_paths(writes);
+            StmtKind::VbBlock(code, reads, writes)
+        },
+
+        StmtKind::Assert(cond, msg, text) =>
+            StmtKind::Assert(folder.fold_expr(cond, module, Some(function)),
+              msg.map(|msg| folder.fold_expr(msg, module, Some(function))),
+              text),
     };
 
     let loc = folder.fold_srcloc(loc);
@@ -487,6 +564,13 @@ pub fn noop_fold_expr<F: ASTFolder + ?Sized>(folder: &mut F,
                   .collect()
             ),
 
+        ExprKind::Slice(expr, lo, hi) =>
+            ExprKind::Slice(
+                Box::new(folder.fold_expr(*expr, module, function)),
+                Box::new(folder.fold_expr(*lo, module, function)),
+                Box::new(folder.fold_expr(*hi, module, function)),
+            ),
+
         ExprKind::Call(path, args, optargs) =>
             ExprKind::Call(
               folder.fold_path(path,
@@ -541,8 +625,17 @@ pub fn noop_fold_expr<F: ASTFolder + ?Sized>(folder: &mut F,
             ExprKind::Cast(Box::new(expr), ty)
         },
 
+        ExprKind::Tuple(exprs) =>
+            ExprKind::Tuple(folder.fold_expr_list(exprs, module, function)),
+
         ExprKind::VbExpr(data) =>
             ExprKind::VbExpr(folder.fold_vbexpr(data, module, function, &loc)),
+
+        ExprKind::WithSubject => ExprKind::WithSubject,
+
+        ExprKind::CheckedArith(op, args) =>
+            ExprKind::CheckedArith(op,
+              folder.fold_expr_list(args, module, function)),
     };
 
     let ty = ty.map(|ty| folder.fold_type(ty, module, &loc));
@@ -557,7 +650,7 @@ pub fn noop_fold_expr<F: ASTFolder + ?Sized>(folder: &mut F,
 }
 
 pub fn noop_fold_forspec<F: ASTFolder + ?Sized>(folder: &mut F, spec: ForSpec,
-  module: &Ident, function: &Ident, _loc: &SrcLoc) -> ForSpec {
+  module: &Ident, function: &Ident, loc: &SrcLoc) -> ForSpec {
     match spec {
         ForSpec::Range(from, to, step) =>
             ForSpec::Range(
@@ -566,8 +659,16 @@ pub fn noop_fold_forspec<F: ASTFolder + ?Sized>(folder: &mut F, spec: ForSpec,
                 step.map(|step| folder.fold_expr(step, module, Some(function)))
             ),
 
-        ForSpec::Each(of) =>
-            ForSpec::Each(folder.fold_expr(of, module, Some(function))),
+        ForSpec::Each(of, indices) => {
+            let index_ty = index_type(&of);
+            ForSpec::Each(folder.fold_expr(of, module, Some(function)),
+              indices.into_iter().map(|index|
+                  folder.fold_ident(index,
+                    NameCtxt::DefValue(module, Some(function), &index_ty,
+                      Access::Private),
+                    loc)
+              ).collect())
+        },
     }
 }
 
@@ -614,6 +715,32 @@ pub fn noop_fold_type<F: ASTFolder + ?Sized>(folder: &mut F, ty: Type,
             Type::Array(Box::new(base), bounds)
         },
 
+        Type::Nullable(base) =>
+            Type::Nullable(Box::new(folder.fold_type(*base, module, loc))),
+
+        Type::Map(key, value) => {
+            let key = folder.fold_type(*key, module, loc);
+            let value = folder.fold_type(*value, module, loc);
+            Type::Map(Box::new(key), Box::new(value))
+        },
+
+        Type::List(base) =>
+            Type::List(Box::new(folder.fold_type(*base, module, loc))),
+
+        Type::ArrayView(base) =>
+            Type::ArrayView(Box::new(folder.fold_type(*base, module, loc))),
+
+        Type::Tuple(tys) =>
+            Type::Tuple(tys.into_iter().map(|ty|
+              folder.fold_type(ty, module, loc)).collect()),
+
+        Type::FnPtr(params, ret) => {
+            let params = params.into_iter().map(|(mode, ty)|
+              (mode, folder.fold_type(ty, module, loc))).collect();
+            let ret = folder.fold_type(*ret, module, loc);
+            Type::FnPtr(params, Box::new(ret))
+        },
+
         Type::Struct(path) =>
             Type::Struct(folder.fold_path(path,
               NameCtxt::Type(module, Access::Private), loc)),
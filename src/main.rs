@@ -15,8 +15,27 @@ fn main() {
         return;
     }
 
+    // anything starting with -- is an option; the rest are files
+    let mut checked = false;
+    let mut asserts = transform::AssertMode::Raise;
+    let mut maps = transform::MapBinding::Late;
+    let mut files = vec![];
+    for arg in &args[1..] {
+        match arg.to_str() {
+            Some("--checked") => checked = true,
+            Some("--debug-asserts") => asserts = transform::AssertMode::Debug,
+            Some("--no-asserts") => asserts = transform::AssertMode::Strip,
+            Some("--early-bound") => maps = transform::MapBinding::Early,
+            Some(opt) if opt.starts_with("--") => {
+                println!("unknown option: {}", opt);
+                return;
+            },
+            _ => files.push(arg),
+        }
+    }
+
     let mut dumpsters = vec![];
-    for f in &args[1..] {
+    for f in files {
         let mut file = File::open(f).expect(
             &format!("Unable to open {}.", f.to_string_lossy()));
         let mut contents = vec![];
@@ -67,11 +86,27 @@ fn main() {
     //   (these need symbols and access to typing)
     //   (they also may emit new symbols etc)
     // order matters here!
+    let dumpster = transform::assert_rewrite(dumpster, asserts);
+    let dumpster = transform::constexpr_rewrite(dumpster);
+    let dumpster = transform::method_rewrite(dumpster);
+    let dumpster = transform::named_arg_rewrite(dumpster, &mut symtab);
+    let dumpster = transform::tuple_rewrite(dumpster, &mut symtab);
     let dumpster = transform::cast_rewrite(dumpster, &mut symtab);
+    let dumpster = transform::map_rewrite(dumpster, &mut symtab, maps);
+    let dumpster = transform::list_rewrite(dumpster, &mut symtab);
+    let dumpster = if checked {
+        transform::checked_arithmetic_rewrite(dumpster)
+    } else {
+        dumpster
+    };
     let dumpster = transform::short_circuit_logicals(dumpster, &mut symtab);
     let dumpster = transform::array_loop_rewrite(dumpster, &mut symtab);
     let dumpster = transform::along_loop_rewrite(dumpster);
     let dumpster = transform::alloc_along_rewrite(dumpster, &mut symtab);
+    let dumpster = transform::slice_rewrite(dumpster, &mut symtab);
+    let dumpster = transform::eq_rewrite(dumpster, &mut symtab);
+    let dumpster = transform::debug_str_rewrite(dumpster, &mut symtab);
+    let dumpster = transform::udf_rewrite(dumpster);
 
     // codegen pass
     for m in dumpster.modules.iter() {
@@ -24,6 +24,7 @@ enum RecExprRest {
 // the "rest" (recursive part) of a "unitary" recursive expr
 enum UnitaryRecExprRest {
     Indexed(Vec<Expr>, usize),
+    Slice(Expr, Expr, usize),
     Member(Ident, usize),
     MemberInvoke(Ident, Vec<Expr>, usize),
     Cast(Type, usize),
@@ -37,13 +38,15 @@ enum UnitaryRecExprRest {
 // 3 : ^
 // 4 : * / %
 // 5 : + - @
-// 6 : > < >= <=
-// 7 : == !=
-// 8 : &
-// 9 : |
-// 10: &&
-// 11: ||
-// 12: x ? y : z
+// 6 : << >>
+// 7 : > < >= <=
+// 8 : == !=
+// 9 : &
+// 10: ^^
+// 11: |
+// 12: &&
+// 13: ||
+// 14: x ? y : z
 
 // pull a nonrecursive expr, and maybe a recursive rest
 #[inline]
@@ -105,10 +108,12 @@ macro_rules! binopexpr {
 binopexpr!(powexpr = unitary_op_expr | pow_op);
 binopexpr!(muldivexpr = powexpr | muldiv_op);
 binopexpr!(addsubexpr = muldivexpr | addsub_op);
-binopexpr!(cmpexpr = addsubexpr | cmp_op);
+binopexpr!(shiftexpr = addsubexpr | shift_op);
+binopexpr!(cmpexpr = shiftexpr | cmp_op);
 binopexpr!(eqexpr = cmpexpr | eq_op);
 binopexpr!(bitandexpr = eqexpr | bitand_op);
-binopexpr!(bitorexpr = bitandexpr | bitor_op);
+binopexpr!(bitxorexpr = bitandexpr | bitxor_op);
+binopexpr!(bitorexpr = bitxorexpr | bitor_op);
 binopexpr!(logandexpr = bitorexpr | logand_op);
 binopexpr!(logorexpr = logandexpr | logor_op);
 
@@ -162,7 +167,8 @@ fn unitary_op_expr1(input: &[u8]) -> CutParseResult<Expr> {
 fn unitary_expr(input: &[u8]) -> CutParseResult<Expr> {
     let (i, first) = require!(nonrec_unitary_expr(input));
     let (i, rest) = require!(many(i, |i| alt!(i,
-        indexed(i)
+        slice(i)
+      ; indexed(i)
       ; memberinvoke(i)
       ; member(i)
       ; cast(i)
@@ -180,6 +186,7 @@ fn nonrec_unitary_expr(input: &[u8]) -> CutParseResult<Expr> {
       ; pathexpr(input)
       ; grouped(input)
       ; vbexpr(input)
+      ; withsubject(input)
     )
 }
 
@@ -195,6 +202,16 @@ fn fold_unitary_exprs(first: Expr, rest: Vec<UnitaryRecExprRest>) -> Expr {
                 }
             },
 
+            UnitaryRecExprRest::Slice(lo, hi, len) => {
+                let loc = SrcLoc::raw(sofar.loc.start, sofar.loc.len + len);
+                Expr {
+                    data: ExprKind::Slice(
+                        Box::new(sofar), Box::new(lo), Box::new(hi)),
+                    ty: None,
+                    loc,
+                }
+            },
+
             UnitaryRecExprRest::Member(i, len) => {
                 let loc = SrcLoc::raw(sofar.loc.start, sofar.loc.len + len);
                 Expr {
@@ -236,13 +253,24 @@ fn fncall(input: &[u8]) -> CutParseResult<Expr> {
     let (i, _) = require!(byte(i, b'('));
 
     // cut on error after this point
-    let (i, args) = require_or_cut!(delimited(i,
-        expr,
+    let (i, callargs) = require_or_cut!(delimited(i,
+        callarg,
         |i| chain!(i,
             |i| opt(i, multispace) =>
             |i| byte(i, b',')
         )) => ParseErrorKind::ExpectedExpr);
 
+    // positional arguments come first, then any named ones
+    let mut args = Vec::new();
+    let mut named = Vec::new();
+    for (name, arg) in callargs {
+        match name {
+            Some(name) => named.push((name, arg)),
+            None if named.is_empty() => args.push(arg),
+            None => return cut!(i, ParseErrorKind::ExpectedNamedArgument),
+        }
+    }
+
     let (i, _) = opt(i, multispace)?;
 
     let (i, optargs) = require!(opt!(chain!(i,
@@ -259,14 +287,25 @@ fn fncall(input: &[u8]) -> CutParseResult<Expr> {
     let (i, _) = require_or_cut!(byte(i, b')'));
     let (i, end_pos) = require_or_cut!(pos(i));
 
+    named.extend(optargs.unwrap_or(Vec::new()));
+
     ok!(i, Expr {
-        data: ExprKind::Call(name, args, optargs.unwrap_or(Vec::new())),
+        data: ExprKind::Call(name, args, named),
         ty: None,
         loc: SrcLoc::raw(start_pos, end_pos - start_pos),
     })
 }
 
-// an optional function argument
+// a positional or named function argument
+#[inline]
+fn callarg(input: &[u8]) -> CutParseResult<(Option<Ident>, Expr)> {
+    alt!(input,
+        optarg(input) => |(name, arg)| (Some(name), arg)
+      ; expr(input) => |arg| (None, arg)
+    )
+}
+
+// an optional (or named) function argument
 #[inline]
 fn optarg(input: &[u8]) -> CutParseResult<(Ident, Expr)> {
     let (i, _) = opt(input, multispace)?;
@@ -305,17 +344,30 @@ fn litexpr(input: &[u8]) -> CutParseResult<Expr> {
 }
 
 // an expr grouped in parentheses, to force precedence
+//   (or, with commas, a tuple expression)
 fn grouped(input: &[u8]) -> CutParseResult<Expr> {
     let (i, _) = opt(input, multispace)?;
     let (i, start_pos) = require!(pos(i));
     let (i, _) = require!(byte(i, b'('));
-    let (i, e) = require!(expr(i));
+    let (i, mut es) = require!(delimited_at_least_one(i,
+      expr,
+      |i| chain!(i,
+          |i| opt(i, multispace) =>
+          |i| byte(i, b',')
+      )));
     // TODO: should we cut on fail after here?
     let (i, _) = opt(i, multispace)?;
     let (i, _) = require!(byte(i, b')'));
     let (i, end_pos) = require!(pos(i));
+
+    let data = if es.len() == 1 {
+        es.pop().unwrap().data
+    } else {
+        ExprKind::Tuple(es)
+    };
+
     ok!(i, Expr {
-        data: e.data,
+        data,
         ty: None,
         loc: SrcLoc::raw(start_pos, end_pos - start_pos),
     })
@@ -380,6 +432,19 @@ fn vbexpr(input: &[u8]) -> CutParseResult<Expr> {
     })
 }
 
+// the implicit subject of a leading-dot member in a with block; we only
+//   peek at the dot, so the member itself is parsed as a recursive rest
+fn withsubject(input: &[u8]) -> CutParseResult<Expr> {
+    let (i, _) = opt(input, multispace)?;
+    let (i, start_pos) = require!(pos(i));
+    let (_, _) = require!(byte(i, b'.'));
+    ok!(i, Expr {
+        data: ExprKind::WithSubject,
+        ty: None,
+        loc: SrcLoc::raw(start_pos, 0),
+    })
+}
+
 // various possible recursive "rests" of unitary exprs
 
 fn indexed(input: &[u8]) -> CutParseResult<UnitaryRecExprRest> {
@@ -399,6 +464,21 @@ fn indexed(input: &[u8]) -> CutParseResult<UnitaryRecExprRest> {
     ok!(i, UnitaryRecExprRest::Indexed(indices, end_pos - start_pos))
 }
 
+fn slice(input: &[u8]) -> CutParseResult<UnitaryRecExprRest> {
+    let (i, start_pos) = require!(pos(input));
+    let (i, _) = opt(i, multispace)?;
+    let (i, _) = require!(byte(i, b'['));
+    let (i, lo) = require!(expr(i));
+    let (i, _) = opt(i, multispace)?;
+    let (i, _) = require!(byte(i, b':'));
+    // cut on error after this point
+    let (i, hi) = require_or_cut!(expr(i) => ParseErrorKind::ExpectedExpr);
+    let (i, _) = opt(i, multispace)?;
+    let (i, _) = require_or_cut!(byte(i, b']'));
+    let (i, end_pos) = require_or_cut!(pos(i));
+    ok!(i, UnitaryRecExprRest::Slice(lo, hi, end_pos - start_pos))
+}
+
 fn member(input: &[u8]) -> CutParseResult<UnitaryRecExprRest> {
     let (i, start_pos) = require!(pos(input));
     let (i, _) = opt(i, multispace)?;
@@ -462,6 +542,15 @@ mod test {
         expect_parse!(member(b" . y") =>
           UnitaryRecExprRest::Member(Ident(_, None), 4));
         expect_parse_cut!(member(b".777") => ParseErrorKind::ExpectedIdent);
+
+        expect_parse!(expr(b" .x.y") => Expr {
+            data: ExprKind::Member(_, Ident(_, None)),
+            ..
+        });
+        expect_parse!(expr(b" .f(1, 2)") => Expr {
+            data: ExprKind::MemberInvoke(_, Ident(_, None), _),
+            ..
+        });
     }
 
     #[test]
@@ -484,6 +573,29 @@ mod test {
             ..
         });
 
+        expect_parse!(fncall(b"f(x = 1, y = 2)") => Expr {
+            data: ExprKind::Call(_, _, _),
+            ..
+        });
+
+        match fncall(b"f(1, y = a == b; z = 3)") {
+            Ok((_, Ok(Expr { data: ExprKind::Call(_, args, named), .. }))) => {
+                assert_eq!(args.len(), 1);
+                let names: Vec<_> = named.iter()
+                    .map(|&(ref name, _)| name.0.as_str())
+                    .collect();
+                assert_eq!(names, vec!["y", "z"]);
+            },
+            r => panic!("bad named argument parse: {:?}", r),
+        }
+
+        expect_parse!(fncall(b"f(x == 1)") => Expr {
+            data: ExprKind::Call(_, _, _),
+            ..
+        });
+
+        expect_parse_cut!(fncall(b"f(x = 1, 2)") =>
+          ParseErrorKind::ExpectedNamedArgument);
         expect_parse_cut!(fncall(b"f(<<>>)") =>
           ParseErrorKind::ExpectedByte(b')'));
         expect_parse_cut!(fncall(b"f(1,2,3;)") =>
@@ -507,6 +619,11 @@ mod test {
             ..
         });
 
+        expect_parse!(expr(b"`Application.Caller` as obj") => Expr {
+            data: ExprKind::Cast(_, Type::Obj),
+            ..
+        });
+
         expect_parse!(expr(b"1345.67") => Expr {
             data: ExprKind::Lit(Literal::Float64(1345.67)),
             ..
@@ -525,6 +642,19 @@ mod test {
         expect_parse_cut!(expr(b"module::array[()]") =>
           ParseErrorKind::ExpectedExpr);
 
+        expect_parse!(expr(b"xs[2 : n - 1]") => Expr {
+            data: ExprKind::Slice(_, _, _),
+            ..
+        });
+
+        expect_parse!(expr(b"xs[b ? 1 : 2]") => Expr {
+            data: ExprKind::Index(_, _),
+            ..
+        });
+
+        expect_parse_cut!(expr(b"xs[2:]") =>
+          ParseErrorKind::ExpectedExpr);
+
         expect_parse!(expr(b"module::o.f(12, x[17])") => Expr {
             data: ExprKind::MemberInvoke(_, _, _),
             ..
@@ -535,6 +665,26 @@ mod test {
             ..
         });
 
+        expect_parse!(expr(b"x << 2 < y ^^ z") => Expr {
+            data: ExprKind::BinOpApp(_, _, BinOp::BitXor),
+            ..
+        });
+
+        expect_parse!(expr(b"(x, \"y\", f(1, 2))") => Expr {
+            data: ExprKind::Tuple(_),
+            ..
+        });
+
+        expect_parse!(expr(b"((1, 2), 3 + 4)") => Expr {
+            data: ExprKind::Tuple(_),
+            ..
+        });
+
+        expect_parse!(expr(b"(x) * 2") => Expr {
+            data: ExprKind::BinOpApp(_, _, BinOp::Mul),
+            ..
+        });
+
         expect_parse_cut!(expr(b"module::o.17") =>
           ParseErrorKind::ExpectedIdent);
 
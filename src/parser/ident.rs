@@ -14,20 +14,24 @@ pub const IDENT_CONT_CHARS: &'static [u8] =
       0123456789\
       _";
 
-pub const KEYWORDS: [&'static [u8]; 41] = [
+pub const KEYWORDS: [&'static [u8]; 45] = [
     b"let",
     b"as",
     b"print",
+    b"assert",
     b"return",
     b"for",
     b"while",
+    b"with",
     b"pub",
     b"mod",
     b"fn",
+    b"extern",
     b"class",
     b"new",
     b"this",
     b"struct",
+    b"impl",
     b"enum",
     b"static",
     b"const",
@@ -100,14 +104,30 @@ pub fn typename(input: &[u8]) -> CutParseResult<Type> {
       ; keyword_immediate(i, b"isize") => |_| Type::IntPtr
       ; keyword_immediate(i, b"f32") => |_| Type::Float32
       ; keyword_immediate(i, b"f64") => |_| Type::Float64
+      ; fixed_string_typename(i)
       ; keyword_immediate(i, b"str") => |_| Type::String
       ; keyword_immediate(i, b"currency") => |_| Type::Currency
       ; keyword_immediate(i, b"date") => |_| Type::Date
       ; keyword_immediate(i, b"var") => |_| Type::Variant
       ; keyword_immediate(i, b"obj") => |_| Type::Obj
+      ; map_typename(i)
+      ; list_typename(i)
+      ; tuple_typename(i)
+      ; fn_ptr_typename(i)
       ; path(i) => |p| Type::Deferred(p)
     ));
 
+    let (i, nullable) = require!(opt(i, |i| byte(i, b'?')));
+    let base = match nullable {
+        None => base,
+        Some(_) => Type::Nullable(Box::new(base)),
+    };
+
+    let (i, view) = require!(opt(i, array_view_spec));
+    if view.is_some() {
+        return ok!(i, Type::ArrayView(Box::new(base)));
+    }
+
     let (i, spec) = require!(opt(i, array_spec));
     match spec {
         None => ok!(i, base),
@@ -115,6 +135,105 @@ pub fn typename(input: &[u8]) -> CutParseResult<Type> {
     }
 }
 
+fn fixed_string_typename(input: &[u8]) -> CutParseResult<Type> {
+    let (i, _) = require!(keyword_immediate(input, b"fstr"));
+    let (i, _) = opt(i, multispace)?;
+    let (i, _) = require!(byte(i, b'<'));
+    // everything past here should cut: we know we're in a string length
+    let (i, _) = opt(i, multispace)?;
+    let (i, len) = require_or_cut!(alt!(i,
+        literal(i) => |l| StaticArrayDim::Lit(l)
+      ; path(i) => |p| StaticArrayDim::Named(p)
+    ) => ParseErrorKind::ExpectedDimSpecifier);
+    let (i, _) = opt(i, multispace)?;
+    let (i, _) = require_or_cut!(byte(i, b'>'));
+    ok!(i, Type::FixedString(len))
+}
+
+fn map_typename(input: &[u8]) -> CutParseResult<Type> {
+    let (i, _) = require!(keyword_immediate(input, b"map"));
+    let (i, _) = opt(i, multispace)?;
+    let (i, _) = require!(byte(i, b'<'));
+    // everything past here should cut: we know we're in a map type
+    let (i, key) = require_or_cut!(typename(i)
+      => ParseErrorKind::ExpectedTypename);
+    let (i, _) = opt(i, multispace)?;
+    let (i, _) = require_or_cut!(byte(i, b','));
+    let (i, value) = require_or_cut!(typename(i)
+      => ParseErrorKind::ExpectedTypename);
+    let (i, _) = opt(i, multispace)?;
+    let (i, _) = require_or_cut!(byte(i, b'>'));
+    ok!(i, Type::Map(Box::new(key), Box::new(value)))
+}
+
+fn list_typename(input: &[u8]) -> CutParseResult<Type> {
+    let (i, _) = require!(keyword_immediate(input, b"list"));
+    let (i, _) = opt(i, multispace)?;
+    let (i, _) = require!(byte(i, b'<'));
+    // everything past here should cut: we know we're in a list type
+    let (i, elem) = require_or_cut!(typename(i)
+      => ParseErrorKind::ExpectedTypename);
+    let (i, _) = opt(i, multispace)?;
+    let (i, _) = require_or_cut!(byte(i, b'>'));
+    ok!(i, Type::List(Box::new(elem)))
+}
+
+fn tuple_typename(input: &[u8]) -> CutParseResult<Type> {
+    let (i, _) = require!(byte(input, b'('));
+    // everything past here should cut: we know we're in a tuple type
+    let (i, tys) = require_or_cut!(delimited_at_least_one(i,
+      typename,
+      |i| chain!(i,
+          |i| opt(i, multispace) =>
+          |i| byte(i, b',')
+      )) => ParseErrorKind::ExpectedTypename);
+    let (i, _) = opt(i, multispace)?;
+    let (i, _) = require_or_cut!(byte(i, b')'));
+
+    // no 1-tuples; (T) is just a parenthesized T, which isn't a thing
+    if tys.len() < 2 {
+        return cut!(i, ParseErrorKind::ExpectedByte(b','));
+    }
+
+    ok!(i, Type::Tuple(tys))
+}
+
+fn fn_ptr_typename(input: &[u8]) -> CutParseResult<Type> {
+    let (i, _) = require!(keyword_immediate(input, b"fn"));
+    let (i, _) = opt(i, multispace)?;
+    let (i, _) = require!(byte(i, b'('));
+    // everything past here should cut: we know we're in a function pointer
+    let (i, params) = require_or_cut!(delimited(i,
+      fn_ptr_param,
+      |i| chain!(i,
+          |i| opt(i, multispace) =>
+          |i| byte(i, b',')
+      )));
+    let (i, _) = opt(i, multispace)?;
+    let (i, _) = require_or_cut!(byte(i, b')'));
+    let (i, ret) = require_or_cut!(opt!(chain!(i,
+        |i| keyword(i, b"->") =>
+        |i| cut_if_err!(typename(i) => ParseErrorKind::ExpectedTypename)
+    )));
+    ok!(i, Type::FnPtr(params, Box::new(ret.unwrap_or(Type::Void))))
+}
+
+fn fn_ptr_param(input: &[u8]) -> CutParseResult<(ParamMode, Type)> {
+    let (i, _) = opt(input, multispace)?;
+    let (i, byref) = require!(opt(i, |i| byte(i, b'&')));
+    let (i, _) = opt(i, multispace)?;
+    let (i, ty) = match byref {
+        Some(_) => require_or_cut!(typename(i) =>
+          ParseErrorKind::ExpectedTypename),
+        None => require!(typename(i)),
+    };
+    let mode = match byref {
+        Some(_) => ParamMode::ByRef,
+        None => ParamMode::ByVal,
+    };
+    ok!(i, (mode, ty))
+}
+
 fn array_spec(input: &[u8]) -> CutParseResult<ArrayBounds> {
     let (i, _) = opt(input, multispace)?;
     let (i, _) = require!(byte(i, b'['));
@@ -128,6 +247,16 @@ fn array_spec(input: &[u8]) -> CutParseResult<ArrayBounds> {
     ok!(i, bounds)
 }
 
+fn array_view_spec(input: &[u8]) -> CutParseResult<()> {
+    let (i, _) = opt(input, multispace)?;
+    let (i, _) = require!(byte(i, b'['));
+    let (i, _) = require!(keyword(i, b".."));
+    // everything past here should cut: we know we're in an array view
+    let (i, _) = opt(i, multispace)?;
+    let (i, _) = require_or_cut!(byte(i, b']'));
+    ok!(i, ())
+}
+
 fn array_dynamic_bounds(input: &[u8]) -> CutParseResult<ArrayBounds> {
     let (i, commas) = require!(many(input,
       |i| chain!(i,
@@ -193,6 +322,122 @@ mod test {
           ParseErrorKind::KeywordAsIdent(b"for"));
     }
 
+    #[test]
+    fn parse_fixed_string_typenames() {
+        expect_parse!(typename(b"fstr<8>") =>
+          Type::FixedString(StaticArrayDim::Lit(Literal::Int32(8))));
+        expect_parse!(typename(b"fstr < some::LEN >") =>
+          Type::FixedString(StaticArrayDim::Named(_)));
+        expect_parse!(typename(b"fstr<16>[10]") =>
+          Type::Array(_, ArrayBounds::Static(_)));
+        expect_parse!(typename(b"fstring") => Type::Deferred(_));
+
+        expect_parse_cut!(typename(b"fstr<>") =>
+          ParseErrorKind::ExpectedDimSpecifier);
+        expect_parse_cut!(typename(b"fstr<8") =>
+          ParseErrorKind::ExpectedByte(b'>'));
+    }
+
+    #[test]
+    fn parse_fn_ptr_typenames() {
+        expect_parse!(typename(b"fn()") => Type::FnPtr(_, _));
+        expect_parse!(typename(b"fn(i32, &isize) -> i32") =>
+          Type::FnPtr(_, _));
+        expect_parse!(typename(b"fn (i32) -> bool[]") =>
+          Type::FnPtr(_, _));
+        expect_parse!(typename(b"fnord") => Type::Deferred(_));
+
+        match typename(b"fn(i32, &isize) -> i32") {
+            Ok((_, Ok(Type::FnPtr(params, ret)))) => {
+                assert_eq!(params, vec![
+                    (ParamMode::ByVal, Type::Int32),
+                    (ParamMode::ByRef, Type::IntPtr),
+                ]);
+                assert_eq!(*ret, Type::Int32);
+            },
+            r => panic!("bad function pointer parse: {:?}", r),
+        }
+
+        expect_parse_cut!(typename(b"fn(i32") =>
+          ParseErrorKind::ExpectedByte(b')'));
+        expect_parse_cut!(typename(b"fn(&)") =>
+          ParseErrorKind::ExpectedTypename);
+    }
+
+    #[test]
+    fn parse_nullable_typenames() {
+        expect_parse!(typename(b"obj?") => Type::Nullable(_));
+        expect_parse!(typename(b"some::Widget?") => Type::Nullable(_));
+        expect_parse!(typename(b"obj?[]") =>
+          Type::Array(_, ArrayBounds::Dynamic(1)));
+        expect_parse!(typename(b"(obj?, i32)") => Type::Tuple(_));
+    }
+
+    #[test]
+    fn parse_map_typenames() {
+        expect_parse!(typename(b"map<str, i32>") => Type::Map(_, _));
+        expect_parse!(typename(b"map < i32,map<str, obj?> >") =>
+          Type::Map(_, _));
+        expect_parse!(typename(b"map<str, f64>[]") =>
+          Type::Array(_, ArrayBounds::Dynamic(1)));
+        expect_parse!(typename(b"mapping") => Type::Deferred(_));
+
+        match typename(b"map<str, obj?>") {
+            Ok((_, Ok(Type::Map(key, value)))) => {
+                assert_eq!(*key, Type::String);
+                assert_eq!(*value, Type::Nullable(Box::new(Type::Obj)));
+            },
+            r => panic!("bad map type parse: {:?}", r),
+        }
+
+        expect_parse_cut!(typename(b"map<str>") =>
+          ParseErrorKind::ExpectedByte(b','));
+        expect_parse_cut!(typename(b"map<str, i32") =>
+          ParseErrorKind::ExpectedByte(b'>'));
+        expect_parse_cut!(typename(b"map<>") =>
+          ParseErrorKind::ExpectedTypename);
+    }
+
+    #[test]
+    fn parse_list_typenames() {
+        expect_parse!(typename(b"list<i32>") => Type::List(_));
+        expect_parse!(typename(b"list < list<str> >") => Type::List(_));
+        expect_parse!(typename(b"list<f64>[]") =>
+          Type::Array(_, ArrayBounds::Dynamic(1)));
+        expect_parse!(typename(b"listing") => Type::Deferred(_));
+
+        match typename(b"list<some::Point>") {
+            Ok((_, Ok(Type::List(elem)))) => match *elem {
+                Type::Deferred(Path(Some(ref m), ref name)) => {
+                    assert_eq!(m.0, "some");
+                    assert_eq!(name.0, "Point");
+                },
+                ref ty => panic!("bad list element type: {:?}", ty),
+            },
+            r => panic!("bad list type parse: {:?}", r),
+        }
+
+        expect_parse_cut!(typename(b"list<i32") =>
+          ParseErrorKind::ExpectedByte(b'>'));
+        expect_parse_cut!(typename(b"list<>") =>
+          ParseErrorKind::ExpectedTypename);
+    }
+
+    #[test]
+    fn parse_tuple_typenames() {
+        expect_parse!(typename(b"(i32, str)") => Type::Tuple(_));
+        expect_parse!(typename(b" ( f64,some::ty , bool[] )") =>
+          Type::Tuple(_));
+        expect_parse!(typename(b"(i32, (str, date))[]") =>
+          Type::Array(_, ArrayBounds::Dynamic(1)));
+
+        expect_parse_cut!(typename(b"(i32)") =>
+          ParseErrorKind::ExpectedByte(b','));
+        expect_parse_cut!(typename(b"(i32, str") =>
+          ParseErrorKind::ExpectedByte(b')'));
+        expect_parse_cut!(typename(b"()") => _);
+    }
+
     #[test]
     fn parse_typenames() {
         expect_parse!(typename(b"  i32") => Type::Int32);
@@ -204,6 +449,7 @@ mod test {
           Type::Array(_, ArrayBounds::Static(_)));
         expect_parse!(typename(b"something::else[,,,]") =>
           Type::Array(_, ArrayBounds::Dynamic(_)));
+        expect_parse!(typename(b"f64[ .. ]") => Type::ArrayView(_));
 
         expect_parse_err!(typename(b"__cant_be_ident") =>
           ParseErrorKind::NoAltMatch);
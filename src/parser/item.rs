@@ -4,6 +4,7 @@ use super::{ParseErrorKind, CutParseResult, SrcLoc};
 use super::bits::*;
 use super::lit::*;
 use super::ident::*;
+use super::expr::*;
 use super::stmt::*;
 
 use ast::*;
@@ -17,10 +18,48 @@ pub fn normal_item(input: &[u8]) -> CutParseResult<NormalItem> {
     )
 }
 
+/// items, where an impl block yields one (lowered) function per method
+pub fn normal_items(input: &[u8]) -> CutParseResult<Vec<NormalItem>> {
+    alt!(input,
+        impl_block(input) =>
+          |defs: Vec<FunDef>| defs.into_iter().map(NormalItem::Function)
+            .collect()
+      ; normal_item(input) => |item| vec![item]
+    )
+}
+
 pub fn fundef(input: &[u8]) -> CutParseResult<FunDef> {
+    fundef_or_method(input, None)
+}
+
+pub fn impl_block(input: &[u8]) -> CutParseResult<Vec<FunDef>> {
     let (i, _) = opt(input, multispace)?;
+    let (i, _) = require!(keyword_immediate(i, b"impl"));
+    let (i, _) = require!(multispace(i));
+    // cut on error after this point
+    let (i, st) = require_or_cut!(ident(i) => ParseErrorKind::ExpectedIdent);
+    let (i, _) = opt(i, multispace)?;
+    let (i, _) = require_or_cut!(byte(i, b'{'));
+    let (i, methods) = require_or_cut!(many(i,
+      |i| fundef_or_method(i, Some(&st))));
+    let (i, _) = opt(i, multispace)?;
+    let (i, _) = require_or_cut!(byte(i, b'}'));
+    ok!(i, methods)
+}
+
+// methods (in impl blocks) take &self first, which becomes an explicit
+//   by-reference parameter of the struct type; extern fns (outside impl
+//   blocks) end with a semicolon where the body would be
+fn fundef_or_method<'a>(input: &'a [u8], self_ty: Option<&Ident>)
+  -> CutParseResult<'a, FunDef> {
+    let (i, attrs) = require!(attributes(input));
+    let (i, _) = opt(i, multispace)?;
     let (i, start_pos) = require!(pos(i));
     let (i, access) = require!(access(i));
+    let (i, lib) = match self_ty {
+        Some(_) => (i, None),
+        None => require!(opt!(i, extern_lib(i))),
+    };
     let (i, _) = require!(keyword_immediate(i, b"fn"));
     let (i, _) = require!(multispace(i));
 
@@ -30,12 +69,32 @@ pub fn fundef(input: &[u8]) -> CutParseResult<FunDef> {
     let (i, _) = opt(i, multispace)?;
     let (i, _) = require_or_cut!(byte(i, b'('));
 
-    let (i, params) = require_or_cut!(delimited(i,
+    let (i, self_param) = match self_ty {
+        Some(st) => {
+            let (i, p) = require_or_cut!(self_param(i, st) =>
+              ParseErrorKind::ExpectedSelfParam);
+            let (i, _) = require!(opt(i, |i| chain!(i,
+                |i| opt(i, multispace) =>
+                |i| byte(i, b',')
+            )));
+            (i, Some(p))
+        },
+        None => (i, None),
+    };
+
+    let (i, mut params) = require_or_cut!(delimited(i,
         fnparam,
         |i| chain!(i,
             |i| opt(i, multispace) =>
             |i| byte(i, b',')
         )));
+    if let Some(p) = self_param {
+        params.insert(0, p);
+    }
+    let name = match self_ty {
+        Some(st) => method_name(st, &name),
+        None => name,
+    };
 
     let (i, _) = opt(i, multispace)?;
     let (i, optparams) = require_or_cut!(opt!(optparams(i)));
@@ -43,11 +102,21 @@ pub fn fundef(input: &[u8]) -> CutParseResult<FunDef> {
     let (i, _) = require_or_cut!(byte(i, b')'));
     let (i, ret) = require!(opt!(i, fnret(i)));
 
-    let (i, _) = opt(i, multispace)?;
-    let (i, _) = require_or_cut!(byte(i, b'{'));
-    let (i, body) = require_or_cut!(many(i, stmt));
-    let (i, _) = opt(i, multispace)?;
-    let (i, _) = require_or_cut!(byte(i, b'}'));
+    let (i, body) = match lib {
+        Some(_) => {
+            let (i, _) = require_or_cut!(terminator(i));
+            (i, vec![])
+        },
+
+        None => {
+            let (i, _) = opt(i, multispace)?;
+            let (i, _) = require_or_cut!(byte(i, b'{'));
+            let (i, body) = require_or_cut!(many(i, stmt));
+            let (i, _) = opt(i, multispace)?;
+            let (i, _) = require_or_cut!(byte(i, b'}'));
+            (i, body)
+        },
+    };
     let (i, end_pos) = require!(pos(i));
     ok!(i, FunDef {
         name,
@@ -56,12 +125,30 @@ pub fn fundef(input: &[u8]) -> CutParseResult<FunDef> {
         optparams,
         ret: ret.unwrap_or(Type::Void),
         body,
+        lib,
+        attrs,
         loc: SrcLoc::raw(start_pos, end_pos - start_pos),
     })
 }
 
+// extern "lib", before the fn keyword of a declared external function
+#[inline]
+fn extern_lib(input: &[u8]) -> CutParseResult<String> {
+    let (i, _) = require!(keyword_immediate(input, b"extern"));
+    let (i, _) = require!(multispace(i));
+    // cut on error after this point
+    let (i, lib) = require_or_cut!(literal_string(i) =>
+      ParseErrorKind::ExpectedLiteral);
+    let (i, _) = require_or_cut!(multispace(i));
+    match lib {
+        Literal::String(lib) => ok!(i, lib),
+        _ => panic!("dumpster fire: non-string library name"),
+    }
+}
+
 pub fn structdef(input: &[u8]) -> CutParseResult<StructDef> {
-    let (i, _) = opt(input, multispace)?;
+    let (i, attrs) = require!(attributes(input));
+    let (i, _) = opt(i, multispace)?;
     let (i, start_pos) = require!(pos(i));
     let (i, access) = require!(access(i));
     let (i, _) = require!(keyword_immediate(i, b"struct"));
@@ -85,12 +172,14 @@ pub fn structdef(input: &[u8]) -> CutParseResult<StructDef> {
         name,
         access,
         members,
+        attrs,
         loc: SrcLoc::raw(start_pos, end_pos - start_pos),
     })
 }
 
 pub fn staticdef(input: &[u8]) -> CutParseResult<Static> {
-    let (i, _) = opt(input, multispace)?;
+    let (i, attrs) = require!(attributes(input));
+    let (i, _) = opt(i, multispace)?;
     let (i, start_pos) = require!(pos(i));
     let (i, access) = require!(access(i));
     let (i, _) = require!(keyword_immediate(i, b"static"));
@@ -114,12 +203,14 @@ pub fn staticdef(input: &[u8]) -> CutParseResult<Static> {
         access,
         ty,
         init,
+        attrs,
         loc: SrcLoc::raw(start_pos, end_pos - start_pos),
     })
 }
 
 pub fn constantdef(input: &[u8]) -> CutParseResult<Constant> {
-    let (i, _) = opt(input, multispace)?;
+    let (i, attrs) = require!(attributes(input));
+    let (i, _) = opt(i, multispace)?;
     let (i, start_pos) = require!(pos(i));
     let (i, access) = require!(access(i));
     let (i, _) = require!(keyword_immediate(i, b"const"));
@@ -133,8 +224,8 @@ pub fn constantdef(input: &[u8]) -> CutParseResult<Constant> {
     let (i, _) = opt(i, multispace)?;
     let (i, _) = require_or_cut!(byte(i, b'='));
     let (i, _) = opt(i, multispace)?;
-    let (i, value) = require_or_cut!(literal(i) =>
-      ParseErrorKind::ExpectedLiteral);
+    let (i, value) = require_or_cut!(expr(i) =>
+      ParseErrorKind::ExpectedExpr);
     let (i, _) = require_or_cut!(terminator(i));
     let (i, end_pos) = require_or_cut!(pos(i));
     ok!(i, Constant {
@@ -142,6 +233,82 @@ pub fn constantdef(input: &[u8]) -> CutParseResult<Constant> {
         access,
         ty,
         value,
+        attrs,
+        loc: SrcLoc::raw(start_pos, end_pos - start_pos),
+    })
+}
+
+pub fn attributes(input: &[u8]) -> CutParseResult<Vec<Attribute>> {
+    many(input, attribute)
+}
+
+fn attribute(input: &[u8]) -> CutParseResult<Attribute> {
+    let (i, _) = opt(input, multispace)?;
+    let (i, start_pos) = require!(pos(i));
+    let (i, _) = require!(keyword_immediate(i, b"#["));
+    // cut on error after this point
+    let (i, name) = require_or_cut!(ident(i) => ParseErrorKind::ExpectedIdent);
+    let (i, _) = opt(i, multispace)?;
+    let (i, args) = require_or_cut!(opt!(attribute_args(i)));
+    let (i, _) = opt(i, multispace)?;
+    let (i, _) = require_or_cut!(byte(i, b']'));
+    let (i, end_pos) = require!(pos(i));
+    ok!(i, Attribute {
+        name,
+        args: args.unwrap_or_default(),
+        loc: SrcLoc::raw(start_pos, end_pos - start_pos),
+    })
+}
+
+#[inline]
+fn attribute_args(input: &[u8]) -> CutParseResult<Vec<AttributeArg>> {
+    let (i, _) = require!(byte(input, b'('));
+    // cut on error after this point
+    let (i, args) = require_or_cut!(delimited(i,
+        attribute_arg,
+        |i| chain!(i,
+            |i| opt(i, multispace) =>
+            |i| byte(i, b',')
+        )));
+    let (i, _) = opt(i, multispace)?;
+    let (i, _) = require_or_cut!(byte(i, b')'));
+    ok!(i, args)
+}
+
+#[inline]
+fn attribute_arg(input: &[u8]) -> CutParseResult<AttributeArg> {
+    alt!(input,
+        literal(input) => AttributeArg::Lit
+      ; named_attribute_arg(input)
+      ; ident(input) => AttributeArg::Flag
+    )
+}
+
+#[inline]
+fn named_attribute_arg(input: &[u8]) -> CutParseResult<AttributeArg> {
+    let (i, key) = require!(ident(input));
+    let (i, _) = opt(i, multispace)?;
+    let (i, _) = require!(byte(i, b'='));
+    // cut on error after this point
+    let (i, _) = opt(i, multispace)?;
+    let (i, value) = require_or_cut!(literal(i) =>
+      ParseErrorKind::ExpectedLiteral);
+    ok!(i, AttributeArg::Named(key, value))
+}
+
+#[inline]
+fn self_param<'a>(input: &'a [u8], st: &Ident)
+  -> CutParseResult<'a, FunParam> {
+    let (i, _) = opt(input, multispace)?;
+    let (i, start_pos) = require!(pos(i));
+    let (i, _) = require!(byte(i, b'&'));
+    let (i, _) = opt(i, multispace)?;
+    let (i, _) = require!(keyword_immediate(i, b"self"));
+    let (i, end_pos) = require!(pos(i));
+    ok!(i, FunParam {
+        name: Ident(String::from("self"), None),
+        ty: Type::Deferred(Path(None, st.clone())),
+        mode: ParamMode::ByRef,
         loc: SrcLoc::raw(start_pos, end_pos - start_pos),
     })
 }
@@ -193,16 +360,23 @@ fn varargs(input: &[u8]) -> CutParseResult<FunOptParams> {
     let (i, _) = opt(input, multispace)?;
     let (i, start_pos) = require!(pos(i));
     let (i, name) = require!(ident(i));
+    let (i, ty) = require!(opt!(chain!(i,
+        |i| opt(i, multispace) =>
+        |i| byte(i, b':') =>
+        |i| opt(i, multispace) =>
+        typename
+    )));
     let (i, _) = require!(keyword(i, b"..."));
     let (i, end_pos) = require!(pos(i));
     ok!(i, FunOptParams::VarArgs(
             name,
+            ty.unwrap_or(Type::Variant),
             SrcLoc::raw(start_pos, end_pos - start_pos)
     ))
 }
 
 #[inline]
-fn optfnparam(input: &[u8]) -> CutParseResult<(FunParam, Literal)> {
+fn optfnparam(input: &[u8]) -> CutParseResult<(FunParam, Expr)> {
     let (i, _) = opt(input, multispace)?;
     let (i, start_pos) = require!(pos(i));
     let (i, name) = require!(ident(i) => ParseErrorKind::ExpectedIdent);
@@ -218,7 +392,7 @@ fn optfnparam(input: &[u8]) -> CutParseResult<(FunParam, Literal)> {
     let (i, _) = opt(i, multispace)?;
     let (i, _) = require_or_cut!(byte(i, b'=') =>
       ParseErrorKind::ExpectedDefaultArgument);
-    let (i, default) = require_or_cut!(literal(i) =>
+    let (i, default) = require_or_cut!(expr(i) =>
       ParseErrorKind::ExpectedDefaultArgument);
     let (i, end_pos) = require!(pos(i));
     ok!(i, (
@@ -304,6 +478,62 @@ mod test {
           ParseErrorKind::ExpectedDefaultArgument);
     }
 
+    #[test]
+    fn parse_externs() {
+        expect_parse!(fundef(b" pub extern \"user32\" fn EnumWindows(\
+          cb: fn(isize, isize) -> i32, l: isize) -> i32;") =>
+          FunDef { lib: Some(_), .. });
+        expect_parse!(fundef(b" fn f() { }") => FunDef { lib: None, .. });
+        expect_parse_cut!(fundef(b" extern user32 fn f();") =>
+          ParseErrorKind::ExpectedLiteral);
+        expect_parse_cut!(fundef(b" extern \"user32\" fn f() { }") =>
+          ParseErrorKind::ExpectedByte(b';'));
+        expect_parse_cut!(impl_block(b" impl r { extern \"k\" fn f(); }")
+          => ParseErrorKind::ExpectedByte(b'}'));
+    }
+
+    #[test]
+    fn parse_impls() {
+        expect_parse!(impl_block(b" impl ratio { }") => _);
+        expect_parse_cut!(impl_block(b" impl ratio { fn f(x: i32) { } }") =>
+          ParseErrorKind::ExpectedSelfParam);
+        expect_parse_cut!(impl_block(b" impl { }") =>
+          ParseErrorKind::ExpectedIdent);
+
+        match impl_block(b" impl ratio {\n\
+                             pub fn value(&self) -> f64 { return 1.0; }\n\
+                             fn scale(& self, k: i32) { }\n\
+                           }") {
+            Ok((_, Ok(ref defs))) => {
+                assert_eq!(defs.len(), 2);
+                assert_eq!(defs[0].name, method_name(
+                  &Ident(String::from("ratio"), None),
+                  &Ident(String::from("value"), None)));
+                assert_eq!(defs[0].params.len(), 1);
+                assert_eq!(defs[1].params.len(), 2);
+                assert_eq!(defs[1].params[0].mode, ParamMode::ByRef);
+            },
+            r => panic!("parse result: {:?}", r),
+        }
+
+        expect_parse!(normal_items(b" impl ratio { }") => _);
+        expect_parse!(normal_items(b" fn f() { }") => _);
+    }
+
+    #[test]
+    fn parse_varargs() {
+        expect_parse!(optparams(b"; xs...") =>
+          FunOptParams::VarArgs(_, Type::Variant, _));
+        expect_parse!(optparams(b"; xs: f64...") =>
+          FunOptParams::VarArgs(_, Type::Float64, _));
+        expect_parse!(optparams(b";xs : str ...") =>
+          FunOptParams::VarArgs(_, Type::String, _));
+        expect_parse!(optparams(b"; x: i32 = 7") =>
+          FunOptParams::Named(_));
+        expect_parse!(optparams(b"; tol: f64 = EPS * 10, n: i32 = -1") =>
+          FunOptParams::Named(_));
+    }
+
     #[test]
     fn parse_ret() {
         expect_parse!(fnret(b" -> i32[,,]" ) => Type::Array(_, _));
@@ -336,8 +566,41 @@ mod test {
     #[test]
     fn parse_constants() {
         expect_parse!(constantdef(b" const m: i32 = 7 ;") => Constant { .. });
+        expect_parse!(constantdef(b" const m: i32 = ROWS * COLS + 1;") =>
+          Constant { value: Expr { data: ExprKind::BinOpApp(_, _, _), .. }, .. });
         expect_parse_cut!(constantdef(b" const m: i32 = ! ;") =>
+          ParseErrorKind::ExpectedExpr);
+    }
+
+    #[test]
+    fn parse_attributes() {
+        expect_parse!(attribute(b" #[default_member]") => Attribute { .. });
+        expect_parse!(attribute(b" #[description(\"does a thing\")]") =>
+          Attribute { .. });
+        expect_parse!(attribute(b"#[udf( category = \"Math\", volatile )]") =>
+          Attribute { .. });
+        expect_parse!(staticdef(b"#[description(\"x\")] static x: i32;") =>
+          Static { .. });
+        expect_parse_cut!(attribute(b"#[ ! ]") =>
+          ParseErrorKind::ExpectedIdent);
+        expect_parse_cut!(attribute(b"#[description(\"x\"]") =>
+          ParseErrorKind::ExpectedByte(b')'));
+        expect_parse_cut!(attribute(b"#[udf(category = )]") =>
           ParseErrorKind::ExpectedLiteral);
+
+        match fundef(b" #[description(\"f\")] #[enumerator]\n\
+                       pub fn f() { }") {
+            Ok((_, Ok(FunDef { ref attrs, .. }))) => {
+                assert_eq!(attrs.len(), 2);
+                match attrs[0].args[..] {
+                    [AttributeArg::Lit(Literal::String(ref s))] =>
+                        assert_eq!(s, "f"),
+                    ref args => panic!("attribute args: {:?}", args),
+                }
+                assert!(attrs[1].args.is_empty());
+            },
+            r => panic!("parse result: {:?}", r),
+        }
     }
 
     #[test]
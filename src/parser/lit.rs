@@ -4,6 +4,7 @@ use std::str;
 
 use super::{ParseErrorKind, CutParseResult};
 use super::bits::*;
+use super::ident::IDENT_CONT_CHARS;
 
 use ast::*;
 
@@ -15,8 +16,7 @@ pub fn literal(input: &[u8]) -> CutParseResult<Literal> {
       ; literal_float(input)
       ; literal_int(input)
       ; literal_string(input)
-  //  TODO: "wacky" literal types
-  //  ; literal_date));
+      ; literal_date(input)
     )
 }
 
@@ -37,7 +37,26 @@ fn literal_bool(input: &[u8]) -> CutParseResult<Literal> {
 
 fn literal_int(input: &[u8]) -> CutParseResult<Literal> {
     let (i, _) = opt(input, multispace)?;
-    let (i, num) = require!(digits(i));
+    let (i, radix) = require!(opt!(alt!(i,
+        keyword_immediate(i, b"0x") => |_| Radix::Hex
+      ; keyword_immediate(i, b"0o") => |_| Radix::Octal
+      ; keyword_immediate(i, b"0b") => |_| Radix::Binary
+    )));
+
+    let base = match radix {
+        None => 10,
+        Some(Radix::Hex) => 16,
+        Some(Radix::Octal) => 8,
+        Some(Radix::Binary) => 2,
+    };
+
+    let (i, num) = match radix {
+        None => require!(radix_digits(i, base)),
+        // past the 0x (etc.), this has to be a number
+        Some(_) => require_or_cut!(radix_digits(i, base) =>
+          ParseErrorKind::InvalidLiteral),
+    };
+
     let (i, tag) = require!(opt!(alt!(i,
         keyword_immediate(i, b"u8")
       ; keyword_immediate(i, b"i16")
@@ -45,24 +64,67 @@ fn literal_int(input: &[u8]) -> CutParseResult<Literal> {
       ; keyword_immediate(i, b"isize")
     )));
 
-    let num = unsafe { str::from_utf8_unchecked(num) };
+    // catch e.g. 0b102 or 0o78 here rather than as a confusing
+    //   error later
+    if radix.is_some() {
+        if let Some(b) = i.first() {
+            if IDENT_CONT_CHARS.contains(b) {
+                return cut!(input, ParseErrorKind::InvalidLiteral);
+            }
+        }
+    }
+
     let parsed = match tag {
-        None => num.parse::<i32>().map(Literal::Int32),
-        Some(b"u8") => num.parse::<u8>().map(Literal::UInt8),
-        Some(b"i16") => num.parse::<i16>().map(Literal::Int16),
-        Some(b"i32") => num.parse::<i32>().map(Literal::Int32),
-        Some(b"isize") => num.parse::<i64>().map(Literal::IntPtr),
+        None => i32::from_str_radix(&num, base).map(Literal::Int32),
+        Some(b"u8") => u8::from_str_radix(&num, base).map(Literal::UInt8),
+        Some(b"i16") => i16::from_str_radix(&num, base).map(Literal::Int16),
+        Some(b"i32") => i32::from_str_radix(&num, base).map(Literal::Int32),
+        Some(b"isize") =>
+            i64::from_str_radix(&num, base).map(Literal::IntPtr),
         _ => panic!("dumpster fire: bad tag in int literal"),
     };
 
     match parsed {
-        Ok(lit) => ok!(i, lit),
+        Ok(lit) => match radix {
+            None => ok!(i, lit),
+            Some(radix) => ok!(i, Literal::NonDecimal(radix, Box::new(lit))),
+        },
         // if we have numbers and a tag but fail the numeric parse,
         //   that's an unrecoverable error
         Err(_) => cut!(input, ParseErrorKind::InvalidLiteral),
     }
 }
 
+// digits in the given base, with optional single _ separators between them
+fn radix_digits(input: &[u8], base: u32) -> CutParseResult<String> {
+    let is_digit = |b: &u8| (*b as char).is_digit(base);
+
+    match input.first() {
+        Some(b) if is_digit(b) => { },
+        _ => return err!(input, ParseErrorKind::ExpectedDigit),
+    };
+
+    let mut len = 1;
+    loop {
+        match input.get(len) {
+            Some(b) if is_digit(b) => len += 1,
+            Some(&b'_') => match input.get(len + 1) {
+                Some(b) if is_digit(b) => len += 2,
+                // a trailing or doubled separator
+                _ => return cut!(input, ParseErrorKind::InvalidLiteral),
+            },
+            _ => break,
+        }
+    }
+
+    let num = input[..len].iter()
+        .filter(|b| **b != b'_')
+        .map(|b| *b as char)
+        .collect();
+
+    ok!(&input[len..], num)
+}
+
 fn literal_float(input: &[u8]) -> CutParseResult<Literal> {
     let (i, _) = opt(input, multispace)?;
     let (i, whole) = require!(digits(i));
@@ -127,7 +189,7 @@ fn literal_currency(input: &[u8]) -> CutParseResult<Literal> {
     ok!(i, make_currency(whole, frac))
 }
 
-fn literal_string(input: &[u8]) -> CutParseResult<Literal> {
+pub fn literal_string(input: &[u8]) -> CutParseResult<Literal> {
     let (i, _) = opt(input, multispace)?;
     let (i, _) = require!(byte(i, b'"'));
     let (i, escaped) = require!(escaped_string(i));
@@ -139,6 +201,66 @@ fn literal_string(input: &[u8]) -> CutParseResult<Literal> {
     }
 }
 
+// #yyyy-mm-dd# or #yyyy-mm-dd hh:mm[:ss]#
+fn literal_date(input: &[u8]) -> CutParseResult<Literal> {
+    let (i, _) = opt(input, multispace)?;
+    let (i, _) = require!(byte(i, b'#'));
+
+    // past the #, this can only be a date
+    let (i, year) = require_or_cut!(digits(i) => ParseErrorKind::InvalidLiteral);
+    let (i, _) = require_or_cut!(byte(i, b'-') => ParseErrorKind::InvalidLiteral);
+    let (i, month) = require_or_cut!(digits(i) => ParseErrorKind::InvalidLiteral);
+    let (i, _) = require_or_cut!(byte(i, b'-') => ParseErrorKind::InvalidLiteral);
+    let (i, day) = require_or_cut!(digits(i) => ParseErrorKind::InvalidLiteral);
+
+    let (i, time) = require_or_cut!(
+      opt(i, date_time) => ParseErrorKind::InvalidLiteral);
+    let (i, _) = require_or_cut!(byte(i, b'#') => ParseErrorKind::InvalidLiteral);
+
+    let (hour, minute, second) = match time {
+        None => (b"0" as &[u8], b"0" as &[u8], b"0" as &[u8]),
+        Some((hour, minute, second)) =>
+            (hour, minute, second.unwrap_or(b"0")),
+    };
+
+    fn num<T: str::FromStr>(digits: &[u8]) -> Option<T> {
+        unsafe { str::from_utf8_unchecked(digits) }.parse::<T>().ok()
+    }
+
+    let dt = match (num(year), num(month), num(day),
+      num(hour), num(minute), num(second)) {
+        (Some(year), Some(month), Some(day),
+          Some(hour), Some(minute), Some(second)) => DateTime {
+            year,
+            month,
+            day,
+            hour,
+            minute,
+            second,
+        },
+        _ => return cut!(input, ParseErrorKind::InvalidLiteral),
+    };
+
+    if !dt.is_valid() {
+        return cut!(input, ParseErrorKind::InvalidLiteral);
+    }
+
+    ok!(i, Literal::Date(dt.to_serial()))
+}
+
+// the hh:mm[:ss] part of a date literal
+fn date_time(input: &[u8]) -> CutParseResult<(&[u8], &[u8], Option<&[u8]>)> {
+    let (i, _) = require!(byte(input, b' '));
+    let (i, hour) = require!(digits(i));
+    let (i, _) = require!(byte(i, b':'));
+    let (i, minute) = require!(digits(i));
+    let (i, second) = require!(opt!(chain!(i,
+        |i| byte(i, b':') =>
+        digits
+    )));
+    ok!(i, (hour, minute, second))
+}
+
 #[inline]
 fn make_currency(whole: i64, frac: i16) -> Literal {
     let frac_digits = (frac as f32).log10().ceil() as i16;
@@ -221,6 +343,31 @@ mod test {
           ParseErrorKind::InvalidLiteral);
     }
 
+    #[test]
+    fn parse_radix_ints() {
+        expect_parse!(literal_int(b"1_000_000") => Literal::Int32(1000000));
+        expect_parse!(literal_int(b"0xFF_FF") =>
+          Literal::NonDecimal(Radix::Hex, _));
+        expect_parse!(literal_int(b"0o755i16") =>
+          Literal::NonDecimal(Radix::Octal, _));
+        expect_parse!(literal_int(b"0b1010_0001u8") =>
+          Literal::NonDecimal(Radix::Binary, _));
+        expect_parse_cut!(literal_int(b"0xFFFFi16") =>
+          ParseErrorKind::InvalidLiteral);
+        expect_parse_cut!(literal_int(b"0b102") =>
+          ParseErrorKind::InvalidLiteral);
+        expect_parse_cut!(literal_int(b"0x") =>
+          ParseErrorKind::InvalidLiteral);
+        expect_parse_cut!(literal_int(b"1_") =>
+          ParseErrorKind::InvalidLiteral);
+        expect_parse_cut!(literal_int(b"1__000") =>
+          ParseErrorKind::InvalidLiteral);
+        expect_parse_cut!(literal_int(b"0xFF_i16") =>
+          ParseErrorKind::InvalidLiteral);
+        expect_parse_cut!(literal_int(b"0b_1") =>
+          ParseErrorKind::InvalidLiteral);
+    }
+
     #[test]
     fn parse_floats() {
         expect_parse!(literal_float(b"124.5") => Literal::Float64(124.5));
@@ -247,4 +394,21 @@ mod test {
         expect_parse_err!(literal_string(b"\"unclosed") => _);
         expect_parse_cut!(literal_string(b"   \"invalid \\x escape\"") => _);
     }
+
+    #[test]
+    fn parse_dates() {
+        expect_parse!(literal_date(b"#1899-12-30#") => Literal::Date(0.0));
+        expect_parse!(literal_date(b" #2024-03-15#") => Literal::Date(45366.0));
+        expect_parse!(literal_date(b"#1900-01-01 06:00:00#") =>
+          Literal::Date(2.25));
+        expect_parse!(literal_date(b"#1899-12-29 18:00#") =>
+          Literal::Date(-1.75));
+        expect_parse_err!(literal_date(b"2024-03-15") => _);
+        expect_parse_cut!(literal_date(b"#2023-02-29#") =>
+          ParseErrorKind::InvalidLiteral);
+        expect_parse_cut!(literal_date(b"#2024-03-15 24:00:00#") =>
+          ParseErrorKind::InvalidLiteral);
+        expect_parse_cut!(literal_date(b"#2024-03#") =>
+          ParseErrorKind::InvalidLiteral);
+    }
 }
@@ -22,7 +22,9 @@ pub enum ParseErrorKind {
     ExpectedForSpecifier,
     ExpectedOptParams,
     ExpectedDefaultArgument,
+    ExpectedNamedArgument,
     ExpectedModule,
+    ExpectedSelfParam,
     NoAltMatch,
     LookAhead,
     InvalidLiteral,
@@ -66,8 +68,12 @@ impl fmt::Display for ParseErrorKind {
                 write!(f, "expected optional parameters"),
             ParseErrorKind::ExpectedDefaultArgument =>
                 write!(f, "expected default argument"),
+            ParseErrorKind::ExpectedNamedArgument =>
+                write!(f, "expected named argument"),
             ParseErrorKind::ExpectedModule
                 => write!(f, "expected module definition"),
+            ParseErrorKind::ExpectedSelfParam
+                => write!(f, "expected &self parameter"),
             ParseErrorKind::NoAltMatch
                 => write!(f, "all alternatives failed"), // dumpster fire?
             ParseErrorKind::LookAhead
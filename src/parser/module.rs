@@ -25,7 +25,8 @@ pub fn module(input: &[u8]) -> CutParseResult<Module> {
 
 #[inline]
 fn normal_module(input: &[u8]) -> CutParseResult<Module> {
-    let (i, _) = opt(input, multispace)?;
+    let (i, attrs) = require!(attributes(input));
+    let (i, _) = opt(i, multispace)?;
     let (i, start_pos) = require!(pos(i));
     let (i, _) = require!(keyword_immediate(i, b"mod"));
     let (i, _) = require!(multispace(i));
@@ -33,13 +34,15 @@ fn normal_module(input: &[u8]) -> CutParseResult<Module> {
     let (i, name) = require_or_cut!(ident(i) => ParseErrorKind::ExpectedIdent);
     let (i, _) = opt(i, multispace)?;
     let (i, _) = require_or_cut!(byte(i, b'{'));
-    let (i, items) = require_or_cut!(many(i, normal_item));
+    let (i, items) = require_or_cut!(many(i, normal_items));
+    let items = items.into_iter().flatten().collect();
     let (i, _) = opt(i, multispace)?;
     let (i, _) = require_or_cut!(byte(i, b'}'));
     let (i, end_pos) = require!(pos(i));
     ok!(i, Module {
         name,
         data: ModuleKind::Normal(items),
+        attrs,
         loc: SrcLoc::raw(start_pos, end_pos - start_pos),
     })
 }
@@ -63,7 +66,47 @@ mod test {
           Module { data: ModuleKind::Normal(_), .. });
         expect_parse!(module(b" mod m { fn f() { } struct x { y: i32 }}") =>
           Module { data: ModuleKind::Normal(_), .. });
+        expect_parse!(module(b" #[description(\"m\")] mod m { }") =>
+          Module { data: ModuleKind::Normal(_), .. });
         expect_parse_cut!(module(b"mod { fn f() { } }") =>
           ParseErrorKind::ExpectedIdent);
     }
+
+    #[test]
+    fn parse_doc_comments() {
+        let src = b"/// a module\n\
+                    mod m {\n\
+                        /// adds\n\
+                        ///\n\
+                        fn f() {\n\
+                            /// dropped\n\
+                            return;\n\
+                        }\n\
+                        //// not a doc comment\n\
+                        static x: i32;\n\
+                    }";
+        let d = ::parser::parse_dumpster("test", src).unwrap();
+        let m = &d.modules[0];
+        assert_eq!(m.attrs.len(), 1);
+        match m.data {
+            ModuleKind::Normal(ref items) => {
+                match items[0] {
+                    NormalItem::Function(ref def) => {
+                        assert_eq!(def.attrs.len(), 2);
+                        match def.attrs[0].args[..] {
+                            [AttributeArg::Lit(Literal::String(ref s))] =>
+                                assert_eq!(s, "adds"),
+                            ref args => panic!("doc args: {:?}", args),
+                        }
+                    },
+                    ref item => panic!("item: {:?}", item),
+                }
+
+                match items[1] {
+                    NormalItem::Static(ref s) => assert!(s.attrs.is_empty()),
+                    ref item => panic!("item: {:?}", item),
+                }
+            },
+        }
+    }
 }
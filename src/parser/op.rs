@@ -16,6 +16,7 @@ pub fn assign_op(input: &[u8]) -> CutParseResult<AssignOp> {
       ; keyword_immediate(i, b"/=") => |_| AssignOp::DivAssign
       ; keyword_immediate(i, b"%=") => |_| AssignOp::ModAssign
       ; keyword_immediate(i, b"^=") => |_| AssignOp::PowAssign
+      ; keyword_immediate(i, b"^^=") => |_| AssignOp::BitXorAssign
       ; keyword_immediate(i, b"@=") => |_| AssignOp::StrCatAssign
       ; keyword_immediate(i, b"&=") => |_| AssignOp::BitAndAssign
       ; keyword_immediate(i, b"|=") => |_| AssignOp::BitOrAssign
@@ -39,7 +40,12 @@ pub fn un_op(input: &[u8]) -> CutParseResult<UnOp> {
 pub fn pow_op(input: &[u8]) -> CutParseResult<BinOp> {
     let (i, _) = opt(input, multispace)?;
     let (i, _) = require!(byte(i, b'^'));
-    ok!(i, BinOp::Pow)
+    // disambiguate from ^^ operator by lookahead
+    if let Some(&b'^') = i.first() {
+        err!(input, ParseErrorKind::LookAhead)
+    } else {
+        ok!(i, BinOp::Pow)
+    }
 }
 
 #[inline]
@@ -62,6 +68,15 @@ pub fn addsub_op(input: &[u8]) -> CutParseResult<BinOp> {
     )
 }
 
+#[inline]
+pub fn shift_op(input: &[u8]) -> CutParseResult<BinOp> {
+    let (i, _) = opt(input, multispace)?;
+    alt!(i,
+        keyword_immediate(i, b"<<") => |_| BinOp::Shl
+      ; keyword_immediate(i, b">>") => |_| BinOp::Shr
+    )
+}
+
 #[inline]
 pub fn cmp_op(input: &[u8]) -> CutParseResult<BinOp> {
     let (i, _) = opt(input, multispace)?;
@@ -70,8 +85,8 @@ pub fn cmp_op(input: &[u8]) -> CutParseResult<BinOp> {
       ; keyword_immediate(i, b">=") => |_| BinOp::GtEq
       ; match byte(i, b'<')? {
             (i, Ok(_)) => {
-                // disambiguate from <- "operator" by lookahead
-                if let Some(&b'-') = i.first() {
+                // disambiguate from <- "operator" and << by lookahead
+                if let Some(&b'-') | Some(&b'<') = i.first() {
                         err!(i, ParseErrorKind::LookAhead)
                     } else {
                         ok!(i, BinOp::Lt)
@@ -80,7 +95,18 @@ pub fn cmp_op(input: &[u8]) -> CutParseResult<BinOp> {
 
             (_, Err(e)) => err!(i, e),
         }
-      ; byte(i, b'>') => |_| BinOp::Gt
+      ; match byte(i, b'>')? {
+            (i, Ok(_)) => {
+                // disambiguate from >> by lookahead
+                if let Some(&b'>') = i.first() {
+                        err!(i, ParseErrorKind::LookAhead)
+                    } else {
+                        ok!(i, BinOp::Gt)
+                    }
+            },
+
+            (_, Err(e)) => err!(i, e),
+        }
     )
 }
 
@@ -107,6 +133,18 @@ pub fn bitand_op(input: &[u8]) -> CutParseResult<BinOp> {
     }
 }
 
+#[inline]
+pub fn bitxor_op(input: &[u8]) -> CutParseResult<BinOp> {
+    let (i, _) = opt(input, multispace)?;
+    let (i, _) = require!(keyword_immediate(i, b"^^"));
+    // disambiguate from ^^= operator by lookahead
+    if let Some(&b'=') = i.first() {
+        err!(input, ParseErrorKind::LookAhead)
+    } else {
+        ok!(i, BinOp::BitXor)
+    }
+}
+
 #[inline]
 pub fn bitor_op(input: &[u8]) -> CutParseResult<BinOp> {
     let (i, _) = opt(input, multispace)?;
@@ -141,6 +179,7 @@ mod test {
     fn parse_assign_ops() {
         expect_parse!(assign_op(b" =") => AssignOp::Assign);
         expect_parse!(assign_op(b"&&=") => AssignOp::LogAndAssign);
+        expect_parse!(assign_op(b"^^=") => AssignOp::BitXorAssign);
         expect_parse_err!(assign_op(b"**=") => _);
     }
 
@@ -162,6 +201,13 @@ mod test {
         expect_parse!(bitor_op(b"|") => BinOp::BitOr);
         expect_parse!(logand_op(b"  &&") => BinOp::LogAnd);
         expect_parse!(logor_op(b"  ||") => BinOp::LogOr);
+        expect_parse!(bitxor_op(b" ^^") => BinOp::BitXor);
+        expect_parse!(shift_op(b"<<") => BinOp::Shl);
+        expect_parse!(shift_op(b" >>") => BinOp::Shr);
         expect_parse_err!(pow_op(b"xx") => _);
+        expect_parse_err!(pow_op(b"^^") => ParseErrorKind::LookAhead);
+        expect_parse_err!(bitxor_op(b"^^=") => ParseErrorKind::LookAhead);
+        expect_parse_err!(cmp_op(b"<<") => _);
+        expect_parse_err!(cmp_op(b">>") => _);
     }
 }
@@ -4,8 +4,8 @@ use std::fmt;
 
 use super::{CutParseResult, ParseError, ParseResult};
 
-use ast::Dumpster;
-use fold::ASTFolder;
+use ast::*;
+use fold::*;
 
 #[derive(Clone, Debug)]
 pub struct SrcLoc {
@@ -55,6 +55,8 @@ pub struct MappedSource {
     gaps: Vec<(usize, usize)>,
     // line beginnings
     lines: Vec<usize>,
+    // doc comments: (position in processed source, text)
+    docs: Vec<(usize, String)>,
 }
 
 impl MappedSource {
@@ -89,7 +91,10 @@ impl MappedSource {
     }
 
     pub fn rebase_srclocs(&self, dumpster: Dumpster) -> Dumpster {
-        let mut folder = SrcLocRebaseFolder { map: self };
+        let mut folder = SrcLocRebaseFolder {
+            map: self,
+            doc_window_start: 0,
+        };
         folder.fold_dumpster(dumpster)
     }
 
@@ -125,6 +130,7 @@ pub fn map_source(file: &str, input: &[u8]) -> MappedSource {
         src: Vec::new(),
         gaps: Vec::new(),
         lines: vec![0],
+        docs: Vec::new(),
     };
 
     let mut gap_begin = 0;
@@ -201,6 +207,17 @@ pub fn map_source(file: &str, input: &[u8]) -> MappedSource {
                     in_line_comment = true;
                     gap_begin = pos;
                     skipped = 2;
+
+                    // /// (but not ////) starts a doc comment
+                    if input.get(pos + 2) == Some(&b'/')
+                      && input.get(pos + 3) != Some(&b'/') {
+                        let text = input[pos + 3..].split(|&b| b == b'\n')
+                            .next().unwrap_or(&[]);
+                        let text = String::from_utf8_lossy(text);
+                        let text = text.trim_end_matches('\r');
+                        let text = text.strip_prefix(' ').unwrap_or(text);
+                        res.docs.push((res.src.len(), String::from(text)));
+                    }
                 },
                 Some((_, b'*')) => {
                     in_block_comment = true;
@@ -232,9 +249,81 @@ pub fn map_source(file: &str, input: &[u8]) -> MappedSource {
 
 struct SrcLocRebaseFolder<'a> {
     pub map: &'a MappedSource,
+    // doc comments between here and the start of the next item or module
+    //   belong to it
+    doc_window_start: usize,
+}
+
+impl<'a> SrcLocRebaseFolder<'a> {
+    // doc comments become #[doc("...")] attributes, ahead of any others
+    fn attach_docs(&self, attrs: Vec<Attribute>, start: usize)
+      -> Vec<Attribute> {
+        let mut docs: Vec<Attribute> = self.map.docs.iter()
+          .filter(|&&(pos, _)| pos >= self.doc_window_start && pos < start)
+          .map(|&(pos, ref text)| Attribute {
+              name: Ident(String::from("doc"), None),
+              args: vec![AttributeArg::Lit(Literal::String(text.clone()))],
+              loc: SrcLoc::raw(pos + self.map.base(), 0),
+          }).collect();
+        docs.extend(attrs);
+        docs
+    }
+
+    fn raw_start(&self, loc: &SrcLoc) -> usize {
+        loc.start - self.map.base()
+    }
+
+    fn raw_end(&self, loc: &SrcLoc) -> usize {
+        loc.start + loc.len - self.map.base()
+    }
 }
 
 impl<'a> ASTFolder for SrcLocRebaseFolder<'a> {
+    fn fold_module(&mut self, mut m: Module) -> Module {
+        let start = self.raw_start(&m.loc);
+        let end = self.raw_end(&m.loc);
+        m.attrs = self.attach_docs(m.attrs, start);
+        self.doc_window_start = start;
+        let m = noop_fold_module(self, m);
+        self.doc_window_start = end;
+        m
+    }
+
+    fn fold_normal_item(&mut self, item: NormalItem, module: &Ident)
+      -> NormalItem {
+        let (start, end) = {
+            let loc = match item {
+                NormalItem::Function(ref def) => &def.loc,
+                NormalItem::Struct(ref def) => &def.loc,
+                NormalItem::Static(ref def) => &def.loc,
+                NormalItem::Const(ref def) => &def.loc,
+            };
+            (self.raw_start(loc), self.raw_end(loc))
+        };
+
+        let item = match item {
+            NormalItem::Function(mut def) => {
+                def.attrs = self.attach_docs(def.attrs, start);
+                NormalItem::Function(def)
+            },
+            NormalItem::Struct(mut def) => {
+                def.attrs = self.attach_docs(def.attrs, start);
+                NormalItem::Struct(def)
+            },
+            NormalItem::Static(mut def) => {
+                def.attrs = self.attach_docs(def.attrs, start);
+                NormalItem::Static(def)
+            },
+            NormalItem::Const(mut def) => {
+                def.attrs = self.attach_docs(def.attrs, start);
+                NormalItem::Const(def)
+            },
+        };
+
+        self.doc_window_start = end;
+        noop_fold_normal_item(self, item, module)
+    }
+
     fn fold_srcloc(&mut self, loc: SrcLoc) -> SrcLoc {
         let (line, start) = self.map.pos_to_line_pos(
             self.map.map_to_original(loc.start - self.map.base()));
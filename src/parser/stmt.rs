@@ -10,11 +10,15 @@ use ast::*;
 
 pub fn stmt(input: &[u8]) -> CutParseResult<Stmt> {
     alt!(input,
-        decl(input)
+        destructure(input) // must try this before decl
+      ; decl(input)
       ; ret(input)
       ; print(input)
+      ; assert(input)
+      ; vbblock(input)
       ; ifstmt(input)
       ; whileloop(input)
+      ; withstmt(input)
       ; foralong(input) // must try this before forloop
       ; forloop(input)
       ; dealloc(input) // must try this before alloc/realloc
@@ -53,6 +57,35 @@ fn decl(input: &[u8]) -> CutParseResult<Stmt> {
     })
 }
 
+fn destructure(input: &[u8]) -> CutParseResult<Stmt> {
+    let (i, _) = opt(input, multispace)?;
+    let (i, start_pos) = require!(pos(i));
+    let (i, _) = require!(keyword_immediate(i, b"let"));
+    let (i, _) = opt(i, multispace)?;
+    let (i, _) = require!(byte(i, b'('));
+    // cut on error from this point
+    let (i, idents) = require_or_cut!(delimited_at_least_one(i,
+        ident,
+        |i| chain!(i,
+            |i| opt(i, multispace) =>
+            |i| byte(i, b',')
+        )) => ParseErrorKind::ExpectedIdent);
+    let (i, _) = opt(i, multispace)?;
+    let (i, _) = require_or_cut!(byte(i, b')'));
+    let (i, _) = opt(i, multispace)?;
+    let (i, ty) = require!(opt!(chain!(i,
+        |i| byte(i, b':') =>
+        |i| cut_if_err!(typename(i) => ParseErrorKind::ExpectedTypename)
+    )));
+    let (i, init) = require_or_cut!(varinit(i));
+    let (i, _) = require_or_cut!(terminator(i));
+    let (i, end_pos) = require!(pos(i));
+    ok!(i, Stmt {
+        data: StmtKind::Destructure(idents, ty, init),
+        loc: SrcLoc::raw(start_pos, end_pos - start_pos),
+    })
+}
+
 #[inline]
 fn vardeclinit(input: &[u8]) -> CutParseResult<(Ident, Type, Option<Expr>)> {
     let (i, decl) = require!(vardecl(input));
@@ -119,6 +152,34 @@ fn print(input: &[u8]) -> CutParseResult<Stmt> {
     })
 }
 
+fn assert(input: &[u8]) -> CutParseResult<Stmt> {
+    let (i, _) = opt(input, multispace)?;
+    let (i, start_pos) = require!(pos(i));
+    let (i, _) = require!(keyword_immediate(i, b"assert"));
+    let (i, _) = require!(multispace(i));
+    // cut on error after this point
+    let cond_input = i;
+    let (i, cond) = require_or_cut!(expr(i) => ParseErrorKind::ExpectedExpr);
+    let (i, cond_end) = require!(pos(i));
+    let text = &cond_input[..cond_end - cond_input.as_ptr() as usize];
+    let text = String::from_utf8_lossy(text).split_whitespace()
+        .collect::<Vec<_>>().join(" ");
+
+    let (i, msg) = require!(opt(i, |i| {
+        let (i, _) = opt(i, multispace)?;
+        let (i, _) = require!(byte(i, b','));
+        let (i, msg) = require_or_cut!(expr(i)
+          => ParseErrorKind::ExpectedExpr);
+        ok!(i, msg)
+    }));
+    let (i, _) = require_or_cut!(terminator(i));
+    let (i, end_pos) = require!(pos(i));
+    ok!(i, Stmt {
+        data: StmtKind::Assert(cond, msg, text),
+        loc: SrcLoc::raw(start_pos, end_pos - start_pos),
+    })
+}
+
 fn ifstmt(input: &[u8]) -> CutParseResult<Stmt> {
     let (i, _) = opt(input, multispace)?;
     let (i, start_pos) = require!(pos(i));
@@ -196,6 +257,26 @@ fn whileloop(input: &[u8]) -> CutParseResult<Stmt> {
     })
 }
 
+fn withstmt(input: &[u8]) -> CutParseResult<Stmt> {
+    let (i, _) = opt(input, multispace)?;
+    let (i, start_pos) = require!(pos(i));
+    let (i, _) = require!(keyword_immediate(i, b"with"));
+    let (i, _) = require!(multispace(i));
+    // after here we can cut on error
+    let (i, subject) = require_or_cut!(expr(i)
+      => ParseErrorKind::ExpectedExpr);
+    let (i, _) = opt(i, multispace)?;
+    let (i, _) = require_or_cut!(byte(i, b'{'));
+    let (i, body) = require_or_cut!(many(i, stmt));
+    let (i, _) = opt(i, multispace)?;
+    let (i, _) = require_or_cut!(byte(i, b'}'));
+    let (i, end_pos) = require!(pos(i));
+    ok!(i, Stmt {
+        data: StmtKind::With(subject, body),
+        loc: SrcLoc::raw(start_pos, end_pos - start_pos),
+    })
+}
+
 // must be tried before forloop or forloop will cut on lack of type ascription
 //   in vars
 fn foralong(input: &[u8]) -> CutParseResult<Stmt> {
@@ -235,11 +316,25 @@ fn forloop(input: &[u8]) -> CutParseResult<Stmt> {
     let (i, _) = require!(keyword_immediate(i, b"for"));
     let (i, _) = require!(multispace(i));
     // after this point, cut on error
-    let (i, var) = require_or_cut!(forvardecl(i));
-    let (i, spec) = require_or_cut!(alt!(i,
-        for_range(i) => |(from, to, step)| ForSpec::Range(from, to, step)
-      ; for_each(i) => ForSpec::Each
-    ) => ParseErrorKind::ExpectedForSpecifier);
+    let (i, indexed) = require!(opt(i, indexed_forvars));
+    let (i, (indices, var)) = match indexed {
+        Some(vars) => (i, vars),
+        None => {
+            let (i, var) = require_or_cut!(forvardecl(i));
+            (i, (vec![], var))
+        },
+    };
+    // only for-each loops can have index variables
+    let (i, spec) = if indices.is_empty() {
+        require_or_cut!(alt!(i,
+            for_range(i) => |(from, to, step)| ForSpec::Range(from, to, step)
+          ; for_each(i) => |expr| ForSpec::Each(expr, vec![])
+        ) => ParseErrorKind::ExpectedForSpecifier)
+    } else {
+        let (i, of) = require_or_cut!(for_each(i)
+          => ParseErrorKind::ExpectedForSpecifier);
+        (i, ForSpec::Each(of, indices))
+    };
     let (i, _) = opt(i, multispace)?;
     let (i, _) = require_or_cut!(byte(i, b'{'));
     let (i, body) = require_or_cut!(many(i, stmt));
@@ -256,6 +351,28 @@ fn forloop(input: &[u8]) -> CutParseResult<Stmt> {
     })
 }
 
+// index variables and element variable for an indexed for-each loop,
+//   e.g. `(i, j, x: &f64)`
+fn indexed_forvars(input: &[u8])
+  -> CutParseResult<(Vec<Ident>, (Ident, Type, ParamMode))> {
+    let (i, _) = opt(input, multispace)?;
+    let (i, _) = require!(byte(i, b'('));
+    let (i, indices) = require!(many(i, |input| {
+        let (i, index) = require!(ident(input));
+        let (i, _) = opt(i, multispace)?;
+        let (i, _) = require!(input, byte(i, b','));
+        ok!(i, index)
+    }));
+    // after this point, cut on error
+    if indices.is_empty() {
+        return cut!(i, ParseErrorKind::ExpectedIdent);
+    }
+    let (i, var) = require_or_cut!(forvardecl(i));
+    let (i, _) = opt(i, multispace)?;
+    let (i, _) = require_or_cut!(byte(i, b')'));
+    ok!(i, (indices, var))
+}
+
 #[inline]
 fn forvardecl(input: &[u8]) -> CutParseResult<(Ident, Type, ParamMode)> {
     let (i, name) = require!(ident(input) => ParseErrorKind::ExpectedIdent);
@@ -417,6 +534,50 @@ fn assignment(input: &[u8]) -> CutParseResult<Stmt> {
 }
 
 #[inline]
+// a passthrough VB statement block e.g. vb reads(x) writes(y) `...`;
+fn vbblock(input: &[u8]) -> CutParseResult<Stmt> {
+    let (i, _) = opt(input, multispace)?;
+    let (i, start_pos) = require!(pos(i));
+    let (i, _) = require!(keyword_immediate(i, b"vb"));
+    let (i, _) = require!(multispace(i));
+    let (i, reads) = require!(opt(i, |i| vb_effects(i, b"reads")));
+    let (i, writes) = require!(opt(i, |i| vb_effects(i, b"writes")));
+    let (i, _) = opt(i, multispace)?;
+    let (i, _) = if reads.is_some() || writes.is_some() {
+        require_or_cut!(byte(i, b'`'))
+    } else {
+        require!(byte(i, b'`'))
+    };
+    // cut on error after this point
+    let (i, vb) = require_or_cut!(bytes_not(i, b'`'));
+    let (i, _) = require_or_cut!(byte(i, b'`'));
+    let (i, _) = require_or_cut!(terminator(i));
+    let (i, end_pos) = require!(pos(i));
+    ok!(i, Stmt {
+        data: StmtKind::VbBlock(Vec::from(vb), reads.unwrap_or_default(),
+          writes.unwrap_or_default()),
+        loc: SrcLoc::raw(start_pos, end_pos - start_pos),
+    })
+}
+
+fn vb_effects<'a>(input: &'a [u8], kw: &'static [u8])
+  -> CutParseResult<'a, Vec<Path>> {
+    let (i, _) = opt(input, multispace)?;
+    let (i, _) = require!(keyword_immediate(i, kw));
+    let (i, _) = opt(i, multispace)?;
+    let (i, _) = require!(byte(i, b'('));
+    // cut on error after this point
+    let (i, names) = require_or_cut!(delimited_at_least_one(i,
+        ident,
+        |i| chain!(i,
+            |i| opt(i, multispace) =>
+            |i| byte(i, b',')
+        )) => ParseErrorKind::ExpectedIdent);
+    let (i, _) = opt(i, multispace)?;
+    let (i, _) = require_or_cut!(byte(i, b')'));
+    ok!(i, names.into_iter().map(|n| Path(None, n)).collect())
+}
+
 fn exprstmt(input: &[u8]) -> CutParseResult<Stmt> {
     let (i, e) = require!(expr(input));
     let (i, _) = require_or_cut!(terminator(i));
@@ -433,6 +594,25 @@ mod test {
 
     #[test]
     fn test_stmts() {
+        expect_parse!(stmt(b"let (a, b) = f(x);") => Stmt {
+            data: StmtKind::Destructure(_, None, _),
+            ..
+        });
+
+        expect_parse!(stmt(b"let(a,b ,c) : (i32, str, f64) = t;") => Stmt {
+            data: StmtKind::Destructure(_, Some(Type::Tuple(_)), _),
+            ..
+        });
+
+        expect_parse_cut!(stmt(b"let (a, 3) = f(x);") =>
+          ParseErrorKind::ExpectedByte(b')'));
+
+        expect_parse_cut!(stmt(b"let () = f(x);") =>
+          ParseErrorKind::ExpectedIdent);
+
+        expect_parse_cut!(stmt(b"let (a, b);") =>
+          ParseErrorKind::ExpectedByte(b'='));
+
         expect_parse!(stmt(b"print f(x);") => Stmt {
             data: StmtKind::Print(_),
             ..
@@ -513,6 +693,48 @@ mod test {
         expect_parse!(stmt(b" while 1 { return 17; }") =>
           Stmt { data: StmtKind::WhileLoop{ .. }, .. });
 
+        expect_parse!(stmt(b" with wb.sheets.item(1) { .name = \"x\"; \
+          .range(\"A1\").value = .name @ \"!\"; .activate(); }") =>
+          Stmt { data: StmtKind::With(_, _), .. });
+
+        expect_parse_cut!(stmt(b" with { .x = 1; }") =>
+          ParseErrorKind::ExpectedExpr);
+
+        match stmt(b" assert  x >\t0 &&  y, \"bad\" @ z;") {
+            Ok((_, Ok(Stmt {
+                data: StmtKind::Assert(_, Some(_), ref text), ..
+            }))) => assert_eq!(text, "x > 0 && y"),
+            r => panic!("bad assert parse: {:?}", r),
+        }
+
+        expect_parse!(stmt(b" assert f(x);") =>
+          Stmt { data: StmtKind::Assert(_, None, _), .. });
+
+        expect_parse_cut!(stmt(b" assert x,;") =>
+          ParseErrorKind::ExpectedExpr);
+
+        expect_parse!(stmt(b" asserted(x);") =>
+          Stmt { data: StmtKind::ExprStmt(_), .. });
+
+        expect_parse!(stmt(b" vb `On Error Resume Next`;") =>
+          Stmt { data: StmtKind::VbBlock(_, _, _), .. });
+
+        match stmt(b" vb reads(a, b) writes (c) `\n  Line Input #1, c\n`;") {
+            Ok((_, Ok(Stmt {
+                data: StmtKind::VbBlock(_, ref reads, ref writes), ..
+            }))) => {
+                assert_eq!(reads.len(), 2);
+                assert_eq!(writes.len(), 1);
+            },
+            r => panic!("bad vb block parse: {:?}", r),
+        }
+
+        expect_parse_cut!(stmt(b" vb writes(c);") =>
+          ParseErrorKind::ExpectedByte(b'`'));
+
+        expect_parse!(stmt(b" vb = 1;") =>
+          Stmt { data: StmtKind::Assign(_, _, _), .. });
+
         expect_parse!(stmt(b" for x: i32 = 1:10 { print x; }") => Stmt {
             data: StmtKind::ForLoop {
                 spec: ForSpec::Range(_, _, _),
@@ -523,7 +745,7 @@ mod test {
 
         expect_parse!(stmt(b" for x: i32 in xs { print x; }") => Stmt {
             data: StmtKind::ForLoop {
-                spec: ForSpec::Each(_),
+                spec: ForSpec::Each(_, _),
                 ..
             },
             ..
@@ -531,12 +753,27 @@ mod test {
 
         expect_parse!(stmt(b" for x: &something in xs { print x; }") => Stmt {
             data: StmtKind::ForLoop {
-                spec: ForSpec::Each(_),
+                spec: ForSpec::Each(_, _),
                 ..
             },
             ..
         });
 
+        match stmt(b" for (i, j, x: &f64) in xs { x = i * j; }") {
+            Ok((_, Ok(Stmt {
+                data: StmtKind::ForLoop {
+                    var: (_, Type::Float64, ParamMode::ByRef),
+                    spec: ForSpec::Each(_, indices),
+                    ..
+                },
+                ..
+            }))) => assert_eq!(indices.len(), 2),
+            r => panic!("bad indexed for-each parse: {:?}", r),
+        }
+
+        expect_parse_cut!(stmt(b" for (i, x: i32) = 1:10 { }") =>
+          ParseErrorKind::ExpectedForSpecifier);
+
         expect_parse!(stmt(b" for x,y,z along arr { print arr[x, y, z]; }") =>
           Stmt {
               data: StmtKind::ForAlong {
@@ -187,6 +187,29 @@ impl ASTVisitor for FnNameLocalGensymCollectVisitor {
 
 struct ForLoopVarGensymFolder;
 
+impl ForLoopVarGensymFolder {
+    // rename a loop's variable within its body
+    fn rename(ident: &Ident, body: Vec<Stmt>, module: &Ident,
+      function: &Ident) -> (Ident, Vec<Stmt>) {
+        let g = gensym(Some(ident.clone()));
+        let mut sub = ScopedSubstitutionFolder {
+            orig: ident.clone(),
+            replace: g.clone(),
+            module: Some(module.clone()),
+            function: Some(function.clone()),
+            defns: false, // I think
+            values: true,
+            fns: false,
+            types: false,
+            members: false,
+            modules: false,
+        };
+
+        let body = sub.fold_stmt_list(body, module, function);
+        (g, body)
+    }
+}
+
 impl ASTFolder for ForLoopVarGensymFolder {
     fn fold_stmt(&mut self, stmt: Stmt, module: &Ident, function: &Ident)
   -> Stmt {
@@ -194,22 +217,23 @@ impl ASTFolder for ForLoopVarGensymFolder {
 
         match stmt.data {
             StmtKind::ForLoop { var: (ident, ty, mode), spec, body } => {
-                let g = gensym(Some(ident.clone()));
-                let body = {
-                    let mut sub = ScopedSubstitutionFolder {
-                        orig: ident.clone(),
-                        replace: g.clone(),
-                        module: Some(module.clone()),
-                        function: Some(function.clone()),
-                        defns: false, // I think
-                        values: true,
-                        fns: false,
-                        types: false,
-                        members: false,
-                        modules: false,
-                    };
-
-                    sub.fold_stmt_list(body, module, function)
+                let (g, mut body) = Self::rename(&ident, body, module,
+                  function);
+
+                // index variables are scoped the same way
+                let spec = match spec {
+                    ForSpec::Each(of, indices) => {
+                        let mut new_indices = vec![];
+                        for index in indices {
+                            let (g, renamed) = Self::rename(&index, body,
+                              module, function);
+                            body = renamed;
+                            new_indices.push(g);
+                        }
+                        ForSpec::Each(of, new_indices)
+                    },
+
+                    spec => spec,
                 };
 
                 Stmt {
@@ -226,25 +250,9 @@ impl ASTFolder for ForLoopVarGensymFolder {
             StmtKind::ForAlong { vars, along, mut body } => {
                 let mut new_vars = vec![];
                 for v in vars {
-                    let g = gensym(Some(v.clone()));
-
-                    body = {
-                        let mut sub = ScopedSubstitutionFolder {
-                            orig: v.clone(),
-                            replace: g.clone(),
-                            module: Some(module.clone()),
-                            function: Some(function.clone()),
-                            defns: false, // I think
-                            values: true,
-                            fns: false,
-                            types: false,
-                            members: false,
-                            modules: false,
-                        };
-
-                        sub.fold_stmt_list(body, module, function)
-                    };
-
+                    let (g, renamed) = Self::rename(&v, body, module,
+                      function);
+                    body = renamed;
                     new_vars.push(g);
                 }
 
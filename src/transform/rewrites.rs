@@ -2,9 +2,13 @@ use ast::*;
 use analysis::*;
 use super::gensym::*;
 use parser::SrcLoc;
+use codegen::runtime::RuntimeHelper;
+
+use std::collections::{HashMap, HashSet};
 
 use fold;
 use fold::ASTFolder;
+use visit::NameCtxt;
 
 /// combine multiple dumpsters into one
 pub fn merge_dumpsters(dumpsters: Vec<Dumpster>) -> Dumpster {
@@ -14,6 +18,88 @@ pub fn merge_dumpsters(dumpsters: Vec<Dumpster>) -> Dumpster {
     }
 }
 
+/// how map types are bound to Scripting.Dictionary
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum MapBinding {
+    /// as Object, created with CreateObject (no reference needed)
+    Late,
+    /// as Scripting.Dictionary (needs a reference to Microsoft Scripting
+    ///   Runtime)
+    Early,
+}
+
+/// lower map operations to Scripting.Dictionary calls (reading a missing key
+/// raises an error, rather than adding it), and map types to the dictionary
+/// type for the given binding
+pub fn map_rewrite(dumpster: Dumpster, symtab: &mut SymbolTable,
+  binding: MapBinding) -> Dumpster {
+    let mut f = MapRewriteFolder {
+        symtab,
+        binding,
+        keys_fns: Vec::new(),
+        get_fns: Vec::new(),
+        defs: Vec::new(),
+    };
+    let dumpster = f.fold_dumpster(dumpster);
+
+    let mut f = MapTypeFolder { binding };
+    f.fold_dumpster(dumpster)
+}
+
+/// lower list operations to generated helper functions (growing the backing
+/// array by doubling), and list types to generated structs holding the
+/// backing array and length (one per element type)
+pub fn list_rewrite(dumpster: Dumpster, symtab: &mut SymbolTable)
+  -> Dumpster {
+    let mut f = ListRewriteFolder {
+        symtab,
+        list_fns: Vec::new(),
+        allocated_fns: Vec::new(),
+        defs: Vec::new(),
+    };
+    let dumpster = f.fold_dumpster(dumpster);
+
+    let mut f = ListTypeFolder {
+        symtab: f.symtab,
+        shapes: Vec::new(),
+        defs: Vec::new(),
+    };
+    f.fold_dumpster(dumpster)
+}
+
+/// replace constant expressions VB can't evaluate itself (in consts and
+/// optional parameter defaults) with their values
+pub fn constexpr_rewrite(dumpster: Dumpster) -> Dumpster {
+    let mut f = ConstexprRewriteFolder::new(&dumpster);
+    f.fold_dumpster(dumpster)
+}
+
+/// replace method calls on structs with calls to the (mangled, module-level)
+/// functions their impl blocks were lowered to
+pub fn method_rewrite(dumpster: Dumpster) -> Dumpster {
+    let mut f = MethodRewriteFolder { withs: Vec::new() };
+    f.fold_dumpster(dumpster)
+}
+
+/// refer to named arguments by their (possibly gensymmed) parameter names,
+/// or move them into position where VB won't take them by name
+pub fn named_arg_rewrite(dumpster: Dumpster, symtab: &mut SymbolTable)
+  -> Dumpster {
+    let mut f = NamedArgRewriteFolder {
+        symtab,
+        before_stmt_stack: Vec::new(),
+    };
+    f.fold_dumpster(dumpster)
+}
+
+/// replace tuple types with generated structs (one per distinct shape), and
+/// tuple expressions and destructuring with member accesses
+pub fn tuple_rewrite(dumpster: Dumpster, symtab: &mut SymbolTable)
+  -> Dumpster {
+    let mut f = TupleRewriteFolder::new(symtab);
+    f.fold_dumpster(dumpster)
+}
+
 /// replace casts with other expressions where necessary
 pub fn cast_rewrite(dumpster: Dumpster, _symtab: &mut SymbolTable) -> Dumpster {
     let mut f = CastRewriteFolder { };
@@ -48,352 +134,449 @@ pub fn alloc_along_rewrite(dumpster: Dumpster, _symtab: &mut SymbolTable)
     f.fold_dumpster(dumpster)
 }
 
-struct CastRewriteFolder {
-    // we'll need these for object-type gensyms
-    // symtab: &'a mut SymbolTable,
-    // before_stmt_stack: Vec<Vec<Stmt>>,
+/// replace array view parameters with (array, first, last) parameter triples,
+/// and copy slices into temporaries wherever they're used as values
+pub fn slice_rewrite(dumpster: Dumpster, symtab: &mut SymbolTable)
+  -> Dumpster {
+    let mut f = SliceRewriteFolder::new(symtab);
+    f.fold_dumpster(dumpster)
 }
 
-impl CastRewriteFolder {
-    /*
-    fn new(symtab: &'a mut SymbolTable) -> Self {
-        CastRewriteFolder {
-            symtab,
-            // before_stmt_stack: Vec::new(),
-        }
-    }
-    */
+/// replace equality tests on structs and arrays with calls to generated
+/// comparison functions (one per compared type, in each module that needs it)
+pub fn eq_rewrite(dumpster: Dumpster, symtab: &mut SymbolTable) -> Dumpster {
+    let mut f = EqRewriteFolder {
+        symtab,
+        eq_fns: Vec::new(),
+        allocated_fns: Vec::new(),
+        defs: Vec::new(),
+    };
+    f.fold_dumpster(dumpster)
 }
 
-impl ASTFolder for CastRewriteFolder {
-    fn fold_expr(&mut self, expr: Expr, module: &Ident,
-      function: Option<&Ident>) -> Expr {
-        let Expr { data, ty, loc } =
-            fold::noop_fold_expr(self, expr, module, function);
+/// replace printing and string casts of structs and arrays with calls to
+/// generated rendering functions (structs must derive Debug)
+pub fn debug_str_rewrite(dumpster: Dumpster, symtab: &mut SymbolTable)
+  -> Dumpster {
+    let mut f = DebugStrRewriteFolder {
+        symtab,
+        str_fns: Vec::new(),
+        allocated_fns: Vec::new(),
+        defs: Vec::new(),
+    };
+    f.fold_dumpster(dumpster)
+}
 
-        let data = match data {
-            ExprKind::Cast(expr, cast_ty) => {
-                let expr_ty = expr.ty.as_ref()
-                  .expect("dumpster fire: untyped expression \
-                          in cast rewriter")
-                  .clone(); // TODO: this is stupid and only to satisfy the
-                            //   borrow checker
+/// how assert statements are compiled
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum AssertMode {
+    /// raise an error reporting the source location and condition
+    Raise,
+    /// use VB's Debug.Assert (which only breaks in the IDE)
+    Debug,
+    /// remove them entirely
+    Strip,
+}
 
-                // no-op cast: just lift out the expression
-                if expr_ty == cast_ty {
-                    return *expr;
-                }
+/// lower assert statements according to the assertion mode
+pub fn assert_rewrite(dumpster: Dumpster, mode: AssertMode) -> Dumpster {
+    let mut f = AssertRewriteFolder { mode };
+    f.fold_dumpster(dumpster)
+}
 
-                // no special implementations when casting to variant
-                //   (we're just boxing)
-                if cast_ty == Type::Variant {
-                    return Expr {
-                        data: ExprKind::Cast(expr, cast_ty),
-                        ty,
-                        loc,
-                    };
-                }
+/// replace fixed-width integer arithmetic with calls to runtime helpers which
+/// report the source location of overflows
+pub fn checked_arithmetic_rewrite(dumpster: Dumpster) -> Dumpster {
+    let mut f = CheckedArithmeticFolder;
+    f.fold_dumpster(dumpster)
+}
 
-                let loc = expr.loc.clone();
+/// insert Application.Volatile into volatile Excel UDFs, and generate a
+/// module with a sub registering each UDF's description, category, and
+/// parameter descriptions via Application.MacroOptions
+pub fn udf_rewrite(dumpster: Dumpster) -> Dumpster {
+    let mut f = UdfRewriteFolder { registrations: Vec::new() };
+    let mut dumpster = f.fold_dumpster(dumpster);
 
-                match expr_ty {
-                    Type::Bool => ExprKind::CondExpr {
-                        cond: expr,
-                        if_expr: Box::new(Expr {
-                            data: ExprKind::Lit(
-                              Literal::num_of_type(&cast_ty, 1)
-                              .expect("dumpster fire: bad numeric type \
-                                in cast rewriter")),
-                            ty: Some(cast_ty.clone()),
-                            loc: loc.clone(),
-                        }),
-                        else_expr: Box::new(Expr {
-                            data: ExprKind::Lit(
-                              Literal::num_of_type(&cast_ty, 0)
-                              .expect("dumpster fire: bad numeric type \
-                                in cast rewriter")),
-                            ty: Some(cast_ty.clone()),
-                            loc,
-                        })
-                    },
+    if f.registrations.is_empty() {
+        return dumpster;
+    }
 
-                    Type::Obj | Type::Object(_) => {
-                        panic!("TODO: object dynamic casts")
-                    },
+    // don't clobber a user module (VB names are case-insensitive)
+    let mut name = String::from("udf_registration");
+    let mut n = 0;
+    while dumpster.modules.iter()
+      .any(|m| m.name.0.eq_ignore_ascii_case(&name)) {
+        n += 1;
+        name = format!("udf_registration{}", n);
+    }
 
-                    _ => ExprKind::Cast(expr, cast_ty),
-                }
-            },
+    let loc = SrcLoc::empty();
+    let mut code = Vec::new();
+    for line in f.registrations {
+        code.extend(line.into_bytes());
+        code.push(b'\n');
+    }
 
-            data => data,
-        };
+    let register = FunDef {
+        name: Ident(String::from("register_udfs"), None),
+        access: Access::Public,
+        params: vec![],
+        optparams: None,
+        ret: Type::Void,
+        body: vec![Stmt {
+            data: StmtKind::VbBlock(code, vec![], vec![]),
+            loc: loc.clone(),
+        }],
+        lib: None,
+        attrs: vec![],
+        loc: loc.clone(),
+    };
+
+    dumpster.modules.push(Module {
+        name: Ident(name, None),
+        data: ModuleKind::Normal(vec![NormalItem::Function(register)]),
+        attrs: vec![],
+        loc,
+    });
+
+    dumpster
+}
 
-        Expr {
-            data,
-            ty,
-            loc,
-        }
+// building blocks for the helper functions the rewriters generate (these
+//   come after typecheck, so they carry their types themselves)
+
+fn local(name: &str, ty: &Type, loc: &SrcLoc) -> Expr {
+    Expr {
+        data: ExprKind::Name(Path(None, Ident(String::from(name), None))),
+        ty: Some(ty.clone()),
+        loc: loc.clone(),
     }
 }
 
-struct ShortCircuitLogicalsFolder<'a> {
-    symtab: &'a mut SymbolTable,
-    before_stmt_stack: Vec<Vec<Stmt>>,
+fn lit(lit: Literal, ty: Type, loc: &SrcLoc) -> Expr {
+    Expr {
+        data: ExprKind::Lit(lit),
+        ty: Some(ty),
+        loc: loc.clone(),
+    }
 }
 
-impl<'a> ShortCircuitLogicalsFolder<'a> {
-    fn new(symtab: &'a mut SymbolTable) -> Self {
-        ShortCircuitLogicalsFolder {
-            symtab,
-            before_stmt_stack: Vec::new(),
-        }
+fn str_lit(s: &str, loc: &SrcLoc) -> Expr {
+    lit(Literal::String(String::from(s)), Type::String, loc)
+}
+
+fn binop(lhs: Expr, rhs: Expr, op: BinOp, ty: Type) -> Expr {
+    let loc = lhs.loc.clone();
+    Expr {
+        data: ExprKind::BinOpApp(Box::new(lhs), Box::new(rhs), op),
+        ty: Some(ty),
+        loc,
     }
 }
 
-impl<'a> ASTFolder for ShortCircuitLogicalsFolder<'a> {
-    fn fold_stmt_list(&mut self, stmts: Vec<Stmt>, module: &Ident,
-      function: &Ident) -> Vec<Stmt> {
-        stmts.into_iter().flat_map(|stmt| {
-            let stmt = self.fold_stmt(stmt, module, function);
+fn log_not(expr: Expr) -> Expr {
+    let loc = expr.loc.clone();
+    Expr {
+        data: ExprKind::UnOpApp(Box::new(expr), UnOp::LogNot),
+        ty: Some(Type::Bool),
+        loc,
+    }
+}
 
-            let mut before_stmts = self.before_stmt_stack.pop()
-                .expect("dumpster fire: error in before statement stack");
-            let mut result: Vec<_> = before_stmts.drain(..).collect();
+fn member(expr: &Expr, name: &Ident, ty: &Type) -> Expr {
+    Expr {
+        data: ExprKind::Member(Box::new(expr.clone()), name.clone()),
+        ty: Some(ty.clone()),
+        loc: expr.loc.clone(),
+    }
+}
 
-            // am I crazy?
-            result = self.fold_stmt_list(result, module, function);
+fn index(array: &Expr, indices: Vec<Expr>) -> Expr {
+    let elem = match array.ty {
+        Some(Type::Array(ref elem, _)) => (**elem).clone(),
+        _ => panic!("dumpster fire: indexing non-array in generated code"),
+    };
 
-            result.push(stmt);
-            result
-        }).collect()
+    Expr {
+        data: ExprKind::Index(Box::new(array.clone()), indices),
+        ty: Some(elem),
+        loc: array.loc.clone(),
     }
+}
 
-    fn fold_stmt(&mut self, stmt: Stmt, module: &Ident,
-      function: &Ident) -> Stmt {
-        // push a new before-context
-        self.before_stmt_stack.push(Vec::new());
+fn extent(array: &Expr, kind: ExtentKind, dim: usize) -> Expr {
+    Expr {
+        data: ExprKind::ExtentExpr(Box::new(array.clone()), kind, dim),
+        ty: Some(Type::Int32),
+        loc: array.loc.clone(),
+    }
+}
 
-        // first recurse into the statement...
-        let Stmt { data, loc } =
-            fold::noop_fold_stmt(self, stmt, module, function);
+// (VB builtins are called by name, too)
+fn call(name: &Ident, args: Vec<Expr>, ret: &Type, loc: &SrcLoc) -> Expr {
+    Expr {
+        data: ExprKind::Call(Path(None, name.clone()), args, vec![]),
+        ty: Some(ret.clone()),
+        loc: loc.clone(),
+    }
+}
 
-        let data = match data {
-            StmtKind::Assign(lhs, op, rhs) => {
-                match op {
-                    AssignOp::LogAndAssign => StmtKind::IfStmt {
-                        cond: lhs.clone(),
-                        body: vec![
-                            Stmt {
-                                data: StmtKind::Assign(
-                                          lhs, AssignOp::LogAndAssign, rhs),
-                                loc: loc.clone(),
-                            }
-                        ],
-                        elsifs: vec![],
-                        els: None
-                    },
+fn stmt(data: StmtKind, loc: &SrcLoc) -> Stmt {
+    Stmt {
+        data,
+        loc: loc.clone(),
+    }
+}
 
-                    AssignOp::LogOrAssign => StmtKind::IfStmt {
-                        cond: Expr {
-                            data: ExprKind::UnOpApp(
-                                      Box::new(lhs.clone()), UnOp::LogNot),
-                            ty: lhs.ty.clone(),
-                            loc: loc.clone(),
-                        },
-                        body: vec![
-                            Stmt {
-                                data: StmtKind::Assign(
-                                          lhs, AssignOp::LogOrAssign, rhs),
-                                loc: loc.clone(),
-                            }
-                        ],
-                        elsifs: vec![],
-                        els: None,
-                    },
+fn if_stmt(cond: Expr, body: Vec<Stmt>, els: Option<Vec<Stmt>>) -> Stmt {
+    let loc = cond.loc.clone();
+    Stmt {
+        data: StmtKind::IfStmt {
+            cond,
+            body,
+            elsifs: vec![],
+            els,
+        },
+        loc,
+    }
+}
 
-                    op => StmtKind::Assign(lhs, op, rhs),
-                }
-            },
+fn param(name: &str, ty: &Type, mode: ParamMode, loc: &SrcLoc) -> FunParam {
+    FunParam {
+        name: Ident(String::from(name), None),
+        ty: ty.clone(),
+        mode,
+        loc: loc.clone(),
+    }
+}
 
-            s => s,
-        };
+// (raise subscript out of range, as indexing past the end of an array would)
+fn out_of_range(loc: &SrcLoc) -> Stmt {
+    stmt(StmtKind::VbBlock(b"Err.Raise 9\n".to_vec(), vec![], vec![]), loc)
+}
 
-        Stmt {
-            data,
-            loc,
-        }
+fn helper_fn(name: &Ident, params: Vec<FunParam>, ret: Type,
+  body: Vec<Stmt>, loc: &SrcLoc) -> FunDef {
+    FunDef {
+        name: name.clone(),
+        access: Access::Private,
+        params,
+        optparams: None,
+        ret,
+        body,
+        lib: None,
+        attrs: vec![],
+        loc: loc.clone(),
     }
+}
 
-    fn fold_expr(&mut self, expr: Expr, module: &Ident,
-      function: Option<&Ident>) -> Expr {
-        // first recurse into the expression...
-        let Expr { data, ty, loc } =
-            fold::noop_fold_expr(self, expr, module, function);
+// the function telling whether a dynamic array of some element type has
+//   been allocated (VB has no bounds to give us for one which hasn't),
+//   generating it into defs if it isn't in fns yet
+//
+// fn øN(a: &T[]) -> bool {
+//     (On Error Resume Next)
+//     let n: i32 = first_index<0>(a);
+//     let r: bool = (Err.Number = 0);
+//     (Err.Clear)
+//     return r;
+// }
+fn allocated_fn(fns: &mut Vec<(Type, Ident)>, defs: &mut Vec<FunDef>,
+  elem_ty: &Type, loc: &SrcLoc) -> Ident {
+    if let Some(&(_, ref name)) = fns.iter().find(|f| f.0 == *elem_ty) {
+        return name.clone();
+    }
 
-        let data = match data {
-            ExprKind::BinOpApp(lhs, rhs, op) => {
-                match op {
-                    BinOp::LogAnd => {
-                        // use a gensym for this term
-                        let g = gensym(None);
+    let name = gensym(None);
+    fns.push((elem_ty.clone(), name.clone()));
 
-                        let before_stmts = self.before_stmt_stack.last_mut()
-                          .expect("dumpster fire: \
-                                  error in before statement stack");
+    let array_ty = Type::Array(Box::new(elem_ty.clone()),
+      ArrayBounds::Dynamic(1));
+    let a = local("a", &array_ty, loc);
+    let vb = |code: &str| stmt(StmtKind::VbBlock(code.as_bytes().to_vec(),
+      vec![], vec![]), loc);
 
-                        // add symbol table entry for it
-                        self.symtab.add_value_entry(&g, module, function,
-                          &Type::Bool, Access::Private, &loc)
-                          .expect("dumpster fire: \
-                                  failure adding symtab entry for gensym");
+    let body = vec![
+        vb("On Error Resume Next\n"),
 
-                        // push a declaration for it
-                        before_stmts.push(Stmt {
-                            data: StmtKind::VarDecl(vec![
-                              (g.clone(), Type::Bool, Some(*lhs))
-                            ]),
-                            loc: loc.clone(),
-                        });
+        stmt(StmtKind::VarDecl(vec![(Ident(String::from("n"), None),
+          Type::Int32, Some(extent(&a, ExtentKind::First, 0)))]), loc),
 
-                        // build a path-expression for it
-                        let g_expr = Expr {
-                            data: ExprKind::Name(Path(None, g)),
-                            ty: Some(Type::Bool),
-                            loc: loc.clone(),
-                        };
+        stmt(StmtKind::VarDecl(vec![(Ident(String::from("r"), None),
+          Type::Bool, Some(Expr {
+              data: ExprKind::VbExpr(b"(Err.Number = 0)".to_vec()),
+              ty: Some(Type::Bool),
+              loc: loc.clone(),
+          }))]), loc),
 
-                        // push an &&= for it, using existing short-circuiting
-                        //   rules in .fold_stmt(...)
-                        before_stmts.push(Stmt {
-                            data: StmtKind::Assign(
-                                g_expr.clone(),
-                                AssignOp::LogAndAssign,
-                                *rhs
-                            ),
-                            loc: loc.clone(),
-                        });
+        vb("Err.Clear\n"),
 
-                        // use the gensym as our new expression
-                        g_expr.data
-                    },
+        stmt(StmtKind::Return(Some(local("r", &Type::Bool, loc))), loc),
+    ];
 
-                    BinOp::LogOr => {
-                        // use a gensym for this term
-                        let g = gensym(None);
+    defs.push(helper_fn(&name,
+      vec![param("a", &array_ty, ParamMode::ByRef, loc)], Type::Bool, body,
+      loc));
 
-                        let before_stmts = self.before_stmt_stack.last_mut()
-                          .expect("dumpster fire: \
-                                  error in before statement stack");
+    name
+}
 
-                        // add symbol table entry for it
-                        self.symtab.add_value_entry(&g, module, function,
-                          &Type::Bool, Access::Private, &loc)
-                          .expect("dumpster fire: \
-                                  failure adding symtab entry for gensym");
+struct ConstexprRewriteFolder {
+    // keyed by (module, const)
+    consts: HashMap<(String, String), Literal>,
+    // keyed by (module, function, parameter)
+    defaults: HashMap<(String, String, String), Literal>,
+}
 
-                        // push a declaration for it
-                        before_stmts.push(Stmt {
-                            data: StmtKind::VarDecl(vec![
-                              (g.clone(), Type::Bool, Some(*lhs))
-                            ]),
-                            loc: loc.clone(),
-                        });
+impl ConstexprRewriteFolder {
+    fn new(dumpster: &Dumpster) -> Self {
+        let mut eval = ConstEvaluator::new(dumpster);
+        let mut consts = HashMap::new();
+        let mut defaults = HashMap::new();
 
-                        // build a path-expression for it
-                        let g_expr = Expr {
-                            data: ExprKind::Name(Path(None, g)),
-                            ty: Some(Type::Bool),
-                            loc: loc.clone(),
-                        };
+        for m in &dumpster.modules {
+            let ModuleKind::Normal(ref items) = m.data;
 
-                        // push an &&= for it, using existing short-circuiting
-                        //   rules in .fold_stmt(...)
-                        before_stmts.push(Stmt {
-                            data: StmtKind::Assign(
-                                g_expr.clone(),
-                                AssignOp::LogOrAssign,
-                                *rhs
-                            ),
-                            loc: loc.clone(),
-                        });
+            // VB consts may only refer to consts declared before them
+            let mut declared = HashSet::new();
 
-                        // use the gensym as our new expression
-                        g_expr.data
-                    },
+            for i in items {
+                if let NormalItem::Const(ref c) = *i {
+                    let vb_ok = vb_constexpr(&c.value, &m.name, &declared);
+                    declared.insert(c.name.0.clone());
+                    if vb_ok {
+                        continue;
+                    }
 
-                    op => {
-                        ExprKind::BinOpApp(lhs, rhs, op)
-                    },
+                    let lit = eval.const_value(&m.name, &c.name)
+                        .expect("dumpster fire: constant evaluation \
+                          failed after typecheck");
+                    consts.insert((m.name.0.clone(), c.name.0.clone()), lit);
                 }
-            },
+            }
 
-            ExprKind::CondExpr { cond, if_expr, else_expr } => {
-                let g = gensym(None);
+            // (procedures come after all declarations)
+            for i in items {
+                let f = match *i {
+                    NormalItem::Function(ref f) => f,
+                    _ => continue,
+                };
 
-                let this_expr = Expr {
-                    data: ExprKind::CondExpr {
-                        cond: cond.clone(),
-                        if_expr: if_expr.clone(),
-                        else_expr: else_expr.clone()
-                    },
-                    ty: ty.clone(),
-                    loc: loc.clone(),
+                let params = match f.optparams {
+                    Some(FunOptParams::Named(ref params)) => params,
+                    _ => continue,
                 };
 
-                let ty = this_expr.ty.as_ref()
-                  .expect("dumpster fire: \
-                     untyped condexpr in short-ciruiter");
+                for &(ref p, ref default) in params {
+                    if vb_constexpr(default, &m.name, &declared) {
+                        continue;
+                    }
 
-                // add symbol table entry for g
-                self.symtab.add_value_entry(&g, module, function,
-                  &ty, Access::Private, &loc).expect("dumpster fire: \
-                                    failure adding symtab entry for gensym");
+                    let lit = eval.eval_as(default, &p.ty, &m.name)
+                        .expect("dumpster fire: constant evaluation \
+                          failed after typecheck");
+                    defaults.insert((m.name.0.clone(), f.name.0.clone(),
+                      p.name.0.clone()), lit);
+                }
+            }
+        }
 
-                let before_stmts = self.before_stmt_stack.last_mut()
-                  .expect("dumpster fire: error in before statement stack");
+        ConstexprRewriteFolder {
+            consts,
+            defaults,
+        }
+    }
 
-                // push declaration for g
-                before_stmts.push(Stmt {
-                    data: StmtKind::VarDecl(vec![
-                        (g.clone(), ty.clone(), None)
-                    ]),
-                    loc: loc.clone(),
-                });
+    fn lit_expr(lit: Literal, loc: SrcLoc) -> Expr {
+        Expr {
+            ty: Some(lit.ty()),
+            data: ExprKind::Lit(lit),
+            loc,
+        }
+    }
+}
 
-                let g_expr = Expr {
-                    data: ExprKind::Name(Path(None, g)),
-                    ty: Some(ty.clone()),
-                    loc: loc.clone(),
-                };
+// can VB evaluate this itself? (there's no calling our shift helpers in a
+//   const, and it has no short-circuiting or conditional expressions)
+fn vb_constexpr(expr: &Expr, module: &Ident, declared: &HashSet<String>)
+  -> bool {
+    match expr.data {
+        ExprKind::Lit(_) => true,
 
-                before_stmts.push(Stmt {
-                    data: StmtKind::IfStmt {
-                        cond: *cond,
-                        body: vec![
-                            Stmt {
-                                data: StmtKind::Assign(
-                                  g_expr.clone(), AssignOp::Assign, *if_expr),
-                                loc: loc.clone(),
-                            }
-                        ],
-                        // TODO: we could use this for condexpr chains
-                        elsifs: vec![],
-                        els: Some(vec![
-                           Stmt {
-                               data: StmtKind::Assign(
-                                 g_expr.clone(), AssignOp::Assign, *else_expr),
-                               loc: loc.clone()
-                           }
-                        ]),
-                    },
+        ExprKind::Name(Path(Some(ref m), _)) if m != module => true,
+        ExprKind::Name(ref path) => declared.contains(&path.1 .0),
 
-                    loc: loc.clone(),
-                });
+        // VB would do this in Integer and overflow
+        ExprKind::UnOpApp(ref e, UnOp::BitNot)
+          if e.ty == Some(Type::UInt8) => false,
 
-                g_expr.data
+        ExprKind::UnOpApp(ref e, _) => vb_constexpr(e, module, declared),
+
+        ExprKind::BinOpApp(_, _, BinOp::Shl)
+      | ExprKind::BinOpApp(_, _, BinOp::Shr)
+      | ExprKind::BinOpApp(_, _, BinOp::LogAnd)
+      | ExprKind::BinOpApp(_, _, BinOp::LogOr) => false,
+
+        ExprKind::BinOpApp(ref lhs, ref rhs, _) =>
+            vb_constexpr(lhs, module, declared)
+              && vb_constexpr(rhs, module, declared),
+
+        _ => false,
+    }
+}
+
+impl ASTFolder for ConstexprRewriteFolder {
+    fn fold_optparam(&mut self, (param, default): (FunParam, Expr),
+      module: &Ident, function: &Ident) -> (FunParam, Expr) {
+        let key = (module.0.clone(), function.0.clone(), param.name.0.clone());
+        match self.defaults.remove(&key) {
+            Some(lit) => {
+                let loc = default.loc;
+                (param, Self::lit_expr(lit, loc))
             },
+            None => (param, default),
+        }
+    }
 
-            e => e,
+    fn fold_constant(&mut self, c: Constant, module: &Ident) -> Constant {
+        let key = (module.0.clone(), c.name.0.clone());
+        match self.consts.remove(&key) {
+            Some(lit) => {
+                let value = Self::lit_expr(lit, c.value.loc.clone());
+                Constant {
+                    value,
+                    ..c
+                }
+            },
+            None => c,
+        }
+    }
+}
+
+struct MethodRewriteFolder {
+    // subjects of enclosing with blocks, innermost last, in terms of the
+    //   outermost (typecheck makes sure they can be evaluated again)
+    withs: Vec<Expr>,
+}
+
+impl MethodRewriteFolder {
+    fn with_subject(&self, expr: Expr) -> Expr {
+        let Expr { data, ty, loc } = expr;
+
+        let data = match data {
+            ExprKind::WithSubject => match self.withs.last() {
+                Some(subject) => return subject.clone(),
+                None => ExprKind::WithSubject,
+            },
+
+            ExprKind::Member(base, mem) =>
+                ExprKind::Member(Box::new(self.with_subject(*base)), mem),
+
+            ExprKind::Index(base, indices) =>
+                ExprKind::Index(Box::new(self.with_subject(*base)), indices),
+
+            data => data,
         };
 
         Expr {
@@ -404,225 +587,404 @@ impl<'a> ASTFolder for ShortCircuitLogicalsFolder<'a> {
     }
 }
 
-struct ArrayLoopRewriteFolder<'a> {
+impl ASTFolder for MethodRewriteFolder {
+    fn fold_stmt(&mut self, stmt: Stmt, module: &Ident, function: &Ident)
+      -> Stmt {
+        let Stmt { data, loc } = stmt;
+
+        match data {
+            StmtKind::With(subject, body) => {
+                let subject = self.fold_expr(subject, module, Some(function));
+                let resolved = self.with_subject(subject.clone());
+
+                self.withs.push(resolved);
+                let body = self.fold_stmt_list(body, module, function);
+                self.withs.pop();
+
+                Stmt {
+                    data: StmtKind::With(subject, body),
+                    loc,
+                }
+            },
+
+            data => fold::noop_fold_stmt(self, Stmt { data, loc }, module,
+              function),
+        }
+    }
+
+    fn fold_expr(&mut self, expr: Expr, module: &Ident,
+      function: Option<&Ident>) -> Expr {
+        let Expr { data, ty, loc } =
+            fold::noop_fold_expr(self, expr, module, function);
+
+        let data = match data {
+            ExprKind::MemberInvoke(expr, mem, args) => {
+                let method = match expr.ty {
+                    Some(Type::Struct(ref path)) =>
+                        Some(Path(path.0.clone(), method_name(&path.1, &mem))),
+                    _ => None,
+                };
+
+                match method {
+                    Some(method) => {
+                        // VB can't pass a with block's subject itself
+                        //   along, so we name it again
+                        let receiver = match expr.data {
+                            ExprKind::WithSubject => self.with_subject(*expr),
+                            _ => *expr,
+                        };
+                        let mut self_args = vec![receiver];
+                        self_args.extend(args);
+                        ExprKind::Call(method, self_args, vec![])
+                    },
+                    None => ExprKind::MemberInvoke(expr, mem, args),
+                }
+            },
+
+            data => data,
+        };
+
+        Expr {
+            data,
+            ty,
+            loc,
+        }
+    }
+}
+
+struct MapRewriteFolder<'a> {
     symtab: &'a mut SymbolTable,
-    before_stmt_stack: Vec<Vec<Stmt>>,
+    binding: MapBinding,
+    // keys() conversion functions for the current module, by key type
+    keys_fns: Vec<(Type, Ident)>,
+    // checked lookups for the current module, by map type
+    get_fns: Vec<(Type, Ident)>,
+    defs: Vec<FunDef>,
 }
 
-impl<'a> ArrayLoopRewriteFolder<'a> {
-    fn new(symtab: &'a mut SymbolTable) -> Self {
-        ArrayLoopRewriteFolder {
-            symtab,
-            before_stmt_stack: Vec::new(),
+impl<'a> MapRewriteFolder<'a> {
+    // dictionaries tell keys apart by subtype (an Integer 1 isn't a Long 1),
+    //   so keys are always converted to exactly the key type; variables
+    //   of the key type already are
+    fn key_expr(key: Expr, key_ty: &Type) -> Expr {
+        if *key_ty == Type::String
+          || (key.ty.as_ref() == Some(key_ty) && key.is_lvalue()) {
+            return key;
+        }
+
+        let loc = key.loc.clone();
+        Expr {
+            data: ExprKind::Cast(Box::new(key), key_ty.clone()),
+            ty: Some(key_ty.clone()),
+            loc,
         }
     }
 
-    // TODO: this whole loop-and-a-half is ugly hot garbage
-    fn array_for_loop(&mut self, var: Ident, ty: Type, mode: ParamMode,
-      expr: Expr, base: &Type, bounds: &ArrayBounds, mut body: Vec<Stmt>,
-      loc: &SrcLoc, module: &Ident, function: &Ident) -> StmtKind {
-        let dims = bounds.dims();
+    fn new_map(&self, ty: &Type, loc: &SrcLoc) -> Expr {
+        let code = match self.binding {
+            MapBinding::Late => "CreateObject(\"Scripting.Dictionary\")",
+            MapBinding::Early => "New Scripting.Dictionary",
+        };
 
-        // build indexing gensyms by dimension
-        // inclusive ranges would be nice here...
-        let mut g_iters: Vec<_> = (1..dims + 1).map(|_| gensym(None)).collect();
+        Expr {
+            data: ExprKind::VbExpr(code.as_bytes().to_vec()),
+            ty: Some(ty.clone()),
+            loc: loc.clone(),
+        }
+    }
 
-        let index_expr = Expr {
-            data: ExprKind::Index(Box::new(expr.clone()),
-              g_iters.iter().cloned().map(|g| Expr {
-                  data: ExprKind::Name(Path(None, g)),
-                  ty: Some(Type::Int32),
-                  loc: loc.clone(),
-              }).collect()),
-            ty: Some(base.clone()),
+    // the function copying a dictionary's keys (which come out as a var
+    //   array) into an array of the key type, generating it if we haven't
+    //
+    // fn øN(d: &map<K, var>) -> K[] {
+    //     let r: K[];
+    //     if d.Count > 0 {
+    //         alloc r[0:d.Count - 1];
+    //         let i: i32;
+    //         for k: var in d.Keys() {
+    //             r[i] = k;
+    //             i += 1;
+    //         }
+    //     }
+    //     return r;
+    // }
+    fn keys_fn(&mut self, key_ty: &Type, loc: &SrcLoc) -> Ident {
+        if let Some(&(_, ref name)) = self.keys_fns.iter()
+          .find(|f| f.0 == *key_ty) {
+            return name.clone();
+        }
+
+        let name = gensym(None);
+        self.keys_fns.push((key_ty.clone(), name.clone()));
+
+        let map_ty = Type::Map(Box::new(key_ty.clone()),
+          Box::new(Type::Variant));
+        let keys_ty = Type::Array(Box::new(key_ty.clone()),
+          ArrayBounds::Dynamic(1));
+        let d = local("d", &map_ty, loc);
+        let r = local("r", &keys_ty, loc);
+        let i = local("i", &Type::Int32, loc);
+        let count = member(&d, &Ident(String::from("Count"), None),
+          &Type::Int32);
+        let int = |n| lit(Literal::Int32(n), Type::Int32, loc);
+
+        let body = vec![
+            stmt(StmtKind::VarDecl(vec![
+              (Ident(String::from("r"), None), keys_ty.clone(), None)]), loc),
+
+            if_stmt(binop(count.clone(), int(0), BinOp::Gt, Type::Bool), vec![
+                stmt(StmtKind::Alloc(r.clone(), vec![
+                  AllocExtent::Range(Some(int(0)),
+                    binop(count, int(1), BinOp::Sub, Type::Int32))]), loc),
+
+                stmt(StmtKind::VarDecl(vec![
+                  (Ident(String::from("i"), None), Type::Int32, None)]), loc),
+
+                stmt(StmtKind::ForLoop {
+                    var: (Ident(String::from("k"), None), Type::Variant,
+                      ParamMode::ByVal),
+                    spec: ForSpec::Each(Expr {
+                        data: ExprKind::MemberInvoke(Box::new(d),
+                          Ident(String::from("Keys"), None), vec![]),
+                        ty: Some(Type::Variant),
+                        loc: loc.clone(),
+                    }, vec![]),
+                    body: vec![
+                        stmt(StmtKind::Assign(index(&r, vec![i.clone()]),
+                          AssignOp::Assign,
+                          local("k", &Type::Variant, loc)), loc),
+                        stmt(StmtKind::Assign(i, AssignOp::AddAssign,
+                          int(1)), loc),
+                    ],
+                }, loc),
+            ], None),
+
+            stmt(StmtKind::Return(Some(r)), loc),
+        ];
+
+        self.defs.push(helper_fn(&name,
+          vec![param("d", &map_ty, ParamMode::ByRef, loc)], keys_ty, body,
+          loc));
+
+        name
+    }
+
+    // the function looking up a key which has to be there (reading a
+    //   missing key from a dictionary would quietly add it), generating it if
+    //   we haven't
+    //
+    // fn øN(d: &map<K, V>, k: K) -> V {
+    //     if !d.Exists(k) { (raise subscript out of range) }
+    //     return d[k];
+    // }
+    fn get_fn(&mut self, map_ty: &Type, loc: &SrcLoc) -> Ident {
+        if let Some(&(_, ref name)) = self.get_fns.iter()
+          .find(|f| f.0 == *map_ty) {
+            return name.clone();
+        }
+
+        let name = gensym(None);
+        self.get_fns.push((map_ty.clone(), name.clone()));
+
+        let (key_ty, value_ty) = match *map_ty {
+            Type::Map(ref key, ref value) =>
+                ((**key).clone(), (**value).clone()),
+            _ => panic!("dumpster fire: map lookup in non-map"),
+        };
+        let d = local("d", map_ty, loc);
+        let k = local("k", &key_ty, loc);
+
+        let exists = Expr {
+            data: ExprKind::MemberInvoke(Box::new(d.clone()),
+              Ident(String::from("Exists"), None), vec![k.clone()]),
+            ty: Some(Type::Bool),
             loc: loc.clone(),
         };
 
-        // add gensyms to symbol table
-        for g in &g_iters {
-            self.symtab.add_value_entry(g, module, Some(function),
-              &Type::Int32, Access::Private, &loc)
-              .expect("dumpster fire: failure adding symtab entry for gensym");
-        }
+        let body = vec![
+            if_stmt(log_not(exists), vec![out_of_range(loc)], None),
 
-        let mut body = match mode {
-            ParamMode::ByVal => {
-                // push a before-declaration for the original variable
-                let before_stmts = self.before_stmt_stack.last_mut()
-                  .expect("dumpster fire: \
-                          error in before statement stack");
+            stmt(StmtKind::Return(Some(Expr {
+                data: ExprKind::Index(Box::new(d), vec![k]),
+                ty: Some(value_ty.clone()),
+                loc: loc.clone(),
+            })), loc),
+        ];
 
-                before_stmts.push(Stmt {
-                    data: StmtKind::VarDecl(vec![
-                      (var.clone(), ty.clone(), None)
-                    ]),
-                    loc: loc.clone(),
-                });
+        self.defs.push(helper_fn(&name, vec![
+            param("d", map_ty, ParamMode::ByRef, loc),
+            param("k", &key_ty, ParamMode::ByVal, loc),
+        ], value_ty, body, loc));
 
-                // and copy into it at the beginning of each iteration
-                let copy_stmt = Stmt {
-                    data: StmtKind::Assign(
-                        Expr {
-                            data: ExprKind::Name(Path(None, var)),
-                            ty: Some(ty),
-                            loc: loc.clone(),
-                        },
-                        AssignOp::Assign,
-                        index_expr,
-                    ),
-                    loc: loc.clone(),
+        name
+    }
+
+    // an assignment target m[k] stays an index into the dictionary, which
+    //   adds k if it's missing (so m[k] += 1 starts a missing k off at its
+    //   default)
+    fn fold_target(&mut self, lhs: Expr, module: &Ident, function: &Ident)
+      -> Expr {
+        let Expr { data, ty, loc } = lhs;
+        match data {
+            ExprKind::Index(base, indices) => {
+                let key_ty = match base.ty {
+                    Some(Type::Map(ref key, _)) => Some((**key).clone()),
+                    _ => None,
                 };
 
-                body.insert(0, copy_stmt);
+                let base = self.fold_expr(*base, module, Some(function));
+                let mut indices: Vec<_> = indices.into_iter()
+                    .map(|e| self.fold_expr(e, module, Some(function)))
+                    .collect();
 
-                body
+                if let Some(key_ty) = key_ty {
+                    let key = indices.pop()
+                        .expect("dumpster fire: map index without key");
+                    indices.push(Self::key_expr(key, &key_ty));
+                }
+
+                Expr {
+                    data: ExprKind::Index(Box::new(base), indices),
+                    ty,
+                    loc,
+                }
+            },
+
+            data => self.fold_expr(Expr { data, ty, loc }, module,
+              Some(function)),
+        }
+    }
+
+    // for (k, v: V) in m { ... }
+    //   =>
+    // for øk: var in m.Keys { let k: K = øk; let v: V = m[øk]; ... }
+    //   (a reference v is m[øk] itself)
+    fn map_for_loop(&mut self, (var, ty, mode): (Ident, Type, ParamMode),
+      expr: Expr, indices: Vec<Ident>, body: Vec<Stmt>, module: &Ident,
+      function: &Ident) -> StmtKind {
+        let loc = expr.loc.clone();
+        let (key_ty, value_ty) = match expr.ty {
+            Some(Type::Map(ref key, ref value)) =>
+                ((**key).clone(), (**value).clone()),
+            _ => panic!("dumpster fire: map loop over non-map"),
+        };
+
+        let g = gensym(None);
+        self.symtab.add_value_entry(&g, module, Some(function),
+          &Type::Variant, Access::Private, &loc)
+          .expect("dumpster fire: failure adding symtab entry for gensym");
+
+        let value = Expr {
+            data: ExprKind::Index(Box::new(expr.clone()), vec![Expr {
+                data: ExprKind::Name(Path(None, g.clone())),
+                ty: Some(Type::Variant),
+                loc: loc.clone(),
+            }]),
+            ty: Some(value_ty),
+            loc: loc.clone(),
+        };
+
+        let mut body = match mode {
+            ParamMode::ByVal => {
+                let decl = Stmt {
+                    data: StmtKind::VarDecl(vec![(var, ty, Some(value))]),
+                    loc: loc.clone(),
+                };
+                let mut stmts = vec![decl];
+                stmts.extend(body);
+                stmts
             },
 
             ParamMode::ByRef => {
                 let mut subst_folder = ScopedExprSubstitutionFolder {
                     orig: var,
-                    replace: index_expr,
+                    replace: value,
                     module: module.clone(),
                     function: Some(function.clone()),
                 };
 
                 subst_folder.fold_stmt_list(body, module, function)
             },
-
         };
 
-        // nested inner loops, if needed
-        for dim in (1..dims).rev() {
-            let spec = ForSpec::Range(
-                // TODO: gross
-                Expr {
-                    data: ExprKind::ExtentExpr(
-                              Box::new(expr.clone()),
-                              ExtentKind::First,
-                              dim),
-                    ty: Some(Type::Int32),
-                    loc: loc.clone(),
-                },
-
-                Expr {
-                    data: ExprKind::ExtentExpr(
-                              Box::new(expr.clone()),
-                              ExtentKind::Last,
-                              dim),
-                    ty: Some(Type::Int32),
-                    loc: loc.clone(),
-                },
-                None
-            );
-
-            body = vec![Stmt {
-                data: StmtKind::ForLoop {
-                    var: (g_iters.pop().unwrap(),
-                      Type::Int32, ParamMode::ByVal),
-                    spec,
-                    body,
-                },
+        if let Some(key) = indices.into_iter().next() {
+            body.insert(0, Stmt {
+                data: StmtKind::VarDecl(vec![(key, key_ty,
+                  Some(Expr {
+                      data: ExprKind::Name(Path(None, g.clone())),
+                      ty: Some(Type::Variant),
+                      loc: loc.clone(),
+                  }))]),
                 loc: loc.clone(),
-            }];
+            });
         }
 
-        let var = (g_iters.pop().unwrap(), Type::Int32, ParamMode::ByVal);
-        let spec = ForSpec::Range(
-            Expr {
-                data: ExprKind::ExtentExpr(
-                          Box::new(expr.clone()),
-                          ExtentKind::First,
-                          0usize),
-                ty: Some(Type::Int32),
-                loc: loc.clone(),
-            },
-
-            Expr {
-                data: ExprKind::ExtentExpr(
-                          Box::new(expr.clone()),
-                          ExtentKind::Last,
-                          0usize),
-                ty: Some(Type::Int32),
-                loc: loc.clone(),
-            },
-
-            None
-        );
-
         StmtKind::ForLoop {
-            var,
-            spec,
+            var: (g, Type::Variant, ParamMode::ByVal),
+            spec: ForSpec::Each(Expr {
+                data: ExprKind::MemberInvoke(Box::new(expr),
+                  Ident(String::from("Keys"), None), vec![]),
+                ty: Some(Type::Variant),
+                loc: loc.clone(),
+            }, vec![]),
             body,
         }
     }
-
 }
 
-impl<'a> ASTFolder for ArrayLoopRewriteFolder<'a> {
-    fn fold_stmt_list(&mut self, stmts: Vec<Stmt>, module: &Ident,
-      function: &Ident) -> Vec<Stmt> {
-        stmts.into_iter().flat_map(|stmt| {
-            let stmt = self.fold_stmt(stmt, module, function);
-
-            let mut before_stmts = self.before_stmt_stack.pop()
-                .expect("dumpster fire: error in before statement stack");
-            let mut result: Vec<_> = before_stmts.drain(..).collect();
+impl<'a> ASTFolder for MapRewriteFolder<'a> {
+    fn fold_module(&mut self, m: Module) -> Module {
+        self.keys_fns.clear();
+        self.get_fns.clear();
+        let mut m = fold::noop_fold_module(self, m);
 
-            // am I crazy?
-            result = self.fold_stmt_list(result, module, function);
+        let ModuleKind::Normal(ref mut items) = m.data;
+        items.extend(self.defs.drain(..).map(NormalItem::Function));
 
-            result.push(stmt);
-            result
-        }).collect()
+        m
     }
 
     fn fold_stmt(&mut self, stmt: Stmt, module: &Ident,
       function: &Ident) -> Stmt {
-        // push a new before-context
-        self.before_stmt_stack.push(Vec::new());
+        let Stmt { data, loc } = match stmt.data {
+            StmtKind::Assign(lhs, op, rhs) => Stmt {
+                data: StmtKind::Assign(
+                  self.fold_target(lhs, module, function), op,
+                  self.fold_expr(rhs, module, Some(function))),
+                loc: stmt.loc,
+            },
 
-        // first recurse into the statement...
-        let Stmt { data, loc } =
-            fold::noop_fold_stmt(self, stmt, module, function);
+            data => fold::noop_fold_stmt(self, Stmt { data, loc: stmt.loc },
+              module, function),
+        };
 
         let data = match data {
-            StmtKind::ForLoop { var: (var, ty, mode), spec, body } => {
-                match spec {
-                    ForSpec::Range(first, last, step) =>
-                        StmtKind::ForLoop {
-                            var: (var, ty, mode),
-                            spec: ForSpec::Range(first, last, step),
-                            body,
-                        },
-
-                    ForSpec::Each(expr) => {
-                        let expr_ty = expr.ty.as_ref()
-                            .expect("dumpster fire: \
-                                    untyped expr in loop rewriter")
-                            .clone(); // TODO: this is stupid and only to
-                                      //   satisfy the borrow checker
-
-                        match expr_ty {
-                            Type::Array(ref base, ref bounds) =>
-                                self.array_for_loop(var, ty, mode, expr,
-                                  base, bounds, body, &loc, module, function),
-
-                            Type::VarArgsArray =>
-                                self.array_for_loop(var, ty, mode, expr,
-                                  &Type::Variant, &ArrayBounds::Dynamic(1),
-                                  body, &loc, module, function),
-
-                            _ => StmtKind::ForLoop {
-                                var: (var, ty, mode),
-                                spec: ForSpec::Each(expr),
-                                body,
-                            },
-                        }
-                    },
-                }
+            // a map declared without an initializer starts off empty
+            StmtKind::VarDecl(decls) => StmtKind::VarDecl(decls.into_iter()
+                .map(|(ident, ty, init)| {
+                    let init = match (init, &ty) {
+                        (None, &Type::Map(_, _)) => Some(self.new_map(&ty,
+                          &loc)),
+                        (init, _) => init,
+                    };
+                    (ident, ty, init)
+                }).collect()),
+
+            StmtKind::ForLoop {
+                var,
+                spec: ForSpec::Each(expr, indices),
+                body,
+            } => match expr.ty {
+                Some(Type::Map(_, _)) => self.map_for_loop(var, expr,
+                  indices, body, module, function),
+                _ => StmtKind::ForLoop {
+                    var,
+                    spec: ForSpec::Each(expr, indices),
+                    body,
+                },
             },
 
-            s => s,
+            data => data,
         };
 
         Stmt {
@@ -633,245 +995,3562 @@ impl<'a> ASTFolder for ArrayLoopRewriteFolder<'a> {
 
     fn fold_expr(&mut self, expr: Expr, module: &Ident,
       function: Option<&Ident>) -> Expr {
-        // first recurse into the expression...
         let Expr { data, ty, loc } =
             fold::noop_fold_expr(self, expr, module, function);
 
-        let data = match data {
-            ExprKind::BinOpApp(lhs, rhs, op) => {
-                match op {
-                    BinOp::LogAnd => {
-                        // use a gensym for this term
-                        let g = gensym(None);
+        let key_ty = |expr: &Expr| match expr.ty {
+            Some(Type::Map(ref key, _)) => Some((**key).clone()),
+            _ => None,
+        };
 
-                        let before_stmts = self.before_stmt_stack.last_mut()
-                          .expect("dumpster fire: \
-                                  error in before statement stack");
+        let data = match data {
+            // (assignment targets are dealt with in fold_target)
+            ExprKind::Index(expr, mut indices) => match expr.ty {
+                Some(ref map_ty @ Type::Map(_, _)) => {
+                    let get_fn = self.get_fn(map_ty, &loc);
+                    let key = indices.pop()
+                        .expect("dumpster fire: map index without key");
+                    ExprKind::Call(Path(None, get_fn), vec![*expr, key],
+                      vec![])
+                },
 
-                        // add symbol table entry for it
-                        self.symtab.add_value_entry(&g, module, function,
-                          &Type::Bool, Access::Private, &loc)
-                          .expect("dumpster fire: \
-                                  failure adding symtab entry for gensym");
+                _ => ExprKind::Index(expr, indices),
+            },
 
-                        // push a declaration for it
-                        before_stmts.push(Stmt {
-                            data: StmtKind::VarDecl(vec![
-                              (g.clone(), Type::Bool, Some(*lhs))
-                            ]),
-                            loc: loc.clone(),
-                        });
+            ExprKind::MemberInvoke(expr, mem, mut args) => {
+                match key_ty(&expr) {
+                    Some(key_ty) => match mem.0.as_str() {
+                        "contains" | "remove" => {
+                            let key = args.pop()
+                                .expect("dumpster fire: map method \
+                                  without key");
+                            let method = if mem.0 == "contains" {
+                                "Exists"
+                            } else {
+                                "Remove"
+                            };
+                            ExprKind::MemberInvoke(expr,
+                              Ident(String::from(method), None),
+                              vec![Self::key_expr(key, &key_ty)])
+                        },
 
-                        // build a path-expression for it
-                        let g_expr = Expr {
-                            data: ExprKind::Name(Path(None, g)),
-                            ty: Some(Type::Bool),
-                            loc: loc.clone(),
-                        };
+                        "len" => ExprKind::Member(expr,
+                          Ident(String::from("Count"), None)),
 
-                        // push an &&= for it, using existing short-circuiting
-                        //   rules in .fold_stmt(...)
-                        before_stmts.push(Stmt {
-                            data: StmtKind::Assign(
-                                g_expr.clone(),
-                                AssignOp::LogAndAssign,
-                                *rhs
-                            ),
-                            loc: loc.clone(),
-                        });
+                        "keys" => {
+                            let keys_fn = self.keys_fn(&key_ty, &loc);
+                            ExprKind::Call(Path(None, keys_fn), vec![*expr],
+                              vec![])
+                        },
 
-                        // use the gensym as our new expression
-                        g_expr.data
+                        _ => panic!("dumpster fire: unknown map method {} \
+                          slipped past typecheck", mem),
                     },
 
-                    BinOp::LogOr => {
-                        // use a gensym for this term
-                        let g = gensym(None);
+                    None => ExprKind::MemberInvoke(expr, mem, args),
+                }
+            },
 
-                        let before_stmts = self.before_stmt_stack.last_mut()
-                          .expect("dumpster fire: \
-                                  error in before statement stack");
+            // maps and the things they coerce to are all just dictionary
+            //   references underneath
+            ExprKind::Cast(expr, cast_ty) => {
+                let is_map = |ty: Option<&Type>| match ty {
+                    Some(&Type::Map(_, _)) => true,
+                    _ => false,
+                };
 
-                        // add symbol table entry for it
-                        self.symtab.add_value_entry(&g, module, function,
-                          &Type::Bool, Access::Private, &loc)
-                          .expect("dumpster fire: \
-                                  failure adding symtab entry for gensym");
+                if is_map(expr.ty.as_ref()) || is_map(Some(&cast_ty)) {
+                    return Expr {
+                        ty: Some(cast_ty),
+                        ..*expr
+                    };
+                }
 
-                        // push a declaration for it
-                        before_stmts.push(Stmt {
-                            data: StmtKind::VarDecl(vec![
-                              (g.clone(), Type::Bool, Some(*lhs))
-                            ]),
-                            loc: loc.clone(),
-                        });
+                ExprKind::Cast(expr, cast_ty)
+            },
 
-                        // build a path-expression for it
-                        let g_expr = Expr {
-                            data: ExprKind::Name(Path(None, g)),
-                            ty: Some(Type::Bool),
-                            loc: loc.clone(),
-                        };
+            data => data,
+        };
 
-                        // push an &&= for it, using existing short-circuiting
-                        //   rules in .fold_stmt(...)
-                        before_stmts.push(Stmt {
-                            data: StmtKind::Assign(
-                                g_expr.clone(),
-                                AssignOp::LogOrAssign,
-                                *rhs
-                            ),
-                            loc: loc.clone(),
-                        });
+        Expr {
+            data,
+            ty,
+            loc,
+        }
+    }
+}
 
-                        // use the gensym as our new expression
-                        g_expr.data
-                    },
+struct MapTypeFolder {
+    binding: MapBinding,
+}
 
-                    op => {
-                        ExprKind::BinOpApp(lhs, rhs, op)
-                    },
-                }
+impl ASTFolder for MapTypeFolder {
+    fn fold_type(&mut self, ty: Type, module: &Ident, loc: &SrcLoc) -> Type {
+        match fold::noop_fold_type(self, ty, module, loc) {
+            Type::Map(_, _) => match self.binding {
+                MapBinding::Late => Type::Obj,
+                MapBinding::Early => Type::Object(Path(
+                  Some(Ident(String::from("Scripting"), None)),
+                  Ident(String::from("Dictionary"), None))),
             },
+            ty => ty,
+        }
+    }
+}
 
-            ExprKind::CondExpr { cond, if_expr, else_expr } => {
-                let g = gensym(None);
+struct ListRewriteFolder<'a> {
+    symtab: &'a mut SymbolTable,
+    // helper functions for the current module, by operation and list type
+    list_fns: Vec<(&'static str, Type, Ident)>,
+    // allocation checks for the current module, by element type
+    allocated_fns: Vec<(Type, Ident)>,
+    defs: Vec<FunDef>,
+}
 
-                let this_expr = Expr {
-                    data: ExprKind::CondExpr {
-                        cond: cond.clone(),
-                        if_expr: if_expr.clone(),
-                        else_expr: else_expr.clone()
-                    },
-                    ty: ty.clone(),
-                    loc: loc.clone(),
-                };
-                
-                let ty = this_expr.ty.as_ref()
-                  .expect("dumpster fire: \
-                     untypeable condexpr in short-ciruiter");
+impl<'a> ListRewriteFolder<'a> {
+    fn elem_ty(ty: Option<&Type>) -> Type {
+        match ty {
+            Some(&Type::List(ref elem)) => (**elem).clone(),
+            _ => panic!("dumpster fire: list operation on non-list type"),
+        }
+    }
 
-                // add symbol table entry for g
-                self.symtab.add_value_entry(&g, module, function,
-                  &ty, Access::Private, &loc).expect("dumpster fire: \
-                                    failure adding symtab entry for gensym");
+    // the backing array of a list
+    fn items(list: &Expr) -> Expr {
+        let elem = Self::elem_ty(list.ty.as_ref());
+        member(list, &Ident(String::from("items"), None),
+          &Type::Array(Box::new(elem), ArrayBounds::Dynamic(1)))
+    }
 
-                let before_stmts = self.before_stmt_stack.last_mut()
-                  .expect("dumpster fire: error in before statement stack");
+    // the number of live elements in a list (VB reserves Len)
+    fn count(list: &Expr) -> Expr {
+        member(list, &Ident(String::from("count"), None), &Type::Int32)
+    }
 
-                // push declaration for g
-                before_stmts.push(Stmt {
-                    data: StmtKind::VarDecl(vec![
-                        (g.clone(), ty.clone(), None)
-                    ]),
-                    loc: loc.clone(),
-                });
+    fn int(n: i32, loc: &SrcLoc) -> Expr {
+        lit(Literal::Int32(n), Type::Int32, loc)
+    }
 
-                let g_expr = Expr {
-                    data: ExprKind::Name(Path(None, g)),
-                    ty: Some(ty.clone()),
-                    loc: loc.clone(),
-                };
+    // can we name this list twice (once to check an index against its
+    //   count, and once to index its items) without evaluating anything
+    //   with side effects twice?
+    fn repeatable(&self, expr: &Expr) -> bool {
+        match expr.data {
+            ExprKind::Name(_)
+          | ExprKind::Lit(_) => true,
 
-                before_stmts.push(Stmt {
-                    data: StmtKind::IfStmt {
-                        cond: *cond,
-                        body: vec![
-                            Stmt {
-                                data: StmtKind::Assign(
-                                  g_expr.clone(), AssignOp::Assign, *if_expr),
-                                loc: loc.clone(),
-                            }
-                        ],
-                        // TODO: we could use this for condexpr chains
-                        elsifs: vec![],
-                        els: Some(vec![
-                           Stmt {
-                               data: StmtKind::Assign(
-                                 g_expr.clone(), AssignOp::Assign, *else_expr),
-                               loc: loc.clone()
-                           }
-                        ]),
+            ExprKind::Member(ref base, _)
+          | ExprKind::UnOpApp(ref base, _) => self.repeatable(base),
+
+            ExprKind::Index(ref base, ref indices) => self.repeatable(base)
+              && indices.iter().all(|i| self.repeatable(i)),
+
+            ExprKind::BinOpApp(ref lhs, ref rhs, _) =>
+                self.repeatable(lhs) && self.repeatable(rhs),
+
+            // (our own index checks only ever raise)
+            ExprKind::Call(Path(None, ref name), ref args, _) =>
+                self.list_fns.iter().any(|f| f.0 == "index" && f.2 == *name)
+                  && args.iter().all(|a| self.repeatable(a)),
+
+            _ => false,
+        }
+    }
+
+    // the helper function for a list operation, generating it if we haven't
+    fn list_fn(&mut self, op: &'static str, list_ty: &Type, loc: &SrcLoc)
+      -> Ident {
+        if let Some(&(_, _, ref name)) = self.list_fns.iter()
+          .find(|f| f.0 == op && f.1 == *list_ty) {
+            return name.clone();
+        }
+
+        let name = gensym(None);
+        self.list_fns.push((op, list_ty.clone(), name.clone()));
+
+        let (params, ret, body) = match op {
+            "push" => Self::push_fn(list_ty, loc),
+            "pop" => Self::pop_fn(list_ty, loc),
+            "index" => Self::index_fn(list_ty, loc),
+            "get" => Self::get_fn(list_ty, loc),
+            "to_array" => Self::to_array_fn(list_ty, loc),
+            "from_array" => self.list_from_array_fn(list_ty, loc),
+            _ => panic!("dumpster fire: unknown list helper {}", op),
+        };
+
+        self.defs.push(helper_fn(&name, params, ret, body, loc));
+
+        name
+    }
+
+    // sub push(l: &list<T>, v: T) {
+    //     if l.count == 0 {
+    //         realloc l.items[4];
+    //     } else if l.count > last_index<0>(l.items) {
+    //         realloc l.items[l.count * 2];
+    //     }
+    //     l.items[l.count] = v;
+    //     l.count += 1;
+    // }
+    fn push_fn(list_ty: &Type, loc: &SrcLoc)
+      -> (Vec<FunParam>, Type, Vec<Stmt>) {
+        let elem = Self::elem_ty(Some(list_ty));
+        // VB can't pass UDTs by value
+        let v_mode = if elem.is_scalar() {
+            ParamMode::ByVal
+        } else {
+            ParamMode::ByRef
+        };
+
+        let l = local("l", list_ty, loc);
+        let items = Self::items(&l);
+        let count = Self::count(&l);
+
+        let body = vec![
+            stmt(StmtKind::IfStmt {
+                cond: binop(count.clone(), Self::int(0, loc), BinOp::Eq,
+                  Type::Bool),
+                body: vec![stmt(StmtKind::ReAlloc(items.clone(), 0,
+                  AllocExtent::Range(None, Self::int(4, loc))), loc)],
+                elsifs: vec![(
+                    binop(count.clone(),
+                      extent(&items, ExtentKind::Last, 0), BinOp::Gt,
+                      Type::Bool),
+                    vec![stmt(StmtKind::ReAlloc(items.clone(), 0,
+                      AllocExtent::Range(None, binop(count.clone(),
+                        Self::int(2, loc), BinOp::Mul, Type::Int32))), loc)],
+                )],
+                els: None,
+            }, loc),
+
+            stmt(StmtKind::Assign(index(&items, vec![count.clone()]),
+              AssignOp::Assign, local("v", &elem, loc)), loc),
+
+            stmt(StmtKind::Assign(count, AssignOp::AddAssign,
+              Self::int(1, loc)), loc),
+        ];
+
+        let params = vec![
+            param("l", list_ty, ParamMode::ByRef, loc),
+            param("v", &elem, v_mode, loc),
+        ];
+
+        (params, Type::Void, body)
+    }
+
+    // fn pop(l: &list<T>) -> T {
+    //     if l.count == 0 { (raise subscript out of range) }
+    //     l.count -= 1;
+    //     let v: T = l.items[l.count];
+    //     let blank: T;
+    //     l.items[l.count] = blank;
+    //     return v;
+    // }
+    //   (the emptied slot doesn't keep an object alive)
+    fn pop_fn(list_ty: &Type, loc: &SrcLoc)
+      -> (Vec<FunParam>, Type, Vec<Stmt>) {
+        let elem = Self::elem_ty(Some(list_ty));
+        let l = local("l", list_ty, loc);
+        let items = Self::items(&l);
+        let count = Self::count(&l);
+        let slot = index(&items, vec![count.clone()]);
+
+        let body = vec![
+            if_stmt(binop(count.clone(), Self::int(0, loc), BinOp::Eq,
+              Type::Bool), vec![out_of_range(loc)], None),
+
+            stmt(StmtKind::Assign(count, AssignOp::SubAssign,
+              Self::int(1, loc)), loc),
+
+            stmt(StmtKind::VarDecl(vec![(Ident(String::from("v"), None),
+              elem.clone(), Some(slot.clone()))]), loc),
+
+            stmt(StmtKind::VarDecl(vec![(Ident(String::from("blank"), None),
+              elem.clone(), None)]), loc),
+
+            stmt(StmtKind::Assign(slot, AssignOp::Assign,
+              local("blank", &elem, loc)), loc),
+
+            stmt(StmtKind::Return(Some(local("v", &elem, loc))), loc),
+        ];
+
+        let params = vec![param("l", list_ty, ParamMode::ByRef, loc)];
+
+        (params, elem, body)
+    }
+
+    // (l[i] is l.items[index(l, i)])
+    //
+    // fn index(l: &list<T>, i: i32) -> i32 {
+    //     if i < 0 { (raise subscript out of range) }
+    //     if i >= l.count { (raise subscript out of range) }
+    //     return i;
+    // }
+    fn index_fn(list_ty: &Type, loc: &SrcLoc)
+      -> (Vec<FunParam>, Type, Vec<Stmt>) {
+        let l = local("l", list_ty, loc);
+        let i = local("i", &Type::Int32, loc);
+
+        // (no ||, since these go through the short-circuiting rewriter,
+        //   which would want their locals in the symbol table)
+        let body = vec![
+            if_stmt(binop(i.clone(), Self::int(0, loc), BinOp::Lt, Type::Bool),
+              vec![out_of_range(loc)], None),
+
+            if_stmt(binop(i.clone(), Self::count(&l), BinOp::GtEq,
+              Type::Bool), vec![out_of_range(loc)], None),
+
+            stmt(StmtKind::Return(Some(i)), loc),
+        ];
+
+        let params = vec![
+            param("l", list_ty, ParamMode::ByRef, loc),
+            param("i", &Type::Int32, ParamMode::ByVal, loc),
+        ];
+
+        (params, Type::Int32, body)
+    }
+
+    // (l[i] for a list which isn't an lvalue, so we can't name it twice)
+    //
+    // fn get(l: &list<T>, i: i32) -> T {
+    //     (check i, as index does)
+    //     return l.items[i];
+    // }
+    fn get_fn(list_ty: &Type, loc: &SrcLoc)
+      -> (Vec<FunParam>, Type, Vec<Stmt>) {
+        let elem = Self::elem_ty(Some(list_ty));
+        let (params, _, mut body) = Self::index_fn(list_ty, loc);
+
+        let l = local("l", list_ty, loc);
+        let i = local("i", &Type::Int32, loc);
+        body.pop();
+        body.push(stmt(StmtKind::Return(Some(
+          index(&Self::items(&l), vec![i]))), loc));
+
+        (params, elem, body)
+    }
+
+    // fn to_array(l: &list<T>) -> T[] {
+    //     let r: T[];
+    //     if l.count > 0 {
+    //         r = l.items;
+    //         realloc r[l.count];
+    //     }
+    //     return r;
+    // }
+    //   (VB has no empty arrays but unallocated ones, so that's what an
+    //   empty list gives us)
+    fn to_array_fn(list_ty: &Type, loc: &SrcLoc)
+      -> (Vec<FunParam>, Type, Vec<Stmt>) {
+        let elem = Self::elem_ty(Some(list_ty));
+        let array_ty = Type::Array(Box::new(elem), ArrayBounds::Dynamic(1));
+        let l = local("l", list_ty, loc);
+        let r = local("r", &array_ty, loc);
+        let count = Self::count(&l);
+
+        let body = vec![
+            stmt(StmtKind::VarDecl(vec![
+              (Ident(String::from("r"), None), array_ty.clone(), None)]), loc),
+
+            if_stmt(binop(count.clone(), Self::int(0, loc), BinOp::Gt,
+              Type::Bool), vec![
+                stmt(StmtKind::Assign(r.clone(), AssignOp::Assign,
+                  Self::items(&l)), loc),
+                stmt(StmtKind::ReAlloc(r.clone(), 0,
+                  AllocExtent::Range(None, count)), loc),
+            ], None),
+
+            stmt(StmtKind::Return(Some(r)), loc),
+        ];
+
+        let params = vec![param("l", list_ty, ParamMode::ByRef, loc)];
+
+        (params, array_ty, body)
+    }
+
+    // fn from_array(a: &T[]) -> list<T> {
+    //     let l: list<T>;
+    //     if !allocated(a) { return l; }
+    //     l.count = last_index<0>(a) - first_index<0>(a) + 1;
+    //     alloc l.items[l.count];
+    //     for i: i32 = first_index<0>(a):last_index<0>(a) {
+    //         l.items[i - first_index<0>(a)] = a[i];
+    //     }
+    //     return l;
+    // }
+    fn list_from_array_fn(&mut self, list_ty: &Type, loc: &SrcLoc)
+      -> (Vec<FunParam>, Type, Vec<Stmt>) {
+        let elem = Self::elem_ty(Some(list_ty));
+        let array_ty = Type::Array(Box::new(elem.clone()),
+          ArrayBounds::Dynamic(1));
+        let l = local("l", list_ty, loc);
+        let a = local("a", &array_ty, loc);
+        let i = local("i", &Type::Int32, loc);
+        let items = Self::items(&l);
+        let count = Self::count(&l);
+        let first = extent(&a, ExtentKind::First, 0);
+        let last = extent(&a, ExtentKind::Last, 0);
+        let allocated = allocated_fn(&mut self.allocated_fns, &mut self.defs,
+          &elem, loc);
+
+        let body = vec![
+            stmt(StmtKind::VarDecl(vec![
+              (Ident(String::from("l"), None), list_ty.clone(), None)]), loc),
+
+            if_stmt(log_not(call(&allocated, vec![a.clone()], &Type::Bool,
+              loc)), vec![stmt(StmtKind::Return(Some(l.clone())), loc)],
+              None),
+
+            stmt(StmtKind::Assign(count.clone(), AssignOp::Assign,
+              binop(binop(last.clone(), first.clone(), BinOp::Sub,
+                Type::Int32), Self::int(1, loc), BinOp::Add, Type::Int32)),
+              loc),
+
+            stmt(StmtKind::Alloc(items.clone(),
+              vec![AllocExtent::Range(None, count)]), loc),
+
+            stmt(StmtKind::ForLoop {
+                var: (Ident(String::from("i"), None), Type::Int32,
+                  ParamMode::ByVal),
+                spec: ForSpec::Range(first.clone(), last, None),
+                body: vec![stmt(StmtKind::Assign(
+                  index(&items, vec![binop(i.clone(), first, BinOp::Sub,
+                    Type::Int32)]),
+                  AssignOp::Assign,
+                  index(&a, vec![i])), loc)],
+            }, loc),
+
+            stmt(StmtKind::Return(Some(l)), loc),
+        ];
+
+        let params = vec![param("a", &array_ty, ParamMode::ByRef, loc)];
+
+        (params, list_ty.clone(), body)
+    }
+
+    // for (i, x: T) in xs { ... }
+    //   =>
+    // for i: i32 = 0:xs.count - 1 { let x: T = xs.items[i]; ... }
+    //   (a reference x is xs.items[i] itself; i is a gensym if not bound)
+    fn list_for_loop(&mut self, (var, ty, mode): (Ident, Type, ParamMode),
+      expr: Expr, indices: Vec<Ident>, body: Vec<Stmt>, module: &Ident,
+      function: &Ident) -> StmtKind {
+        let loc = expr.loc.clone();
+
+        let counter = match indices.into_iter().next() {
+            Some(counter) => counter,
+            None => {
+                let g = gensym(None);
+                self.symtab.add_value_entry(&g, module, Some(function),
+                  &Type::Int32, Access::Private, &loc)
+                  .expect("dumpster fire: failure adding symtab entry for \
+                    gensym");
+                g
+            },
+        };
+
+        let value = index(&Self::items(&expr), vec![Expr {
+            data: ExprKind::Name(Path(None, counter.clone())),
+            ty: Some(Type::Int32),
+            loc: loc.clone(),
+        }]);
+
+        let body = match mode {
+            ParamMode::ByVal => {
+                let decl = stmt(
+                  StmtKind::VarDecl(vec![(var, ty, Some(value))]), &loc);
+                let mut stmts = vec![decl];
+                stmts.extend(body);
+                stmts
+            },
+
+            ParamMode::ByRef => {
+                let mut subst_folder = ScopedExprSubstitutionFolder {
+                    orig: var,
+                    replace: value,
+                    module: module.clone(),
+                    function: Some(function.clone()),
+                };
+
+                subst_folder.fold_stmt_list(body, module, function)
+            },
+        };
+
+        StmtKind::ForLoop {
+            var: (counter, Type::Int32, ParamMode::ByVal),
+            spec: ForSpec::Range(Self::int(0, &loc),
+              binop(Self::count(&expr), Self::int(1, &loc), BinOp::Sub,
+                Type::Int32), None),
+            body,
+        }
+    }
+}
+
+impl<'a> ASTFolder for ListRewriteFolder<'a> {
+    fn fold_module(&mut self, m: Module) -> Module {
+        self.list_fns.clear();
+        self.allocated_fns.clear();
+        let mut m = fold::noop_fold_module(self, m);
+
+        let ModuleKind::Normal(ref mut items) = m.data;
+        items.extend(self.defs.drain(..).map(NormalItem::Function));
+
+        m
+    }
+
+    fn fold_stmt(&mut self, stmt: Stmt, module: &Ident,
+      function: &Ident) -> Stmt {
+        let Stmt { data, loc } =
+            fold::noop_fold_stmt(self, stmt, module, function);
+
+        let data = match data {
+            StmtKind::ForLoop {
+                var,
+                spec: ForSpec::Each(expr, indices),
+                body,
+            } => match expr.ty {
+                Some(Type::List(_)) => self.list_for_loop(var, expr,
+                  indices, body, module, function),
+                _ => StmtKind::ForLoop {
+                    var,
+                    spec: ForSpec::Each(expr, indices),
+                    body,
+                },
+            },
+
+            data => data,
+        };
+
+        Stmt {
+            data,
+            loc,
+        }
+    }
+
+    fn fold_expr(&mut self, expr: Expr, module: &Ident,
+      function: Option<&Ident>) -> Expr {
+        let Expr { data, ty, loc } =
+            fold::noop_fold_expr(self, expr, module, function);
+
+        let is_list = |ty: Option<&Type>| match ty {
+            Some(&Type::List(_)) => true,
+            _ => false,
+        };
+
+        let data = match data {
+            // indices are checked against the count, not the capacity
+            ExprKind::Index(expr, mut indices) => {
+                if !is_list(expr.ty.as_ref()) {
+                    return Expr {
+                        data: ExprKind::Index(expr, indices),
+                        ty,
+                        loc,
+                    };
+                }
+
+                let list_ty = expr.ty.clone().unwrap();
+                let i = indices.pop()
+                    .expect("dumpster fire: list index without index");
+                if self.repeatable(&expr) {
+                    let check = self.list_fn("index", &list_ty, &loc);
+                    let items = Self::items(&expr);
+                    ExprKind::Index(Box::new(items),
+                      vec![call(&check, vec![*expr, i], &Type::Int32, &loc)])
+                } else {
+                    let get = self.list_fn("get", &list_ty, &loc);
+                    ExprKind::Call(Path(None, get), vec![*expr, i], vec![])
+                }
+            },
+
+            ExprKind::MemberInvoke(expr, mem, args) => {
+                if !is_list(expr.ty.as_ref()) {
+                    return Expr {
+                        data: ExprKind::MemberInvoke(expr, mem, args),
+                        ty,
+                        loc,
+                    };
+                }
+
+                let list_ty = expr.ty.clone().unwrap();
+                match mem.0.as_str() {
+                    "push" | "pop" => {
+                        let op = if mem.0 == "push" { "push" } else { "pop" };
+                        let list_fn = self.list_fn(op, &list_ty, &loc);
+                        let mut fn_args = vec![*expr];
+                        fn_args.extend(args);
+                        ExprKind::Call(Path(None, list_fn), fn_args, vec![])
+                    },
+
+                    "len" => Self::count(&expr).data,
+
+                    _ => panic!("dumpster fire: unknown list method {} \
+                      slipped past typecheck", mem),
+                }
+            },
+
+            // conversions copy elements to or from a fresh backing array
+            ExprKind::Cast(expr, cast_ty) => {
+                let op = match (expr.ty.as_ref(), &cast_ty) {
+                    (Some(&Type::List(_)), &Type::Array(_, _)) =>
+                        Some(("to_array", expr.ty.clone().unwrap())),
+                    (Some(&Type::Array(_, _)), &Type::List(_)) =>
+                        Some(("from_array", cast_ty.clone())),
+                    _ => None,
+                };
+
+                match op {
+                    Some((op, list_ty)) => {
+                        let list_fn = self.list_fn(op, &list_ty, &loc);
+                        ExprKind::Call(Path(None, list_fn), vec![*expr],
+                          vec![])
                     },
+                    None => ExprKind::Cast(expr, cast_ty),
+                }
+            },
+
+            data => data,
+        };
+
+        Expr {
+            data,
+            ty,
+            loc,
+        }
+    }
+}
+
+struct ListTypeFolder<'a> {
+    symtab: &'a mut SymbolTable,
+    // (element type, struct path) for each list type seen so far
+    shapes: Vec<(Type, Path)>,
+    // generated struct definitions, by the module they'll live in
+    defs: Vec<(Ident, StructDef)>,
+}
+
+impl<'a> ListTypeFolder<'a> {
+    // find (or generate) the struct for a list type; like tuple structs,
+    //   these live in the first module to use them, and are public unless
+    //   the element type is private
+    fn list_struct(&mut self, elem: Type, module: &Ident, loc: &SrcLoc)
+      -> Path {
+        if let Some(shape) = self.shapes.iter().find(|s| s.0 == elem) {
+            return shape.1.clone();
+        }
+
+        let name = Ident(format!("ølist{}", self.shapes.len()), None);
 
+        let access = self.symtab.type_access(&elem, module, loc)
+          .expect("dumpster fire: list element type not found");
+
+        let def = StructDef {
+            name: name.clone(),
+            access,
+            members: vec![
+                StructMem {
+                    name: Ident(String::from("items"), None),
+                    ty: Type::Array(Box::new(elem.clone()),
+                      ArrayBounds::Dynamic(1)),
                     loc: loc.clone(),
-                });
+                },
+                StructMem {
+                    name: Ident(String::from("count"), None),
+                    ty: Type::Int32,
+                    loc: loc.clone(),
+                },
+            ],
+            attrs: vec![],
+            loc: loc.clone(),
+        };
+
+        self.symtab.add_struct_entry(&def, module)
+          .expect("dumpster fire: failure adding symtab entry for list");
+
+        let path = Path(Some(module.clone()), name);
+        self.shapes.push((elem, path.clone()));
+        self.defs.push((module.clone(), def));
+        path
+    }
+}
+
+impl<'a> ASTFolder for ListTypeFolder<'a> {
+    fn fold_dumpster(&mut self, dumpster: Dumpster) -> Dumpster {
+        let mut dumpster = fold::noop_fold_dumpster(self, dumpster);
+        insert_generated_structs(&mut dumpster,
+          self.defs.drain(..).collect());
+        dumpster
+    }
+
+    fn fold_type(&mut self, ty: Type, module: &Ident, loc: &SrcLoc) -> Type {
+        match fold::noop_fold_type(self, ty, module, loc) {
+            Type::List(elem) =>
+                Type::Struct(self.list_struct(*elem, module, loc)),
+            ty => ty,
+        }
+    }
+}
+
+// type declarations must precede any user types and fns, but may need
+//   leading consts (e.g. for fixed-length strings), and must follow any
+//   structs in the same module that their members use (including generated
+//   ones, e.g. nested tuples, or a list of tuples)
+fn insert_generated_structs(dumpster: &mut Dumpster,
+  defs: Vec<(Ident, StructDef)>) {
+    fn member_struct(ty: &Type) -> Option<&Path> {
+        match *ty {
+            Type::Struct(ref path) => Some(path),
+            Type::Array(ref base, _) => member_struct(base),
+            _ => None,
+        }
+    }
+
+    // (generated structs also stay in the order they were generated)
+    let mut inserted: Vec<(Ident, Ident)> = Vec::new();
+
+    for (module, def) in defs {
+        let m = dumpster.modules.iter_mut().find(|m| m.name == module)
+          .expect("dumpster fire: no module for generated struct");
+
+        let deps: Vec<&Ident> = def.members.iter()
+            .filter_map(|mem| member_struct(&mem.ty))
+            .filter(|path| match path.0 {
+                Some(ref m) => *m == module,
+                None => true,
+            })
+            .map(|path| &path.1)
+            .collect();
+
+        match m.data {
+            ModuleKind::Normal(ref mut items) => {
+                let consts = items.iter().position(|i| match *i {
+                    NormalItem::Const(_) => false,
+                    _ => true,
+                }).unwrap_or(items.len());
+
+                let after_deps = items.iter().rposition(|i| match *i {
+                    NormalItem::Struct(ref s) => deps.contains(&&s.name)
+                      || inserted.iter().any(|&(ref im, ref is)|
+                           *im == module && *is == s.name),
+                    _ => false,
+                }).map_or(0, |pos| pos + 1);
+
+                inserted.push((module.clone(), def.name.clone()));
+                items.insert(consts.max(after_deps), NormalItem::Struct(def));
+            },
+        }
+    }
+}
+
+struct NamedArgRewriteFolder<'a> {
+    symtab: &'a mut SymbolTable,
+    before_stmt_stack: Vec<Vec<Stmt>>,
+}
+
+impl<'a> NamedArgRewriteFolder<'a> {
+    // VB won't take named arguments along with a ParamArray, or for a
+    //   Declare'd function; and the slice rewriter splits view parameters
+    //   into three (positionally)
+    fn takes_names(fun: &FunDef) -> bool {
+        let varargs = match fun.optparams {
+            Some(FunOptParams::VarArgs(_, _, _)) => true,
+            _ => false,
+        };
+
+        let views = fun.params.iter().any(|p| match p.ty {
+            Type::ArrayView(_) => true,
+            _ => false,
+        });
+
+        !varargs && !views && fun.lib.is_none()
+    }
+
+    // evaluate an argument into a temporary before the current statement,
+    //   returning a path-expression for it
+    fn temp(&mut self, arg: Expr, module: &Ident, function: Option<&Ident>)
+      -> Expr {
+        let g = gensym(None);
+        let ty = arg.ty.clone()
+            .expect("dumpster fire: untyped expression in named arguments");
+        let loc = arg.loc.clone();
+
+        self.symtab.add_value_entry(&g, module, function,
+          &ty, Access::Private, &loc)
+          .expect("dumpster fire: failure adding symtab entry for gensym");
+
+        self.before_stmt_stack.last_mut()
+          .expect("dumpster fire: error in before statement stack")
+          .push(Stmt {
+              data: StmtKind::VarDecl(vec![(g.clone(), ty.clone(), Some(arg))]),
+              loc: loc.clone(),
+          });
+
+        Expr {
+            data: ExprKind::Name(Path(None, g)),
+            ty: Some(ty),
+            loc,
+        }
+    }
+}
+
+impl<'a> ASTFolder for NamedArgRewriteFolder<'a> {
+    fn fold_stmt_list(&mut self, stmts: Vec<Stmt>, module: &Ident,
+      function: &Ident) -> Vec<Stmt> {
+        stmts.into_iter().flat_map(|stmt| {
+            let stmt = self.fold_stmt(stmt, module, function);
+
+            let mut result = self.before_stmt_stack.pop()
+                .expect("dumpster fire: error in before statement stack");
+            result.push(stmt);
+            result
+        }).collect()
+    }
+
+    fn fold_stmt(&mut self, stmt: Stmt, module: &Ident,
+      function: &Ident) -> Stmt {
+        // push a new before-context
+        self.before_stmt_stack.push(Vec::new());
+        fold::noop_fold_stmt(self, stmt, module, function)
+    }
+
+    fn fold_expr(&mut self, expr: Expr, module: &Ident,
+      function: Option<&Ident>) -> Expr {
+        let Expr { data, ty, loc } =
+            fold::noop_fold_expr(self, expr, module, function);
+
+        let data = match data {
+            ExprKind::Call(path, mut args, optargs) => {
+                if optargs.is_empty() {
+                    return Expr {
+                        data: ExprKind::Call(path, args, optargs),
+                        ty,
+                        loc,
+                    };
+                }
+
+                let fun = match self.symtab.symbol_at_path(&path,
+                  NameCtxt::Function(module, Access::Private), &loc) {
+                    Ok(&Symbol::Fun { ref def, .. }) => def.clone(),
+                    _ => panic!("dumpster fire: non-function slipped past \
+                      typecheck"),
+                };
+
+                // each named argument's parameter
+                let params = optargs.iter().map(|&(ref name, _)| {
+                    if let Some(p) = fun.params.iter()
+                      .find(|p| param_named(&p.name, name)) {
+                        return p.clone();
+                    }
+
+                    let optparams = match fun.optparams {
+                        Some(FunOptParams::Named(ref optparams)) => optparams,
+                        _ => panic!("dumpster fire: named optional \
+                          arguments slipped past typecheck"),
+                    };
+                    optparams.iter()
+                        .find(|&&(ref p, _)| param_named(&p.name, name))
+                        .expect("dumpster fire: unknown named argument \
+                          slipped past typecheck")
+                        .0.clone()
+                }).collect::<Vec<_>>();
+
+                if Self::takes_names(&fun) {
+                    let named = params.into_iter().zip(optargs)
+                        .map(|(p, (_, arg))| (p.name, arg))
+                        .collect();
+                    return Expr {
+                        data: ExprKind::Call(path, args, named),
+                        ty,
+                        loc,
+                    };
+                }
+
+                // otherwise these can only be required parameters; put them
+                //   back into position, first evaluating (in the order they
+                //   were written) any with side effects which would be
+                //   reordered (by-reference ones must stay where they are)
+                let reordered = params.windows(2).any(|w|
+                    fun.params.iter().position(|p| p.name == w[0].name) >
+                      fun.params.iter().position(|p| p.name == w[1].name));
+
+                let mut optargs = params.iter().zip(optargs)
+                  .map(|(p, (_, arg))| {
+                    let effects = match arg.data {
+                        ExprKind::Lit(_)
+                      | ExprKind::Name(_)
+                      | ExprKind::UnOpApp(_, UnOp::AddressOf) => false,
+                        _ => true,
+                    };
+
+                    if reordered && effects && p.mode == ParamMode::ByVal {
+                        Some(self.temp(arg, module, function))
+                    } else {
+                        Some(arg)
+                    }
+                }).collect::<Vec<_>>();
+
+                for p in &fun.params[args.len()..] {
+                    let i = params.iter().position(|q| q.name == p.name)
+                        .expect("dumpster fire: required argument missing \
+                          after typecheck");
+                    args.push(optargs[i].take()
+                        .expect("dumpster fire: argument used twice"));
+                }
+
+                ExprKind::Call(path, args, vec![])
+            },
+
+            data => data,
+        };
+
+        Expr {
+            data,
+            ty,
+            loc,
+        }
+    }
+}
+
+struct TupleRewriteFolder<'a> {
+    symtab: &'a mut SymbolTable,
+    before_stmt_stack: Vec<Vec<Stmt>>,
+    // (element types, struct path) for each shape seen so far
+    shapes: Vec<(Vec<Type>, Path)>,
+    // generated struct definitions, by the module they'll live in
+    defs: Vec<(Ident, StructDef)>,
+    // constructor functions for the current module, by struct path
+    ctor_fns: Vec<(Path, Ident)>,
+    ctor_defs: Vec<FunDef>,
+}
+
+impl<'a> TupleRewriteFolder<'a> {
+    fn new(symtab: &'a mut SymbolTable) -> Self {
+        TupleRewriteFolder {
+            symtab,
+            before_stmt_stack: Vec::new(),
+            shapes: Vec::new(),
+            defs: Vec::new(),
+            ctor_fns: Vec::new(),
+            ctor_defs: Vec::new(),
+        }
+    }
+
+    fn member_name(i: usize) -> Ident {
+        Ident(format!("item{}", i), None)
+    }
+
+    // find (or generate) the struct for a tuple shape; new structs live in
+    //   the first module to use them, and are public so they can be shared,
+    //   unless an element type is private (and so only usable there anyway)
+    fn tuple_struct(&mut self, tys: Vec<Type>, module: &Ident, loc: &SrcLoc)
+      -> Path {
+        if let Some(shape) = self.shapes.iter().find(|s| s.0 == tys) {
+            return shape.1.clone();
+        }
+
+        let name = Ident(format!("øtuple{}", self.shapes.len()), None);
+
+        let access = self.symtab.type_access(&Type::Tuple(tys.clone()),
+          module, loc)
+          .expect("dumpster fire: tuple element type not found");
+
+        let def = StructDef {
+            name: name.clone(),
+            access,
+            members: tys.iter().enumerate().map(|(i, ty)| StructMem {
+                name: Self::member_name(i),
+                ty: ty.clone(),
+                loc: loc.clone(),
+            }).collect(),
+            attrs: vec![],
+            loc: loc.clone(),
+        };
+
+        self.symtab.add_struct_entry(&def, module)
+          .expect("dumpster fire: failure adding symtab entry for tuple");
+
+        let path = Path(Some(module.clone()), name);
+        self.shapes.push((tys, path.clone()));
+        self.defs.push((module.clone(), def));
+        path
+    }
+
+    fn shape(&self, path: &Path) -> &Vec<Type> {
+        self.shapes.iter().find(|s| s.1 == *path)
+          .map(|s| &s.0)
+          .expect("dumpster fire: no shape for tuple struct")
+    }
+
+    fn member_expr(base: &Expr, i: usize, ty: &Type) -> Expr {
+        Expr {
+            data: ExprKind::Member(Box::new(base.clone()),
+              Self::member_name(i)),
+            ty: Some(ty.clone()),
+            loc: base.loc.clone(),
+        }
+    }
+
+    // the constructor function for a tuple struct, generating it if we
+    //   haven't; building tuples in a call (rather than in a temporary ahead
+    //   of the statement) evaluates the elements exactly where, and as
+    //   often as, the tuple expression itself is (e.g. in a while condition,
+    //   or on one side of &&)
+    //
+    // fn øN(item0: T0, item1: &T1, ...) -> øtupleK {
+    //     let r: øtupleK;
+    //     r.item0 = item0;
+    //     r.item1 = item1;
+    //     ...
+    //     return r;
+    // }
+    fn tuple_ctor(&mut self, path: &Path, loc: &SrcLoc) -> Ident {
+        if let Some(&(_, ref name)) = self.ctor_fns.iter()
+          .find(|f| f.0 == *path) {
+            return name.clone();
+        }
+
+        let name = gensym(None);
+        self.ctor_fns.push((path.clone(), name.clone()));
+
+        let ty = Type::Struct(path.clone());
+        let shape = self.shape(path).clone();
+        let r = Expr {
+            data: ExprKind::Name(Path(None, Ident(String::from("r"), None))),
+            ty: Some(ty.clone()),
+            loc: loc.clone(),
+        };
+
+        let params = shape.iter().enumerate().map(|(i, elem_ty)| {
+            // VB can't take fixed-length strings as parameters, or UDTs
+            //   and arrays by value
+            let (ty, mode) = match *elem_ty {
+                Type::FixedString(_) => (Type::String, ParamMode::ByVal),
+                ref ty if ty.is_scalar() => (ty.clone(), ParamMode::ByVal),
+                ref ty => (ty.clone(), ParamMode::ByRef),
+            };
+
+            FunParam {
+                name: Self::member_name(i),
+                ty,
+                mode,
+                loc: loc.clone(),
+            }
+        }).collect::<Vec<_>>();
+
+        let mut body = vec![Stmt {
+            data: StmtKind::VarDecl(vec![
+              (Ident(String::from("r"), None), ty.clone(), None)]),
+            loc: loc.clone(),
+        }];
+
+        body.extend(params.iter().zip(shape.iter()).enumerate()
+          .map(|(i, (param, elem_ty))| Stmt {
+              data: StmtKind::Assign(Self::member_expr(&r, i, elem_ty),
+                AssignOp::Assign, Expr {
+                    data: ExprKind::Name(Path(None, param.name.clone())),
+                    ty: Some(param.ty.clone()),
+                    loc: loc.clone(),
+                }),
+              loc: loc.clone(),
+          }));
+
+        body.push(Stmt {
+            data: StmtKind::Return(Some(r)),
+            loc: loc.clone(),
+        });
+
+        self.ctor_defs.push(FunDef {
+            name: name.clone(),
+            access: Access::Private,
+            params,
+            optparams: None,
+            ret: ty,
+            body,
+            lib: None,
+            attrs: vec![],
+            loc: loc.clone(),
+        });
+
+        name
+    }
+
+    // declare a gensym of the given type (with an optional initializer)
+    //   before the current statement, returning a path-expression for it
+    fn temp(&mut self, ty: &Type, init: Option<Expr>, module: &Ident,
+      function: &Ident, loc: &SrcLoc) -> Expr {
+        let g = gensym(None);
+
+        self.symtab.add_value_entry(&g, module, Some(function),
+          ty, Access::Private, loc)
+          .expect("dumpster fire: failure adding symtab entry for gensym");
+
+        self.before_stmt_stack.last_mut()
+          .expect("dumpster fire: error in before statement stack")
+          .push(Stmt {
+              data: StmtKind::VarDecl(vec![(g.clone(), ty.clone(), init)]),
+              loc: loc.clone(),
+          });
+
+        Expr {
+            data: ExprKind::Name(Path(None, g)),
+            ty: Some(ty.clone()),
+            loc: loc.clone(),
+        }
+    }
+}
+
+impl<'a> ASTFolder for TupleRewriteFolder<'a> {
+    fn fold_dumpster(&mut self, dumpster: Dumpster) -> Dumpster {
+        let mut dumpster = fold::noop_fold_dumpster(self, dumpster);
+        insert_generated_structs(&mut dumpster,
+          self.defs.drain(..).collect());
+        dumpster
+    }
+
+    fn fold_module(&mut self, m: Module) -> Module {
+        self.ctor_fns.clear();
+        let mut m = fold::noop_fold_module(self, m);
+
+        let ModuleKind::Normal(ref mut items) = m.data;
+        items.extend(self.ctor_defs.drain(..).map(NormalItem::Function));
+
+        m
+    }
+
+    fn fold_stmt_list(&mut self, stmts: Vec<Stmt>, module: &Ident,
+      function: &Ident) -> Vec<Stmt> {
+        stmts.into_iter().flat_map(|stmt| {
+            let stmt = self.fold_stmt(stmt, module, function);
+
+            let mut result = self.before_stmt_stack.pop()
+                .expect("dumpster fire: error in before statement stack");
+            result.push(stmt);
+            result
+        }).collect()
+    }
+
+    fn fold_stmt(&mut self, stmt: Stmt, module: &Ident,
+      function: &Ident) -> Stmt {
+        // push a new before-context
+        self.before_stmt_stack.push(Vec::new());
+
+        let Stmt { data, loc } =
+            fold::noop_fold_stmt(self, stmt, module, function);
+
+        let data = match data {
+            StmtKind::Destructure(idents, Some(Type::Struct(path)), init) => {
+                let ty = Type::Struct(path.clone());
+                let shape = self.shape(&path).clone();
+
+                // no need for a temporary if we already have a name
+                let base = match init.data {
+                    ExprKind::Name(_) => init,
+                    _ => self.temp(&ty, Some(init), module, function, &loc),
+                };
+
+                StmtKind::VarDecl(idents.into_iter().zip(shape).enumerate()
+                  .map(|(i, (ident, elem_ty))| {
+                      let init = Self::member_expr(&base, i, &elem_ty);
+                      (ident, elem_ty, Some(init))
+                  }).collect())
+            },
+
+            StmtKind::Destructure(..) =>
+                panic!("dumpster fire: untyped destructure in tuple rewriter"),
+
+            s => s,
+        };
+
+        Stmt {
+            data,
+            loc,
+        }
+    }
+
+    fn fold_expr(&mut self, expr: Expr, module: &Ident,
+      function: Option<&Ident>) -> Expr {
+        let Expr { data, ty, loc } =
+            fold::noop_fold_expr(self, expr, module, function);
+
+        let data = match data {
+            ExprKind::Tuple(exprs) => {
+                let path = match ty {
+                    Some(Type::Struct(ref path)) => path.clone(),
+                    _ => panic!("dumpster fire: untransformed tuple type \
+                      in tuple rewriter"),
+                };
+                let ctor = self.tuple_ctor(&path, &loc);
+                ExprKind::Call(Path(None, ctor), exprs, vec![])
+            },
+
+            data => data,
+        };
+
+        Expr {
+            data,
+            ty,
+            loc,
+        }
+    }
+
+    fn fold_type(&mut self, ty: Type, module: &Ident, loc: &SrcLoc) -> Type {
+        match fold::noop_fold_type(self, ty, module, loc) {
+            Type::Tuple(tys) => Type::Struct(self.tuple_struct(tys, module, loc)),
+            ty => ty,
+        }
+    }
+}
+
+struct CastRewriteFolder {
+    // we'll need these for object-type gensyms
+    // symtab: &'a mut SymbolTable,
+    // before_stmt_stack: Vec<Vec<Stmt>>,
+}
+
+impl CastRewriteFolder {
+    /*
+    fn new(symtab: &'a mut SymbolTable) -> Self {
+        CastRewriteFolder {
+            symtab,
+            // before_stmt_stack: Vec::new(),
+        }
+    }
+    */
+}
+
+impl ASTFolder for CastRewriteFolder {
+    fn fold_expr(&mut self, expr: Expr, module: &Ident,
+      function: Option<&Ident>) -> Expr {
+        let Expr { data, ty, loc } =
+            fold::noop_fold_expr(self, expr, module, function);
+
+        let data = match data {
+            ExprKind::Cast(expr, cast_ty) => {
+                let expr_ty = expr.ty.as_ref()
+                  .expect("dumpster fire: untyped expression \
+                          in cast rewriter")
+                  .clone(); // TODO: this is stupid and only to satisfy the
+                            //   borrow checker
+
+                // no-op cast: just lift out the expression
+                if expr_ty == cast_ty {
+                    return *expr;
+                }
+
+                // no special implementations when casting to variant
+                //   (we're just boxing), and map casts are lowered along
+                //   with the rest of maps
+                if let Type::Variant | Type::Map(_, _) = cast_ty {
+                    return Expr {
+                        data: ExprKind::Cast(expr, cast_ty),
+                        ty,
+                        loc,
+                    };
+                }
+
+                let loc = expr.loc.clone();
+
+                match expr_ty {
+                    Type::Bool => ExprKind::CondExpr {
+                        cond: expr,
+                        if_expr: Box::new(Expr {
+                            data: ExprKind::Lit(
+                              Literal::num_of_type(&cast_ty, 1)
+                              .expect("dumpster fire: bad numeric type \
+                                in cast rewriter")),
+                            ty: Some(cast_ty.clone()),
+                            loc: loc.clone(),
+                        }),
+                        else_expr: Box::new(Expr {
+                            data: ExprKind::Lit(
+                              Literal::num_of_type(&cast_ty, 0)
+                              .expect("dumpster fire: bad numeric type \
+                                in cast rewriter")),
+                            ty: Some(cast_ty.clone()),
+                            loc,
+                        })
+                    },
+
+                    Type::Obj | Type::Object(_) | Type::Nullable(_) => {
+                        panic!("TODO: object dynamic casts")
+                    },
+
+                    _ => ExprKind::Cast(expr, cast_ty),
+                }
+            },
+
+            data => data,
+        };
+
+        Expr {
+            data,
+            ty,
+            loc,
+        }
+    }
+}
+
+struct ShortCircuitLogicalsFolder<'a> {
+    symtab: &'a mut SymbolTable,
+    before_stmt_stack: Vec<Vec<Stmt>>,
+}
+
+impl<'a> ShortCircuitLogicalsFolder<'a> {
+    fn new(symtab: &'a mut SymbolTable) -> Self {
+        ShortCircuitLogicalsFolder {
+            symtab,
+            before_stmt_stack: Vec::new(),
+        }
+    }
+}
+
+impl<'a> ASTFolder for ShortCircuitLogicalsFolder<'a> {
+    fn fold_stmt_list(&mut self, stmts: Vec<Stmt>, module: &Ident,
+      function: &Ident) -> Vec<Stmt> {
+        stmts.into_iter().flat_map(|stmt| {
+            let stmt = self.fold_stmt(stmt, module, function);
+
+            let mut before_stmts = self.before_stmt_stack.pop()
+                .expect("dumpster fire: error in before statement stack");
+            let mut result: Vec<_> = before_stmts.drain(..).collect();
+
+            // am I crazy?
+            result = self.fold_stmt_list(result, module, function);
+
+            result.push(stmt);
+            result
+        }).collect()
+    }
+
+    fn fold_stmt(&mut self, stmt: Stmt, module: &Ident,
+      function: &Ident) -> Stmt {
+        // push a new before-context
+        self.before_stmt_stack.push(Vec::new());
+
+        // first recurse into the statement...
+        let Stmt { data, loc } =
+            fold::noop_fold_stmt(self, stmt, module, function);
+
+        let data = match data {
+            StmtKind::Assign(lhs, op, rhs) => {
+                match op {
+                    AssignOp::LogAndAssign => StmtKind::IfStmt {
+                        cond: lhs.clone(),
+                        body: vec![
+                            Stmt {
+                                data: StmtKind::Assign(
+                                          lhs, AssignOp::LogAndAssign, rhs),
+                                loc: loc.clone(),
+                            }
+                        ],
+                        elsifs: vec![],
+                        els: None
+                    },
+
+                    AssignOp::LogOrAssign => StmtKind::IfStmt {
+                        cond: Expr {
+                            data: ExprKind::UnOpApp(
+                                      Box::new(lhs.clone()), UnOp::LogNot),
+                            ty: lhs.ty.clone(),
+                            loc: loc.clone(),
+                        },
+                        body: vec![
+                            Stmt {
+                                data: StmtKind::Assign(
+                                          lhs, AssignOp::LogOrAssign, rhs),
+                                loc: loc.clone(),
+                            }
+                        ],
+                        elsifs: vec![],
+                        els: None,
+                    },
+
+                    op => StmtKind::Assign(lhs, op, rhs),
+                }
+            },
+
+            s => s,
+        };
+
+        Stmt {
+            data,
+            loc,
+        }
+    }
+
+    fn fold_expr(&mut self, expr: Expr, module: &Ident,
+      function: Option<&Ident>) -> Expr {
+        // first recurse into the expression...
+        let Expr { data, ty, loc } =
+            fold::noop_fold_expr(self, expr, module, function);
+
+        let data = match data {
+            ExprKind::BinOpApp(lhs, rhs, op) => {
+                match op {
+                    BinOp::LogAnd => {
+                        // use a gensym for this term
+                        let g = gensym(None);
+
+                        let before_stmts = self.before_stmt_stack.last_mut()
+                          .expect("dumpster fire: \
+                                  error in before statement stack");
+
+                        // add symbol table entry for it
+                        self.symtab.add_value_entry(&g, module, function,
+                          &Type::Bool, Access::Private, &loc)
+                          .expect("dumpster fire: \
+                                  failure adding symtab entry for gensym");
+
+                        // push a declaration for it
+                        before_stmts.push(Stmt {
+                            data: StmtKind::VarDecl(vec![
+                              (g.clone(), Type::Bool, Some(*lhs))
+                            ]),
+                            loc: loc.clone(),
+                        });
+
+                        // build a path-expression for it
+                        let g_expr = Expr {
+                            data: ExprKind::Name(Path(None, g)),
+                            ty: Some(Type::Bool),
+                            loc: loc.clone(),
+                        };
+
+                        // push an &&= for it, using existing short-circuiting
+                        //   rules in .fold_stmt(...)
+                        before_stmts.push(Stmt {
+                            data: StmtKind::Assign(
+                                g_expr.clone(),
+                                AssignOp::LogAndAssign,
+                                *rhs
+                            ),
+                            loc: loc.clone(),
+                        });
+
+                        // use the gensym as our new expression
+                        g_expr.data
+                    },
+
+                    BinOp::LogOr => {
+                        // use a gensym for this term
+                        let g = gensym(None);
+
+                        let before_stmts = self.before_stmt_stack.last_mut()
+                          .expect("dumpster fire: \
+                                  error in before statement stack");
+
+                        // add symbol table entry for it
+                        self.symtab.add_value_entry(&g, module, function,
+                          &Type::Bool, Access::Private, &loc)
+                          .expect("dumpster fire: \
+                                  failure adding symtab entry for gensym");
+
+                        // push a declaration for it
+                        before_stmts.push(Stmt {
+                            data: StmtKind::VarDecl(vec![
+                              (g.clone(), Type::Bool, Some(*lhs))
+                            ]),
+                            loc: loc.clone(),
+                        });
+
+                        // build a path-expression for it
+                        let g_expr = Expr {
+                            data: ExprKind::Name(Path(None, g)),
+                            ty: Some(Type::Bool),
+                            loc: loc.clone(),
+                        };
+
+                        // push an &&= for it, using existing short-circuiting
+                        //   rules in .fold_stmt(...)
+                        before_stmts.push(Stmt {
+                            data: StmtKind::Assign(
+                                g_expr.clone(),
+                                AssignOp::LogOrAssign,
+                                *rhs
+                            ),
+                            loc: loc.clone(),
+                        });
+
+                        // use the gensym as our new expression
+                        g_expr.data
+                    },
+
+                    op => {
+                        ExprKind::BinOpApp(lhs, rhs, op)
+                    },
+                }
+            },
+
+            ExprKind::CondExpr { cond, if_expr, else_expr } => {
+                let g = gensym(None);
+
+                let this_expr = Expr {
+                    data: ExprKind::CondExpr {
+                        cond: cond.clone(),
+                        if_expr: if_expr.clone(),
+                        else_expr: else_expr.clone()
+                    },
+                    ty: ty.clone(),
+                    loc: loc.clone(),
+                };
+
+                let ty = this_expr.ty.as_ref()
+                  .expect("dumpster fire: \
+                     untyped condexpr in short-ciruiter");
+
+                // add symbol table entry for g
+                self.symtab.add_value_entry(&g, module, function,
+                  &ty, Access::Private, &loc).expect("dumpster fire: \
+                                    failure adding symtab entry for gensym");
+
+                let before_stmts = self.before_stmt_stack.last_mut()
+                  .expect("dumpster fire: error in before statement stack");
+
+                // push declaration for g
+                before_stmts.push(Stmt {
+                    data: StmtKind::VarDecl(vec![
+                        (g.clone(), ty.clone(), None)
+                    ]),
+                    loc: loc.clone(),
+                });
+
+                let g_expr = Expr {
+                    data: ExprKind::Name(Path(None, g)),
+                    ty: Some(ty.clone()),
+                    loc: loc.clone(),
+                };
+
+                before_stmts.push(Stmt {
+                    data: StmtKind::IfStmt {
+                        cond: *cond,
+                        body: vec![
+                            Stmt {
+                                data: StmtKind::Assign(
+                                  g_expr.clone(), AssignOp::Assign, *if_expr),
+                                loc: loc.clone(),
+                            }
+                        ],
+                        // TODO: we could use this for condexpr chains
+                        elsifs: vec![],
+                        els: Some(vec![
+                           Stmt {
+                               data: StmtKind::Assign(
+                                 g_expr.clone(), AssignOp::Assign, *else_expr),
+                               loc: loc.clone()
+                           }
+                        ]),
+                    },
+
+                    loc: loc.clone(),
+                });
+
+                g_expr.data
+            },
+
+            e => e,
+        };
+
+        Expr {
+            data,
+            ty,
+            loc,
+        }
+    }
+}
+
+struct ArrayLoopRewriteFolder<'a> {
+    symtab: &'a mut SymbolTable,
+    before_stmt_stack: Vec<Vec<Stmt>>,
+    // allocation checks for the current module, by element type
+    allocated_fns: Vec<(Type, Ident)>,
+    defs: Vec<FunDef>,
+}
+
+impl<'a> ArrayLoopRewriteFolder<'a> {
+    fn new(symtab: &'a mut SymbolTable) -> Self {
+        ArrayLoopRewriteFolder {
+            symtab,
+            before_stmt_stack: Vec::new(),
+            allocated_fns: Vec::new(),
+            defs: Vec::new(),
+        }
+    }
+
+    // TODO: this whole loop-and-a-half is ugly hot garbage
+    fn array_for_loop(&mut self, var: Ident, ty: Type, mode: ParamMode,
+      expr: Expr, indices: Vec<Ident>, base: &Type, bounds: &ArrayBounds,
+      mut body: Vec<Stmt>, loc: &SrcLoc, module: &Ident, function: &Ident)
+      -> StmtKind {
+        let dims = bounds.dims();
+
+        // anything but an lvalue (e.g. keys() of a map) is evaluated once,
+        //   before the loop; as a function result, it might come back
+        //   unallocated (an empty map has no keys to allocate), which we
+        //   treat as empty (slices are left for the slice rewriter)
+        let hoisted = match expr.data {
+            ExprKind::Slice(_, _, _) => false,
+            _ => !expr.is_lvalue(),
+        };
+
+        let (expr, allocated) = if !hoisted {
+            (expr, None)
+        } else {
+            let g = gensym(None);
+            let array_ty = Type::Array(Box::new(base.clone()),
+              ArrayBounds::Dynamic(dims));
+            self.symtab.add_value_entry(&g, module, Some(function),
+              &array_ty, Access::Private, loc)
+              .expect("dumpster fire: failure adding symtab entry for \
+                gensym");
+
+            self.before_stmt_stack.last_mut()
+              .expect("dumpster fire: error in before statement stack")
+              .push(Stmt {
+                  data: StmtKind::VarDecl(vec![
+                    (g.clone(), array_ty.clone(), Some(expr))
+                  ]),
+                  loc: loc.clone(),
+              });
+
+            let g_expr = Expr {
+                data: ExprKind::Name(Path(None, g)),
+                ty: Some(array_ty),
+                loc: loc.clone(),
+            };
+            let check = allocated_fn(&mut self.allocated_fns, &mut self.defs,
+              base, loc);
+            (g_expr.clone(), Some(call(&check, vec![g_expr], &Type::Bool,
+              loc)))
+        };
+
+        // build indexing gensyms by dimension (unless the loop names its own
+        //   index variables, which are already in the symbol table)
+        // inclusive ranges would be nice here...
+        let named_indices = !indices.is_empty();
+        let mut g_iters: Vec<_> = if named_indices {
+            indices
+        } else {
+            (1..dims + 1).map(|_| gensym(None)).collect()
+        };
+
+        let index_expr = Expr {
+            data: ExprKind::Index(Box::new(expr.clone()),
+              g_iters.iter().cloned().map(|g| Expr {
+                  data: ExprKind::Name(Path(None, g)),
+                  ty: Some(Type::Int32),
+                  loc: loc.clone(),
+              }).collect()),
+            ty: Some(base.clone()),
+            loc: loc.clone(),
+        };
+
+        // add gensyms to symbol table
+        if !named_indices {
+            for g in &g_iters {
+                self.symtab.add_value_entry(g, module, Some(function),
+                  &Type::Int32, Access::Private, &loc)
+                  .expect("dumpster fire: failure adding symtab entry for \
+                    gensym");
+            }
+        }
+
+        let mut body = match mode {
+            ParamMode::ByVal => {
+                // push a before-declaration for the original variable
+                let before_stmts = self.before_stmt_stack.last_mut()
+                  .expect("dumpster fire: \
+                          error in before statement stack");
+
+                before_stmts.push(Stmt {
+                    data: StmtKind::VarDecl(vec![
+                      (var.clone(), ty.clone(), None)
+                    ]),
+                    loc: loc.clone(),
+                });
+
+                // elements stored as var (e.g. variadic arguments) get
+                //   converted explicitly to the loop variable's type
+                let index_expr = if *base == Type::Variant {
+                    var_conversion(index_expr, &ty)
+                } else {
+                    index_expr
+                };
+
+                // and copy into it at the beginning of each iteration
+                let copy_stmt = Stmt {
+                    data: StmtKind::Assign(
+                        Expr {
+                            data: ExprKind::Name(Path(None, var)),
+                            ty: Some(ty),
+                            loc: loc.clone(),
+                        },
+                        AssignOp::Assign,
+                        index_expr,
+                    ),
+                    loc: loc.clone(),
+                };
+
+                body.insert(0, copy_stmt);
+
+                body
+            },
+
+            ParamMode::ByRef => {
+                let mut subst_folder = ScopedExprSubstitutionFolder {
+                    orig: var,
+                    replace: index_expr,
+                    module: module.clone(),
+                    function: Some(function.clone()),
+                };
+
+                subst_folder.fold_stmt_list(body, module, function)
+            },
+
+        };
+
+        // nested inner loops, if needed
+        for dim in (1..dims).rev() {
+            let spec = ForSpec::Range(
+                // TODO: gross
+                Expr {
+                    data: ExprKind::ExtentExpr(
+                              Box::new(expr.clone()),
+                              ExtentKind::First,
+                              dim),
+                    ty: Some(Type::Int32),
+                    loc: loc.clone(),
+                },
+
+                Expr {
+                    data: ExprKind::ExtentExpr(
+                              Box::new(expr.clone()),
+                              ExtentKind::Last,
+                              dim),
+                    ty: Some(Type::Int32),
+                    loc: loc.clone(),
+                },
+                None
+            );
+
+            body = vec![Stmt {
+                data: StmtKind::ForLoop {
+                    var: (g_iters.pop().unwrap(),
+                      Type::Int32, ParamMode::ByVal),
+                    spec,
+                    body,
+                },
+                loc: loc.clone(),
+            }];
+        }
+
+        let var = (g_iters.pop().unwrap(), Type::Int32, ParamMode::ByVal);
+        let spec = ForSpec::Range(
+            Expr {
+                data: ExprKind::ExtentExpr(
+                          Box::new(expr.clone()),
+                          ExtentKind::First,
+                          0usize),
+                ty: Some(Type::Int32),
+                loc: loc.clone(),
+            },
+
+            Expr {
+                data: ExprKind::ExtentExpr(
+                          Box::new(expr.clone()),
+                          ExtentKind::Last,
+                          0usize),
+                ty: Some(Type::Int32),
+                loc: loc.clone(),
+            },
+
+            None
+        );
+
+        let for_loop = StmtKind::ForLoop {
+            var,
+            spec,
+            body,
+        };
+
+        match allocated {
+            Some(allocated) => StmtKind::IfStmt {
+                cond: allocated,
+                body: vec![Stmt {
+                    data: for_loop,
+                    loc: loc.clone(),
+                }],
+                elsifs: vec![],
+                els: None,
+            },
+            None => for_loop,
+        }
+    }
+
+    // VB's For Each needs a var or object control variable, so for any
+    //   other type we loop over a var gensym and convert each element
+    fn object_for_loop(&mut self, var: Ident, ty: Type, expr: Expr,
+      mut body: Vec<Stmt>, loc: &SrcLoc, module: &Ident, function: &Ident)
+      -> StmtKind {
+        if ty == Type::Variant || ty.is_object() == Some(true) {
+            return StmtKind::ForLoop {
+                var: (var, ty, ParamMode::ByVal),
+                spec: ForSpec::Each(expr, vec![]),
+                body,
+            };
+        }
+
+        let g = gensym(None);
+        self.symtab.add_value_entry(&g, module, Some(function),
+          &Type::Variant, Access::Private, loc)
+          .expect("dumpster fire: failure adding symtab entry for gensym");
+
+        // push a before-declaration for the original variable
+        let before_stmts = self.before_stmt_stack.last_mut()
+          .expect("dumpster fire: \
+                  error in before statement stack");
+
+        before_stmts.push(Stmt {
+            data: StmtKind::VarDecl(vec![
+              (var.clone(), ty.clone(), None)
+            ]),
+            loc: loc.clone(),
+        });
+
+        // and convert into it at the beginning of each iteration
+        let elem = Expr {
+            data: ExprKind::Name(Path(None, g.clone())),
+            ty: Some(Type::Variant),
+            loc: loc.clone(),
+        };
+
+        let copy_stmt = Stmt {
+            data: StmtKind::Assign(
+                Expr {
+                    data: ExprKind::Name(Path(None, var)),
+                    ty: Some(ty.clone()),
+                    loc: loc.clone(),
+                },
+                AssignOp::Assign,
+                var_conversion(elem, &ty),
+            ),
+            loc: loc.clone(),
+        };
+
+        body.insert(0, copy_stmt);
+
+        StmtKind::ForLoop {
+            var: (g, Type::Variant, ParamMode::ByVal),
+            spec: ForSpec::Each(expr, vec![]),
+            body,
+        }
+    }
+}
+
+impl<'a> ASTFolder for ArrayLoopRewriteFolder<'a> {
+    fn fold_module(&mut self, m: Module) -> Module {
+        self.allocated_fns.clear();
+        let mut m = fold::noop_fold_module(self, m);
+
+        let ModuleKind::Normal(ref mut items) = m.data;
+        items.extend(self.defs.drain(..).map(NormalItem::Function));
+
+        m
+    }
+
+    fn fold_stmt_list(&mut self, stmts: Vec<Stmt>, module: &Ident,
+      function: &Ident) -> Vec<Stmt> {
+        stmts.into_iter().flat_map(|stmt| {
+            let stmt = self.fold_stmt(stmt, module, function);
+
+            let mut before_stmts = self.before_stmt_stack.pop()
+                .expect("dumpster fire: error in before statement stack");
+            let mut result: Vec<_> = before_stmts.drain(..).collect();
+
+            // am I crazy?
+            result = self.fold_stmt_list(result, module, function);
+
+            result.push(stmt);
+            result
+        }).collect()
+    }
+
+    fn fold_stmt(&mut self, stmt: Stmt, module: &Ident,
+      function: &Ident) -> Stmt {
+        // push a new before-context
+        self.before_stmt_stack.push(Vec::new());
+
+        // first recurse into the statement...
+        let Stmt { data, loc } =
+            fold::noop_fold_stmt(self, stmt, module, function);
+
+        let data = match data {
+            StmtKind::ForLoop { var: (var, ty, mode), spec, body } => {
+                match spec {
+                    ForSpec::Range(first, last, step) =>
+                        StmtKind::ForLoop {
+                            var: (var, ty, mode),
+                            spec: ForSpec::Range(first, last, step),
+                            body,
+                        },
+
+                    ForSpec::Each(expr, indices) => {
+                        let expr_ty = expr.ty.as_ref()
+                            .expect("dumpster fire: \
+                                    untyped expr in loop rewriter")
+                            .clone(); // TODO: this is stupid and only to
+                                      //   satisfy the borrow checker
+
+                        match expr_ty {
+                            Type::Array(ref base, ref bounds) =>
+                                self.array_for_loop(var, ty, mode, expr,
+                                  indices, base, bounds, body, &loc, module,
+                                  function),
+
+                            Type::ArrayView(ref base) =>
+                                self.array_for_loop(var, ty, mode, expr,
+                                  indices, base, &ArrayBounds::Dynamic(1),
+                                  body, &loc, module, function),
+
+                            Type::VarArgsArray(_) =>
+                                self.array_for_loop(var, ty, mode, expr,
+                                  indices, &Type::Variant,
+                                  &ArrayBounds::Dynamic(1), body, &loc, module,
+                                  function),
+
+                            // (VB's For Each only ever gives us a copy)
+                            Type::Variant
+                          | Type::Obj
+                          | Type::Object(_) => match mode {
+                                ParamMode::ByVal => self.object_for_loop(var,
+                                  ty, expr, body, &loc, module, function),
+                                ParamMode::ByRef => panic!("dumpster fire: \
+                                  reference loop variable over object or var \
+                                  slipped past typecheck"),
+                            },
+
+                            _ => StmtKind::ForLoop {
+                                var: (var, ty, mode),
+                                spec: ForSpec::Each(expr, indices),
+                                body,
+                            },
+                        }
+                    },
+                }
+            },
+
+            s => s,
+        };
+
+        Stmt {
+            data,
+            loc,
+        }
+    }
+
+    fn fold_expr(&mut self, expr: Expr, module: &Ident,
+      function: Option<&Ident>) -> Expr {
+        // first recurse into the expression...
+        let Expr { data, ty, loc } =
+            fold::noop_fold_expr(self, expr, module, function);
+
+        let data = match data {
+            ExprKind::BinOpApp(lhs, rhs, op) => {
+                match op {
+                    BinOp::LogAnd => {
+                        // use a gensym for this term
+                        let g = gensym(None);
+
+                        let before_stmts = self.before_stmt_stack.last_mut()
+                          .expect("dumpster fire: \
+                                  error in before statement stack");
+
+                        // add symbol table entry for it
+                        self.symtab.add_value_entry(&g, module, function,
+                          &Type::Bool, Access::Private, &loc)
+                          .expect("dumpster fire: \
+                                  failure adding symtab entry for gensym");
+
+                        // push a declaration for it
+                        before_stmts.push(Stmt {
+                            data: StmtKind::VarDecl(vec![
+                              (g.clone(), Type::Bool, Some(*lhs))
+                            ]),
+                            loc: loc.clone(),
+                        });
+
+                        // build a path-expression for it
+                        let g_expr = Expr {
+                            data: ExprKind::Name(Path(None, g)),
+                            ty: Some(Type::Bool),
+                            loc: loc.clone(),
+                        };
+
+                        // push an &&= for it, using existing short-circuiting
+                        //   rules in .fold_stmt(...)
+                        before_stmts.push(Stmt {
+                            data: StmtKind::Assign(
+                                g_expr.clone(),
+                                AssignOp::LogAndAssign,
+                                *rhs
+                            ),
+                            loc: loc.clone(),
+                        });
+
+                        // use the gensym as our new expression
+                        g_expr.data
+                    },
+
+                    BinOp::LogOr => {
+                        // use a gensym for this term
+                        let g = gensym(None);
+
+                        let before_stmts = self.before_stmt_stack.last_mut()
+                          .expect("dumpster fire: \
+                                  error in before statement stack");
+
+                        // add symbol table entry for it
+                        self.symtab.add_value_entry(&g, module, function,
+                          &Type::Bool, Access::Private, &loc)
+                          .expect("dumpster fire: \
+                                  failure adding symtab entry for gensym");
+
+                        // push a declaration for it
+                        before_stmts.push(Stmt {
+                            data: StmtKind::VarDecl(vec![
+                              (g.clone(), Type::Bool, Some(*lhs))
+                            ]),
+                            loc: loc.clone(),
+                        });
+
+                        // build a path-expression for it
+                        let g_expr = Expr {
+                            data: ExprKind::Name(Path(None, g)),
+                            ty: Some(Type::Bool),
+                            loc: loc.clone(),
+                        };
+
+                        // push an &&= for it, using existing short-circuiting
+                        //   rules in .fold_stmt(...)
+                        before_stmts.push(Stmt {
+                            data: StmtKind::Assign(
+                                g_expr.clone(),
+                                AssignOp::LogOrAssign,
+                                *rhs
+                            ),
+                            loc: loc.clone(),
+                        });
+
+                        // use the gensym as our new expression
+                        g_expr.data
+                    },
+
+                    op => {
+                        ExprKind::BinOpApp(lhs, rhs, op)
+                    },
+                }
+            },
+
+            ExprKind::CondExpr { cond, if_expr, else_expr } => {
+                let g = gensym(None);
+
+                let this_expr = Expr {
+                    data: ExprKind::CondExpr {
+                        cond: cond.clone(),
+                        if_expr: if_expr.clone(),
+                        else_expr: else_expr.clone()
+                    },
+                    ty: ty.clone(),
+                    loc: loc.clone(),
+                };
+                
+                let ty = this_expr.ty.as_ref()
+                  .expect("dumpster fire: \
+                     untypeable condexpr in short-ciruiter");
+
+                // add symbol table entry for g
+                self.symtab.add_value_entry(&g, module, function,
+                  &ty, Access::Private, &loc).expect("dumpster fire: \
+                                    failure adding symtab entry for gensym");
+
+                let before_stmts = self.before_stmt_stack.last_mut()
+                  .expect("dumpster fire: error in before statement stack");
+
+                // push declaration for g
+                before_stmts.push(Stmt {
+                    data: StmtKind::VarDecl(vec![
+                        (g.clone(), ty.clone(), None)
+                    ]),
+                    loc: loc.clone(),
+                });
+
+                let g_expr = Expr {
+                    data: ExprKind::Name(Path(None, g)),
+                    ty: Some(ty.clone()),
+                    loc: loc.clone(),
+                };
+
+                before_stmts.push(Stmt {
+                    data: StmtKind::IfStmt {
+                        cond: *cond,
+                        body: vec![
+                            Stmt {
+                                data: StmtKind::Assign(
+                                  g_expr.clone(), AssignOp::Assign, *if_expr),
+                                loc: loc.clone(),
+                            }
+                        ],
+                        // TODO: we could use this for condexpr chains
+                        elsifs: vec![],
+                        els: Some(vec![
+                           Stmt {
+                               data: StmtKind::Assign(
+                                 g_expr.clone(), AssignOp::Assign, *else_expr),
+                               loc: loc.clone()
+                           }
+                        ]),
+                    },
+
+                    loc: loc.clone(),
+                });
+
+                g_expr.data
+            },
+
+            e => e,
+        };
+
+        Expr {
+            data,
+            ty,
+            loc,
+        }
+    }
+}
+
+struct AlongLoopRewriteFolder;
+
+impl AlongLoopRewriteFolder {
+    fn new() -> Self {
+        Self {
+        }
+    }
+}
+
+impl ASTFolder for AlongLoopRewriteFolder {
+    fn fold_stmt(&mut self, stmt: Stmt, module: &Ident, function: &Ident)
+      -> Stmt {
+        // first recurse...
+        let Stmt { data, loc } =
+            fold::noop_fold_stmt(self, stmt, module, function);
+
+        let data = match data {
+            StmtKind::ForAlong { mut vars, along, mut body } => {
+                let dims = vars.len();
+
+                for dim in (0..dims).rev() {
+                    body = vec![
+                        Stmt {
+                            data: StmtKind::ForLoop {
+                                var: (
+                                         vars.pop().unwrap(),
+                                         Type::Int32,
+                                         ParamMode::ByVal
+                                ),
+                                spec: ForSpec::Range(
+                                    Expr {
+                                        data: ExprKind::ExtentExpr(
+                                            Box::new(along.clone()),
+                                            ExtentKind::First,
+                                            dim
+                                        ),
+                                        ty: Some(Type::Int32),
+                                        loc: loc.clone(),
+                                    },
+                                    Expr {
+                                        data: ExprKind::ExtentExpr(
+                                            Box::new(along.clone()),
+                                            ExtentKind::Last,
+                                            dim
+                                        ),
+                                        ty: Some(Type::Int32),
+                                        loc: loc.clone(),
+                                    },
+                                    None
+                                ),
+                                body,
+                            },
+                            loc: loc.clone(),
+                        }
+                    ];
+                }
+
+                body.pop().unwrap().data
+            },
+
+            data => data,
+        };
+
+        Stmt {
+            data,
+            loc,
+        }
+    }
+}
+
+struct AllocAlongRewriteFolder;
+
+impl ASTFolder for AllocAlongRewriteFolder {
+    fn fold_stmt(&mut self, Stmt { data, loc }: Stmt, module: &Ident,
+      function: &Ident) -> Stmt {
+        let data = match data {
+
+            StmtKind::Alloc(expr, mut extents) => {
+                if extents.len() == 1 {
+                    let howmany = match extents[0] {
+                        AllocExtent::Along(_) => {
+                            let expr_ty = expr.ty.as_ref()
+                              .expect("dumpster fire: \
+                                      untyped condexpr in realloc rewriter");
+
+                            if let Type::Array(_, ref bounds) = *expr_ty {
+                                Some(bounds.dims() - 1)
+                            } else {
+                                panic!("dumpster fire: \
+                                       non-array in realloc rewriter");
+                            }
+                        },
+
+                        _ => None,
+                    };
+                    
+                    if let Some(howmany) = howmany {
+                        for _ in 0..howmany {
+                            let extent = extents[0].clone();
+                            extents.push(extent);
+                        }
+                    }
+                }
+
+                let extents = extents.into_iter().enumerate()
+                    .map(|(i, extent)| {
+                        match extent {
+                            AllocExtent::Along(other) =>
+                                AllocExtent::Range(
+                                    Some(Expr {
+                                        data: ExprKind::ExtentExpr(
+                                                  Box::new(other.clone()),
+                                                  ExtentKind::First,
+                                                  i),
+                                        ty: Some(Type::Int32),
+                                        loc: other.loc.clone(),
+                                    }),
+
+                                    Expr {
+                                        data: ExprKind::ExtentExpr(
+                                                  Box::new(other.clone()),
+                                                  ExtentKind::Last,
+                                                  i),
+                                        ty: Some(Type::Int32),
+                                        loc: other.loc.clone(),
+                                    },
+                                ),
+
+                            extent => extent,
+                        }
+                }).collect();
+
+                StmtKind::Alloc(expr, extents)
+            },
+
+            StmtKind::ReAlloc(expr, preserved, extent) => {
+                match extent {
+                    AllocExtent::Along(other) => {
+                        let extent = AllocExtent::Range(
+                            Some(Expr {
+                                data: ExprKind::ExtentExpr(
+                                          Box::new(other.clone()),
+                                          ExtentKind::First,
+                                          preserved),
+                                ty: Some(Type::Int32),
+                                loc: other.loc.clone(),
+                            }),
+
+                            Expr {
+                                data: ExprKind::ExtentExpr(
+                                          Box::new(other.clone()),
+                                          ExtentKind::Last,
+                                          preserved),
+                                ty: Some(Type::Int32),
+                                loc: other.loc.clone(),
+                            }
+                        );
+
+                        StmtKind::ReAlloc(expr, preserved, extent)
+                    },
+
+                    extent => StmtKind::ReAlloc(expr, preserved, extent),
+                }
+            },
+
+            data => data
+        };
+
+        fold::noop_fold_stmt(self, Stmt { data, loc },
+          module, function)
+    }
+}
+
+struct SliceRewriteFolder<'a> {
+    symtab: &'a mut SymbolTable,
+    // array view parameters of the current function, with the names of
+    //   their first and last index parameters
+    views: HashMap<String, (Ident, Ident)>,
+    before_stmt_stack: Vec<Vec<Stmt>>,
+    // slice copying functions for the current module, by element type
+    copy_fns: Vec<(Type, Ident)>,
+    copy_defs: Vec<FunDef>,
+}
+
+impl<'a> SliceRewriteFolder<'a> {
+    fn new(symtab: &'a mut SymbolTable) -> Self {
+        SliceRewriteFolder {
+            symtab,
+            views: HashMap::new(),
+            before_stmt_stack: Vec::new(),
+            copy_fns: Vec::new(),
+            copy_defs: Vec::new(),
+        }
+    }
+
+    fn name_expr(name: &Ident, ty: Type, loc: &SrcLoc) -> Expr {
+        Expr {
+            data: ExprKind::Name(Path(None, name.clone())),
+            ty: Some(ty),
+            loc: loc.clone(),
+        }
+    }
+
+    fn extent_expr(expr: &Expr, kind: ExtentKind) -> Expr {
+        Expr {
+            data: ExprKind::ExtentExpr(Box::new(expr.clone()), kind, 0),
+            ty: Some(Type::Int32),
+            loc: expr.loc.clone(),
+        }
+    }
+
+    // last - first + 1
+    fn length_expr(first: Expr, last: Expr, loc: &SrcLoc) -> Expr {
+        let diff = Expr {
+            data: ExprKind::BinOpApp(Box::new(last), Box::new(first),
+              BinOp::Sub),
+            ty: Some(Type::Int32),
+            loc: loc.clone(),
+        };
+
+        Expr {
+            data: ExprKind::BinOpApp(
+                Box::new(diff),
+                Box::new(Expr {
+                    data: ExprKind::Lit(Literal::Int32(1)),
+                    ty: Some(Type::Int32),
+                    loc: loc.clone(),
+                }),
+                BinOp::Add),
+            ty: Some(Type::Int32),
+            loc: loc.clone(),
+        }
+    }
+
+    // if expr names an array view parameter, the view's first and last
+    //   index parameters
+    fn view_bounds(&self, expr: &Expr) -> Option<(Ident, Ident)> {
+        match expr.data {
+            ExprKind::Name(Path(None, ref name)) => match expr.ty {
+                Some(Type::ArrayView(_)) => self.views.get(&name.0).cloned(),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    // the (array, first, last) triple standing in for a view or slice;
+    //   the array is left unfolded if it names a view
+    fn view_parts(&mut self, expr: Expr, module: &Ident,
+      function: Option<&Ident>) -> (Expr, Expr, Expr) {
+        if let Some((first, last)) = self.view_bounds(&expr) {
+            let loc = expr.loc.clone();
+            return (self.view_array(expr),
+              Self::name_expr(&first, Type::Int32, &loc),
+              Self::name_expr(&last, Type::Int32, &loc));
+        }
+
+        match expr.data {
+            ExprKind::Slice(base, first, last) => {
+                let base = if self.view_bounds(&base).is_some() {
+                    self.view_array(*base)
+                } else {
+                    self.fold_expr(*base, module, function)
+                };
+                (base,
+                 self.fold_expr(*first, module, function),
+                 self.fold_expr(*last, module, function))
+            },
+
+            data => {
+                let expr = Expr { data, ..expr };
+                let expr = self.fold_expr(expr, module, function);
+                let expr = self.hoist_array(expr, module, function);
+                let first = Self::extent_expr(&expr, ExtentKind::First);
+                let last = Self::extent_expr(&expr, ExtentKind::Last);
+                (expr, first, last)
+            },
+        }
+    }
+
+    // a view parameter's name, retyped as the underlying array
+    fn view_array(&self, expr: Expr) -> Expr {
+        let ty = match expr.ty {
+            Some(Type::ArrayView(ref base)) =>
+                Type::Array(base.clone(), ArrayBounds::Dynamic(1)),
+            _ => panic!("dumpster fire: non-view in slice rewriter"),
+        };
+
+        Expr {
+            ty: Some(ty),
+            ..expr
+        }
+    }
+
+    // store an array-valued expression in a temporary unless it's already
+    //   safe to evaluate repeatedly
+    fn hoist_array(&mut self, expr: Expr, module: &Ident,
+      function: Option<&Ident>) -> Expr {
+        match expr.data {
+            ExprKind::Name(_) | ExprKind::Member(_, _) => expr,
+            _ => {
+                let ty = expr.ty.clone()
+                    .expect("dumpster fire: untyped expr in slice rewriter");
+                let loc = expr.loc.clone();
+                self.temp(ty, Some(expr), module, function, &loc)
+            },
+        }
+    }
+
+    fn temp(&mut self, ty: Type, init: Option<Expr>, module: &Ident,
+      function: Option<&Ident>, loc: &SrcLoc) -> Expr {
+        let g = gensym(None);
+
+        self.symtab.add_value_entry(&g, module, function, &ty,
+          Access::Private, loc)
+          .expect("dumpster fire: failure adding symtab entry for gensym");
+
+        let before_stmts = self.before_stmt_stack.last_mut()
+          .expect("dumpster fire: error in before statement stack");
+
+        before_stmts.push(Stmt {
+            data: StmtKind::VarDecl(vec![(g.clone(), ty.clone(), init)]),
+            loc: loc.clone(),
+        });
+
+        Self::name_expr(&g, ty, loc)
+    }
+
+    // copy the elements of a view or slice into a new array with the same
+    //   indices (in a function, so the copy happens right where, and as
+    //   often as, the slice is evaluated)
+    fn copy_slice(&mut self, expr: Expr, module: &Ident,
+      function: Option<&Ident>) -> ExprKind {
+        let loc = expr.loc.clone();
+        let elem_ty = match expr.ty {
+            Some(Type::Array(ref elem, _)) | Some(Type::ArrayView(ref elem)) =>
+                (**elem).clone(),
+            _ => panic!("dumpster fire: non-array slice in slice rewriter"),
+        };
+
+        let (base, first, last) = self.view_parts(expr, module, function);
+        let copy = self.copy_fn(&elem_ty, &loc);
+        ExprKind::Call(Path(None, copy), vec![base, first, last], vec![])
+    }
+
+    // the copying function for arrays of an element type, generating it if
+    //   we haven't
+    //
+    // fn øN(a: &T[], first: i32, last: i32) -> T[] {
+    //     let r: T[];
+    //     r <- alloc[first:last];
+    //     for i: i32 = first:last {
+    //         r[i] = a[i];
+    //     }
+    //     return r;
+    // }
+    fn copy_fn(&mut self, elem_ty: &Type, loc: &SrcLoc) -> Ident {
+        if let Some(&(_, ref name)) = self.copy_fns.iter()
+          .find(|f| f.0 == *elem_ty) {
+            return name.clone();
+        }
+
+        let name = gensym(None);
+        self.copy_fns.push((elem_ty.clone(), name.clone()));
+
+        let ty = Type::Array(Box::new(elem_ty.clone()),
+          ArrayBounds::Dynamic(1));
+        let local = |name: &str, ty: &Type| Self::name_expr(
+          &Ident(String::from(name), None), ty.clone(), loc);
+        let index = |arr: &str| Expr {
+            data: ExprKind::Index(Box::new(local(arr, &ty)),
+              vec![local("i", &Type::Int32)]),
+            ty: Some(elem_ty.clone()),
+            loc: loc.clone(),
+        };
+        let stmt = |data| Stmt {
+            data,
+            loc: loc.clone(),
+        };
+
+        let body = vec![
+            stmt(StmtKind::VarDecl(vec![
+              (Ident(String::from("r"), None), ty.clone(), None)])),
+
+            stmt(StmtKind::Alloc(local("r", &ty), vec![
+              AllocExtent::Range(Some(local("first", &Type::Int32)),
+                local("last", &Type::Int32))])),
+
+            stmt(StmtKind::ForLoop {
+                var: (Ident(String::from("i"), None), Type::Int32,
+                  ParamMode::ByVal),
+                spec: ForSpec::Range(local("first", &Type::Int32),
+                  local("last", &Type::Int32), None),
+                body: vec![stmt(StmtKind::Assign(index("r"), AssignOp::Assign,
+                  index("a")))],
+            }),
+
+            stmt(StmtKind::Return(Some(local("r", &ty)))),
+        ];
+
+        let param = |name: &str, ty: &Type, mode| FunParam {
+            name: Ident(String::from(name), None),
+            ty: ty.clone(),
+            mode,
+            loc: loc.clone(),
+        };
+
+        self.copy_defs.push(FunDef {
+            name: name.clone(),
+            access: Access::Private,
+            params: vec![
+                param("a", &ty, ParamMode::ByRef),
+                param("first", &Type::Int32, ParamMode::ByVal),
+                param("last", &Type::Int32, ParamMode::ByVal),
+            ],
+            optparams: None,
+            ret: ty.clone(),
+            body,
+            lib: None,
+            attrs: vec![],
+            loc: loc.clone(),
+        });
+
+        name
+    }
+}
+
+impl<'a> ASTFolder for SliceRewriteFolder<'a> {
+    fn fold_module(&mut self, m: Module) -> Module {
+        self.copy_fns.clear();
+        let mut m = fold::noop_fold_module(self, m);
+
+        let ModuleKind::Normal(ref mut items) = m.data;
+        items.extend(self.copy_defs.drain(..).map(NormalItem::Function));
+
+        m
+    }
+
+    fn fold_stmt(&mut self, stmt: Stmt, module: &Ident, function: &Ident)
+      -> Stmt {
+        let Stmt { data, loc } = stmt;
+
+        match data {
+            // arrays hoisted out of the condition have to be re-evaluated
+            //   before each test, so we redo them at the end of the body
+            StmtKind::WhileLoop { cond, body } => {
+                let hoisted = self.before_stmt_stack.last()
+                    .expect("dumpster fire: error in before statement stack")
+                    .len();
+                let cond = self.fold_expr(cond, module, Some(function));
+                let mut body = self.fold_stmt_list(body, module, function);
+
+                let before = self.before_stmt_stack.last()
+                    .expect("dumpster fire: error in before statement stack");
+                for stmt in &before[hoisted..] {
+                    let (name, ty, init) = match stmt.data {
+                        StmtKind::VarDecl(ref decls) if decls.len() == 1 =>
+                            &decls[0],
+                        _ => panic!("dumpster fire: unexpected statement \
+                          hoisted from while condition"),
+                    };
+                    let init = init.clone().expect("dumpster fire: \
+                      uninitialized temporary in while condition");
+
+                    body.push(Stmt {
+                        data: StmtKind::Assign(
+                          Self::name_expr(name, ty.clone(), &stmt.loc),
+                          AssignOp::Assign, init),
+                        loc: stmt.loc.clone(),
+                    });
+                }
+
+                Stmt {
+                    data: StmtKind::WhileLoop { cond, body },
+                    loc,
+                }
+            },
+
+            data => fold::noop_fold_stmt(self, Stmt { data, loc }, module,
+              function),
+        }
+    }
+
+    fn fold_fundef(&mut self, mut def: FunDef, module: &Ident) -> FunDef {
+        self.views.clear();
+
+        let mut params = Vec::new();
+        for p in def.params {
+            match p.ty {
+                Type::ArrayView(base) => {
+                    let first = gensym(None);
+                    let last = gensym(None);
+
+                    for g in &[&first, &last] {
+                        self.symtab.add_value_entry(g, module,
+                          Some(&def.name), &Type::Int32, Access::Private,
+                          &p.loc)
+                          .expect("dumpster fire: failure adding symtab \
+                            entry for gensym");
+                    }
+
+                    params.push(FunParam {
+                        name: p.name.clone(),
+                        ty: Type::Array(base, ArrayBounds::Dynamic(1)),
+                        mode: ParamMode::ByRef,
+                        loc: p.loc.clone(),
+                    });
+
+                    for g in &[&first, &last] {
+                        params.push(FunParam {
+                            name: (*g).clone(),
+                            ty: Type::Int32,
+                            mode: ParamMode::ByVal,
+                            loc: p.loc.clone(),
+                        });
+                    }
+
+                    self.views.insert(p.name.0, (first, last));
+                },
+
+                _ => params.push(p),
+            }
+        }
+        def.params = params;
+
+        fold::noop_fold_fundef(self, def, module)
+    }
+
+    fn fold_stmt_list(&mut self, stmts: Vec<Stmt>, module: &Ident,
+      function: &Ident) -> Vec<Stmt> {
+        stmts.into_iter().flat_map(|stmt| {
+            self.before_stmt_stack.push(Vec::new());
+            let stmt = self.fold_stmt(stmt, module, function);
+
+            let mut result = self.before_stmt_stack.pop()
+                .expect("dumpster fire: error in before statement stack");
+            result.push(stmt);
+            result
+        }).collect()
+    }
+
+    fn fold_expr(&mut self, expr: Expr, module: &Ident,
+      function: Option<&Ident>) -> Expr {
+        let Expr { data, ty, loc } = expr;
+
+        let data = match data {
+            // index into the underlying array directly (slices keep their
+            //   original indices)
+            ExprKind::Index(base, indices) => {
+                let base = match base.data {
+                    ExprKind::Slice(base, _, _) => base,
+                    _ => base,
+                };
+
+                let base = if self.view_bounds(&base).is_some() {
+                    self.view_array(*base)
+                } else {
+                    self.fold_expr(*base, module, function)
+                };
+
+                ExprKind::Index(Box::new(base), indices.into_iter()
+                  .map(|i| self.fold_expr(i, module, function)).collect())
+            },
+
+            ExprKind::ExtentExpr(base, kind, dim) => {
+                let is_view = match base.data {
+                    ExprKind::Slice(_, _, _) => true,
+                    _ => self.view_bounds(&base).is_some(),
+                };
+
+                if is_view {
+                    let (_, first, last) =
+                        self.view_parts(*base, module, function);
+                    return match kind {
+                        ExtentKind::First => first,
+                        ExtentKind::Last => last,
+                        ExtentKind::Length =>
+                            Self::length_expr(first, last, &loc),
+                    };
+                }
+
+                ExprKind::ExtentExpr(
+                  Box::new(self.fold_expr(*base, module, function)),
+                  kind, dim)
+            },
+
+            ExprKind::Call(path, args, optargs) => {
+                let views: Vec<bool> = match self.symtab.symbol_at_path(&path,
+                  NameCtxt::Function(module, Access::Private), &loc) {
+                    Ok(&Symbol::Fun { ref def, .. }) =>
+                        def.params.iter().map(|p| match p.ty {
+                            Type::ArrayView(_) => true,
+                            _ => false,
+                        }).collect(),
+                    // (runtime helpers never take views)
+                    _ => Vec::new(),
+                };
+
+                let mut new_args = Vec::new();
+                for (i, arg) in args.into_iter().enumerate() {
+                    if views.get(i).cloned().unwrap_or(false) {
+                        let (base, first, last) =
+                            self.view_parts(arg, module, function);
+                        new_args.push(base);
+                        new_args.push(first);
+                        new_args.push(last);
+                    } else {
+                        new_args.push(self.fold_expr(arg, module, function));
+                    }
+                }
+
+                let optargs = optargs.into_iter()
+                    .map(|(name, arg)|
+                         (name, self.fold_expr(arg, module, function)))
+                    .collect();
+
+                ExprKind::Call(path, new_args, optargs)
+            },
+
+            data => {
+                let expr = Expr { data, ty, loc };
+
+                let is_copy = match expr.data {
+                    ExprKind::Slice(_, _, _) => true,
+                    _ => self.view_bounds(&expr).is_some(),
+                };
+
+                if is_copy {
+                    let ty = Type::Array(
+                        match expr.ty {
+                            Some(Type::Array(ref elem, _))
+                          | Some(Type::ArrayView(ref elem)) => elem.clone(),
+                            _ => panic!("dumpster fire: non-array slice \
+                              in slice rewriter"),
+                        },
+                        ArrayBounds::Dynamic(1));
+                    let loc = expr.loc.clone();
+                    let data = self.copy_slice(expr, module, function);
+                    return Expr {
+                        data,
+                        ty: Some(ty),
+                        loc,
+                    };
+                }
+
+                return fold::noop_fold_expr(self, expr, module, function);
+            },
+        };
+
+        Expr {
+            data,
+            ty,
+            loc,
+        }
+    }
+}
+
+struct EqRewriteFolder<'a> {
+    symtab: &'a mut SymbolTable,
+    // comparison functions for the current module, by compared type
+    eq_fns: Vec<(Type, Ident)>,
+    // allocation checks for the current module, by element type
+    allocated_fns: Vec<(Type, Ident)>,
+    defs: Vec<FunDef>,
+}
+
+impl<'a> EqRewriteFolder<'a> {
+    // VB can pass fixed and dynamic arrays alike to a ByRef a() parameter
+    fn compared_type(ty: &Type) -> Type {
+        match *ty {
+            Type::Array(ref base, ref bounds) => Type::Array(base.clone(),
+              ArrayBounds::Dynamic(bounds.dims())),
+            Type::ArrayView(ref base) => Type::Array(base.clone(),
+              ArrayBounds::Dynamic(1)),
+            ref ty => ty.clone(),
+        }
+    }
+
+    // a condition which holds when lhs and rhs (of type ty) differ
+    fn not_equal(&mut self, ty: &Type, lhs: Expr, rhs: Expr, module: &Ident)
+      -> Expr {
+        match *ty {
+            Type::Obj
+          | Type::Object(_)
+          | Type::Nullable(_) => binop(lhs, rhs, BinOp::NotIdentEq, Type::Bool),
+
+            Type::Struct(_)
+          | Type::Array(_, _)
+          | Type::Variant => {
+                let loc = lhs.loc.clone();
+                let name = self.eq_fn(ty, module, &loc);
+                log_not(call(&name, vec![lhs, rhs], &Type::Bool, &loc))
+            },
+
+            _ => binop(lhs, rhs, BinOp::NotEq, Type::Bool),
+        }
+    }
+
+    // the comparison function for a struct or array type, generating it (and
+    //   any it depends on) if we haven't yet
+    //
+    // fn øN(a: &S, b: &S) -> bool {
+    //     if a.m != b.m { return false; }
+    //     ...
+    //     return true;
+    // }
+    //
+    // fn øN(a: &T[,], b: &T[,]) -> bool {
+    //     if !allocated(a) || !allocated(b) {
+    //         return allocated(a) == allocated(b);
+    //     }
+    //     if first_index<0>(a) != first_index<0>(b)
+    //       || last_index<0>(a) != last_index<0>(b) { return false; }
+    //     ...
+    //     for i1: i32 = first_index<0>(a):last_index<0>(a) {
+    //         for i2: i32 = first_index<1>(a):last_index<1>(a) {
+    //             if a[i1, i2] != b[i1, i2] { return false; }
+    //         }
+    //     }
+    //     return true;
+    // }
+    //
+    // fn øN(a: &var, b: &var) -> bool {
+    //     if IsObject(a) || IsObject(b) {
+    //         if IsObject(a) && IsObject(b) { return a === b; }
+    //         return false;
+    //     }
+    //     if IsNull(a) || IsNull(b) { return IsNull(a) && IsNull(b); }
+    //     if IsArray(a) || IsArray(b) { return false; }
+    //     if a != b { return false; }
+    //     return true;
+    // }
+    //   (<> raises on objects and arrays, and is Null for Null; arrays
+    //   held in vars never compare equal)
+    fn eq_fn(&mut self, ty: &Type, module: &Ident, loc: &SrcLoc) -> Ident {
+        let ty = Self::compared_type(ty);
+        if let Some(&(_, ref name)) = self.eq_fns.iter().find(|f| f.0 == ty) {
+            return name.clone();
+        }
+
+        let name = gensym(None);
+        self.eq_fns.push((ty.clone(), name.clone()));
+
+        let a = local("a", &ty, loc);
+        let b = local("b", &ty, loc);
+        let return_false = || vec![stmt(StmtKind::Return(Some(
+          lit(Literal::Bool(false), Type::Bool, loc))), loc)];
+
+        let mut body = Vec::new();
+        match ty {
+            Type::Struct(ref path) => {
+                let mut members: Vec<(String, Type)> =
+                  match *self.symtab.symbol_at_path(path,
+                    NameCtxt::Type(module, Access::Private), loc)
+                    .expect("dumpster fire: compared struct not found") {
+                      Symbol::Struct { ref members, .. } => members.iter()
+                          .map(|(m, ty)| (m.clone(), ty.clone())).collect(),
+                      _ => panic!("dumpster fire: non-struct slipped past \
+                        lookup typecheck"),
+                  };
+                members.sort_by(|a, b| a.0.cmp(&b.0));
+
+                for (mem, mem_ty) in members {
+                    let mem = Ident(mem, None);
+                    let ne = self.not_equal(&mem_ty, member(&a, &mem, &mem_ty),
+                      member(&b, &mem, &mem_ty), module);
+                    body.push(if_stmt(ne, return_false(), None));
+                }
+            },
+
+            Type::Array(ref base, ref bounds) => {
+                let allocated = allocated_fn(&mut self.allocated_fns,
+                  &mut self.defs, base, loc);
+                let a_alloc = call(&allocated, vec![a.clone()], &Type::Bool,
+                  loc);
+                let b_alloc = call(&allocated, vec![b.clone()], &Type::Bool,
+                  loc);
+                body.push(if_stmt(
+                  binop(log_not(a_alloc.clone()), log_not(b_alloc.clone()),
+                    BinOp::LogOr, Type::Bool),
+                  vec![stmt(StmtKind::Return(Some(
+                    binop(a_alloc, b_alloc, BinOp::Eq, Type::Bool))), loc)],
+                  None));
+
+                let dims = bounds.dims();
+                for d in 0..dims {
+                    let differ = |kind| binop(extent(&a, kind, d),
+                      extent(&b, kind, d), BinOp::NotEq, Type::Bool);
+                    body.push(if_stmt(binop(differ(ExtentKind::First),
+                      differ(ExtentKind::Last), BinOp::LogOr, Type::Bool),
+                      return_false(), None));
+                }
+
+                let indices: Vec<Expr> = (1..dims + 1)
+                    .map(|d| local(&format!("i{}", d), &Type::Int32, loc))
+                    .collect();
+                let ne = self.not_equal(base, index(&a, indices.clone()),
+                  index(&b, indices), module);
+                let mut inner = vec![if_stmt(ne, return_false(), None)];
+
+                for d in (0..dims).rev() {
+                    inner = vec![stmt(StmtKind::ForLoop {
+                        var: (Ident(format!("i{}", d + 1), None), Type::Int32,
+                          ParamMode::ByVal),
+                        spec: ForSpec::Range(extent(&a, ExtentKind::First, d),
+                          extent(&a, ExtentKind::Last, d), None),
+                        body: inner,
+                    }, loc)];
+                }
+                body.extend(inner);
+            },
+
+            Type::Variant => {
+                let is = |name: &str, v: &Expr| call(
+                  &Ident(String::from(name), None), vec![v.clone()],
+                  &Type::Bool, loc);
+                let either = |name| binop(is(name, &a), is(name, &b),
+                  BinOp::LogOr, Type::Bool);
+                let both = |name| binop(is(name, &a), is(name, &b),
+                  BinOp::LogAnd, Type::Bool);
+                let ret = |expr| stmt(StmtKind::Return(Some(expr)), loc);
+
+                body.push(if_stmt(either("IsObject"), vec![
+                  if_stmt(both("IsObject"), vec![ret(binop(a.clone(),
+                    b.clone(), BinOp::IdentEq, Type::Bool))], None),
+                  ret(lit(Literal::Bool(false), Type::Bool, loc)),
+                ], None));
+                body.push(if_stmt(either("IsNull"),
+                  vec![ret(both("IsNull"))], None));
+                body.push(if_stmt(either("IsArray"), return_false(), None));
+                body.push(if_stmt(binop(a.clone(), b.clone(), BinOp::NotEq,
+                  Type::Bool), return_false(), None));
+            },
+
+            _ => panic!("dumpster fire: structural comparison of type {}",
+              ty),
+        }
+        body.push(stmt(StmtKind::Return(Some(
+          lit(Literal::Bool(true), Type::Bool, loc))), loc));
+
+        self.defs.push(helper_fn(&name, vec![
+            param("a", &ty, ParamMode::ByRef, loc),
+            param("b", &ty, ParamMode::ByRef, loc),
+        ], Type::Bool, body, loc));
+
+        name
+    }
+}
+
+impl<'a> ASTFolder for EqRewriteFolder<'a> {
+    fn fold_module(&mut self, m: Module) -> Module {
+        self.eq_fns.clear();
+        self.allocated_fns.clear();
+        let mut m = fold::noop_fold_module(self, m);
+
+        let ModuleKind::Normal(ref mut items) = m.data;
+        items.extend(self.defs.drain(..).map(NormalItem::Function));
+
+        m
+    }
+
+    fn fold_expr(&mut self, expr: Expr, module: &Ident,
+      function: Option<&Ident>) -> Expr {
+        let Expr { data, ty, loc } =
+            fold::noop_fold_expr(self, expr, module, function);
+
+        let data = match data {
+            ExprKind::BinOpApp(lhs, rhs, op) => {
+                let structural = match op {
+                    BinOp::Eq | BinOp::NotEq => match lhs.ty {
+                        Some(Type::Struct(_))
+                      | Some(Type::Array(_, _))
+                      | Some(Type::ArrayView(_)) => true,
+                        _ => false,
+                    },
+                    _ => false,
+                };
+
+                if structural {
+                    let name = self.eq_fn(lhs.ty.as_ref().unwrap(), module,
+                      &loc);
+                    let call = ExprKind::Call(Path(None, name),
+                      vec![*lhs, *rhs], vec![]);
+                    if op == BinOp::NotEq {
+                        ExprKind::UnOpApp(Box::new(Expr {
+                            data: call,
+                            ty: Some(Type::Bool),
+                            loc: loc.clone(),
+                        }), UnOp::LogNot)
+                    } else {
+                        call
+                    }
+                } else {
+                    ExprKind::BinOpApp(lhs, rhs, op)
+                }
+            },
+
+            data => data,
+        };
+
+        Expr {
+            data,
+            ty,
+            loc,
+        }
+    }
+}
+
+struct DebugStrRewriteFolder<'a> {
+    symtab: &'a mut SymbolTable,
+    // rendering functions for the current module, by rendered type
+    str_fns: Vec<(Type, Ident)>,
+    // allocation checks for the current module, by element type
+    allocated_fns: Vec<(Type, Ident)>,
+    defs: Vec<FunDef>,
+}
+
+impl<'a> DebugStrRewriteFolder<'a> {
+    fn rendered(ty: Option<&Type>) -> bool {
+        match ty {
+            Some(&Type::Struct(_))
+          | Some(&Type::Array(_, _))
+          | Some(&Type::ArrayView(_)) => true,
+            _ => false,
+        }
+    }
+
+    // statements appending the rendering of expr (of type ty) to s
+    fn append(&mut self, ty: &Type, expr: Expr, module: &Ident)
+      -> Vec<Stmt> {
+        let loc = expr.loc.clone();
+        let s = local("s", &Type::String, &loc);
+        let cat = |piece: Expr| stmt(StmtKind::Assign(s.clone(),
+          AssignOp::StrCatAssign, piece), &loc);
+        let builtin = |name: &str, ret: &Type| call(
+          &Ident(String::from(name), None), vec![expr.clone()], ret, &loc);
+        let type_name = || binop(binop(str_lit("<", &loc),
+          builtin("TypeName", &Type::String), BinOp::StrCat, Type::String),
+          str_lit(">", &loc), BinOp::StrCat, Type::String);
+
+        match *ty {
+            // quoted like a VB string literal
+            Type::String | Type::FixedString(_) => {
+                let quoted = call(&Ident(String::from("Replace"), None),
+                  vec![expr.clone(), str_lit("\"", &loc),
+                    str_lit("\"\"", &loc)], &Type::String, &loc);
+                vec![cat(binop(binop(str_lit("\"", &loc), quoted,
+                  BinOp::StrCat, Type::String), str_lit("\"", &loc),
+                  BinOp::StrCat, Type::String))]
+            },
+
+            Type::Bool => vec![if_stmt(expr.clone(),
+              vec![cat(str_lit("true", &loc))],
+              Some(vec![cat(str_lit("false", &loc))]))],
+
+            Type::Obj
+          | Type::Object(_)
+          | Type::Nullable(_) => vec![if_stmt(
+              binop(expr.clone(), lit(Literal::NullPtr, ty.clone(), &loc),
+                BinOp::IdentEq, Type::Bool),
+              vec![cat(str_lit("nullptr", &loc))],
+              Some(vec![cat(type_name())]))],
+
+            Type::Variant => vec![if_stmt(
+              binop(binop(builtin("IsObject", &Type::Bool),
+                builtin("IsNull", &Type::Bool), BinOp::LogOr, Type::Bool),
+                builtin("IsArray", &Type::Bool), BinOp::LogOr, Type::Bool),
+              vec![cat(type_name())],
+              Some(vec![cat(builtin("CStr", &Type::String))]))],
+
+            Type::Struct(_)
+          | Type::Array(_, _) => {
+                let name = self.str_fn(ty, module, &loc);
+                vec![cat(call(&name, vec![expr.clone()], &Type::String,
+                  &loc))]
+            },
+
+            _ => vec![cat(Expr {
+                data: ExprKind::Cast(Box::new(expr.clone()), Type::String),
+                ty: Some(Type::String),
+                loc: loc.clone(),
+            })],
+        }
+    }
+
+    // the rendering function for a struct or array type, generating it (and
+    //   any it depends on) if we haven't yet
+    //
+    // fn øN(a: &S) -> str {
+    //     let s: str = "S { ";
+    //     s @= "m: ";
+    //     (append a.m)
+    //     s @= ", n: ";
+    //     ...
+    //     s @= " }";
+    //     return s;
+    // }
+    //
+    // fn øN(a: &T[,]) -> str {
+    //     if !allocated(a) { return "[]"; }
+    //     let s: str = "[";
+    //     for i1: i32 = first_index<0>(a):last_index<0>(a) {
+    //         if i1 != first_index<0>(a) { s @= ", "; }
+    //         s @= "[";
+    //         for i2: i32 = first_index<1>(a):last_index<1>(a) {
+    //             if i2 != first_index<1>(a) { s @= ", "; }
+    //             (append a[i1, i2])
+    //         }
+    //         s @= "]";
+    //     }
+    //     s @= "]";
+    //     return s;
+    // }
+    fn str_fn(&mut self, ty: &Type, module: &Ident, loc: &SrcLoc) -> Ident {
+        let ty = EqRewriteFolder::compared_type(ty);
+        if let Some(&(_, ref name)) = self.str_fns.iter().find(|f| f.0 == ty) {
+            return name.clone();
+        }
+
+        let name = gensym(None);
+        self.str_fns.push((ty.clone(), name.clone()));
+
+        let a = local("a", &ty, loc);
+        let s = local("s", &Type::String, loc);
+        let cat = |piece: &str| stmt(StmtKind::Assign(s.clone(),
+          AssignOp::StrCatAssign, str_lit(piece, loc)), loc);
+
+        let mut body = Vec::new();
+        match ty {
+            Type::Struct(ref path) => {
+                // members in declaration order, under their source names
+                let members: Vec<(Ident, Type)> =
+                  match *self.symtab.symbol_at_path(path,
+                    NameCtxt::Type(module, Access::Private), loc)
+                    .expect("dumpster fire: rendered struct not found") {
+                      Symbol::Struct { ref def, ref members } => def.members
+                          .iter().map(|mem| (mem.name.clone(),
+                            members[&mem.name.0].clone())).collect(),
+                      _ => panic!("dumpster fire: non-struct slipped past \
+                        lookup typecheck"),
+                  };
+
+                body.push(stmt(StmtKind::VarDecl(vec![(
+                  Ident(String::from("s"), None), Type::String,
+                  Some(str_lit(&format!("{} {{ ", path.1), loc)))]), loc));
+                for (i, (mem, mem_ty)) in members.into_iter().enumerate() {
+                    body.push(cat(&format!("{}{}: ",
+                      if i == 0 { "" } else { ", " }, mem)));
+                    let value = member(&a, &Ident(mem.0, None), &mem_ty);
+                    body.extend(self.append(&mem_ty, value, module));
+                }
+                body.push(cat(" }"));
+            },
+
+            Type::Array(ref base, ref bounds) => {
+                let dims = bounds.dims();
+                let indices: Vec<Expr> = (1..dims + 1)
+                    .map(|d| local(&format!("i{}", d), &Type::Int32, loc))
+                    .collect();
+
+                let mut inner = self.append(base, index(&a, indices.clone()),
+                  module);
+                for d in (0..dims).rev() {
+                    let first = extent(&a, ExtentKind::First, d);
+                    let mut loop_body = vec![if_stmt(
+                      binop(indices[d].clone(), first.clone(), BinOp::NotEq,
+                        Type::Bool),
+                      vec![cat(", ")], None)];
+                    if d + 1 < dims {
+                        loop_body.push(cat("["));
+                        loop_body.extend(inner);
+                        loop_body.push(cat("]"));
+                    } else {
+                        loop_body.extend(inner);
+                    }
+
+                    inner = vec![stmt(StmtKind::ForLoop {
+                        var: (Ident(format!("i{}", d + 1), None), Type::Int32,
+                          ParamMode::ByVal),
+                        spec: ForSpec::Range(first,
+                          extent(&a, ExtentKind::Last, d), None),
+                        body: loop_body,
+                    }, loc)];
+                }
+
+                let allocated = allocated_fn(&mut self.allocated_fns,
+                  &mut self.defs, base, loc);
+                body.push(if_stmt(
+                  log_not(call(&allocated, vec![a.clone()], &Type::Bool, loc)),
+                  vec![stmt(StmtKind::Return(Some(str_lit("[]", loc))), loc)],
+                  None));
+
+                body.push(stmt(StmtKind::VarDecl(vec![(
+                  Ident(String::from("s"), None), Type::String,
+                  Some(str_lit("[", loc)))]), loc));
+                body.extend(inner);
+                body.push(cat("]"));
+            },
+
+            _ => panic!("dumpster fire: derived rendering of type {}", ty),
+        }
+        body.push(stmt(StmtKind::Return(Some(s.clone())), loc));
+
+        self.defs.push(helper_fn(&name,
+          vec![param("a", &ty, ParamMode::ByRef, loc)], Type::String, body,
+          loc));
+
+        name
+    }
+
+    fn render(&mut self, expr: Expr, module: &Ident) -> Expr {
+        let name = self.str_fn(expr.ty.as_ref().unwrap(), module, &expr.loc);
+        let loc = expr.loc.clone();
+        Expr {
+            data: ExprKind::Call(Path(None, name), vec![expr], vec![]),
+            ty: Some(Type::String),
+            loc,
+        }
+    }
+}
+
+impl<'a> ASTFolder for DebugStrRewriteFolder<'a> {
+    fn fold_module(&mut self, m: Module) -> Module {
+        self.str_fns.clear();
+        self.allocated_fns.clear();
+        let mut m = fold::noop_fold_module(self, m);
+
+        let ModuleKind::Normal(ref mut items) = m.data;
+        items.extend(self.defs.drain(..).map(NormalItem::Function));
+
+        m
+    }
+
+    fn fold_stmt(&mut self, stmt: Stmt, module: &Ident,
+      function: &Ident) -> Stmt {
+        let Stmt { data, loc } =
+            fold::noop_fold_stmt(self, stmt, module, function);
+
+        let data = match data {
+            StmtKind::Print(exprs) => StmtKind::Print(exprs.into_iter()
+                .map(|e| if Self::rendered(e.ty.as_ref()) {
+                    self.render(e, module)
+                } else {
+                    e
+                }).collect()),
+
+            data => data,
+        };
+
+        Stmt {
+            data,
+            loc,
+        }
+    }
+
+    fn fold_expr(&mut self, expr: Expr, module: &Ident,
+      function: Option<&Ident>) -> Expr {
+        let expr = fold::noop_fold_expr(self, expr, module, function);
+
+        match expr.data {
+            ExprKind::Cast(inner, Type::String)
+              if Self::rendered(inner.ty.as_ref()) =>
+                self.render(*inner, module),
+
+            data => Expr {
+                data,
+                ..expr
+            },
+        }
+    }
+}
+
+struct AssertRewriteFolder {
+    mode: AssertMode,
+}
+
+impl AssertRewriteFolder {
+    fn str_expr(e: ExprKind, loc: &SrcLoc) -> Expr {
+        Expr {
+            data: e,
+            ty: Some(Type::String),
+            loc: loc.clone(),
+        }
+    }
+
+    // If Not cond Then øassert_fail loc, "assertion failed: ..."
+    fn raise(cond: Expr, msg: Option<Expr>, text: String, loc: SrcLoc)
+      -> StmtKind {
+        let desc = format!("assertion failed: {} @ {}", text, loc);
+        let desc = match msg {
+            Some(msg) => {
+                let desc = Self::str_expr(ExprKind::Lit(
+                  Literal::String(desc + ": ")), &loc);
+                Self::str_expr(ExprKind::BinOpApp(
+                  Box::new(desc), Box::new(msg), BinOp::StrCat), &loc)
+            },
+            None => Self::str_expr(ExprKind::Lit(Literal::String(desc)), &loc),
+        };
+
+        let loc_expr = Self::str_expr(
+          ExprKind::Lit(Literal::String(loc.to_string())), &loc);
+
+        let fail = Stmt {
+            data: StmtKind::ExprStmt(Expr {
+                data: ExprKind::Call(
+                  Path(None, RuntimeHelper::AssertFail.name()),
+                  vec![loc_expr, desc], vec![]),
+                ty: Some(Type::Void),
+                loc: loc.clone(),
+            }),
+            loc: loc.clone(),
+        };
 
-                g_expr.data
+        StmtKind::IfStmt {
+            cond: Expr {
+                data: ExprKind::UnOpApp(Box::new(cond), UnOp::LogNot),
+                ty: Some(Type::Bool),
+                loc: loc.clone(),
             },
+            body: vec![fail],
+            elsifs: vec![],
+            els: None,
+        }
+    }
+}
 
-            e => e,
+impl ASTFolder for AssertRewriteFolder {
+    fn fold_stmt_list(&mut self, stmts: Vec<Stmt>, module: &Ident,
+      function: &Ident) -> Vec<Stmt> {
+        let strip = self.mode == AssertMode::Strip;
+        stmts.into_iter()
+            .filter(|stmt| match stmt.data {
+                StmtKind::Assert(_, _, _) => !strip,
+                _ => true,
+            })
+            .map(|stmt| self.fold_stmt(stmt, module, function))
+            .collect()
+    }
+
+    fn fold_stmt(&mut self, stmt: Stmt, module: &Ident,
+      function: &Ident) -> Stmt {
+        let Stmt { data, loc } =
+            fold::noop_fold_stmt(self, stmt, module, function);
+
+        let data = match data {
+            StmtKind::Assert(cond, msg, text) =>
+                if self.mode == AssertMode::Raise {
+                    Self::raise(cond, msg, text, loc.clone())
+                } else {
+                    StmtKind::Assert(cond, msg, text)
+                },
+
+            data => data,
         };
 
-        Expr {
+        Stmt {
             data,
-            ty,
             loc,
         }
     }
 }
 
-struct AlongLoopRewriteFolder;
+struct UdfRewriteFolder {
+    // Application.MacroOptions calls, one per UDF
+    registrations: Vec<String>,
+}
 
-impl AlongLoopRewriteFolder {
-    fn new() -> Self {
-        Self {
+impl UdfRewriteFolder {
+    fn vb_string(s: &str) -> String {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    }
+
+    // doc comment lines like `* name: text` (or `- name: text`) describe
+    //   parameters
+    fn param_description(attrs: &[Attribute], param: &Ident)
+      -> Option<String> {
+        let name = param.to_string();
+        attrs.iter().filter(|a| a.name.0 == "doc")
+            .filter_map(|a| match a.lit_arg() {
+                Some(&Literal::String(ref text)) => Some(text),
+                _ => None,
+            })
+            .filter_map(|text| {
+                let text = text.trim_start();
+                let text = text.trim_start_matches(&['*', '-'][..])
+                    .trim_start();
+                let text = text.trim_start_matches('`');
+                if !text.starts_with(&name) {
+                    return None;
+                }
+                text[name.len()..].trim_start_matches('`').trim_start()
+                    .strip_prefix(':')
+                    .map(|desc| String::from(desc.trim()))
+            })
+            .next()
+    }
+
+    // the macro name is qualified, since UDFs in different modules may share
+    //   a name
+    fn registration(def: &FunDef, module: &Ident, category: Option<&Literal>)
+      -> String {
+        let mut call = format!("Application.MacroOptions Macro:={}",
+          Self::vb_string(&format!("{}.{}", module, def.name)));
+
+        if let Some(&Literal::String(ref desc)) = description(&def.attrs) {
+            call.push_str(&format!(", Description:={}",
+              Self::vb_string(desc)));
+        }
+
+        if let Some(&Literal::String(ref category)) = category {
+            call.push_str(&format!(", Category:={}",
+              Self::vb_string(category)));
+        }
+
+        let mut params: Vec<&FunParam> = def.params.iter().collect();
+        if let Some(FunOptParams::Named(ref optparams)) = def.optparams {
+            params.extend(optparams.iter().map(|&(ref p, _)| p));
+        }
+
+        let descs: Vec<Option<String>> = params.iter()
+            .map(|p| Self::param_description(&def.attrs, &p.name))
+            .collect();
+
+        if descs.iter().any(|d| d.is_some()) {
+            let descs: Vec<String> = descs.into_iter()
+                .map(|d| Self::vb_string(&d.unwrap_or_default()))
+                .collect();
+            call.push_str(&format!(", ArgumentDescriptions:=Array({})",
+              descs.join(", ")));
         }
+
+        call
     }
 }
 
-impl ASTFolder for AlongLoopRewriteFolder {
-    fn fold_stmt(&mut self, stmt: Stmt, module: &Ident, function: &Ident)
-      -> Stmt {
-        // first recurse...
+impl ASTFolder for UdfRewriteFolder {
+    fn fold_fundef(&mut self, def: FunDef, module: &Ident) -> FunDef {
+        let mut def = fold::noop_fold_fundef(self, def, module);
+
+        let (category, volatile) = match def.attrs.iter()
+          .find(|a| a.name.0 == "udf") {
+            Some(attr) => {
+                let category = attr.args.iter().filter_map(|arg| match *arg {
+                    AttributeArg::Named(ref key, ref lit)
+                      if key.0 == "category" => Some(lit.clone()),
+                    _ => None,
+                }).next();
+                let volatile = attr.args.iter().any(|arg| match *arg {
+                    AttributeArg::Flag(ref key) => key.0 == "volatile",
+                    _ => false,
+                });
+                (category, volatile)
+            },
+
+            None => return def,
+        };
+
+        self.registrations.push(
+          Self::registration(&def, module, category.as_ref()));
+
+        if volatile {
+            def.body.insert(0, Stmt {
+                data: StmtKind::VbBlock(b"Application.Volatile".to_vec(),
+                  vec![], vec![]),
+                loc: def.loc.clone(),
+            });
+        }
+
+        def
+    }
+}
+
+struct CheckedArithmeticFolder;
+
+impl ASTFolder for CheckedArithmeticFolder {
+    // constant expressions are evaluated at compile time, and VB won't call
+    //   functions in them anyway
+    fn fold_optparam(&mut self, param: (FunParam, Expr), _module: &Ident,
+      _function: &Ident) -> (FunParam, Expr) {
+        param
+    }
+
+    fn fold_constant(&mut self, c: Constant, _module: &Ident) -> Constant {
+        c
+    }
+
+    fn fold_stmt(&mut self, stmt: Stmt, module: &Ident,
+      function: &Ident) -> Stmt {
         let Stmt { data, loc } =
             fold::noop_fold_stmt(self, stmt, module, function);
 
         let data = match data {
-            StmtKind::ForAlong { mut vars, along, mut body } => {
-                let dims = vars.len();
+            StmtKind::Assign(lhs, op, rhs) => {
+                let binop = match op {
+                    AssignOp::AddAssign => Some(BinOp::Add),
+                    AssignOp::SubAssign => Some(BinOp::Sub),
+                    AssignOp::MulAssign => Some(BinOp::Mul),
+                    AssignOp::DivAssign => Some(BinOp::Div),
+                    AssignOp::ModAssign => Some(BinOp::Mod),
+                    _ => None,
+                };
 
-                for dim in (0..dims).rev() {
-                    body = vec![
-                        Stmt {
-                            data: StmtKind::ForLoop {
-                                var: (
-                                         vars.pop().unwrap(),
-                                         Type::Int32,
-                                         ParamMode::ByVal
-                                ),
-                                spec: ForSpec::Range(
-                                    Expr {
-                                        data: ExprKind::ExtentExpr(
-                                            Box::new(along.clone()),
-                                            ExtentKind::First,
-                                            dim
-                                        ),
-                                        ty: Some(Type::Int32),
-                                        loc: loc.clone(),
-                                    },
-                                    Expr {
-                                        data: ExprKind::ExtentExpr(
-                                            Box::new(along.clone()),
-                                            ExtentKind::Last,
-                                            dim
-                                        ),
-                                        ty: Some(Type::Int32),
-                                        loc: loc.clone(),
-                                    },
-                                    None
-                                ),
-                                body,
-                            },
+                let checked = match (binop, lhs.ty.as_ref()) {
+                    (Some(binop), Some(ty))
+                      if RuntimeHelper::checkable(CheckedOp::Bin(binop), ty) =>
+                        Some(CheckedOp::Bin(binop)),
+                    _ => None,
+                };
+
+                match checked {
+                    Some(checked) => {
+                        let value = Expr {
+                            data: ExprKind::CheckedArith(checked,
+                              vec![lhs.clone(), rhs]),
+                            ty: lhs.ty.clone(),
                             loc: loc.clone(),
-                        }
-                    ];
-                }
+                        };
+                        StmtKind::Assign(lhs, AssignOp::Assign, value)
+                    },
 
-                body.pop().unwrap().data
+                    None => StmtKind::Assign(lhs, op, rhs),
+                }
             },
 
-            data => data,
+            s => s,
         };
 
         Stmt {
@@ -879,107 +4558,415 @@ impl ASTFolder for AlongLoopRewriteFolder {
             loc,
         }
     }
+
+    fn fold_expr(&mut self, expr: Expr, module: &Ident,
+      function: Option<&Ident>) -> Expr {
+        let Expr { data, ty, loc } =
+            fold::noop_fold_expr(self, expr, module, function);
+
+        let checked = match (&data, ty.as_ref()) {
+            (&ExprKind::BinOpApp(_, _, op), Some(ty))
+              if RuntimeHelper::checkable(CheckedOp::Bin(op), ty) =>
+                Some(CheckedOp::Bin(op)),
+            // (negative literals can't overflow)
+            (&ExprKind::UnOpApp(ref operand, UnOp::Negate), Some(ty))
+              if RuntimeHelper::checkable(CheckedOp::Negate, ty) => {
+                match operand.data {
+                    ExprKind::Lit(_) => None,
+                    _ => Some(CheckedOp::Negate),
+                }
+            },
+            _ => None,
+        };
+
+        let data = match (data, checked) {
+            (ExprKind::BinOpApp(lhs, rhs, _), Some(checked)) =>
+                ExprKind::CheckedArith(checked, vec![*lhs, *rhs]),
+
+            (ExprKind::UnOpApp(operand, _), Some(checked)) =>
+                ExprKind::CheckedArith(checked, vec![*operand]),
+
+            (data, _) => data,
+        };
+
+        Expr {
+            data,
+            ty,
+            loc,
+        }
+    }
 }
 
-struct AllocAlongRewriteFolder;
+// wrap a var-typed expression in the VB conversion function for a
+//   (non-object) target type, if there is one
+fn var_conversion(expr: Expr, ty: &Type) -> Expr {
+    match *ty {
+        Type::Bool
+      | Type::UInt8
+      | Type::Int16
+      | Type::Int32
+      | Type::IntPtr
+      | Type::Float32
+      | Type::Float64
+      | Type::String
+      | Type::Currency
+      | Type::Date => {
+            let loc = expr.loc.clone();
+            Expr {
+                data: ExprKind::Cast(Box::new(expr), ty.clone()),
+                ty: Some(ty.clone()),
+                loc,
+            }
+        },
 
-impl ASTFolder for AllocAlongRewriteFolder {
-    fn fold_stmt(&mut self, Stmt { data, loc }: Stmt, module: &Ident,
-      function: &Ident) -> Stmt {
-        let data = match data {
+        _ => expr,
+    }
+}
 
-            StmtKind::Alloc(expr, mut extents) => {
-                if extents.len() == 1 {
-                    let howmany = match extents[0] {
-                        AllocExtent::Along(_) => {
-                            let expr_ty = expr.ty.as_ref()
-                              .expect("dumpster fire: \
-                                      untyped condexpr in realloc rewriter");
+#[cfg(test)]
+mod test {
+    use super::*;
+    use parser;
+
+    fn typechecked(src: &str)
+      -> AnalysisResultMany<(Dumpster, SymbolTable)> {
+        let mut dumpster = parser::parse_dumpster("test", src.as_bytes())
+            .expect("syntax error");
+        let symtab = SymbolTable::build(&mut dumpster)?;
+        typecheck(&mut dumpster, &symtab)?;
+        Ok((dumpster, symtab))
+    }
 
-                            if let Type::Array(_, ref bounds) = *expr_ty {
-                                Some(bounds.dims() - 1)
-                            } else {
-                                panic!("dumpster fire: \
-                                       non-array in realloc rewriter");
-                            }
-                        },
+    // the access of each generated struct in a module, by name
+    fn struct_access(dumpster: &Dumpster, module: &str)
+      -> Vec<(String, Access)> {
+        let m = dumpster.modules.iter()
+            .find(|m| m.name.0 == module)
+            .expect("no such module");
+        let ModuleKind::Normal(ref items) = m.data;
+        items.iter().filter_map(|item| match *item {
+            NormalItem::Struct(ref def) if def.name.0.starts_with('ø') =>
+                Some((def.name.0.clone(), def.access)),
+            _ => None,
+        }).collect()
+    }
 
-                        _ => None,
-                    };
-                    
-                    if let Some(howmany) = howmany {
-                        for _ in 0..howmany {
-                            let extent = extents[0].clone();
-                            extents.push(extent);
-                        }
-                    }
+    // the first arguments of method calls in a module m's function f, which
+    //   is its last item
+    fn receivers(dumpster: &Dumpster) -> Vec<String> {
+        fn walk(stmts: &[Stmt], out: &mut Vec<String>) {
+            for stmt in stmts {
+                match stmt.data {
+                    StmtKind::ExprStmt(Expr {
+                        data: ExprKind::Call(_, ref args, _), ..
+                    }) => out.push(receiver(&args[0])),
+                    StmtKind::With(_, ref body) => walk(body, out),
+                    _ => { },
                 }
+            }
+        }
 
-                let extents = extents.into_iter().enumerate()
-                    .map(|(i, extent)| {
-                        match extent {
-                            AllocExtent::Along(other) =>
-                                AllocExtent::Range(
-                                    Some(Expr {
-                                        data: ExprKind::ExtentExpr(
-                                                  Box::new(other.clone()),
-                                                  ExtentKind::First,
-                                                  i),
-                                        ty: Some(Type::Int32),
-                                        loc: other.loc.clone(),
-                                    }),
+        fn receiver(expr: &Expr) -> String {
+            match expr.data {
+                ExprKind::Name(ref path) => path.1.to_string(),
+                ExprKind::Member(ref base, ref mem) =>
+                    format!("{}.{}", receiver(base), mem),
+                ExprKind::Index(ref base, _) =>
+                    format!("{}(_)", receiver(base)),
+                ExprKind::WithSubject => String::new(),
+                _ => String::from("?"),
+            }
+        }
 
-                                    Expr {
-                                        data: ExprKind::ExtentExpr(
-                                                  Box::new(other.clone()),
-                                                  ExtentKind::Last,
-                                                  i),
-                                        ty: Some(Type::Int32),
-                                        loc: other.loc.clone(),
-                                    },
-                                ),
+        let ModuleKind::Normal(ref items) = dumpster.modules[0].data;
+        let mut out = Vec::new();
+        match items.last() {
+            Some(&NormalItem::Function(ref def)) => walk(&def.body, &mut out),
+            _ => panic!("no function f"),
+        }
+        out
+    }
 
-                            extent => extent,
-                        }
-                }).collect();
+    #[test]
+    fn method_on_with_subject() {
+        let src = "mod m { \
+          struct r { n: i32 } \
+          struct p { a: r, b: r } \
+          impl r { fn scale(&self, k: i32) { self.n *= k; } } \
+          fn f(ps: &p[]) { \
+              let x: r; \
+              with x { .scale(2); } \
+              with ps[1] { with .a { .scale(3); } .b.scale(4); } \
+          } }";
+        let dumpster = method_rewrite(typechecked(src).unwrap().0);
+        assert_eq!(receivers(&dumpster), vec!["x", "ps(_).a", ".b"]);
+
+        let src = "mod m { \
+          struct r { n: i32 } \
+          impl r { fn scale(&self, k: i32) { self.n *= k; } } \
+          fn g() -> i32 { return 1; } \
+          fn f(rs: &r[]) { with rs[g()] { .scale(2); } } }";
+        assert_eq!(typechecked(src).err().map(|errs| errs.len()), Some(1));
+    }
 
-                StmtKind::Alloc(expr, extents)
+    #[test]
+    fn tuple_struct_access() {
+        let src = "mod m1 { \
+          pub fn f() -> (i32, str) { return (1, \"a\"); } } \
+          mod m2 { \
+          struct priv { n: i32 } \
+          fn g() -> (priv, i32) { let p: priv; return (p, 2); } \
+          fn h() -> str { let (n, s) = m1::f(); return s; } }";
+        let (dumpster, mut symtab) = typechecked(src).unwrap();
+        let dumpster = tuple_rewrite(dumpster, &mut symtab);
+        assert_eq!(struct_access(&dumpster, "m1"),
+          vec![(String::from("øtuple0"), Access::Public)]);
+        assert_eq!(struct_access(&dumpster, "m2"),
+          vec![(String::from("øtuple1"), Access::Private)]);
+    }
+
+    #[test]
+    fn named_args() {
+        let src = "mod m { \
+          fn f(a: i32, b: i32; c: i32 = 3) { } \
+          fn v(a: i32, b: i32; xs: i32...) { } \
+          fn g() -> i32 { return 1; } \
+          fn h() { f(b = g(), a = 1); v(b = g(), a = 1); } }";
+        let (dumpster, mut symtab) = typechecked(src).unwrap();
+        let dumpster = named_arg_rewrite(dumpster, &mut symtab);
+
+        let ModuleKind::Normal(ref items) = dumpster.modules[0].data;
+        let body = match items.last() {
+            Some(&NormalItem::Function(ref def)) => &def.body,
+            _ => panic!("no function h"),
+        };
+
+        // passed by name, as written
+        match body[0].data {
+            StmtKind::ExprStmt(Expr {
+                data: ExprKind::Call(_, ref args, ref named), ..
+            }) => {
+                assert!(args.is_empty());
+                let names: Vec<_> = named.iter()
+                    .map(|&(ref name, _)| name.0.as_str()).collect();
+                assert_eq!(names, vec!["b", "a"]);
             },
+            ref s => panic!("not a call: {:?}", s),
+        }
 
-            StmtKind::ReAlloc(expr, preserved, extent) => {
-                match extent {
-                    AllocExtent::Along(other) => {
-                        let extent = AllocExtent::Range(
-                            Some(Expr {
-                                data: ExprKind::ExtentExpr(
-                                          Box::new(other.clone()),
-                                          ExtentKind::First,
-                                          preserved),
-                                ty: Some(Type::Int32),
-                                loc: other.loc.clone(),
-                            }),
+        // back in position, with the call evaluated first
+        match (&body[1].data, &body[2].data) {
+            (&StmtKind::VarDecl(ref decls), &StmtKind::ExprStmt(Expr {
+                data: ExprKind::Call(_, ref args, ref named), ..
+            })) => {
+                assert!(named.is_empty());
+                match (&args[0].data, &args[1].data) {
+                    (&ExprKind::Lit(_), &ExprKind::Name(ref path)) =>
+                        assert_eq!(path.1, decls[0].0),
+                    args => panic!("arguments: {:?}", args),
+                }
+            },
+            stmts => panic!("statements: {:?}", stmts),
+        }
+    }
 
-                            Expr {
-                                data: ExprKind::ExtentExpr(
-                                          Box::new(other.clone()),
-                                          ExtentKind::Last,
-                                          preserved),
-                                ty: Some(Type::Int32),
-                                loc: other.loc.clone(),
-                            }
-                        );
+    // the statements of a generated function, one line each (nested ones
+    //   indented), with gensyms shown as ø
+    fn outline(def: &FunDef) -> Vec<String> {
+        fn expr(e: &Expr) -> String {
+            match e.data {
+                ExprKind::Name(ref path) => name(&path.1),
+                ExprKind::Lit(Literal::Bool(b)) => b.to_string(),
+                ExprKind::Member(ref base, ref mem) =>
+                    format!("{}.{}", expr(base), mem),
+                ExprKind::Index(ref base, ref indices) =>
+                    format!("{}[{}]", expr(base), list(indices)),
+                ExprKind::Call(ref path, ref args, _) =>
+                    format!("{}({})", name(&path.1), list(args)),
+                ExprKind::MemberInvoke(ref base, ref mem, ref args) =>
+                    format!("{}.{}({})", expr(base), mem, list(args)),
+                ExprKind::UnOpApp(ref e, UnOp::LogNot) =>
+                    format!("!{}", expr(e)),
+                ExprKind::BinOpApp(ref l, ref r, op) =>
+                    format!("({} {} {})", expr(l), match op {
+                        BinOp::Eq => "==",
+                        BinOp::NotEq => "!=",
+                        BinOp::IdentEq => "===",
+                        BinOp::NotIdentEq => "!==",
+                        BinOp::LogAnd => "&&",
+                        BinOp::LogOr => "||",
+                        _ => "?",
+                    }, expr(r)),
+                ExprKind::ExtentExpr(ref base, kind, dim) =>
+                    format!("{}<{}>({})", match kind {
+                        ExtentKind::First => "first_index",
+                        ExtentKind::Last => "last_index",
+                        ExtentKind::Length => "array_length",
+                    }, dim, expr(base)),
+                _ => String::from("?"),
+            }
+        }
 
-                        StmtKind::ReAlloc(expr, preserved, extent)
-                    },
+        fn name(ident: &Ident) -> String {
+            if ident.0.starts_with('ø') {
+                String::from("ø")
+            } else {
+                ident.0.clone()
+            }
+        }
 
-                    extent => StmtKind::ReAlloc(expr, preserved, extent),
+        fn list(exprs: &[Expr]) -> String {
+            exprs.iter().map(expr).collect::<Vec<_>>().join(", ")
+        }
+
+        fn walk(stmts: &[Stmt], depth: usize, out: &mut Vec<String>) {
+            for stmt in stmts {
+                let indent = "  ".repeat(depth);
+                match stmt.data {
+                    StmtKind::IfStmt { ref cond, ref body, .. } => {
+                        out.push(format!("{}if {}", indent, expr(cond)));
+                        walk(body, depth + 1, out);
+                    },
+                    StmtKind::ForLoop { ref var, ref body, .. } => {
+                        out.push(format!("{}for {}", indent, var.0));
+                        walk(body, depth + 1, out);
+                    },
+                    StmtKind::Return(Some(ref e)) =>
+                        out.push(format!("{}return {}", indent, expr(e))),
+                    StmtKind::VbBlock(ref code, _, _) => out.push(format!(
+                      "{}vb {}", indent, String::from_utf8_lossy(code).trim())),
+                    _ => out.push(format!("{}?", indent)),
                 }
-            },
+            }
+        }
 
-            data => data
+        let mut out = Vec::new();
+        walk(&def.body, 0, &mut out);
+        out
+    }
+
+    // the generated function a module's function f calls in its return
+    //   statement, and all of the generated functions
+    fn eq_helpers(src: &str) -> (FunDef, Vec<FunDef>) {
+        let (dumpster, mut symtab) = typechecked(src).unwrap();
+        let dumpster = eq_rewrite(dumpster, &mut symtab);
+
+        let ModuleKind::Normal(ref items) = dumpster.modules[0].data;
+        let fns: Vec<&FunDef> = items.iter().filter_map(|item| match *item {
+            NormalItem::Function(ref def) => Some(def),
+            _ => None,
+        }).collect();
+
+        let f = fns.iter().find(|def| def.name.0 == "f").expect("no f");
+        let called = match f.body[0].data {
+            StmtKind::Return(Some(Expr {
+                data: ExprKind::Call(ref path, _, _), ..
+            })) => &path.1,
+            ref s => panic!("not a call: {:?}", s),
         };
 
-        fold::noop_fold_stmt(self, Stmt { data, loc },
-          module, function)
+        let helper = fns.iter().find(|def| def.name == *called)
+            .expect("no called helper");
+        let generated = fns.iter().filter(|def| def.name.0.starts_with('ø'))
+            .map(|def| (*def).clone()).collect();
+        ((*helper).clone(), generated)
+    }
+
+    #[test]
+    fn eq_struct_helper() {
+        let src = "mod m { \
+          struct p { x: i32, v: var, o: obj? } \
+          fn f(a: &p, b: &p) -> bool { return a == b; } }";
+        let (st, generated) = eq_helpers(src);
+        let var = generated.iter()
+            .find(|def| def.params[0].ty == Type::Variant)
+            .expect("no var helper");
+
+        assert_eq!(outline(&st), vec![
+            "if (a.o !== b.o)", "  return false",
+            "if !ø(a.v, b.v)", "  return false",
+            "if (a.x != b.x)", "  return false",
+            "return true",
+        ]);
+
+        assert_eq!(outline(var), vec![
+            "if (IsObject(a) || IsObject(b))",
+            "  if (IsObject(a) && IsObject(b))",
+            "    return (a === b)",
+            "  return false",
+            "if (IsNull(a) || IsNull(b))",
+            "  return (IsNull(a) && IsNull(b))",
+            "if (IsArray(a) || IsArray(b))", "  return false",
+            "if (a != b)", "  return false",
+            "return true",
+        ]);
+    }
+
+    #[test]
+    fn eq_array_helper() {
+        let src = "mod m { \
+          fn f(a: &i32[,], b: &i32[,]) -> bool { return a == b; } }";
+        let (arr, generated) = eq_helpers(src);
+        // (and the allocation check)
+        assert_eq!(generated.len(), 2);
+
+        assert_eq!(outline(&arr), vec![
+            // unallocated arrays are only equal to each other
+            "if (!ø(a) || !ø(b))", "  return (ø(a) == ø(b))",
+            "if ((first_index<0>(a) != first_index<0>(b)) \
+              || (last_index<0>(a) != last_index<0>(b)))",
+            "  return false",
+            "if ((first_index<1>(a) != first_index<1>(b)) \
+              || (last_index<1>(a) != last_index<1>(b)))",
+            "  return false",
+            "for i1",
+            "  for i2",
+            "    if (a[i1, i2] != b[i1, i2])",
+            "      return false",
+            "return true",
+        ]);
+    }
+
+    #[test]
+    fn map_reads() {
+        let src = "mod m { \
+          fn f(m: &map<str, i32>) { \
+              m[\"a\"] = 1; \
+              m[\"b\"] += 2; \
+              let x: i32 = m[\"c\"]; \
+          } }";
+        let (dumpster, mut symtab) = typechecked(src).unwrap();
+        let dumpster = map_rewrite(dumpster, &mut symtab, MapBinding::Late);
+
+        let ModuleKind::Normal(ref items) = dumpster.modules[0].data;
+        let (f, get) = match (&items[0], items.last()) {
+            (&NormalItem::Function(ref f),
+              Some(&NormalItem::Function(ref get))) => (f, get),
+            _ => panic!("no functions"),
+        };
+
+        // assignments (even compound ones) go straight to the dictionary
+        for s in &f.body[..2] {
+            match s.data {
+                StmtKind::Assign(Expr {
+                    data: ExprKind::Index(_, _), ..
+                }, _, _) => { },
+                ref s => panic!("not an indexed assignment: {:?}", s),
+            }
+        }
+
+        // reads check the key is there first
+        match f.body[2].data {
+            StmtKind::VarDecl(ref decls) => match decls[0].2 {
+                Some(Expr { data: ExprKind::Call(ref path, _, _), .. }) =>
+                    assert_eq!(path.1, get.name),
+                ref init => panic!("not a lookup: {:?}", init),
+            },
+            ref s => panic!("not a declaration: {:?}", s),
+        }
+        assert_eq!(outline(get), vec![
+            "if !d.Exists(k)", "  vb Err.Raise 9",
+            "return d[k]",
+        ]);
     }
 }
@@ -92,7 +92,7 @@ macro_rules! make_ast_vistor {
                 self.walk_optparams(params, module, function)
             }
 
-            fn visit_optparam(&mut self, param: & $($_mut)* (FunParam, Literal),
+            fn visit_optparam(&mut self, param: & $($_mut)* (FunParam, Expr),
               module: &Ident, function: &Ident) {
                 self.walk_optparam(param, module, function)
             }
@@ -186,6 +186,7 @@ macro_rules! make_ast_vistor {
                 let Module {
                     ref $($_mut)* name,
                     ref $($_mut)* data,
+                    attrs: ref $($_mut)* _attrs,
                     ref $($_mut)* loc,
                 } = *m;
 
@@ -224,6 +225,8 @@ macro_rules! make_ast_vistor {
                     ref $($_mut)* optparams,
                     ref $($_mut)* ret,
                     ref $($_mut)* body,
+                    lib: ref $($_mut)* _lib,
+                    attrs: ref $($_mut)* _attrs,
                     ref $($_mut)* loc,
                 } = *def;
 
@@ -268,11 +271,13 @@ macro_rules! make_ast_vistor {
                 match *params {
                     FunOptParams::VarArgs(
                         ref $($_mut)* name,
+                        ref $($_mut)* ty,
                         ref $($_mut)* loc
                     ) => {
+                        self.visit_type(ty, module, loc);
                         self.visit_ident(name,
                           NameCtxt::DefParam(module, function,
-                            &Type::VarArgsArray,
+                            &Type::VarArgsArray(Box::new(ty.clone())),
                             ParamMode::ByRef
                           ),
                           loc);
@@ -287,11 +292,13 @@ macro_rules! make_ast_vistor {
                 }
             }
 
-            fn walk_optparam(&mut self, param: & $($_mut)* (FunParam, Literal),
+            fn walk_optparam(&mut self, param: & $($_mut)* (FunParam, Expr),
               module: &Ident, function: &Ident) {
                 let (ref $($_mut)* param, ref $($_mut)* default) = *param;
                 self.visit_funparam(param, module, function);
-                self.visit_literal(default, module, Some(function), &param.loc);
+                // defaults are constant expressions, so they live at
+                //   module scope
+                self.visit_expr(default, module, None);
             }
 
             fn walk_structdef(&mut self, def: & $($_mut)* StructDef,
@@ -300,6 +307,7 @@ macro_rules! make_ast_vistor {
                     ref $($_mut)* name,
                     access: ref $($_mut)* _access,
                     ref $($_mut)* members,
+                    attrs: ref $($_mut)* _attrs,
                     ref $($_mut)* loc,
                 } = *def;
 
@@ -331,6 +339,7 @@ macro_rules! make_ast_vistor {
                     ref $($_mut)* access,
                     ref $($_mut)* ty,
                     ref $($_mut)* init,
+                    attrs: ref $($_mut)* _attrs,
                     ref $($_mut)* loc,
                 } = *s;
 
@@ -350,13 +359,14 @@ macro_rules! make_ast_vistor {
                     ref $($_mut)* access,
                     ref $($_mut)* ty,
                     ref $($_mut)* value,
+                    attrs: ref $($_mut)* _attrs,
                     ref $($_mut)* loc,
                 } = *c;
 
                 self.visit_ident(name,
                   NameCtxt::DefConstant(module, ty, *access), loc);
                 self.visit_type(ty, module, loc);
-                self.visit_literal(value, module, None, loc);
+                self.visit_expr(value, module, None);
                 self.visit_srcloc(loc);
             }
 
@@ -391,6 +401,33 @@ macro_rules! make_ast_vistor {
                         }
                     },
 
+                    StmtKind::Destructure(
+                        ref $($_mut)* idents,
+                        ref $($_mut)* ty,
+                        ref $($_mut)* init
+                    ) => {
+                        self.visit_expr(init, module, Some(function));
+
+                        if let Some(ref $($_mut)* ty) = *ty {
+                            self.visit_type(ty, module, loc);
+                        }
+
+                        // until the tuple type is inferred, we don't know
+                        //   the types of the variables
+                        for (i, ident) in idents.into_iter().enumerate() {
+                            let elem_ty = match *ty {
+                                Some(Type::Tuple(ref tys)) if i < tys.len() =>
+                                    &tys[i],
+                                _ => &Type::Variant,
+                            };
+
+                            self.visit_ident(ident,
+                              NameCtxt::DefValue(module, Some(function),
+                                elem_ty, Access::Private),
+                              loc);
+                        }
+                    },
+
                     StmtKind::Assign(
                         ref $($_mut)* lhs,
                         _,
@@ -514,7 +551,44 @@ macro_rules! make_ast_vistor {
                         for expr in exprs {
                             self.visit_expr(expr, module, Some(function));
                         }
-                    }
+                    },
+
+                    StmtKind::VbBlock(
+                        ref $($_mut)* code,
+                        ref $($_mut)* reads,
+                        ref $($_mut)* writes
+                    ) => {
+                        self.visit_vbexpr(code, module, Some(function), loc);
+                        for path in reads {
+                            self.visit_path(path, NameCtxt::Value(
+                              module, Some(function), Access::Private), loc);
+                        }
+                        for path in writes {
+                            self.visit_path(path, NameCtxt::Value(
+                              module, Some(function), Access::Private), loc);
+                        }
+                    },
+
+                    StmtKind::Assert(
+                        ref $($_mut)* cond,
+                        ref $($_mut)* msg,
+                        _
+                    ) => {
+                        self.visit_expr(cond, module, Some(function));
+                        if let Some(ref $($_mut)* msg) = *msg {
+                            self.visit_expr(msg, module, Some(function));
+                        }
+                    },
+
+                    StmtKind::With(
+                        ref $($_mut)* subject,
+                        ref $($_mut)* body,
+                    ) => {
+                        self.visit_expr(subject, module, Some(function));
+                        for stmt in body {
+                            self.visit_stmt(stmt, module, function);
+                        }
+                    },
                 }
 
                 self.visit_srcloc(loc);
@@ -547,6 +621,16 @@ macro_rules! make_ast_vistor {
                         }
                     },
 
+                    ExprKind::Slice(
+                        ref $($_mut)* expr,
+                        ref $($_mut)* lo,
+                        ref $($_mut)* hi
+                    ) => {
+                        self.visit_expr(expr, module, function);
+                        self.visit_expr(lo, module, function);
+                        self.visit_expr(hi, module, function);
+                    },
+
                     ExprKind::Call(
                         ref $($_mut)* path,
                         ref $($_mut)* args,
@@ -624,8 +708,22 @@ macro_rules! make_ast_vistor {
                         self.visit_type(ty, module, & $($_mut)* expr.loc);
                     },
 
+                    ExprKind::Tuple(ref $($_mut)* exprs) => {
+                        for expr in exprs {
+                            self.visit_expr(expr, module, function);
+                        }
+                    },
+
                     ExprKind::VbExpr(ref $($_mut)* data) =>
                         self.visit_vbexpr(data, module, function, loc),
+
+                    ExprKind::WithSubject => { },
+
+                    ExprKind::CheckedArith(_, ref $($_mut)* args) => {
+                        for arg in args {
+                            self.visit_expr(arg, module, function);
+                        }
+                    },
                 }
 
                 if let Some(ref $($_mut)* ty) = *ty {
@@ -636,7 +734,7 @@ macro_rules! make_ast_vistor {
             }
 
             fn walk_forspec(&mut self, spec: & $($_mut)* ForSpec,
-              module: &Ident, function: &Ident, _loc: &SrcLoc) {
+              module: &Ident, function: &Ident, loc: &SrcLoc) {
                 match *spec {
                     ForSpec::Range(
                         ref $($_mut)* from,
@@ -652,8 +750,15 @@ macro_rules! make_ast_vistor {
                         }
                     },
 
-                    ForSpec::Each(ref $($_mut)* of) =>
-                        self.visit_expr(of, module, Some(function))
+                    ForSpec::Each(ref $($_mut)* of, ref $($_mut)* indices) => {
+                        self.visit_expr(of, module, Some(function));
+                        let index_ty = index_type(of);
+                        for index in indices {
+                            self.visit_ident(index,
+                              NameCtxt::DefValue(module, Some(function),
+                                &index_ty, Access::Private), loc);
+                        }
+                    },
                 }
             }
 
@@ -695,6 +800,36 @@ macro_rules! make_ast_vistor {
                         self.visit_arraybounds(bounds, base, module, loc);
                     },
 
+                    Type::Nullable(ref $($_mut)* base) => {
+                        self.visit_type(base, module, loc);
+                    },
+
+                    Type::Map(ref $($_mut)* key, ref $($_mut)* value) => {
+                        self.visit_type(key, module, loc);
+                        self.visit_type(value, module, loc);
+                    },
+
+                    Type::List(ref $($_mut)* base) => {
+                        self.visit_type(base, module, loc);
+                    },
+
+                    Type::ArrayView(ref $($_mut)* base) => {
+                        self.visit_type(base, module, loc);
+                    },
+
+                    Type::Tuple(ref $($_mut)* tys) => {
+                        for ty in tys {
+                            self.visit_type(ty, module, loc);
+                        }
+                    },
+
+                    Type::FnPtr(ref $($_mut)* params, ref $($_mut)* ret) => {
+                        for &$($_mut)* (_, ref $($_mut)* ty) in params {
+                            self.visit_type(ty, module, loc);
+                        }
+                        self.visit_type(ret, module, loc);
+                    },
+
                     Type::Struct(ref $($_mut)* path) => {
                         self.visit_path(path,
                           NameCtxt::Type(module, Access::Private), loc)